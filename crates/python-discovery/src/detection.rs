@@ -0,0 +1,809 @@
+//! Python interpreter detection for Serena.
+//!
+//! Serena supports Python 3.11 and 3.12. Detection walks the candidates from
+//! [`crate::discovery`], runs `--version` on each (through the
+//! [`ProbeCache`]) and checks the result against the supported versions, an
+//! optional `python_version` constraint and the [`PythonPolicy`] for
+//! early-adopter builds and releases outside the supported range.
+
+use crate::{Host, Os};
+use std::path::{Component, Path, PathBuf};
+
+use crate::discovery::{self, PythonPreference};
+use crate::probe_cache::{ProbeCache, VersionProbe};
+use crate::process::{Invocation, Runner};
+use crate::regex::Regex;
+use crate::stderr;
+use crate::version::Version;
+
+/// Reports an interpreter's version, e.g. "3.12.1".
+pub fn python_version(python: &str, runner: &dyn Runner) -> Option<String> {
+    let output = runner
+        .run(&Invocation::new(python).args(["--version"]))
+        .ok()?;
+    if !output.success() {
+        return None;
+    }
+    // Python 3.4+ prints the version on stdout, older releases on stderr
+    let banner = if output.stdout.is_empty() {
+        String::from_utf8_lossy(&output.stderr).to_string()
+    } else {
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+    banner
+        .trim()
+        .strip_prefix("Python ")
+        .map(|version| version.to_string())
+}
+
+/// Reports the architecture an interpreter was built for, as
+/// `platform.machine()` names it, e.g. "arm64".
+///
+/// Under Rosetta or Windows' x64 emulation this differs from the host.
+pub fn python_machine(python: &str, runner: &dyn Runner) -> Option<String> {
+    let output = runner
+        .run(&Invocation::new(python).args(["-c", "import platform; print(platform.machine())"]))
+        .ok()?;
+    let machine = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.success() && !machine.is_empty()).then_some(machine)
+}
+
+/// Validates a Python path for security checks.
+///
+/// Rejects empty, overlong and traversal-style paths, control and invisible
+/// formatting characters, and anything that doesn't look like a Python
+/// interpreter. Characters a shell would interpret are legal in paths, so
+/// they're quoted wherever a path reaches a shell instead.
+pub fn validate_python_path(path: &str) -> bool {
+    // Enhanced security checks
+    if path.is_empty() || path.len() >= 1000 {
+        return false;
+    }
+
+    // Prevent path traversal attempts
+    if path.contains("..") || path.contains("//") {
+        return false;
+    }
+
+    // Nothing that changes how the path is run or how it reads on screen
+    if path
+        .chars()
+        .any(|c| c.is_control() || is_invisible_format(c))
+    {
+        return false;
+    }
+
+    // Only allow reasonable executable names/paths
+    let path_lower = path.to_lowercase();
+    path_lower.contains("python")
+        || path_lower.starts_with("/usr/")
+        || path_lower.starts_with("/opt/")
+        || path_lower.starts_with("/library/frameworks/")
+}
+
+/// Zero-width and bidirectional formatting characters, which make a path
+/// display as something else, e.g. U+202E RIGHT-TO-LEFT OVERRIDE.
+fn is_invisible_format(c: char) -> bool {
+    matches!(
+        c,
+        '\u{200b}'..='\u{200f}' | '\u{202a}'..='\u{202e}' | '\u{2060}'..='\u{2069}' | '\u{feff}'
+    )
+}
+
+/// Drops `.` and resolves `..` components without touching the file system.
+///
+/// `..` at the start of a relative path, or above the root, is kept.
+pub fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if normalized.file_name().is_some() => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Python minor versions supported by Serena.
+pub const SUPPORTED_PYTHON_MINORS: [u64; 2] = [11, 12];
+
+/// The upcoming Python minor version early adopters may opt into.
+pub const NEXT_PYTHON_MINOR: u64 = 13;
+
+/// Which interpreter builds auto-detection accepts besides final releases.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PythonPolicy<'a> {
+    /// Accept alpha, beta and release candidate builds
+    pub allow_prereleases: bool,
+    /// Accept free-threaded (no-GIL) builds
+    pub allow_free_threaded: bool,
+    /// Accept any Python 3 release, e.g. 3.10 or 3.14, at the user's risk
+    pub allow_unsupported: bool,
+    /// Pattern that reads the version from banners standard parsing can't,
+    /// e.g. those of vendor builds; see [`accepted_version`]
+    pub version_accept_regex: Option<&'a str>,
+}
+
+/// Release names searched for when unsupported releases are allowed.
+const UNSUPPORTED_NAMES: [&str; 3] = ["python3.13", "python3.14", "python3.10"];
+
+/// True when Serena supports the Python `version`, e.g. "3.12.1", either as a
+/// regular release or as an early-adopter build of the next one.
+pub fn is_supported_python(version: &str) -> bool {
+    let free_threaded = version.trim().ends_with('t');
+    Version::parse(version.trim().trim_end_matches(['t', '+'])).is_some_and(|version| {
+        version.major() == 3
+            && (SUPPORTED_PYTHON_MINORS.contains(&version.minor())
+                || (version.minor() == NEXT_PYTHON_MINOR
+                    && (version.is_prerelease() || free_threaded)))
+    })
+}
+
+/// True when the banner or executable name marks a free-threaded build,
+/// e.g. `Python 3.13.0t` or `python3.13t`.
+pub fn is_free_threaded_build(version_str: &str, executable: &str) -> bool {
+    let banner_token = version_str
+        .trim()
+        .strip_prefix("Python ")
+        .and_then(|rest| rest.split_whitespace().next())
+        .unwrap_or_default();
+    let file_name = Path::new(executable)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let named_free_threaded = file_name
+        .trim_end_matches(".exe")
+        .strip_prefix("python")
+        .and_then(|version| version.strip_suffix('t'))
+        .is_some_and(|version| {
+            !version.is_empty() && version.chars().all(|c| c.is_ascii_digit() || c == '.')
+        });
+
+    banner_token.ends_with('t') || named_free_threaded
+}
+
+/// Checks an interpreter's `--version` banner against Serena's requirements,
+/// an optional "3.11"/"3.12" constraint and the pre-release/free-threaded policy.
+///
+/// The error explains why the interpreter was rejected.
+pub fn check_python_build(
+    version_str: &str,
+    executable: &str,
+    constraint: Option<&str>,
+    policy: PythonPolicy,
+) -> Result<(), String> {
+    let version = banner_version(version_str, policy)?;
+    let free_threaded = is_free_threaded_build(version_str, executable);
+
+    if free_threaded && !policy.allow_free_threaded {
+        return Err(format!(
+            "Python {} is a free-threaded build; set \"allow_free_threaded\": true to use it",
+            version
+        ));
+    }
+    if version.is_prerelease() && !policy.allow_prereleases {
+        return Err(format!(
+            "Python {} is a pre-release; set \"allow_prereleases\": true to use it",
+            version
+        ));
+    }
+
+    // The next minor version is only reachable through an opted-in early-adopter build
+    let early_adopter =
+        version.minor() == NEXT_PYTHON_MINOR && (version.is_prerelease() || free_threaded);
+    let supported = SUPPORTED_PYTHON_MINORS.contains(&version.minor()) || early_adopter;
+    if version.major() != 3 || !(supported || policy.allow_unsupported) {
+        return Err(format!(
+            "Python {} is not supported; Serena requires 3.11 or 3.12 \
+             (set \"allow_unsupported_python\": true to try it anyway)",
+            version
+        ));
+    }
+
+    if let Some(constraint) = constraint {
+        let matches = Version::parse(constraint).is_some_and(|c| version.matches_prefix(&c));
+        if !matches {
+            return Err(format!(
+                "Python {} does not match python_version \"{}\"",
+                version, constraint
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Parses a `--version` banner, falling back on `version_accept_regex`.
+fn banner_version(banner: &str, policy: PythonPolicy) -> Result<Version, String> {
+    if let Some(version) = Version::from_python_banner(banner) {
+        return Ok(version);
+    }
+    let unrecognized = format!("unrecognized version output \"{}\"", banner.trim());
+    match policy.version_accept_regex {
+        Some(pattern) => accepted_version(banner, pattern)
+            .map_err(|reason| format!("{}; {}", unrecognized, reason)),
+        None => Err(unrecognized),
+    }
+}
+
+/// Reads the version from `banner` with the `version_accept_regex`
+/// `pattern`: the text its first group captures, or the whole match when it
+/// has no groups. The version still has to meet Serena's requirements.
+pub fn accepted_version(banner: &str, pattern: &str) -> Result<Version, String> {
+    let regex =
+        Regex::new(pattern).map_err(|err| format!("version_accept_regex is invalid: {}", err))?;
+    let found = regex
+        .find(banner.trim())
+        .ok_or_else(|| "version_accept_regex doesn't match it".to_string())?;
+    Version::parse(found.trim()).ok_or_else(|| {
+        format!(
+            "version_accept_regex matched \"{}\", which isn't a version",
+            found
+        )
+    })
+}
+
+/// Validates the `python_version` setting itself.
+pub fn validate_python_constraint(constraint: &str, policy: PythonPolicy) -> Result<(), String> {
+    let supported = Version::parse(constraint).is_some_and(|version| {
+        version.major() == 3
+            && (policy.allow_unsupported
+                || SUPPORTED_PYTHON_MINORS.contains(&version.minor())
+                || (version.minor() == NEXT_PYTHON_MINOR
+                    && (policy.allow_prereleases || policy.allow_free_threaded)))
+    });
+    if supported {
+        Ok(())
+    } else {
+        Err(format!(
+            "Unsupported python_version \"{}\": Serena requires 3.11 or 3.12",
+            constraint
+        ))
+    }
+}
+
+/// Names of the entries in the directory `path`; empty when it can't be read.
+pub fn list_dir(path: &Path) -> Vec<String> {
+    std::fs::read_dir(path)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .collect()
+}
+
+/// Resolves a bare executable name through `PATH`.
+///
+/// The search runs in-process, so it needs no `which` binary. `PATH` is
+/// split with `host`'s separator rather than the build target's, since a
+/// WASI build runs on every host. Inside a sandbox only the host's own
+/// `which` sees the host's `PATH`.
+fn which(name: &str, host: Host, runner: &dyn Runner) -> Option<String> {
+    if host.sandbox.is_some() {
+        let output = runner
+            .run(&Invocation::new("which").args([name]).on_host(host.sandbox))
+            .ok()?;
+        let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        return (output.success() && !path.is_empty()).then_some(path);
+    }
+    let path = std::env::var("PATH").ok()?;
+    let windows = host.os == Os::Windows;
+    let extensions = std::env::var("PATHEXT").ok().filter(|_| windows);
+    let separator = if windows { ';' } else { ':' };
+    search_path(name, &path, separator, extensions.as_deref())
+}
+
+/// Finds the first executable `name` in the directories of `path`, separated
+/// by `separator`.
+///
+/// With `extensions`, a `PATHEXT`-style list such as ".COM;.EXE", names with
+/// each extension are tried before the bare name.
+fn search_path(
+    name: &str,
+    path: &str,
+    separator: char,
+    extensions: Option<&str>,
+) -> Option<String> {
+    let mut names: Vec<String> = extensions
+        .into_iter()
+        .flat_map(|extensions| extensions.split(';'))
+        .filter(|extension| !extension.is_empty())
+        .map(|extension| format!("{}{}", name, extension.to_ascii_lowercase()))
+        .collect();
+    names.push(name.to_string());
+    path.split(separator)
+        .filter(|dir| !dir.is_empty())
+        .flat_map(|dir| names.iter().map(move |name| Path::new(dir).join(name)))
+        .find(|candidate| is_executable(candidate))
+        .map(|found| found.to_string_lossy().to_string())
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// Resolves a candidate through `PATH` and runs `--version` on it.
+///
+/// Returns the resolved path and the version banner, or why it has none.
+fn probe_python_version(
+    candidate: &str,
+    host: Host,
+    runner: &dyn Runner,
+) -> (String, Result<String, String>) {
+    // Prefer the absolute path so the serena script next to it can be found
+    let path = if candidate.contains(['/', '\\']) {
+        candidate.to_string()
+    } else {
+        which(candidate, host, runner).unwrap_or_else(|| candidate.to_string())
+    };
+    if !validate_python_path(&path) {
+        return (path, Err("not a valid Python path".to_string()));
+    }
+
+    let invocation = Invocation::new(path.as_str())
+        .args(["--version"])
+        .on_host(host.sandbox);
+    let banner = match runner.run(&invocation) {
+        Ok(output) if output.success() => Ok(String::from_utf8_lossy(&output.stdout).to_string()),
+        Ok(output) => Err(format!(
+            "`--version` failed with {}",
+            stderr::failure(output.status, &output.stderr)
+        )),
+        Err(e) => Err(format!("could not run: {}", e)),
+    };
+    (path, banner)
+}
+
+/// Probes a single candidate, such as a configured interpreter, through `cache`.
+pub fn probe_candidate(
+    candidate: &str,
+    host: Host,
+    runner: &dyn Runner,
+    cache: &mut ProbeCache,
+) -> VersionProbe {
+    cache.version(candidate, || probe_python_version(candidate, host, runner))
+}
+
+/// Checks a probed candidate, returning its version when it qualifies and
+/// why it doesn't otherwise.
+pub fn judge(
+    probe: &VersionProbe,
+    python_version: Option<&str>,
+    policy: PythonPolicy,
+) -> Result<String, String> {
+    let banner = probe.banner.clone()?;
+    check_python_build(&banner, &probe.path, python_version, policy)?;
+    banner_version(&banner, policy).map(|version| version.to_string())
+}
+
+/// An interpreter that satisfies Serena's requirements.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Interpreter {
+    /// Path after `PATH` resolution
+    pub path: String,
+    /// Python version, e.g. "3.12.1"
+    pub version: String,
+}
+
+/// True when `path` matches one of the `excluded` globs.
+pub fn is_excluded(path: &str, excluded: &[String]) -> bool {
+    excluded.iter().any(|pattern| matches_glob(pattern, path))
+}
+
+/// Matches `text` against a glob where `*` stands for any run of characters,
+/// path separators included, and `?` for exactly one.
+pub fn matches_glob(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Where the last `*` was seen, and how much text it has swallowed so far
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                // Let the last `*` swallow one more character and try again
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Auto-detects an interpreter that satisfies Serena's requirements.
+///
+/// Returns the first of [`find_python_interpreters`].
+#[allow(clippy::too_many_arguments)]
+pub fn find_python_executable(
+    python_version: Option<&str>,
+    policy: PythonPolicy,
+    preference: PythonPreference,
+    excluded: &[String],
+    project_root: Option<&Path>,
+    host: Host,
+    runner: &dyn Runner,
+    cache: &mut ProbeCache,
+) -> Result<String, String> {
+    let found = find_python_interpreters(
+        python_version,
+        policy,
+        preference,
+        excluded,
+        project_root,
+        host,
+        runner,
+        cache,
+    )?;
+    Ok(found[0].path.clone())
+}
+
+/// Auto-detects every interpreter that satisfies Serena's requirements.
+///
+/// Candidates are probed with `runner` in `preference` order, with results
+/// cached in `cache`; the result keeps that order and is never empty. The
+/// error lists every location tried and why interpreters that were found got
+/// rejected.
+///
+/// Interpreters matching one of the `excluded` globs, by candidate path or by
+/// resolved path, are never returned.
+#[allow(clippy::too_many_arguments)]
+pub fn find_python_interpreters(
+    python_version: Option<&str>,
+    policy: PythonPolicy,
+    preference: PythonPreference,
+    excluded: &[String],
+    project_root: Option<&Path>,
+    host: Host,
+    runner: &dyn Runner,
+    cache: &mut ProbeCache,
+) -> Result<Vec<Interpreter>, String> {
+    if let Some(version) = python_version {
+        validate_python_constraint(version, policy)?;
+    }
+    if let Some(pattern) = policy.version_accept_regex {
+        Regex::new(pattern).map_err(|err| format!("Invalid version_accept_regex: {}", err))?;
+    }
+
+    // Early-adopter builds are only searched for when explicitly allowed
+    let mut names = vec!["python3.11", "python3.12"];
+    if policy.allow_prereleases {
+        names.push("python3.13");
+    }
+    if policy.allow_free_threaded {
+        names.push("python3.13t");
+    }
+    if policy.allow_unsupported {
+        for name in UNSUPPORTED_NAMES {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+
+    // Interpreters shipped in a sandbox go first; the host's may be hidden
+    let lookup_env = |name: &str| std::env::var(name).ok();
+    let mut python_candidates = host
+        .sandbox
+        .map(|sandbox| sandbox.candidates(&names, lookup_env))
+        .unwrap_or_default();
+    for candidate in discovery::candidates(
+        preference,
+        &names,
+        project_root,
+        host.os,
+        host.arch,
+        lookup_env,
+        list_dir,
+    ) {
+        if !python_candidates.contains(&candidate) {
+            python_candidates.push(candidate);
+        }
+    }
+    let mut rejections = Vec::new();
+    let mut found: Vec<Interpreter> = Vec::new();
+
+    for candidate in &python_candidates {
+        // Skipped unprobed, since most candidates don't exist at all
+        if is_excluded(candidate, excluded) {
+            continue;
+        }
+        let probe = cache.version(candidate, || probe_python_version(candidate, host, runner));
+        if is_excluded(&probe.path, excluded) {
+            rejections.push(format!("{}: excluded by excluded_pythons", probe.path));
+            continue;
+        }
+        let checked = judge(&probe, python_version, policy);
+
+        match checked {
+            // Several candidates, e.g. python3 and python3.12, often resolve to one file
+            Ok(_)
+                if found
+                    .iter()
+                    .any(|interpreter| interpreter.path == probe.path) =>
+            {
+                continue
+            }
+            Ok(version) => found.push(Interpreter {
+                path: probe.path,
+                version,
+            }),
+            // Candidates that don't exist or aren't Python paths aren't worth reporting
+            Err(reason)
+                if reason.starts_with("could not run") || reason == "not a valid Python path" =>
+            {
+                continue
+            }
+            Err(reason) => rejections.push(format!("{}: {}", probe.path, reason)),
+        }
+    }
+
+    if !found.is_empty() {
+        // Unsupported releases only win when nothing supported was found
+        if policy.allow_unsupported {
+            found.sort_by_key(|interpreter| !is_supported_python(&interpreter.version));
+        }
+        return Ok(found);
+    }
+
+    let attempted_paths = python_candidates.join(", ");
+    let mut message = format!(
+        "Python 3.11 or 3.12 not found in any of these locations: {}. 
+
+Serena requires Python 3.11 OR 3.12 (either version works).
+
+To fix this issue:
+1. Install Python 3.11: brew install python@3.11
+2. Or install Python 3.12: brew install python@3.12  
+3. Or specify custom path in Zed settings: {{\"python_executable\": \"/path/to/python3.11\"}}",
+        attempted_paths
+    );
+    if let Some(sandbox) = host.sandbox {
+        message.push_str("\n\n");
+        message.push_str(sandbox.advice());
+    }
+    if !rejections.is_empty() {
+        rejections.dedup();
+        message.push_str("\n\nRejected interpreters:");
+        for rejection in &rejections {
+            message.push_str("\n- ");
+            message.push_str(rejection);
+        }
+    }
+    Err(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search_path() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let (first, second) = (root.join("first"), root.join("second"));
+        std::fs::create_dir_all(&first).unwrap();
+        std::fs::create_dir_all(&second).unwrap();
+        std::fs::write(first.join("python3.12"), "").unwrap();
+        std::fs::write(second.join("python3.12"), "").unwrap();
+        std::fs::write(second.join("python3.11.exe"), "").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let executable = std::fs::Permissions::from_mode(0o755);
+            std::fs::set_permissions(second.join("python3.12"), executable).unwrap();
+        }
+        let path = format!("{}:{}", first.display(), second.display());
+
+        // Unix skips files that aren't executable
+        let expected = if cfg!(unix) { &second } else { &first };
+        assert_eq!(
+            search_path("python3.12", &path, ':', None),
+            Some(expected.join("python3.12").to_string_lossy().to_string())
+        );
+        assert_eq!(
+            search_path("python3.11", &path, ':', Some(".COM;.EXE")).is_some(),
+            cfg!(not(unix))
+        );
+        assert_eq!(search_path("python3.13", &path, ':', None), None);
+
+        // A Windows PATH splits on semicolons, whatever the build target
+        let path = format!("{};{}", root.join("missing").display(), second.display());
+        assert_eq!(
+            search_path("python3.12", &path, ';', None),
+            Some(second.join("python3.12").to_string_lossy().to_string())
+        );
+        assert_eq!(search_path("python3.12", &path, ':', None), None);
+    }
+
+    #[test]
+    fn test_validate_python_path() {
+        // Valid paths
+        assert!(validate_python_path("/usr/bin/python3.11"));
+        assert!(validate_python_path("/opt/homebrew/bin/python3.12"));
+        assert!(validate_python_path("/opt/local/bin/python3.12"));
+        assert!(validate_python_path(
+            "/Library/Frameworks/Python.framework/Versions/3.11/bin/python3.11"
+        ));
+        assert!(validate_python_path("python3.11"));
+        assert!(validate_python_path("python3.12"));
+        assert!(validate_python_path("python"));
+
+        // Invalid paths
+        assert!(!validate_python_path(""));
+        assert!(!validate_python_path("path\0with\0null"));
+        assert!(!validate_python_path(&"x".repeat(1001))); // Too long
+        assert!(!validate_python_path("/etc/../passwd")); // Path traversal
+        assert!(!validate_python_path("//malicious//path")); // Double slashes
+        assert!(!validate_python_path("malicious-executable")); // Suspicious name
+        assert!(validate_python_path("/home/o'brien/.venv/bin/python")); // Apostrophe
+        assert!(validate_python_path("/Users/me/R&D/.venv/bin/python")); // Ampersand
+        assert!(!validate_python_path("/usr/bin/python\u{202e}exe")); // Bidi override
+    }
+
+    #[test]
+    fn test_is_valid_python_version() {
+        let is_valid_python_version = |banner: &str| {
+            check_python_build(banner, "python3", None, PythonPolicy::default()).is_ok()
+        };
+
+        // Valid Python 3.11 versions (system needs 3.11 OR 3.12, not both)
+        assert!(is_valid_python_version("Python 3.11.0"));
+        assert!(is_valid_python_version("Python 3.11.5"));
+        assert!(is_valid_python_version(
+            "Python 3.11 (default, Oct  5 2023)"
+        ));
+        assert!(is_valid_python_version("Python 3.11"));
+        assert!(is_valid_python_version("  Python 3.11.7  ")); // With whitespace
+
+        // Valid Python 3.12 versions
+        assert!(is_valid_python_version("Python 3.12.0"));
+        assert!(is_valid_python_version("Python 3.12.1"));
+        assert!(is_valid_python_version("Python 3.12 (main, Dec  7 2023)"));
+
+        // Invalid versions - should NOT match
+        assert!(!is_valid_python_version("Python 3.10.0"));
+        assert!(!is_valid_python_version("Python 3.13.0"));
+        assert!(!is_valid_python_version("Python 2.7.0"));
+        assert!(!is_valid_python_version("Python 3.9.0"));
+        assert!(!is_valid_python_version("Python 3.110.0")); // Edge case - should not match
+        assert!(!is_valid_python_version("Python 3.120.0")); // Edge case - should not match
+        assert!(!is_valid_python_version("Some Python 3.11.0 thing")); // Doesn't start with "Python 3.11"
+    }
+
+    #[test]
+    fn test_matches_python_constraint() {
+        let matches_python_constraint = |banner: &str, constraint: Option<&str>| {
+            check_python_build(banner, "python3", constraint, PythonPolicy::default()).is_ok()
+        };
+        assert!(matches_python_constraint("Python 3.11.4", None));
+        assert!(matches_python_constraint("Python 3.11.4", Some("3.11")));
+        assert!(!matches_python_constraint("Python 3.12.1", Some("3.11")));
+        assert!(!matches_python_constraint("Python 3.10.0", Some("3.10")));
+    }
+
+    #[test]
+    fn test_matches_glob() {
+        assert!(matches_glob("/usr/bin/python3.11", "/usr/bin/python3.11"));
+        assert!(matches_glob("/usr/bin/*", "/usr/bin/python3.11"));
+        assert!(matches_glob(
+            "*/ci-toolchain/*",
+            "/opt/ci-toolchain/bin/python3.12"
+        ));
+        assert!(matches_glob(
+            "/usr/local/bin/python3.1?",
+            "/usr/local/bin/python3.12"
+        ));
+        assert!(matches_glob("*python*3.11", "/opt/python/bin/python3.11"));
+        assert!(!matches_glob("/usr/bin/*", "/usr/local/bin/python3.11"));
+        assert!(!matches_glob("/usr/bin/python3.1?", "/usr/bin/python3.1"));
+        assert!(!matches_glob("python3.11", "/usr/bin/python3.11"));
+
+        let excluded = vec!["/usr/bin/*".to_string(), "*/x86_64/*".to_string()];
+        assert!(is_excluded("/usr/bin/python3", &excluded));
+        assert!(is_excluded("/usr/local/x86_64/bin/python3.12", &excluded));
+        assert!(!is_excluded("/opt/homebrew/bin/python3.12", &excluded));
+    }
+
+    #[test]
+    fn test_is_supported_python() {
+        assert!(is_supported_python("3.11.9"));
+        assert!(is_supported_python("3.12.1"));
+        assert!(is_supported_python("3.13.0rc2"));
+        assert!(is_supported_python("3.13.1t"));
+        assert!(!is_supported_python("3.13.1"));
+        assert!(!is_supported_python("3.10.12"));
+        assert!(!is_supported_python("2.7.18"));
+        assert!(!is_supported_python(""));
+    }
+
+    #[test]
+    fn test_python_build_policy() {
+        let strict = PythonPolicy::default();
+        let early = PythonPolicy {
+            allow_prereleases: true,
+            allow_free_threaded: true,
+            allow_unsupported: false,
+            ..PythonPolicy::default()
+        };
+
+        let err = check_python_build("Python 3.13.0rc2", "python3.13", None, strict).unwrap_err();
+        assert!(err.contains("allow_prereleases"), "{}", err);
+        assert!(check_python_build("Python 3.13.0rc2", "python3.13", None, early).is_ok());
+        assert!(check_python_build("Python 3.12.0b4", "python3.12", None, strict).is_err());
+
+        let err =
+            check_python_build("Python 3.13.1", "/usr/bin/python3.13t", None, strict).unwrap_err();
+        assert!(err.contains("allow_free_threaded"), "{}", err);
+        assert!(check_python_build("Python 3.13.1", "/usr/bin/python3.13t", None, early).is_ok());
+        assert!(check_python_build("Python 3.13.1t", "python3", None, early).is_ok());
+
+        // A regular final 3.13 is still outside Serena's supported range
+        let err = check_python_build("Python 3.13.1", "python3.13", None, early).unwrap_err();
+        assert!(err.contains("requires 3.11 or 3.12"), "{}", err);
+        assert!(check_python_build("Python 3.14.0a1", "python3.14", None, early).is_err());
+
+        // Unsupported releases are allowed on request, but never Python 2
+        let lenient = PythonPolicy {
+            allow_unsupported: true,
+            ..PythonPolicy::default()
+        };
+        assert!(err.contains("allow_unsupported_python"), "{}", err);
+        assert!(check_python_build("Python 3.10.12", "python3.10", None, lenient).is_ok());
+        assert!(check_python_build("Python 3.14.0", "python3.14", None, lenient).is_ok());
+        assert!(check_python_build("Python 2.7.18", "python2", None, lenient).is_err());
+        assert!(validate_python_constraint("3.10", lenient).is_ok());
+
+        assert!(validate_python_constraint("3.13", strict).is_err());
+        assert!(validate_python_constraint("3.13", early).is_ok());
+    }
+    #[test]
+    fn test_version_accept_regex() {
+        let banner = "Intel(R) Distribution for Python 3.11.5 (2024.1.0)";
+        let strict = PythonPolicy::default();
+        let err = check_python_build(banner, "python3", None, strict).unwrap_err();
+        assert!(err.starts_with("unrecognized version output"), "{}", err);
+
+        let vendor = PythonPolicy {
+            version_accept_regex: Some(r"Python (\d+\.\d+\.\d+)"),
+            ..PythonPolicy::default()
+        };
+        assert!(check_python_build(banner, "python3", Some("3.11"), vendor).is_ok());
+        assert!(check_python_build(banner, "python3", Some("3.12"), vendor).is_err());
+        // Standard banners never reach the pattern
+        assert!(check_python_build("Python 3.12.1", "python3", None, vendor).is_ok());
+
+        // The version found still has to be supported
+        let err =
+            check_python_build("Embedded CPython 3.9.2", "python3", None, vendor).unwrap_err();
+        assert!(err.contains("requires 3.11 or 3.12"), "{}", err);
+
+        let err = check_python_build("Embedded 3.12", "python3", None, vendor).unwrap_err();
+        assert!(err.contains("doesn't match"), "{}", err);
+        let err = accepted_version(banner, r"Intel\(R\)").unwrap_err();
+        assert!(err.contains("isn't a version"), "{}", err);
+        let err = accepted_version(banner, "(").unwrap_err();
+        assert!(err.contains("is invalid"), "{}", err);
+    }
+}
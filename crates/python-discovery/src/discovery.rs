@@ -0,0 +1,615 @@
+//! Ordering of Python interpreter candidates during auto-detection.
+//!
+//! Interpreters come from three sources: the project's virtual environment,
+//! environment managers (conda, pyenv, pyenv-virtualenv, uv) and the system.
+//! `python_preference` decides which source wins when several provide a valid
+//! interpreter.
+
+use crate::{Arch, Os};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// Directory names checked for a project virtual environment, in order.
+const VENV_DIRS: [&str; 2] = [".venv", "venv"];
+
+/// Unversioned names looked up on `PATH`, which may point at any version.
+pub const GENERIC_NAMES: [&str; 2] = ["python3", "python"];
+
+/// Install prefix checked for system interpreters besides `PATH` on Linux
+/// and other Unix-likes, where source builds and ports install to.
+const LOCAL_BIN: &str = "/usr/local/bin";
+
+/// MacPorts executables on macOS.
+const MACPORTS_BIN: &str = "/opt/local/bin";
+
+/// Root of the python.org framework installs on macOS.
+const FRAMEWORKS_DIR: &str = "/Library/Frameworks";
+
+/// A Homebrew installation on macOS.
+///
+/// Macs migrated from Intel often keep both, and `PATH` frequently still
+/// points at the Intel one, so the prefix matching the host goes first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HomebrewPrefix {
+    /// `/opt/homebrew`, for Apple Silicon
+    AppleSilicon,
+    /// `/usr/local`, for Intel and Rosetta
+    Intel,
+}
+
+impl HomebrewPrefix {
+    /// The prefix whose builds run natively on `arch`.
+    pub fn native(arch: Arch) -> Self {
+        match arch {
+            Arch::Aarch64 => Self::AppleSilicon,
+            Arch::X86_64 | Arch::X86 => Self::Intel,
+        }
+    }
+
+    /// The other prefix.
+    pub fn other(self) -> Self {
+        match self {
+            Self::AppleSilicon => Self::Intel,
+            Self::Intel => Self::AppleSilicon,
+        }
+    }
+
+    /// Directory holding this prefix's executables.
+    pub fn bin(self) -> &'static str {
+        match self {
+            Self::AppleSilicon => "/opt/homebrew/bin",
+            Self::Intel => "/usr/local/bin",
+        }
+    }
+
+    /// The prefix an interpreter path lives in, if any.
+    pub fn of(path: &str) -> Option<Self> {
+        [Self::AppleSilicon, Self::Intel]
+            .into_iter()
+            .find(|prefix| path.starts_with(&format!("{}/", prefix.bin())))
+    }
+
+    /// Human-readable name for diagnostics.
+    pub fn describe(self) -> &'static str {
+        match self {
+            Self::AppleSilicon => "Apple Silicon Homebrew (/opt/homebrew)",
+            Self::Intel => "Intel Homebrew (/usr/local)",
+        }
+    }
+}
+
+/// Which interpreter source wins when several valid Pythons exist.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "kebab-case")]
+pub enum PythonPreference {
+    /// Project virtual environment, then environment managers, then the system
+    #[default]
+    VenvFirst,
+    /// System interpreters, then the project virtual environment, then environment managers
+    SystemFirst,
+    /// Only the project virtual environment and environment managers, never the system
+    ManagedOnly,
+}
+
+/// Where an interpreter candidate came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PythonSource {
+    /// `$VIRTUAL_ENV` or a virtual environment in the project root
+    Venv,
+    /// conda, pyenv, pyenv-virtualenv or uv
+    Managed,
+    /// `PATH` and well-known install prefixes
+    System,
+}
+
+impl PythonPreference {
+    /// Sources to search, in order.
+    pub fn sources(self) -> &'static [PythonSource] {
+        match self {
+            Self::VenvFirst => &[
+                PythonSource::Venv,
+                PythonSource::Managed,
+                PythonSource::System,
+            ],
+            Self::SystemFirst => &[
+                PythonSource::System,
+                PythonSource::Venv,
+                PythonSource::Managed,
+            ],
+            Self::ManagedOnly => &[PythonSource::Venv, PythonSource::Managed],
+        }
+    }
+}
+
+/// Lists interpreter candidates in the order they should be probed.
+///
+/// `names` are versioned executable names such as `python3.11`. Bare names in
+/// the result are meant to be resolved through `PATH`.
+pub fn candidates(
+    preference: PythonPreference,
+    names: &[&str],
+    project_root: Option<&Path>,
+    os: Os,
+    arch: Arch,
+    lookup_env: impl Fn(&str) -> Option<String>,
+    list_dir: impl Fn(&Path) -> Vec<String>,
+) -> Vec<String> {
+    let mut candidates: Vec<String> = Vec::new();
+    for source in preference.sources() {
+        let found = match source {
+            PythonSource::Venv => venv_candidates(project_root, os, &lookup_env),
+            PythonSource::Managed => managed_candidates(names, os, &lookup_env, &list_dir),
+            PythonSource::System => system_candidates(names, os, arch),
+        };
+        for candidate in found {
+            if !candidates.contains(&candidate) {
+                candidates.push(candidate);
+            }
+        }
+    }
+    candidates
+}
+
+/// Root of the pyenv installation: `$PYENV_ROOT`, or `~/.pyenv`.
+pub fn pyenv_root(lookup_env: impl Fn(&str) -> Option<String>) -> Option<PathBuf> {
+    lookup_env("PYENV_ROOT")
+        .filter(|v| !v.is_empty())
+        .or_else(|| {
+            lookup_env("HOME")
+                .filter(|v| !v.is_empty())
+                .map(|home| format!("{}/.pyenv", home))
+        })
+        .map(PathBuf::from)
+}
+
+/// pyenv-virtualenv environments below `pyenv_root`, sorted by name.
+///
+/// pyenv-virtualenv creates them in `versions/<version>/envs/<name>` and
+/// links `versions/<name>` to them; the shims only reach the active one.
+pub fn pyenv_virtualenvs(
+    pyenv_root: &Path,
+    list_dir: impl Fn(&Path) -> Vec<String>,
+) -> Vec<(String, PathBuf)> {
+    let versions = pyenv_root.join("versions");
+    let mut envs: Vec<(String, PathBuf)> = list_dir(&versions)
+        .into_iter()
+        .flat_map(|version| {
+            let envs = versions.join(version).join("envs");
+            list_dir(&envs)
+                .into_iter()
+                .map(move |name| (name.clone(), envs.join(name)))
+        })
+        .collect();
+    envs.sort();
+    envs
+}
+
+/// Interpreter of the pyenv-virtualenv environment `name`, if it exists.
+pub fn pyenv_virtualenv_python(
+    pyenv_root: &Path,
+    name: &str,
+    os: Os,
+    list_dir: impl Fn(&Path) -> Vec<String>,
+) -> Option<String> {
+    pyenv_virtualenvs(pyenv_root, list_dir)
+        .into_iter()
+        .find(|(env, _)| env == name)
+        .map(|(_, prefix)| env_python(&prefix, os))
+}
+
+/// Whether `python` is the Python of a conda base installation, e.g. the one
+/// Anaconda or Miniconda bundles, rather than of an environment created with
+/// conda.
+///
+/// A base prefix has both `conda-meta` and `condabin`; environments below its
+/// `envs/` only have `conda-meta`.
+pub fn is_conda_base(python: &str, is_dir: impl Fn(&Path) -> bool) -> bool {
+    // bin/python on Unix, python.exe in the prefix itself on Windows
+    Path::new(python)
+        .ancestors()
+        .skip(1)
+        .take(2)
+        .filter(|prefix| !prefix.as_os_str().is_empty())
+        .any(|prefix| is_dir(&prefix.join("conda-meta")) && is_dir(&prefix.join("condabin")))
+}
+
+/// Interpreter inside a virtual environment or conda prefix.
+fn env_python(prefix: &Path, os: Os) -> String {
+    let python = match os {
+        Os::Windows => prefix.join("Scripts").join("python.exe"),
+        Os::Mac | Os::Linux | Os::OtherUnix => prefix.join("bin").join("python"),
+    };
+    python.to_string_lossy().to_string()
+}
+
+fn venv_candidates(
+    project_root: Option<&Path>,
+    os: Os,
+    lookup_env: &impl Fn(&str) -> Option<String>,
+) -> Vec<String> {
+    let mut candidates = Vec::new();
+    if let Some(venv) = lookup_env("VIRTUAL_ENV").filter(|v| !v.is_empty()) {
+        candidates.push(env_python(Path::new(&venv), os));
+    }
+    if let Some(root) = project_root {
+        for dir in VENV_DIRS {
+            candidates.push(env_python(&root.join(dir), os));
+        }
+    }
+    candidates
+}
+
+fn managed_candidates(
+    names: &[&str],
+    os: Os,
+    lookup_env: &impl Fn(&str) -> Option<String>,
+    list_dir: &impl Fn(&Path) -> Vec<String>,
+) -> Vec<String> {
+    let mut candidates = Vec::new();
+    if let Some(conda) = lookup_env("CONDA_PREFIX").filter(|v| !v.is_empty()) {
+        candidates.push(env_python(Path::new(&conda), os));
+    }
+    if os == Os::Windows {
+        return candidates;
+    }
+
+    let home = lookup_env("HOME").filter(|v| !v.is_empty());
+    if let Some(pyenv_root) = pyenv_root(lookup_env) {
+        let shims = pyenv_root.join("shims");
+        candidates.extend(
+            names
+                .iter()
+                .map(|name| shims.join(name).to_string_lossy().to_string()),
+        );
+        candidates.extend(
+            pyenv_virtualenvs(&pyenv_root, list_dir)
+                .iter()
+                .map(|(_, prefix)| env_python(prefix, os)),
+        );
+    }
+    // uv installs versioned executables for managed Pythons into ~/.local/bin
+    if let Some(home) = home {
+        let bin = Path::new(&home).join(".local").join("bin");
+        candidates.extend(
+            names
+                .iter()
+                .map(|name| bin.join(name).to_string_lossy().to_string()),
+        );
+    }
+    candidates
+}
+
+fn system_candidates(names: &[&str], os: Os, arch: Arch) -> Vec<String> {
+    let in_dir = |dir: &str| -> Vec<String> {
+        names
+            .iter()
+            .map(|name| format!("{}/{}", dir, name))
+            .collect()
+    };
+    // The generic names are often a supported release, so they come right
+    // after the versioned ones instead of after every other location
+    let on_path = names
+        .iter()
+        .chain(&GENERIC_NAMES)
+        .map(|name| name.to_string());
+
+    let mut candidates: Vec<String> = Vec::new();
+    match os {
+        // The native Homebrew goes ahead of PATH, the foreign one after it
+        Os::Mac => {
+            let native = HomebrewPrefix::native(arch);
+            candidates.extend(in_dir(native.bin()));
+            candidates.extend(on_path);
+            candidates.extend(in_dir(native.other().bin()));
+            candidates.extend(names.iter().filter_map(|name| framework_python(name)));
+            candidates.extend(in_dir(MACPORTS_BIN));
+        }
+        Os::Linux | Os::OtherUnix => {
+            candidates.extend(on_path);
+            candidates.extend(in_dir(LOCAL_BIN));
+        }
+        Os::Windows => candidates.extend(on_path),
+    }
+    candidates
+}
+
+/// The python.org framework interpreter for a versioned name, e.g.
+/// `python3.12` or the free-threaded `python3.13t`.
+fn framework_python(name: &str) -> Option<String> {
+    let version = name.strip_prefix("python")?;
+    let (framework, version) = match version.strip_suffix('t') {
+        Some(version) => ("PythonT.framework", version),
+        None => ("Python.framework", version),
+    };
+    Some(format!(
+        "{}/{}/Versions/{}/bin/{}",
+        FRAMEWORKS_DIR, framework, version, name
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn no_dirs(_: &Path) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn env(name: &str) -> Option<String> {
+        match name {
+            "HOME" => Some("/home/dev".to_string()),
+            "VIRTUAL_ENV" => Some("/home/dev/envs/serena".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_venv_first() {
+        let candidates = candidates(
+            PythonPreference::VenvFirst,
+            &["python3.12"],
+            Some(Path::new("/src/app")),
+            Os::Linux,
+            Arch::X86_64,
+            env,
+            no_dirs,
+        );
+        assert_eq!(
+            &candidates[..4],
+            [
+                "/home/dev/envs/serena/bin/python",
+                "/src/app/.venv/bin/python",
+                "/src/app/venv/bin/python",
+                "/home/dev/.pyenv/shims/python3.12",
+            ]
+        );
+        assert_eq!(
+            candidates[candidates.len() - 3..],
+            ["python3", "python", "/usr/local/bin/python3.12"]
+        );
+    }
+
+    #[test]
+    fn test_pyenv_virtualenvs() {
+        let list_dir = |dir: &Path| -> Vec<String> {
+            let entries: &[&str] = match dir.to_str() {
+                Some("/home/dev/.pyenv/versions") => &["3.12.1", "serena-env", "3.11.9"],
+                Some("/home/dev/.pyenv/versions/3.12.1/envs") => &["serena-env"],
+                Some("/home/dev/.pyenv/versions/3.11.9/envs") => &["legacy"],
+                _ => &[],
+            };
+            entries.iter().map(|entry| entry.to_string()).collect()
+        };
+        let root = pyenv_root(env).unwrap();
+        assert_eq!(
+            pyenv_virtualenvs(&root, list_dir),
+            [
+                (
+                    "legacy".to_string(),
+                    PathBuf::from("/home/dev/.pyenv/versions/3.11.9/envs/legacy")
+                ),
+                (
+                    "serena-env".to_string(),
+                    PathBuf::from("/home/dev/.pyenv/versions/3.12.1/envs/serena-env")
+                ),
+            ]
+        );
+        assert_eq!(
+            pyenv_virtualenv_python(&root, "serena-env", Os::Linux, list_dir).as_deref(),
+            Some("/home/dev/.pyenv/versions/3.12.1/envs/serena-env/bin/python")
+        );
+        assert_eq!(
+            pyenv_virtualenv_python(&root, "missing", Os::Linux, list_dir),
+            None
+        );
+
+        let found = candidates(
+            PythonPreference::ManagedOnly,
+            &["python3.12"],
+            None,
+            Os::Linux,
+            Arch::X86_64,
+            env,
+            list_dir,
+        );
+        // After $VIRTUAL_ENV, and before uv
+        assert_eq!(
+            found[1..4],
+            [
+                "/home/dev/.pyenv/shims/python3.12",
+                "/home/dev/.pyenv/versions/3.11.9/envs/legacy/bin/python",
+                "/home/dev/.pyenv/versions/3.12.1/envs/serena-env/bin/python",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_is_conda_base() {
+        let is_dir = |dir: &Path| {
+            [
+                "/opt/anaconda3/conda-meta",
+                "/opt/anaconda3/condabin",
+                "/opt/anaconda3/envs/serena/conda-meta",
+                "C:/Anaconda3/conda-meta",
+                "C:/Anaconda3/condabin",
+            ]
+            .iter()
+            .any(|known| dir == Path::new(known))
+        };
+        assert!(is_conda_base("/opt/anaconda3/bin/python3.12", is_dir));
+        assert!(is_conda_base("C:/Anaconda3/python.exe", is_dir));
+        assert!(!is_conda_base(
+            "/opt/anaconda3/envs/serena/bin/python",
+            is_dir
+        ));
+        assert!(!is_conda_base("/usr/bin/python3.12", is_dir));
+        assert!(!is_conda_base("python3", is_dir));
+    }
+
+    #[test]
+    fn test_system_first_and_managed_only() {
+        let system_first = candidates(
+            PythonPreference::SystemFirst,
+            &["python3.11"],
+            Some(Path::new("/src/app")),
+            Os::Mac,
+            Arch::Aarch64,
+            env,
+            no_dirs,
+        );
+        assert_eq!(system_first[0], "/opt/homebrew/bin/python3.11");
+        assert_eq!(system_first[1], "python3.11");
+        assert_eq!(
+            system_first.last().map(String::as_str),
+            Some("/home/dev/.local/bin/python3.11")
+        );
+
+        let managed_only = candidates(
+            PythonPreference::ManagedOnly,
+            &["python3.11"],
+            None,
+            Os::Linux,
+            Arch::X86_64,
+            |_| None,
+            no_dirs,
+        );
+        assert!(managed_only.is_empty());
+    }
+
+    #[test]
+    fn test_windows_venv() {
+        let candidates = candidates(
+            PythonPreference::ManagedOnly,
+            &["python3.12"],
+            Some(Path::new("C:/src/app")),
+            Os::Windows,
+            Arch::X86_64,
+            |_| None,
+            no_dirs,
+        );
+        assert_eq!(
+            candidates,
+            [
+                Path::new("C:/src/app/.venv")
+                    .join("Scripts")
+                    .join("python.exe")
+                    .to_string_lossy(),
+                Path::new("C:/src/app/venv")
+                    .join("Scripts")
+                    .join("python.exe")
+                    .to_string_lossy(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_homebrew_prefix_matches_host() {
+        let system = |arch| {
+            candidates(
+                PythonPreference::SystemFirst,
+                &["python3.12"],
+                None,
+                Os::Mac,
+                arch,
+                |_| None,
+                no_dirs,
+            )
+        };
+        assert_eq!(
+            system(Arch::Aarch64)[..5],
+            [
+                "/opt/homebrew/bin/python3.12",
+                "python3.12",
+                "python3",
+                "python",
+                "/usr/local/bin/python3.12"
+            ]
+        );
+        assert_eq!(
+            system(Arch::X86_64)[..5],
+            [
+                "/usr/local/bin/python3.12",
+                "python3.12",
+                "python3",
+                "python",
+                "/opt/homebrew/bin/python3.12"
+            ]
+        );
+
+        assert_eq!(
+            HomebrewPrefix::of("/opt/homebrew/bin/python3.12"),
+            Some(HomebrewPrefix::AppleSilicon)
+        );
+        assert_eq!(
+            HomebrewPrefix::of("/usr/local/bin/python3.11"),
+            Some(HomebrewPrefix::Intel)
+        );
+        assert_eq!(HomebrewPrefix::of("/usr/bin/python3.11"), None);
+    }
+
+    #[test]
+    fn test_macports_and_framework_installs() {
+        let found = candidates(
+            PythonPreference::SystemFirst,
+            &["python3.12", "python3.13t"],
+            None,
+            Os::Mac,
+            Arch::Aarch64,
+            |_| None,
+            no_dirs,
+        );
+        let framework = found
+            .iter()
+            .position(|c| c == "/Library/Frameworks/Python.framework/Versions/3.12/bin/python3.12")
+            .unwrap();
+        let macports = found
+            .iter()
+            .position(|c| c == "/opt/local/bin/python3.12")
+            .unwrap();
+        let intel_homebrew = found
+            .iter()
+            .position(|c| c == "/usr/local/bin/python3.12")
+            .unwrap();
+        assert!(intel_homebrew < framework && framework < macports);
+        assert!(found.contains(
+            &"/Library/Frameworks/PythonT.framework/Versions/3.13/bin/python3.13t".to_string()
+        ));
+
+        // Neither exists on Linux
+        let linux = candidates(
+            PythonPreference::SystemFirst,
+            &["python3.12"],
+            None,
+            Os::Linux,
+            Arch::X86_64,
+            |_| None,
+            no_dirs,
+        );
+        assert!(!linux
+            .iter()
+            .any(|c| c.starts_with("/opt/local") || c.starts_with("/Library")));
+    }
+
+    #[test]
+    fn test_other_unix_uses_posix_locations() {
+        assert_eq!(Os::from_uname("FreeBSD\n"), Os::OtherUnix);
+        assert_eq!(Os::from_uname("Darwin"), Os::Mac);
+        assert_eq!(Os::from_uname("Linux"), Os::Linux);
+
+        let found = candidates(
+            PythonPreference::VenvFirst,
+            &["python3.11"],
+            Some(Path::new("/src/app")),
+            Os::OtherUnix,
+            Arch::X86_64,
+            |_| None,
+            no_dirs,
+        );
+        assert_eq!(found[0], "/src/app/.venv/bin/python");
+        assert!(found.contains(&"/usr/local/bin/python3.11".to_string()));
+        assert!(!found.iter().any(|c| c.starts_with("/opt/homebrew")
+            || c.starts_with("/Library")
+            || c.starts_with("/opt/local")));
+    }
+}
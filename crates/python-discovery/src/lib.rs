@@ -0,0 +1,81 @@
+//! Python interpreter discovery for launching Python-based tools.
+//!
+//! Finds interpreters in project virtual environments, environment managers
+//! and system locations, checks them against version requirements, and
+//! caches probe results between runs. Nothing here depends on the Zed
+//! extension API, so it builds and tests natively.
+
+#![warn(missing_docs)]
+
+pub mod detection;
+pub mod discovery;
+pub mod probe_cache;
+pub mod process;
+pub mod regex;
+pub mod sandbox;
+pub mod stderr;
+pub mod timings;
+pub mod version;
+
+/// Operating system an interpreter is searched for on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Os {
+    /// macOS
+    Mac,
+    /// Linux
+    Linux,
+    /// Windows
+    Windows,
+    /// Any other Unix-like system, e.g. FreeBSD
+    OtherUnix,
+}
+
+impl Os {
+    /// Refines a Unix platform from the `uname -s` kernel name.
+    ///
+    /// Zed only distinguishes macOS, Linux and Windows, so other Unix-likes
+    /// are reported as one of those.
+    pub fn from_uname(kernel: &str) -> Self {
+        match kernel.trim() {
+            "Darwin" => Self::Mac,
+            "Linux" => Self::Linux,
+            _ => Self::OtherUnix,
+        }
+    }
+}
+
+/// CPU architecture of the host, which decides between Homebrew prefixes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arch {
+    /// 64-bit ARM, e.g. Apple Silicon
+    Aarch64,
+    /// 64-bit x86, e.g. Intel Macs
+    X86_64,
+    /// 32-bit x86
+    X86,
+}
+
+impl Arch {
+    /// Parses Python's `platform.machine()`, e.g. "arm64" or "AMD64".
+    ///
+    /// Returns `None` for architectures the extension doesn't run on.
+    pub fn from_machine(machine: &str) -> Option<Self> {
+        match machine.trim().to_ascii_lowercase().as_str() {
+            "arm64" | "aarch64" => Some(Self::Aarch64),
+            "x86_64" | "amd64" | "x64" => Some(Self::X86_64),
+            "i386" | "i486" | "i586" | "i686" | "x86" => Some(Self::X86),
+            _ => None,
+        }
+    }
+}
+
+/// The machine interpreters are searched on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Host {
+    /// Operating system
+    pub os: Os,
+    /// CPU architecture
+    pub arch: Arch,
+    /// Sandbox Zed runs in, if any
+    pub sandbox: Option<sandbox::Sandbox>,
+}
@@ -0,0 +1,395 @@
+//! Cache of interpreter probe results.
+//!
+//! Auto-detection runs `python --version` and an `import serena` check for
+//! every candidate, and the chosen interpreter is asked for its
+//! architecture, which adds up on each context server restart. Results are kept in the extension state for a configurable time and are
+//! dropped whenever the settings change.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+use std::time::Duration;
+
+use crate::timings::Timings;
+
+/// Default time probe results stay valid.
+pub const DEFAULT_TTL_MINUTES: u64 = 60;
+
+/// Result of resolving a candidate and running `--version` on it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VersionProbe {
+    /// Candidate after `PATH` resolution
+    pub path: String,
+    /// `--version` output, or the reason it could not be obtained
+    pub banner: Result<String, String>,
+    /// Seconds since the Unix epoch
+    pub checked_at: u64,
+}
+
+/// Whether serena is importable from an interpreter.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SerenaProbe {
+    /// Whether `import serena` succeeded
+    pub installed: bool,
+    /// Seconds since the Unix epoch
+    pub checked_at: u64,
+}
+
+/// How the serena installed for an interpreter can be launched.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EntryProbe {
+    /// Module that runs serena with `python -m`, if any was found
+    pub module: Option<String>,
+    /// Installed serena-agent version, if it could be read
+    pub version: Option<String>,
+    /// Seconds since the Unix epoch
+    pub checked_at: u64,
+}
+
+/// CPU architecture an interpreter was built for.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MachineProbe {
+    /// `platform.machine()`, e.g. "arm64"
+    pub machine: String,
+    /// Seconds since the Unix epoch
+    pub checked_at: u64,
+}
+
+/// Probe results persisted between invocations.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProbeCache {
+    /// Hash of the settings the entries were produced under
+    settings_hash: Option<String>,
+    /// Settings hash for which `clear_cache` was last honored
+    cleared_for: Option<String>,
+    /// Version probes keyed by candidate
+    versions: BTreeMap<String, VersionProbe>,
+    /// Serena probes keyed by interpreter path
+    serena: BTreeMap<String, SerenaProbe>,
+    /// Entry point probes keyed by interpreter path
+    entries: BTreeMap<String, EntryProbe>,
+    /// Architecture probes keyed by interpreter path
+    machines: BTreeMap<String, MachineProbe>,
+    #[serde(skip)]
+    ttl_secs: u64,
+    #[serde(skip)]
+    now: u64,
+    /// How long each lookup took during this invocation
+    #[serde(skip)]
+    pub timings: Timings,
+}
+
+impl ProbeCache {
+    /// Readies the cache for one detection run.
+    ///
+    /// Entries are dropped when the settings changed since they were recorded.
+    /// `clear` drops them once: later runs with `clear` still set reuse the
+    /// fresh entries until it is turned off and on again. A zero TTL disables
+    /// the cache.
+    pub fn prepare(&mut self, settings_hash: String, clear: bool, ttl_secs: u64, now: u64) {
+        let cleared = clear && self.cleared_for.as_ref() != Some(&settings_hash);
+        if cleared || self.settings_hash.as_ref() != Some(&settings_hash) {
+            self.versions.clear();
+            self.serena.clear();
+            self.entries.clear();
+            self.machines.clear();
+        }
+        self.cleared_for = clear.then(|| settings_hash.clone());
+        self.settings_hash = Some(settings_hash);
+        self.ttl_secs = ttl_secs;
+        self.now = now;
+    }
+
+    fn is_fresh(&self, checked_at: u64) -> bool {
+        self.ttl_secs > 0 && self.now.saturating_sub(checked_at) < self.ttl_secs
+    }
+
+    /// Returns the version probe for `candidate`, running `probe` on a miss.
+    pub fn version(
+        &mut self,
+        candidate: &str,
+        probe: impl FnOnce() -> (String, Result<String, String>),
+    ) -> VersionProbe {
+        let stage = format!("`{}` version probe", candidate);
+        if let Some(entry) = self.versions.get(candidate) {
+            if self.is_fresh(entry.checked_at) {
+                let entry = entry.clone();
+                self.timings.record(stage, Duration::ZERO, true);
+                return entry;
+            }
+        }
+        let (path, banner) = self.timings.time(stage, probe);
+        let entry = VersionProbe {
+            path,
+            banner,
+            checked_at: self.now,
+        };
+        self.versions.insert(candidate.to_string(), entry.clone());
+        entry
+    }
+
+    /// The version probe recorded for `candidate`, however old.
+    pub fn cached_version(&self, candidate: &str) -> Option<&VersionProbe> {
+        self.versions.get(candidate)
+    }
+
+    /// Every version probe recorded, by candidate.
+    pub fn version_probes(&self) -> impl Iterator<Item = (&str, &VersionProbe)> {
+        self.versions
+            .iter()
+            .map(|(candidate, probe)| (candidate.as_str(), probe))
+    }
+
+    /// Returns whether serena is installed for `python`, running `probe` on a miss.
+    ///
+    /// A failed probe, e.g. one that timed out, is not cached.
+    pub fn serena_installed<E>(
+        &mut self,
+        python: &str,
+        probe: impl FnOnce() -> Result<bool, E>,
+    ) -> Result<bool, E> {
+        let stage = format!("`{}` serena check", python);
+        if let Some(entry) = self.serena.get(python) {
+            if self.is_fresh(entry.checked_at) {
+                let installed = entry.installed;
+                self.timings.record(stage, Duration::ZERO, true);
+                return Ok(installed);
+            }
+        }
+        let installed = self.timings.time(stage, probe)?;
+        self.set_serena_installed(python, installed);
+        Ok(installed)
+    }
+
+    /// Returns the serena entry point for `python`, running `probe` on a miss.
+    ///
+    /// `probe` reports the entry module and the installed version. A failed
+    /// probe is not cached.
+    pub fn serena_entry<E>(
+        &mut self,
+        python: &str,
+        probe: impl FnOnce() -> Result<(Option<String>, Option<String>), E>,
+    ) -> Result<EntryProbe, E> {
+        let stage = format!("`{}` serena entry check", python);
+        if let Some(entry) = self.entries.get(python) {
+            if self.is_fresh(entry.checked_at) {
+                let entry = entry.clone();
+                self.timings.record(stage, Duration::ZERO, true);
+                return Ok(entry);
+            }
+        }
+        let (module, version) = self.timings.time(stage, probe)?;
+        let entry = EntryProbe {
+            module,
+            version,
+            checked_at: self.now,
+        };
+        self.entries.insert(python.to_string(), entry.clone());
+        Ok(entry)
+    }
+
+    /// Returns the architecture `python` was built for, running `probe` on a
+    /// miss. A failed probe is not cached.
+    pub fn machine(
+        &mut self,
+        python: &str,
+        probe: impl FnOnce() -> Option<String>,
+    ) -> Option<String> {
+        let stage = format!("`{}` architecture probe", python);
+        if let Some(entry) = self.machines.get(python) {
+            if self.is_fresh(entry.checked_at) {
+                let machine = entry.machine.clone();
+                self.timings.record(stage, Duration::ZERO, true);
+                return Some(machine);
+            }
+        }
+        let machine = self.timings.time(stage, probe)?;
+        self.machines.insert(
+            python.to_string(),
+            MachineProbe {
+                machine: machine.clone(),
+                checked_at: self.now,
+            },
+        );
+        Some(machine)
+    }
+
+    /// Records the serena status for `python`, e.g. after installing it.
+    ///
+    /// The entry point is probed again, since the install may have changed it.
+    pub fn set_serena_installed(&mut self, python: &str, installed: bool) {
+        self.entries.remove(python);
+        self.serena.insert(
+            python.to_string(),
+            SerenaProbe {
+                installed,
+                checked_at: self.now,
+            },
+        );
+    }
+}
+
+/// Hashes the merged settings so cached probes follow settings changes.
+///
+/// `clear_cache` itself is left out so toggling it doesn't count as a change.
+pub fn settings_hash(settings: Option<&Value>) -> String {
+    let mut settings = settings.cloned();
+    if let Some(Value::Object(map)) = &mut settings {
+        map.remove("clear_cache");
+    }
+    let mut hasher = DefaultHasher::new();
+    settings
+        .map(|settings| settings.to_string())
+        .hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn probe_count(cache: &mut ProbeCache, runs: &mut u32) -> VersionProbe {
+        cache.version("python3.12", || {
+            *runs += 1;
+            (
+                "/usr/bin/python3.12".to_string(),
+                Ok("Python 3.12.1".to_string()),
+            )
+        })
+    }
+
+    #[test]
+    fn test_machine() {
+        let mut cache = ProbeCache::default();
+        cache.prepare("a".to_string(), false, 60, 1_000);
+        assert_eq!(cache.machine("/usr/bin/python3", || None), None);
+        // Failures are probed again
+        assert_eq!(
+            cache.machine("/usr/bin/python3", || Some("x86_64".to_string())),
+            Some("x86_64".to_string())
+        );
+        assert_eq!(
+            cache.machine("/usr/bin/python3", || unreachable!()),
+            Some("x86_64".to_string())
+        );
+        assert_eq!(
+            crate::Arch::from_machine("AMD64"),
+            Some(crate::Arch::X86_64)
+        );
+        assert_eq!(
+            crate::Arch::from_machine("arm64"),
+            Some(crate::Arch::Aarch64)
+        );
+        assert_eq!(crate::Arch::from_machine("riscv64"), None);
+
+        cache.prepare("b".to_string(), false, 60, 1_000);
+        assert_eq!(cache.machine("/usr/bin/python3", || None), None);
+    }
+
+    #[test]
+    fn test_ttl() {
+        let mut cache = ProbeCache::default();
+        let mut runs = 0;
+
+        cache.prepare("a".to_string(), false, 60, 1_000);
+        probe_count(&mut cache, &mut runs);
+        cache.prepare("a".to_string(), false, 60, 1_059);
+        let entry = probe_count(&mut cache, &mut runs);
+        assert_eq!(runs, 1);
+        assert_eq!(entry.path, "/usr/bin/python3.12");
+
+        cache.prepare("a".to_string(), false, 60, 1_060);
+        probe_count(&mut cache, &mut runs);
+        assert_eq!(runs, 2);
+
+        cache.prepare("a".to_string(), false, 0, 1_060);
+        probe_count(&mut cache, &mut runs);
+        assert_eq!(runs, 3);
+
+        // Hits and misses are both timed
+        assert!(cache.timings.describe().contains("| cache |"));
+        assert!(cache.timings.describe().contains("| probe |"));
+    }
+
+    #[test]
+    fn test_invalidation() {
+        let mut cache = ProbeCache::default();
+        let mut runs = 0;
+
+        cache.prepare("a".to_string(), false, 60, 0);
+        probe_count(&mut cache, &mut runs);
+        assert_eq!(
+            cache.serena_installed("/usr/bin/python3.12", || Ok::<_, ()>(true)),
+            Ok(true)
+        );
+
+        // Changed settings drop every entry
+        cache.prepare("b".to_string(), false, 60, 1);
+        probe_count(&mut cache, &mut runs);
+        assert_eq!(
+            cache.serena_installed("/usr/bin/python3.12", || Ok::<_, ()>(false)),
+            Ok(false)
+        );
+        // Failed probes run again next time
+        assert_eq!(
+            cache.serena_installed("/usr/bin/python3.13", || Err("timed out")),
+            Err("timed out")
+        );
+        assert_eq!(
+            cache.serena_installed("/usr/bin/python3.13", || Ok::<_, &str>(true)),
+            Ok(true)
+        );
+        assert_eq!(runs, 2);
+
+        // clear_cache is honored once until it is turned off again
+        cache.prepare("b".to_string(), true, 60, 2);
+        probe_count(&mut cache, &mut runs);
+        cache.prepare("b".to_string(), true, 60, 3);
+        probe_count(&mut cache, &mut runs);
+        assert_eq!(runs, 3);
+
+        cache.prepare("b".to_string(), false, 60, 4);
+        cache.prepare("b".to_string(), true, 60, 5);
+        probe_count(&mut cache, &mut runs);
+        assert_eq!(runs, 4);
+    }
+
+    #[test]
+    fn test_serena_entry() {
+        let mut cache = ProbeCache::default();
+        let mut runs = 0;
+        let mut probe = |cache: &mut ProbeCache| {
+            cache
+                .serena_entry("/usr/bin/python3.12", || {
+                    runs += 1;
+                    Ok::<_, ()>((Some("serena.cli".to_string()), Some("0.1.4".to_string())))
+                })
+                .unwrap()
+        };
+
+        cache.prepare("a".to_string(), false, 60, 0);
+        let entry = probe(&mut cache);
+        assert_eq!(entry.module.as_deref(), Some("serena.cli"));
+        assert_eq!(entry.version.as_deref(), Some("0.1.4"));
+        probe(&mut cache);
+        cache.set_serena_installed("/usr/bin/python3.12", true);
+        probe(&mut cache);
+        assert_eq!(runs, 2);
+    }
+
+    #[test]
+    fn test_settings_hash() {
+        let a = json!({"python_version": "3.12"});
+        let b = json!({"python_version": "3.11"});
+        assert_eq!(settings_hash(Some(&a)), settings_hash(Some(&a.clone())));
+        assert_ne!(settings_hash(Some(&a)), settings_hash(Some(&b)));
+        assert_ne!(settings_hash(Some(&a)), settings_hash(None));
+
+        let cleared = json!({"python_version": "3.12", "clear_cache": true});
+        assert_eq!(settings_hash(Some(&a)), settings_hash(Some(&cleared)));
+    }
+}
@@ -0,0 +1,154 @@
+//! Running helper processes.
+//!
+//! Detection runs interpreters to read their versions, but never starts them
+//! itself: a Zed extension can't spawn processes and has Zed run them, while
+//! native tools use `std::process` and tests answer from a script. Callers
+//! pass the [`Runner`] that fits.
+
+use crate::sandbox::Sandbox;
+
+/// A program to run to completion, with its arguments and environment.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Invocation {
+    /// Path of the program, or a name looked up on `PATH`
+    pub program: String,
+    /// Arguments, without the program
+    pub args: Vec<String>,
+    /// Variables set on top of the inherited environment
+    pub env: Vec<(String, String)>,
+}
+
+impl Invocation {
+    /// Runs `program` without arguments.
+    pub fn new(program: impl Into<String>) -> Self {
+        Self {
+            program: program.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Appends `args`.
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// Sets the variables in `env`.
+    pub fn envs<I, K, V>(mut self, env: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.env.extend(
+            env.into_iter()
+                .map(|(key, value)| (key.into(), value.into())),
+        );
+        self
+    }
+
+    /// Runs the program on the host when inside `sandbox`.
+    pub fn on_host(self, sandbox: Option<Sandbox>) -> Self {
+        match sandbox.map(Sandbox::host_prefix) {
+            Some([prefix, args @ ..]) => Self {
+                program: prefix.to_string(),
+                args: args
+                    .iter()
+                    .map(|arg| arg.to_string())
+                    .chain([self.program])
+                    .chain(self.args)
+                    .collect(),
+                env: self.env,
+            },
+            _ => self,
+        }
+    }
+}
+
+/// How a finished process ended and what it printed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Output {
+    /// Exit code; `None` when a signal ended the process
+    pub status: Option<i32>,
+    /// Everything written to stdout
+    pub stdout: Vec<u8>,
+    /// Everything written to stderr
+    pub stderr: Vec<u8>,
+}
+
+impl Output {
+    /// Whether the process exited with code 0.
+    pub fn success(&self) -> bool {
+        self.status == Some(0)
+    }
+}
+
+/// Starts processes and waits for them to exit.
+pub trait Runner {
+    /// Runs `invocation` with stdin closed, collecting its output.
+    ///
+    /// The error says why the program couldn't be started.
+    fn run(&self, invocation: &Invocation) -> Result<Output, String>;
+}
+
+/// Runs processes with `std::process`, for native tools and tests.
+///
+/// WASI has no processes, so a Zed extension needs a runner that goes through
+/// Zed instead.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StdRunner;
+
+impl Runner for StdRunner {
+    fn run(&self, invocation: &Invocation) -> Result<Output, String> {
+        let output = std::process::Command::new(&invocation.program)
+            .args(&invocation.args)
+            .envs(invocation.env.iter().map(|(key, value)| (key, value)))
+            .stdin(std::process::Stdio::null())
+            .output()
+            .map_err(|e| e.to_string())?;
+        Ok(Output {
+            status: output.status.code(),
+            stdout: output.stdout,
+            stderr: output.stderr,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_on_host() {
+        let invocation = Invocation::new("python3").args(["--version"]);
+        assert_eq!(invocation.clone().on_host(None), invocation);
+        assert_eq!(invocation.clone().on_host(Some(Sandbox::Snap)), invocation);
+        assert_eq!(
+            invocation.on_host(Some(Sandbox::Flatpak)),
+            Invocation::new("flatpak-spawn").args(["--host", "python3", "--version"])
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_std_runner() {
+        let output = StdRunner
+            .run(
+                &Invocation::new("sh")
+                    .args(["-c", "echo $GREETING; echo err >&2; exit 3"])
+                    .envs([("GREETING", "hi")]),
+            )
+            .unwrap();
+        assert_eq!(output.stdout, b"hi\n");
+        assert_eq!(output.stderr, b"err\n");
+        assert_eq!(output.status, Some(3));
+        assert!(!output.success());
+        assert!(StdRunner
+            .run(&Invocation::new("/nonexistent/python"))
+            .is_err());
+    }
+}
@@ -0,0 +1,420 @@
+//! A small regular expression matcher for `version_accept_regex`.
+//!
+//! The extension can't pull in the `regex` crate, and version banners are
+//! short, so a backtracking matcher is enough. It supports literals, `.`,
+//! `^`, `$`, classes such as `[0-9.]` and `[^ ]`, the escapes `\d`, `\w`
+//! and `\s` (and their negations), groups, `(?:...)`, alternation and the
+//! quantifiers `*`, `+`, `?` and `{m,n}`, each optionally lazy.
+
+/// A compiled pattern.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Regex {
+    root: Node,
+    groups: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+    Char(char),
+    Any,
+    Class(Vec<ClassItem>, bool),
+    Start,
+    End,
+    Group(Box<Node>, Option<usize>),
+    Concat(Vec<Node>),
+    Alt(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: usize,
+        max: Option<usize>,
+        greedy: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ClassItem {
+    Range(char, char),
+    /// `\d`, `\w` or `\s`, negated when upper case
+    Escape(char),
+}
+
+impl ClassItem {
+    fn matches(self, c: char) -> bool {
+        match self {
+            Self::Range(low, high) => (low..=high).contains(&c),
+            Self::Escape(escape) => {
+                let matched = match escape.to_ascii_lowercase() {
+                    'd' => c.is_ascii_digit(),
+                    'w' => c.is_alphanumeric() || c == '_',
+                    _ => c.is_whitespace(),
+                };
+                matched != escape.is_ascii_uppercase()
+            }
+        }
+    }
+}
+
+type Captures = Vec<Option<(usize, usize)>>;
+
+impl Regex {
+    /// Compiles `pattern`; the error says what is wrong and where.
+    pub fn new(pattern: &str) -> Result<Self, String> {
+        let mut parser = Parser {
+            chars: pattern.chars().collect(),
+            pos: 0,
+            groups: 0,
+        };
+        let root = parser.alternation()?;
+        if parser.pos < parser.chars.len() {
+            return Err(format!("unmatched `)` at position {}", parser.pos));
+        }
+        Ok(Self {
+            root,
+            groups: parser.groups,
+        })
+    }
+
+    /// Finds the leftmost match in `text` and returns what the first group
+    /// captured, or the whole match when the pattern has no groups.
+    pub fn find(&self, text: &str) -> Option<String> {
+        let text: Vec<char> = text.chars().collect();
+        for start in 0..=text.len() {
+            let mut captures = vec![None; self.groups];
+            let mut end = start;
+            let matched = self.match_node(&self.root, &text, start, &mut captures, &mut |at, _| {
+                end = at;
+                true
+            });
+            if matched {
+                let (from, to) = match self.groups {
+                    0 => (start, end),
+                    _ => captures[0]?,
+                };
+                return Some(text[from..to].iter().collect());
+            }
+        }
+        None
+    }
+
+    fn match_node(
+        &self,
+        node: &Node,
+        text: &[char],
+        pos: usize,
+        captures: &mut Captures,
+        next: &mut dyn FnMut(usize, &mut Captures) -> bool,
+    ) -> bool {
+        match node {
+            Node::Char(c) => text.get(pos) == Some(c) && next(pos + 1, captures),
+            Node::Any => text.get(pos).is_some_and(|&c| c != '\n') && next(pos + 1, captures),
+            Node::Class(items, negated) => {
+                text.get(pos)
+                    .is_some_and(|&c| items.iter().any(|item| item.matches(c)) != *negated)
+                    && next(pos + 1, captures)
+            }
+            Node::Start => pos == 0 && next(pos, captures),
+            Node::End => pos == text.len() && next(pos, captures),
+            Node::Group(inner, index) => {
+                self.match_node(inner, text, pos, captures, &mut |end, captures| {
+                    let Some(index) = *index else {
+                        return next(end, captures);
+                    };
+                    let saved = captures[index];
+                    captures[index] = Some((pos, end));
+                    if next(end, captures) {
+                        return true;
+                    }
+                    captures[index] = saved;
+                    false
+                })
+            }
+            Node::Concat(nodes) => self.match_sequence(nodes, text, pos, captures, next),
+            Node::Alt(branches) => branches
+                .iter()
+                .any(|branch| self.match_node(branch, text, pos, captures, &mut *next)),
+            Node::Repeat {
+                node,
+                min,
+                max,
+                greedy,
+            } => self.match_repeat(node, (*min, *max, *greedy), 0, text, pos, captures, next),
+        }
+    }
+
+    fn match_sequence(
+        &self,
+        nodes: &[Node],
+        text: &[char],
+        pos: usize,
+        captures: &mut Captures,
+        next: &mut dyn FnMut(usize, &mut Captures) -> bool,
+    ) -> bool {
+        match nodes.split_first() {
+            None => next(pos, captures),
+            Some((first, rest)) => {
+                self.match_node(first, text, pos, captures, &mut |at, captures| {
+                    self.match_sequence(rest, text, at, captures, &mut *next)
+                })
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn match_repeat(
+        &self,
+        node: &Node,
+        (min, max, greedy): (usize, Option<usize>, bool),
+        count: usize,
+        text: &[char],
+        pos: usize,
+        captures: &mut Captures,
+        next: &mut dyn FnMut(usize, &mut Captures) -> bool,
+    ) -> bool {
+        let can_stop = count >= min;
+        let can_continue = max.is_none_or(|max| count < max);
+        let again = |captures: &mut Captures,
+                     next: &mut dyn FnMut(usize, &mut Captures) -> bool| {
+            can_continue
+                && self.match_node(node, text, pos, captures, &mut |at, captures| {
+                    // An empty iteration past the minimum would loop forever
+                    (at != pos || count < min)
+                        && self.match_repeat(
+                            node,
+                            (min, max, greedy),
+                            count + 1,
+                            text,
+                            at,
+                            captures,
+                            &mut *next,
+                        )
+                })
+        };
+        // Lazy repetitions try stopping first, greedy ones last
+        if !greedy && can_stop && next(pos, captures) {
+            return true;
+        }
+        again(captures, next) || (greedy && can_stop && next(pos, captures))
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    groups: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let matched = self.peek() == Some(c);
+        if matched {
+            self.pos += 1;
+        }
+        matched
+    }
+
+    fn alternation(&mut self) -> Result<Node, String> {
+        let mut branches = vec![self.sequence()?];
+        while self.eat('|') {
+            branches.push(self.sequence()?);
+        }
+        Ok(if branches.len() == 1 {
+            branches.remove(0)
+        } else {
+            Node::Alt(branches)
+        })
+    }
+
+    fn sequence(&mut self) -> Result<Node, String> {
+        let mut nodes = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.atom()?;
+            nodes.push(self.quantified(atom)?);
+        }
+        Ok(Node::Concat(nodes))
+    }
+
+    fn atom(&mut self) -> Result<Node, String> {
+        let at = self.pos;
+        let c = self.chars[self.pos];
+        self.pos += 1;
+        Ok(match c {
+            '.' => Node::Any,
+            '^' => Node::Start,
+            '$' => Node::End,
+            '(' => {
+                let index = if self.eat('?') {
+                    if !self.eat(':') {
+                        return Err(format!("unsupported group syntax at position {}", at));
+                    }
+                    None
+                } else {
+                    self.groups += 1;
+                    Some(self.groups - 1)
+                };
+                let inner = self.alternation()?;
+                if !self.eat(')') {
+                    return Err(format!("unclosed `(` at position {}", at));
+                }
+                Node::Group(Box::new(inner), index)
+            }
+            '[' => self.class(at)?,
+            '\\' => match self.escape()? {
+                ClassItem::Range(c, _) => Node::Char(c),
+                escape => Node::Class(vec![escape], false),
+            },
+            '*' | '+' | '?' | '{' => {
+                return Err(format!("nothing to repeat at position {}", at));
+            }
+            c => Node::Char(c),
+        })
+    }
+
+    fn escape(&mut self) -> Result<ClassItem, String> {
+        let at = self.pos - 1;
+        let c = self
+            .peek()
+            .ok_or_else(|| format!("trailing `\\` at position {}", at))?;
+        self.pos += 1;
+        match c {
+            'd' | 'D' | 'w' | 'W' | 's' | 'S' => Ok(ClassItem::Escape(c)),
+            'n' => Ok(ClassItem::Range('\n', '\n')),
+            't' => Ok(ClassItem::Range('\t', '\t')),
+            c if c.is_alphanumeric() => {
+                Err(format!("unsupported escape `\\{}` at position {}", c, at))
+            }
+            c => Ok(ClassItem::Range(c, c)),
+        }
+    }
+
+    fn class(&mut self, at: usize) -> Result<Node, String> {
+        let negated = self.eat('^');
+        let mut items = Vec::new();
+        loop {
+            let c = self
+                .peek()
+                .ok_or_else(|| format!("unclosed `[` at position {}", at))?;
+            self.pos += 1;
+            // `]` right after the opening bracket is a literal
+            if c == ']' && !items.is_empty() {
+                break;
+            }
+            let low = if c == '\\' {
+                match self.escape()? {
+                    ClassItem::Range(low, _) => low,
+                    escape => {
+                        items.push(escape);
+                        continue;
+                    }
+                }
+            } else {
+                c
+            };
+            let is_range =
+                self.peek() == Some('-') && self.chars.get(self.pos + 1).is_some_and(|&c| c != ']');
+            if !is_range {
+                items.push(ClassItem::Range(low, low));
+                continue;
+            }
+            self.pos += 1;
+            let high = self.chars[self.pos];
+            self.pos += 1;
+            if high < low {
+                return Err(format!(
+                    "invalid range `{}-{}` at position {}",
+                    low, high, at
+                ));
+            }
+            items.push(ClassItem::Range(low, high));
+        }
+        Ok(Node::Class(items, negated))
+    }
+
+    fn quantified(&mut self, atom: Node) -> Result<Node, String> {
+        let at = self.pos;
+        let (min, max) = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => {
+                let close = self.chars[self.pos..]
+                    .iter()
+                    .position(|&c| c == '}')
+                    .ok_or_else(|| format!("unclosed `{{` at position {}", at))?;
+                let body: String = self.chars[self.pos + 1..self.pos + close].iter().collect();
+                let bounds = match body.split_once(',') {
+                    None => body.parse().ok().map(|count| (count, Some(count))),
+                    Some((min, "")) => min.parse().ok().map(|min| (min, None)),
+                    Some((min, max)) => min
+                        .parse()
+                        .ok()
+                        .zip(max.parse().ok())
+                        .filter(|(min, max)| min <= max)
+                        .map(|(min, max)| (min, Some(max))),
+                };
+                let bounds =
+                    bounds.ok_or_else(|| format!("invalid repetition at position {}", at))?;
+                self.pos += close;
+                bounds
+            }
+            _ => return Ok(atom),
+        };
+        self.pos += 1;
+        if matches!(atom, Node::Start | Node::End) {
+            return Err(format!("nothing to repeat at position {}", at));
+        }
+        let greedy = !self.eat('?');
+        Ok(Node::Repeat {
+            node: Box::new(atom),
+            min,
+            max,
+            greedy,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(pattern: &str, text: &str) -> Option<String> {
+        Regex::new(pattern).unwrap().find(text)
+    }
+
+    #[test]
+    fn test_find() {
+        let intel = "Intel(R) Distribution for Python 3.11.5 (2024.1.0)";
+        assert_eq!(
+            find(r"Python (\d+\.\d+(?:\.\d+)?)", intel).as_deref(),
+            Some("3.11.5")
+        );
+        assert_eq!(find(r"\d+\.\d+", intel).as_deref(), Some("3.11"));
+        assert_eq!(find(r"^Intel.*\((\d{4})", intel).as_deref(), Some("2024"));
+        assert_eq!(find(r"^Python", intel), None);
+        assert_eq!(find(r"(a|ab)(c|bcd)", "abcd").as_deref(), Some("a"));
+        assert_eq!(find(r"x(.*?)y", "xaayby").as_deref(), Some("aa"));
+        assert_eq!(
+            find(r"[^ ]+$", "embedded 3.12.4").as_deref(),
+            Some("3.12.4")
+        );
+        assert_eq!(find(r"[]a-c]+", "x]ab").as_deref(), Some("]ab"));
+        assert_eq!(find(r"(a*)*b", "aaab").as_deref(), Some("aaa"));
+        assert_eq!(find(r"(?:a*){2}$", "").as_deref(), Some(""));
+        assert_eq!(find(r"\W\S", "ab c").as_deref(), Some(" c"));
+    }
+
+    #[test]
+    fn test_invalid_patterns() {
+        for pattern in [
+            "(", ")", "[a", "a{2", "*a", "a{3,1}", r"\q", "(?=a)", "[z-a]", "\\",
+        ] {
+            assert!(Regex::new(pattern).is_err(), "{}", pattern);
+        }
+    }
+}
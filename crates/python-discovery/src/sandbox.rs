@@ -0,0 +1,247 @@
+//! Sandboxed Zed installs on Linux.
+//!
+//! Flatpak and Snap builds of Zed can't see the host's interpreters directly.
+//! Inside Flatpak, host programs run through `flatpak-spawn --host`, which
+//! needs the `org.freedesktop.Flatpak` talk permission. Strictly confined
+//! snaps only see the interpreters shipped inside the snap.
+
+use crate::process::{Invocation, Runner};
+use crate::Os;
+use std::path::Path;
+
+/// File Flatpak places at the root of every sandbox.
+const FLATPAK_INFO: &str = "/.flatpak-info";
+
+/// Prints the sandbox markers a process sees: the variables as `NAME=value`
+/// lines, and [`FLATPAK_INFO`] when it exists.
+const PROBE_SCRIPT: &str = "printf 'FLATPAK_ID=%s\\nSNAP=%s\\n' \"$FLATPAK_ID\" \"$SNAP\"; \
+                            if [ -e /.flatpak-info ]; then echo /.flatpak-info; fi";
+
+/// Command prefix that runs a program on the host from inside Flatpak.
+const FLATPAK_SPAWN: [&str; 2] = ["flatpak-spawn", "--host"];
+
+/// A sandbox Zed may be running in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sandbox {
+    /// Flatpak, e.g. the Flathub build of Zed
+    Flatpak,
+    /// Snap
+    Snap,
+}
+
+impl Sandbox {
+    /// Detects the sandbox from the environment and the filesystem.
+    pub fn detect(
+        os: Os,
+        lookup_env: impl Fn(&str) -> Option<String>,
+        exists: impl Fn(&Path) -> bool,
+    ) -> Option<Self> {
+        let set = |name: &str| lookup_env(name).is_some_and(|value| !value.is_empty());
+        if os != Os::Linux {
+            None
+        } else if set("FLATPAK_ID") || exists(Path::new(FLATPAK_INFO)) {
+            Some(Self::Flatpak)
+        } else if set("SNAP") {
+            Some(Self::Snap)
+        } else {
+            None
+        }
+    }
+
+    /// Detects the sandbox the current process runs in.
+    pub fn current(os: Os) -> Option<Self> {
+        Self::detect(os, |name| std::env::var(name).ok(), Path::exists)
+    }
+
+    /// Detects the sandbox from inside a process `runner` starts.
+    ///
+    /// A WASI extension sees neither Zed's environment nor its filesystem,
+    /// so [`Sandbox::current`] finds nothing there, while the processes Zed
+    /// starts for it share Zed's sandbox.
+    pub fn probe(os: Os, runner: &dyn Runner) -> Option<Self> {
+        if os != Os::Linux {
+            return None;
+        }
+        let output = runner
+            .run(&Invocation::new("sh").args(["-c", PROBE_SCRIPT]))
+            .ok()
+            .filter(|output| output.success())?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let lines: Vec<&str> = stdout.lines().collect();
+        Self::detect(
+            os,
+            |name| {
+                lines.iter().find_map(|line| {
+                    let (key, value) = line.split_once('=')?;
+                    (key == name).then(|| value.to_string())
+                })
+            },
+            |path| lines.iter().any(|line| Path::new(line) == path),
+        )
+    }
+
+    /// Prefix that runs a command on the host instead of in the sandbox.
+    pub fn host_prefix(self) -> &'static [&'static str] {
+        match self {
+            Self::Flatpak => &FLATPAK_SPAWN,
+            Self::Snap => &[],
+        }
+    }
+
+    /// Makes `command` run on the host with `env`.
+    ///
+    /// `flatpak-spawn` doesn't forward the caller's environment, so every
+    /// variable is passed with `--env`, secrets included; redact the result
+    /// before storing or showing it.
+    pub fn wrap(self, command: Vec<String>, env: &[(String, String)]) -> Vec<String> {
+        match self {
+            Self::Flatpak => FLATPAK_SPAWN
+                .iter()
+                .map(|arg| arg.to_string())
+                .chain(
+                    env.iter()
+                        .map(|(key, value)| format!("--env={}={}", key, value)),
+                )
+                .chain(command)
+                .collect(),
+            Self::Snap => command,
+        }
+    }
+
+    /// Interpreters shipped inside the sandbox, checked before the host's.
+    pub fn candidates(
+        self,
+        names: &[&str],
+        lookup_env: impl Fn(&str) -> Option<String>,
+    ) -> Vec<String> {
+        match (self, lookup_env("SNAP").filter(|snap| !snap.is_empty())) {
+            (Self::Snap, Some(snap)) => {
+                let bin = Path::new(&snap).join("usr").join("bin");
+                names
+                    .iter()
+                    .map(|name| bin.join(name).to_string_lossy().to_string())
+                    .collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// What to do when no interpreter can be found from inside the sandbox.
+    pub fn advice(self) -> &'static str {
+        match self {
+            Self::Flatpak => {
+                "Zed is running in a Flatpak sandbox and starts host interpreters with \
+                 `flatpak-spawn --host`. If that is not permitted, grant access with \
+                 `flatpak override --user --talk-name=org.freedesktop.Flatpak dev.zed.Zed` \
+                 and restart Zed, or install Zed from zed.dev instead."
+            }
+            Self::Snap => {
+                "Zed is running in a Snap sandbox, which hides most host interpreters. Set \
+                 `python_executable` to an interpreter the snap can run, or install Zed from \
+                 zed.dev instead."
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::Output;
+
+    #[test]
+    fn test_detect() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(key, _)| *key == name)
+                    .map(|(_, value)| value.to_string())
+            }
+        };
+        let no_files = |_: &Path| false;
+
+        assert_eq!(
+            Sandbox::detect(Os::Linux, env(&[("FLATPAK_ID", "dev.zed.Zed")]), no_files),
+            Some(Sandbox::Flatpak)
+        );
+        assert_eq!(
+            Sandbox::detect(Os::Linux, env(&[]), |path| path == Path::new(FLATPAK_INFO)),
+            Some(Sandbox::Flatpak)
+        );
+        assert_eq!(
+            Sandbox::detect(Os::Linux, env(&[("SNAP", "/snap/zed/12")]), no_files),
+            Some(Sandbox::Snap)
+        );
+        assert_eq!(
+            Sandbox::detect(Os::Linux, env(&[("SNAP", "")]), no_files),
+            None
+        );
+        assert_eq!(
+            Sandbox::detect(Os::Mac, env(&[("FLATPAK_ID", "dev.zed.Zed")]), no_files),
+            None
+        );
+    }
+
+    #[test]
+    fn test_probe() {
+        struct Prints(&'static str);
+        impl Runner for Prints {
+            fn run(&self, _: &Invocation) -> Result<Output, String> {
+                Ok(Output {
+                    status: Some(0),
+                    stdout: self.0.as_bytes().to_vec(),
+                    stderr: Vec::new(),
+                })
+            }
+        }
+
+        let flatpak = Prints("FLATPAK_ID=\nSNAP=\n/.flatpak-info\n");
+        assert_eq!(Sandbox::probe(Os::Linux, &flatpak), Some(Sandbox::Flatpak));
+        let snap = Prints("FLATPAK_ID=\nSNAP=/snap/zed/12\n");
+        assert_eq!(Sandbox::probe(Os::Linux, &snap), Some(Sandbox::Snap));
+        assert_eq!(
+            Sandbox::probe(Os::Linux, &Prints("FLATPAK_ID=\nSNAP=\n")),
+            None
+        );
+        assert_eq!(Sandbox::probe(Os::Mac, &flatpak), None);
+
+        // The script runs in a real shell
+        #[cfg(target_os = "linux")]
+        assert_eq!(
+            Sandbox::probe(Os::Linux, &crate::process::StdRunner),
+            Sandbox::current(Os::Linux)
+        );
+    }
+
+    #[test]
+    fn test_flatpak_wrap_forwards_environment() {
+        let wrapped = Sandbox::Flatpak.wrap(
+            vec!["/usr/bin/python3.12".into(), "-m".into(), "serena".into()],
+            &[("DO_NOT_TRACK".into(), "1".into())],
+        );
+        assert_eq!(
+            wrapped,
+            [
+                "flatpak-spawn",
+                "--host",
+                "--env=DO_NOT_TRACK=1",
+                "/usr/bin/python3.12",
+                "-m",
+                "serena"
+            ]
+        );
+        assert_eq!(Sandbox::Snap.wrap(vec!["python3".into()], &[]), ["python3"]);
+    }
+
+    #[test]
+    fn test_snap_candidates() {
+        let snap = |name: &str| (name == "SNAP").then(|| "/snap/zed/12".to_string());
+        assert_eq!(
+            Sandbox::Snap.candidates(&["python3.12"], snap),
+            ["/snap/zed/12/usr/bin/python3.12"]
+        );
+        assert!(Sandbox::Flatpak
+            .candidates(&["python3.12"], snap)
+            .is_empty());
+    }
+}
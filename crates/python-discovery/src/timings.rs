@@ -0,0 +1,103 @@
+//! Timing of detection stages for the debug diagnostics.
+//!
+//! Slow context server startups are almost always spent in subprocess probes
+//! (`which`, `python --version`, `import serena`). Each stage is recorded
+//! with whether it was answered from the probe cache.
+
+use std::time::{Duration, Instant};
+
+/// One measured stage.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Timing {
+    /// What was measured
+    pub stage: String,
+    /// How long it took
+    pub elapsed: Duration,
+    /// Whether the result came from the probe cache
+    pub cached: bool,
+}
+
+/// Stages measured during one invocation, in the order they ran.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Timings {
+    entries: Vec<Timing>,
+}
+
+impl Timings {
+    /// Records a stage that has already been measured.
+    pub fn record(&mut self, stage: impl Into<String>, elapsed: Duration, cached: bool) {
+        self.entries.push(Timing {
+            stage: stage.into(),
+            elapsed,
+            cached,
+        });
+    }
+
+    /// Runs `f` and records how long it took.
+    pub fn time<T>(&mut self, stage: impl Into<String>, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.record(stage, start.elapsed(), false);
+        result
+    }
+
+    /// Moves the stages recorded in `other` to the end of this list.
+    pub fn append(&mut self, other: &mut Timings) {
+        self.entries.append(&mut other.entries);
+    }
+
+    /// Returns true when nothing has been recorded.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Renders the stages as a markdown table with a total.
+    pub fn describe(&self) -> String {
+        let mut out =
+            String::from("### Detection Timings\n\n| Stage | Time | Source |\n|---|---|---|\n");
+        for timing in &self.entries {
+            out.push_str(&format!(
+                "| {} | {} | {} |\n",
+                timing.stage,
+                format_duration(timing.elapsed),
+                if timing.cached { "cache" } else { "probe" }
+            ));
+        }
+        let total: Duration = self.entries.iter().map(|timing| timing.elapsed).sum();
+        out.push_str(&format!("| **Total** | {} | |\n", format_duration(total)));
+        out
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    if duration.as_millis() >= 1000 {
+        format!("{:.2} s", duration.as_secs_f64())
+    } else {
+        format!("{} ms", duration.as_millis())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe() {
+        let mut timings = Timings::default();
+        timings.record("`python3.12 --version`", Duration::from_millis(42), false);
+        timings.record("serena import check", Duration::from_millis(1_500), true);
+
+        let table = timings.describe();
+        assert!(table.contains("| `python3.12 --version` | 42 ms | probe |"));
+        assert!(table.contains("| serena import check | 1.50 s | cache |"));
+        assert!(table.ends_with("| **Total** | 1.54 s | |\n"));
+    }
+
+    #[test]
+    fn test_time() {
+        let mut timings = Timings::default();
+        assert!(timings.is_empty());
+        assert_eq!(timings.time("stage", || 7), 7);
+        assert!(!timings.is_empty());
+    }
+}
@@ -0,0 +1,292 @@
+//! Version parsing and comparison for Python and Serena releases.
+//!
+//! Follows the parts of PEP 440 that show up in practice: release segments
+//! (`3.12.1`), pre-releases (`3.13.0rc2`, `3.14.0a3`, `3.13.0b1`), post
+//! releases (`0.1.4.post1`), development releases (`0.1.4.dev20250101`) and
+//! local labels (`0.1.4+local`). Ordering is dev < pre < final < post.
+
+use std::cmp::Ordering;
+use std::fmt;
+
+/// Pre-release phase, in increasing order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PreRelease {
+    /// `a1`
+    Alpha(u64),
+    /// `b1`
+    Beta(u64),
+    /// `rc1`
+    ReleaseCandidate(u64),
+}
+
+/// A parsed version number.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Version {
+    /// Release segments, e.g. `[3, 12, 1]`
+    pub release: Vec<u64>,
+    /// Pre-release phase and number
+    pub pre: Option<PreRelease>,
+    /// Post-release number
+    pub post: Option<u64>,
+    /// Development release number
+    pub dev: Option<u64>,
+    /// Local version label after `+`
+    pub local: Option<String>,
+}
+
+impl Version {
+    /// Parses a version string such as `3.12.1`, `3.13.0rc2` or `0.1.4.dev1`.
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim().trim_start_matches(['v', 'V']).to_lowercase();
+        let (text, local) = match text.split_once('+') {
+            Some((version, local)) => (version.to_string(), Some(local.to_string())),
+            None => (text, None),
+        };
+
+        // Release segments are the leading run of dot-separated integers
+        let release_end = text
+            .char_indices()
+            .find(|&(i, c)| {
+                !(c.is_ascii_digit()
+                    || (c == '.' && text[i + 1..].starts_with(|n: char| n.is_ascii_digit())))
+            })
+            .map(|(i, _)| i)
+            .unwrap_or(text.len());
+        let release: Vec<u64> = text[..release_end]
+            .split('.')
+            .map(|part| part.parse().ok())
+            .collect::<Option<_>>()?;
+
+        let mut version = Version {
+            release,
+            pre: None,
+            post: None,
+            dev: None,
+            local,
+        };
+
+        let mut rest = &text[release_end..];
+        while !rest.is_empty() {
+            rest = rest.trim_start_matches(['.', '-', '_']);
+            let (label, number, remainder) = split_label(rest)?;
+            match label {
+                "a" | "alpha" => version.pre = Some(PreRelease::Alpha(number)),
+                "b" | "beta" => version.pre = Some(PreRelease::Beta(number)),
+                "rc" | "c" => version.pre = Some(PreRelease::ReleaseCandidate(number)),
+                "post" | "rev" | "r" => version.post = Some(number),
+                "dev" => version.dev = Some(number),
+                _ => return None,
+            }
+            rest = remainder;
+        }
+
+        Some(version)
+    }
+
+    /// Parses the output of `python --version`, e.g. `Python 3.12.1`.
+    ///
+    /// Build info after the version and a trailing `t` (free-threaded build)
+    /// or `+` are ignored.
+    pub fn from_python_banner(banner: &str) -> Option<Self> {
+        let rest = banner.trim().strip_prefix("Python ")?;
+        let token = rest.split_whitespace().next()?;
+        Self::parse(token.trim_end_matches(['t', '+']))
+    }
+
+    /// First release segment, or 0.
+    pub fn major(&self) -> u64 {
+        self.release.first().copied().unwrap_or(0)
+    }
+
+    /// Second release segment, or 0.
+    pub fn minor(&self) -> u64 {
+        self.release.get(1).copied().unwrap_or(0)
+    }
+
+    /// True for alpha, beta, release candidate and development releases.
+    pub fn is_prerelease(&self) -> bool {
+        self.pre.is_some() || self.dev.is_some()
+    }
+
+    /// True when every release segment of `prefix` matches this version,
+    /// so `3.12.1` matches the prefix `3.12` but `3.120` does not.
+    pub fn matches_prefix(&self, prefix: &Version) -> bool {
+        self.release.starts_with(&prefix.release)
+    }
+}
+
+fn split_label(text: &str) -> Option<(&str, u64, &str)> {
+    let label_end = text
+        .find(|c: char| !c.is_ascii_alphabetic())
+        .unwrap_or(text.len());
+    let (label, rest) = text.split_at(label_end);
+    let rest = rest.trim_start_matches(['.', '-', '_']);
+    let number_end = rest
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(rest.len());
+    let number = if number_end == 0 {
+        0
+    } else {
+        rest[..number_end].parse().ok()?
+    };
+    (!label.is_empty()).then_some((label, number, &rest[number_end..]))
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Compare release segments with implicit trailing zeros (3.12 == 3.12.0)
+        let len = self.release.len().max(other.release.len());
+        for i in 0..len {
+            let a = self.release.get(i).copied().unwrap_or(0);
+            let b = other.release.get(i).copied().unwrap_or(0);
+            match a.cmp(&b) {
+                Ordering::Equal => continue,
+                ordering => return ordering,
+            }
+        }
+
+        // A final release sorts after its pre-releases; a bare dev release
+        // sorts before everything else for the same release
+        let phase = |v: &Version| match (v.pre, v.post, v.dev) {
+            (None, None, Some(_)) => (0, None),
+            (Some(pre), _, _) => (1, Some(pre)),
+            _ => (2, None),
+        };
+        phase(self)
+            .cmp(&phase(other))
+            .then_with(|| self.post.is_some().cmp(&other.post.is_some()))
+            .then_with(|| self.post.cmp(&other.post))
+            // Within the same phase, a dev release precedes the release itself
+            .then_with(|| match (self.dev, other.dev) {
+                (Some(a), Some(b)) => a.cmp(&b),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            })
+            .then_with(|| self.local.cmp(&other.local))
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let release: Vec<String> = self.release.iter().map(u64::to_string).collect();
+        f.write_str(&release.join("."))?;
+        match self.pre {
+            Some(PreRelease::Alpha(n)) => write!(f, "a{}", n)?,
+            Some(PreRelease::Beta(n)) => write!(f, "b{}", n)?,
+            Some(PreRelease::ReleaseCandidate(n)) => write!(f, "rc{}", n)?,
+            None => {}
+        }
+        if let Some(post) = self.post {
+            write!(f, ".post{}", post)?;
+        }
+        if let Some(dev) = self.dev {
+            write!(f, ".dev{}", dev)?;
+        }
+        if let Some(local) = &self.local {
+            write!(f, "+{}", local)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(text: &str) -> Version {
+        Version::parse(text).unwrap_or_else(|| panic!("failed to parse {}", text))
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(v("3.12.1").release, vec![3, 12, 1]);
+        assert_eq!(v("3.13.0rc2").pre, Some(PreRelease::ReleaseCandidate(2)));
+        assert_eq!(v("3.14.0a3").pre, Some(PreRelease::Alpha(3)));
+        assert_eq!(v("3.13.0b1").pre, Some(PreRelease::Beta(1)));
+        assert_eq!(v("0.1.4.dev20250101").dev, Some(20250101));
+        assert_eq!(v("0.1.4.post1").post, Some(1));
+        assert_eq!(v("0.1.4+local.1").local, Some("local.1".to_string()));
+        assert_eq!(v("v1.0").release, vec![1, 0]);
+
+        assert_eq!(Version::parse(""), None);
+        assert_eq!(Version::parse("abc"), None);
+        assert_eq!(Version::parse("3.12.x"), None);
+        assert_eq!(Version::parse("3.12foo"), None);
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        for text in ["3.12.1", "3.13.0rc2", "0.1.4.post1.dev2", "1.0+abc"] {
+            assert_eq!(v(text).to_string(), text);
+        }
+    }
+
+    #[test]
+    fn test_ordering() {
+        let ordered = [
+            "0.1.4.dev1",
+            "0.1.4a1",
+            "0.1.4b2",
+            "0.1.4rc1.dev1",
+            "0.1.4rc1",
+            "0.1.4",
+            "0.1.4.post1",
+            "0.1.5",
+            "0.1.10",
+            "0.2",
+        ];
+        for pair in ordered.windows(2) {
+            assert!(v(pair[0]) < v(pair[1]), "{} < {}", pair[0], pair[1]);
+        }
+        assert_eq!(v("3.12").cmp(&v("3.12.0")), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_python_banner() {
+        assert_eq!(
+            Version::from_python_banner("Python 3.12.1")
+                .unwrap()
+                .release,
+            vec![3, 12, 1]
+        );
+        assert_eq!(
+            Version::from_python_banner("  Python 3.11 (default, Oct  5 2023)\n")
+                .unwrap()
+                .release,
+            vec![3, 11]
+        );
+        assert_eq!(
+            Version::from_python_banner("Python 3.13.0rc2").unwrap().pre,
+            Some(PreRelease::ReleaseCandidate(2))
+        );
+        assert_eq!(
+            Version::from_python_banner("Python 3.13.0t")
+                .unwrap()
+                .minor(),
+            13
+        );
+        assert_eq!(Version::from_python_banner("Some Python 3.11.0"), None);
+
+        assert!(v("3.13.0b1").is_prerelease());
+        assert!(v("0.1.4.dev1").is_prerelease());
+        assert!(!v("3.12.1").is_prerelease());
+        assert!(!v("0.1.4.post1").is_prerelease());
+    }
+
+    #[test]
+    fn test_matches_prefix() {
+        let prefix = v("3.11");
+        assert!(v("3.11.4").matches_prefix(&prefix));
+        assert!(v("3.11").matches_prefix(&prefix));
+        assert!(!v("3.110.0").matches_prefix(&prefix));
+        assert!(!v("3.1").matches_prefix(&prefix));
+        assert!(!v("3.12.0").matches_prefix(&prefix));
+    }
+}
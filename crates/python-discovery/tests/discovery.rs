@@ -0,0 +1,52 @@
+//! Drives interpreter discovery the way a launcher would.
+
+use python_discovery::detection::{check_python_build, PythonPolicy};
+use python_discovery::discovery::{candidates, PythonPreference};
+use python_discovery::probe_cache::ProbeCache;
+use python_discovery::version::Version;
+use python_discovery::{Arch, Os};
+use std::path::Path;
+
+#[test]
+fn detection_accepts_supported_interpreters_from_a_venv() {
+    let found = candidates(
+        PythonPreference::VenvFirst,
+        &["python3.12"],
+        Some(Path::new("/src/app")),
+        Os::Linux,
+        Arch::X86_64,
+        |_| None,
+        |_| Vec::new(),
+    );
+    let venv_python = &found[0];
+    assert_eq!(venv_python, "/src/app/.venv/bin/python");
+
+    let policy = PythonPolicy::default();
+    assert!(check_python_build("Python 3.12.4", venv_python, None, policy).is_ok());
+    assert!(check_python_build("Python 3.10.4", venv_python, None, policy).is_err());
+}
+
+#[test]
+fn probe_cache_reuses_results() {
+    let mut cache = ProbeCache::default();
+    cache.prepare("settings".to_string(), false, 60, 100);
+
+    let mut runs = 0;
+    for _ in 0..3 {
+        cache.version("python3.11", || {
+            runs += 1;
+            (
+                "/usr/bin/python3.11".to_string(),
+                Ok("Python 3.11.9".to_string()),
+            )
+        });
+    }
+    assert_eq!(runs, 1);
+}
+
+#[test]
+fn versions_order_by_pep_440() {
+    let installed = Version::parse("0.1.4rc1").unwrap();
+    let latest = Version::parse("0.1.4").unwrap();
+    assert!(installed < latest);
+}
@@ -0,0 +1,213 @@
+//! Property tests for `validate_python_path` and `normalize_path`.
+//!
+//! Paths are generated from a pool of hostile components (traversal, Windows
+//! device names, unicode lookalikes, overlong names) mixed with arbitrary
+//! text. Each property states what must hold for every path, independently of
+//! how the validator checks it, and proptest shrinks a failure to a minimal
+//! path.
+
+use std::path::{Component, Path};
+
+use proptest::prelude::*;
+use proptest::sample::Index;
+use python_discovery::detection::{normalize_path, validate_python_path};
+
+const COMPONENTS: &[&str] = &[
+    "python3.12",
+    "python",
+    "PYTHON.EXE",
+    "usr",
+    "opt",
+    "bin",
+    "Scripts",
+    "..",
+    ".",
+    "",
+    "...",
+    "CON",
+    "NUL",
+    "COM1",
+    "LPT1.txt",
+    "\0",
+    "~",
+    "$(id)",
+    "`id`",
+    " ",
+    "ünïcødé",
+    "パイソン",
+    "İ",
+    "pyth\u{03bf}n", // Greek omicron
+    "python\u{202e}exe",
+    "\u{ff0e}\u{ff0e}", // fullwidth dots
+    "C:",
+    "\\\\?\\",
+];
+
+const SEPARATORS: &[&str] = &["/", "\\", "//"];
+
+/// Characters that must never be accepted anywhere in a path: control
+/// characters and invisible formatting.
+const HOSTILE: &[char] = &[
+    '\0', '\n', '\r', '\t', '\u{7f}', '\u{200b}', '\u{200e}', '\u{202a}', '\u{202e}', '\u{2066}',
+    '\u{feff}',
+];
+
+/// Characters a shell would interpret, which are still legal in a path.
+const SHELL_SPECIAL: &[char] = &['\'', '&', '$', '`', ';', '|', '<', '>', '*', '?', '!', '"'];
+
+fn component() -> impl Strategy<Value = String> {
+    prop_oneof![
+        4 => prop::sample::select(COMPONENTS).prop_map(str::to_string),
+        2 => "[A-Za-z0-9_.-]{1,12}",
+        1 => "\\PC{1,8}",
+        1 => (1..600usize).prop_map(|len| "a".repeat(len)),
+    ]
+}
+
+fn path() -> impl Strategy<Value = String> {
+    (
+        prop::option::of(prop::sample::select(SEPARATORS)),
+        prop::collection::vec((prop::sample::select(SEPARATORS), component()), 0..8),
+    )
+        .prop_map(|(root, parts)| {
+            let mut path = root.unwrap_or_default().to_string();
+            for (i, (separator, component)) in parts.into_iter().enumerate() {
+                if i > 0 {
+                    path.push_str(separator);
+                }
+                path.push_str(&component);
+            }
+            path
+        })
+}
+
+/// Directories interpreters are really installed in.
+fn interpreter_dir() -> impl Strategy<Value = String> {
+    prop_oneof![
+        Just("/usr/bin".to_string()),
+        Just("/usr/local/bin".to_string()),
+        Just("/opt/homebrew/bin".to_string()),
+        "/home/[a-z][a-z0-9_-]{0,15}/\\.venv/bin",
+        "/home/[a-zäöüé]{1,8}/\\.pyenv/versions/3\\.1[12]\\.[0-9]{1,2}/bin",
+        "C:\\\\Users\\\\[A-Za-z]{1,12}\\\\AppData\\\\Local\\\\Programs\\\\Python\\\\Python31[12]",
+    ]
+}
+
+/// Inserts `c` into `path` at a character boundary picked by `at`.
+fn insert(path: &str, at: Index, c: char) -> String {
+    let boundaries: Vec<usize> = path
+        .char_indices()
+        .map(|(index, _)| index)
+        .chain([path.len()])
+        .collect();
+    let mut path = path.to_string();
+    path.insert(boundaries[at.index(boundaries.len())], c);
+    path
+}
+
+proptest! {
+    #[test]
+    fn hostile_characters_are_always_rejected(
+        path in path(),
+        at in any::<Index>(),
+        c in prop::sample::select(HOSTILE),
+    ) {
+        let path = insert(&path, at, c);
+        prop_assert!(!validate_python_path(&path), "accepted {:?}", path);
+    }
+
+    #[test]
+    fn parent_components_are_always_rejected(before in path(), after in path()) {
+        for separator in ["/", "\\"] {
+            let path = format!("{}{}..{}{}", before, separator, separator, after);
+            prop_assert!(!validate_python_path(&path), "accepted {:?}", path);
+        }
+    }
+
+    #[test]
+    fn overlong_paths_are_always_rejected(path in path()) {
+        let path = format!("/usr/bin/python{}{}", path, "3".repeat(1000));
+        prop_assert!(!validate_python_path(&path));
+    }
+
+    #[test]
+    fn installed_interpreters_are_accepted(
+        dir in interpreter_dir(),
+        name in "python3(\\.1[12])?(\\.exe)?",
+    ) {
+        let separator = if dir.contains('\\') { "\\" } else { "/" };
+        let path = format!("{}{}{}", dir, separator, name);
+        prop_assert!(validate_python_path(&path), "rejected {:?}", path);
+    }
+
+    #[test]
+    fn shell_special_characters_are_accepted(
+        dir in interpreter_dir(),
+        c in prop::sample::select(SHELL_SPECIAL),
+    ) {
+        let separator = if dir.contains('\\') { "\\" } else { "/" };
+        let path = format!("{}{}my{}project{}python3.12", dir, separator, c, separator);
+        prop_assert!(validate_python_path(&path), "rejected {:?}", path);
+    }
+
+    #[test]
+    fn validation_ignores_case_of_the_executable_name(dir in interpreter_dir()) {
+        let separator = if dir.contains('\\') { "\\" } else { "/" };
+        for name in ["python.exe", "Python.exe", "PYTHON.EXE"] {
+            let path = format!("{}{}{}", dir, separator, name);
+            prop_assert!(validate_python_path(&path), "rejected {:?}", path);
+        }
+    }
+
+    #[test]
+    fn normalization_is_idempotent(path in path()) {
+        let once = normalize_path(Path::new(&path));
+        prop_assert_eq!(normalize_path(&once), once);
+    }
+
+    #[test]
+    fn normalized_paths_only_keep_leading_parent_components(path in path()) {
+        let normalized = normalize_path(Path::new(&path));
+        let mut past_leading = false;
+        for component in normalized.components() {
+            match component {
+                Component::CurDir => prop_assert!(false, "kept `.` in {:?}", normalized),
+                Component::ParentDir => {
+                    prop_assert!(!past_leading, "kept inner `..` in {:?}", normalized)
+                }
+                Component::Normal(_) => past_leading = true,
+                Component::RootDir | Component::Prefix(_) => {}
+            }
+        }
+    }
+}
+
+#[test]
+fn hostile_pool_components_are_rejected() {
+    for component in ["python\u{202e}exe", "\0", ".."] {
+        for path in [
+            format!("/usr/bin/{}", component),
+            format!("/opt/{}/bin/python3.12", component),
+        ] {
+            assert!(!validate_python_path(&path), "accepted {:?}", path);
+        }
+    }
+}
+
+#[test]
+fn apostrophes_and_ampersands_are_accepted() {
+    for path in [
+        "/home/o'brien/.venv/bin/python3.12",
+        "/Users/me/R&D/.venv/bin/python3.12",
+        "C:\\Users\\O'Brien\\R&D\\.venv\\Scripts\\python.exe",
+    ] {
+        assert!(validate_python_path(path), "rejected {:?}", path);
+    }
+}
+
+#[test]
+fn device_names_and_lookalikes_are_rejected() {
+    for name in ["CON", "NUL", "COM1", "LPT1.txt", "C:\\NUL", "pyth\u{03bf}n"] {
+        assert!(!validate_python_path(name), "accepted {:?}", name);
+    }
+}
@@ -0,0 +1,252 @@
+//! One-shot maintenance actions.
+//!
+//! Extensions can't register commands, so maintenance is requested through
+//! the `action` setting instead. An action runs once, on the next launch, and
+//! its outcome is shown in the configuration panel. Removing the setting and
+//! adding it again runs the action again.
+
+use std::path::Path;
+use std::time::Duration;
+
+use python_discovery::process::{Invocation, Runner};
+use serde::{Deserialize, Serialize};
+
+use crate::compatibility;
+use crate::installation::Entry;
+use crate::process::{self, output_within};
+use crate::readiness::Readiness;
+use crate::state;
+use python_discovery::detection;
+
+/// A maintenance action the next launch performs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Action {
+    /// Reinstall serena-agent, rebuilding a managed environment from scratch
+    Reinstall,
+    /// Discard cached interpreter probes and the update check
+    ClearCache,
+    /// Rebuild Serena's symbol index for the project
+    Reindex,
+    /// Check the interpreter, serena-agent and the server's handshake
+    Doctor,
+    /// Remove managed environments, caches and downloaded language servers
+    Cleanup,
+}
+
+impl Action {
+    /// The setting value, e.g. "clear-cache".
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Self::Reinstall => "reinstall",
+            Self::ClearCache => "clear-cache",
+            Self::Reindex => "reindex",
+            Self::Doctor => "doctor",
+            Self::Cleanup => "cleanup",
+        }
+    }
+}
+
+/// What an action did.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct ActionReport {
+    pub action: Action,
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+    pub succeeded: bool,
+    /// Markdown describing the outcome
+    pub details: String,
+}
+
+impl ActionReport {
+    /// Records the outcome of `action` at the current time.
+    pub(crate) fn new(action: Action, result: Result<String, String>) -> Self {
+        let succeeded = result.is_ok();
+        Self {
+            action,
+            timestamp: state::now(),
+            succeeded,
+            details: result.unwrap_or_else(|err| err),
+        }
+    }
+}
+
+/// Returns the action to run on this launch and remembers it in `done`.
+///
+/// An action runs when it is requested and wasn't the one run last; an unset
+/// setting forgets the last one, so it can be requested again.
+pub(crate) fn pending(requested: Option<Action>, done: &mut Option<Action>) -> Option<Action> {
+    let run = requested.filter(|action| *done != Some(*action));
+    *done = requested;
+    run
+}
+
+/// Arguments that rebuild Serena's index for `project`.
+fn reindex_args(module: &str, project: &Path) -> Vec<String> {
+    vec![
+        "-m".to_string(),
+        module.to_string(),
+        "project".to_string(),
+        "index".to_string(),
+        project.to_string_lossy().to_string(),
+    ]
+}
+
+/// Rebuilds Serena's symbol index for `project` with `python -m module`.
+pub(crate) fn reindex(
+    python: &str,
+    module: &str,
+    project: &Path,
+    timeout: Duration,
+    runner: &dyn Runner,
+) -> Result<String, String> {
+    let output = output_within(
+        runner,
+        python,
+        Invocation::new(python).args(reindex_args(module, project)),
+        timeout,
+    )
+    .map_err(|e| format!("Failed to index {}: {}", project.display(), e))?;
+    if !output.success() {
+        return Err(format!(
+            "Failed to index {}: {}",
+            project.display(),
+            process::failure(&output)
+        ));
+    }
+    Ok(format!("Indexed `{}`.", project.display()))
+}
+
+/// Summarizes the interpreter, serena-agent and a readiness check; fails when
+/// any of them is a problem.
+pub(crate) fn doctor(
+    python: &str,
+    python_version: Option<&str>,
+    entry: &Entry,
+    readiness: &Readiness,
+) -> Result<String, String> {
+    let mut healthy = true;
+    let mut out = String::new();
+    match python_version {
+        Some(version) if detection::is_supported_python(version) => {
+            out.push_str(&format!("- ✅ Python {} at `{}`\n", version, python));
+        }
+        Some(version) => {
+            healthy = false;
+            out.push_str(&format!(
+                "- ❌ Python {} at `{}` is not supported\n",
+                version, python
+            ));
+        }
+        None => {
+            healthy = false;
+            out.push_str(&format!("- ❌ `{}` did not report its version\n", python));
+        }
+    }
+    out.push_str(&format!(
+        "- ✅ serena-agent {} runs as `{}`\n",
+        entry.version.as_deref().unwrap_or("(unknown version)"),
+        entry.module
+    ));
+    if let Some(advice) = entry
+        .version
+        .as_deref()
+        .and_then(|serena| compatibility::advice(serena, python_version))
+    {
+        healthy = false;
+        out.push_str(&format!("- ❌ {}\n", advice));
+    }
+    match (&readiness.error, readiness.millis) {
+        (None, Some(millis)) => out.push_str(&format!(
+            "- ✅ MCP handshake in {:.1} s with {} tools\n",
+            millis as f64 / 1000.0,
+            readiness.tools.unwrap_or_default()
+        )),
+        (error, _) => {
+            healthy = false;
+            out.push_str(&format!(
+                "- ❌ MCP handshake failed: {}\n",
+                error.as_deref().unwrap_or("unknown error")
+            ));
+        }
+    }
+    if healthy {
+        Ok(out)
+    } else {
+        Err(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pending_runs_once() {
+        let mut done = None;
+        assert_eq!(
+            pending(Some(Action::Reindex), &mut done),
+            Some(Action::Reindex)
+        );
+        // Still set on the next launch: nothing to do
+        assert_eq!(pending(Some(Action::Reindex), &mut done), None);
+        assert_eq!(
+            pending(Some(Action::Doctor), &mut done),
+            Some(Action::Doctor)
+        );
+        // Removing the setting allows the same action again
+        assert_eq!(pending(None, &mut done), None);
+        assert_eq!(
+            pending(Some(Action::Doctor), &mut done),
+            Some(Action::Doctor)
+        );
+    }
+
+    #[test]
+    fn test_doctor() {
+        let entry = Entry {
+            module: "serena.cli".to_string(),
+            version: Some("0.1.4".to_string()),
+        };
+        let ready = Readiness {
+            launch: 0,
+            millis: Some(1500),
+            tools: Some(20),
+            error: None,
+        };
+        let report = doctor("/usr/bin/python3.12", Some("3.12.1"), &entry, &ready).unwrap();
+        assert!(report.contains("Python 3.12.1"), "{}", report);
+        assert!(report.contains("1.5 s with 20 tools"), "{}", report);
+
+        let failed = Readiness {
+            millis: None,
+            tools: None,
+            error: Some("no answer within 30s".to_string()),
+            ..ready
+        };
+        let report = doctor("/usr/bin/python3.8", Some("3.8.10"), &entry, &failed).unwrap_err();
+        assert!(report.contains("is not supported"), "{}", report);
+        assert!(report.contains("no answer within 30s"), "{}", report);
+
+        let mismatch = doctor(
+            "/usr/bin/python3.12",
+            Some("3.12.1"),
+            &Entry {
+                version: Some("0.0.9".to_string()),
+                ..entry
+            },
+            &ready,
+        )
+        .unwrap_err();
+        assert!(mismatch.contains("serena-agent==0.1.*"), "{}", mismatch);
+    }
+
+    #[test]
+    fn test_reindex_args() {
+        assert_eq!(
+            reindex_args("serena.cli", Path::new("/src/app")),
+            ["-m", "serena.cli", "project", "index", "/src/app"]
+        );
+    }
+}
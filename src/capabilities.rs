@@ -0,0 +1,231 @@
+//! Flags the installed Serena release accepts.
+//!
+//! Serena exits with a usage error on an unknown option, and releases have
+//! added `--context`, `--enable-web-dashboard` and transports over time.
+//! Before a launch the extension reads `start-mcp-server --help` once per
+//! serena-agent version and leaves out the flags that release doesn't know.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+
+use python_discovery::process::{Invocation, Runner};
+use serde::{Deserialize, Serialize};
+
+use crate::installation::Entry;
+use crate::process::{self, output_within};
+
+/// Options `start-mcp-server --help` lists.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Capabilities {
+    /// Long options, e.g. "--context"
+    flags: BTreeSet<String>,
+    /// Values `--transport` accepts, when the help lists them
+    transports: Option<BTreeSet<String>>,
+}
+
+impl Capabilities {
+    /// Reads the options from click's help output.
+    pub(crate) fn parse(help: &str) -> Self {
+        let mut capabilities = Self::default();
+        for line in help.lines().map(str::trim_start) {
+            if !line.starts_with("--") {
+                continue;
+            }
+            // Names and metavar come first, the description after a wide gap
+            let spec = line.split("  ").next().unwrap_or_default();
+            for name in spec.split(", ") {
+                let mut words = name.split_whitespace();
+                let Some(flag) = words.next().filter(|flag| flag.starts_with("--")) else {
+                    continue;
+                };
+                let flag = flag.split('/').next().unwrap_or(flag).to_string();
+                if flag == "--transport" {
+                    capabilities.transports = words
+                        .next()
+                        .and_then(|choices| choices.strip_prefix('['))
+                        .and_then(|choices| choices.strip_suffix(']'))
+                        .map(|choices| choices.split('|').map(str::to_string).collect());
+                }
+                capabilities.flags.insert(flag);
+            }
+        }
+        capabilities
+    }
+
+    /// Whether the release accepts `flag`.
+    pub(crate) fn supports(&self, flag: &str) -> bool {
+        self.flags.contains(flag)
+    }
+
+    /// Whether `--transport <transport>` is accepted.
+    pub(crate) fn supports_transport(&self, transport: &str) -> bool {
+        self.supports("--transport")
+            && self
+                .transports
+                .as_ref()
+                .is_none_or(|transports| transports.contains(transport))
+    }
+
+    /// Drops unsupported options from `args`, together with their values.
+    ///
+    /// Arguments before the first option, such as the subcommand, are kept.
+    pub(crate) fn retain_supported(&self, args: Vec<String>) -> Vec<String> {
+        let mut kept = Vec::with_capacity(args.len());
+        let mut dropping = false;
+        for arg in args {
+            if arg.starts_with("--") {
+                dropping = !self.supports(&arg);
+            } else if dropping {
+                // The value of a dropped option
+                dropping = false;
+                continue;
+            }
+            if !dropping {
+                kept.push(arg);
+            }
+        }
+        kept
+    }
+}
+
+/// Reads the options of `python -m <module> start-mcp-server`.
+fn probe(
+    python: &str,
+    module: &str,
+    timeout: Duration,
+    runner: &dyn Runner,
+) -> Result<Capabilities, String> {
+    let output = output_within(
+        runner,
+        python,
+        Invocation::new(python).args(["-m", module, "start-mcp-server", "--help"]),
+        timeout,
+    )
+    .map_err(|e| format!("Failed to read Serena's options: {}", e))?;
+    if !output.success() {
+        return Err(format!(
+            "Failed to read Serena's options: {}",
+            process::failure(&output)
+        ));
+    }
+    Ok(Capabilities::parse(&String::from_utf8_lossy(
+        &output.stdout,
+    )))
+}
+
+/// Returns the options of the serena `entry` installed for `python`.
+///
+/// Results are cached per module and serena-agent version; without a known
+/// version nothing is cached. `None` means the help couldn't be read, in
+/// which case all flags are passed as before.
+pub(crate) fn lookup(
+    cache: &mut BTreeMap<String, Capabilities>,
+    python: &str,
+    entry: &Entry,
+    timeout: Duration,
+    runner: &dyn Runner,
+) -> Option<Capabilities> {
+    let key = entry
+        .version
+        .as_ref()
+        .map(|version| format!("{} {}", entry.module, version));
+    if let Some(capabilities) = key.as_ref().and_then(|key| cache.get(key)) {
+        return Some(capabilities.clone());
+    }
+    let capabilities = probe(python, &entry.module, timeout, runner).ok()?;
+    if let Some(key) = key {
+        cache.insert(key, capabilities.clone());
+    }
+    Some(capabilities)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use python_discovery::process::StdRunner;
+
+    const HELP: &str = "Usage: serena start-mcp-server [OPTIONS]
+
+  Starts the Serena MCP server.
+
+Options:
+  --project, --project-file TEXT  Path to the project
+  --mode TEXT                     Modes to activate
+  --transport [stdio|sse]         Transport protocol.
+  --enable-web-dashboard BOOLEAN  Whether to open the dashboard
+  --help                          Show this message and exit.
+";
+
+    #[test]
+    fn test_parse() {
+        let capabilities = Capabilities::parse(HELP);
+        assert!(capabilities.supports("--project"));
+        assert!(capabilities.supports("--project-file"));
+        assert!(capabilities.supports("--enable-web-dashboard"));
+        assert!(!capabilities.supports("--context"));
+        assert!(capabilities.supports_transport("stdio"));
+        assert!(!capabilities.supports_transport("streamable-http"));
+        assert!(!Capabilities::parse("").supports_transport("stdio"));
+    }
+
+    #[test]
+    fn test_retain_supported() {
+        let args = [
+            "start-mcp-server",
+            "--project",
+            "/src/app",
+            "--context",
+            "ide-assistant",
+            "--mode",
+            "planning",
+            "--serena-config",
+            "/work/overlay.yml",
+        ];
+        assert_eq!(
+            Capabilities::parse(HELP).retain_supported(args.map(String::from).to_vec()),
+            [
+                "start-mcp-server",
+                "--project",
+                "/src/app",
+                "--mode",
+                "planning"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lookup_uses_cache() {
+        let entry = Entry {
+            module: "serena.cli".to_string(),
+            version: Some("0.1.4".to_string()),
+        };
+        let cached = Capabilities::parse(HELP);
+        let mut cache = BTreeMap::from([("serena.cli 0.1.4".to_string(), cached.clone())]);
+        // The interpreter doesn't exist, so only the cache can answer
+        let python = "/nonexistent/python";
+        assert_eq!(
+            lookup(
+                &mut cache,
+                python,
+                &entry,
+                Duration::from_secs(1),
+                &StdRunner
+            ),
+            Some(cached)
+        );
+        let unknown = Entry {
+            version: None,
+            ..entry
+        };
+        assert_eq!(
+            lookup(
+                &mut cache,
+                python,
+                &unknown,
+                Duration::from_secs(1),
+                &StdRunner
+            ),
+            None
+        );
+    }
+}
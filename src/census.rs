@@ -0,0 +1,421 @@
+//! Which languages a project is written in.
+//!
+//! Serena's symbol tools are backed by language servers, so they only help in
+//! languages it supports. Counting source files by extension is enough to
+//! tell a Rust project from a Haskell one and to warn before users debug an
+//! empty `find_symbol` result. The same count tells a toy repository from a
+//! monorepo, which want different defaults.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// A programming language recognised by file extension.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Language {
+    /// Display name, e.g. "C/C++"
+    pub name: &'static str,
+    /// Whether Serena ships a language server for it
+    pub supported: bool,
+    extensions: &'static [&'static str],
+}
+
+const fn language(
+    name: &'static str,
+    supported: bool,
+    extensions: &'static [&'static str],
+) -> Language {
+    Language {
+        name,
+        supported,
+        extensions,
+    }
+}
+
+/// Languages by extension; lowercase, without the dot.
+const LANGUAGES: [Language; 37] = [
+    language("Python", true, &["py", "pyi"]),
+    language(
+        "TypeScript/JavaScript",
+        true,
+        &["ts", "tsx", "mts", "cts", "js", "jsx", "mjs", "cjs"],
+    ),
+    language("PHP", true, &["php"]),
+    language("Go", true, &["go"]),
+    language("Rust", true, &["rs"]),
+    language(
+        "C/C++",
+        true,
+        &["c", "h", "cc", "cpp", "cxx", "hh", "hpp", "hxx"],
+    ),
+    language("Java", true, &["java"]),
+    language("C#", true, &["cs"]),
+    language("Swift", true, &["swift"]),
+    language("Ruby", true, &["rb"]),
+    language("Kotlin", true, &["kt", "kts"]),
+    language("Clojure", true, &["clj", "cljs", "cljc"]),
+    language("Dart", true, &["dart"]),
+    language("Bash", true, &["sh", "bash"]),
+    language("Lua", true, &["lua"]),
+    language("Nix", true, &["nix"]),
+    language("Elixir", true, &["ex", "exs"]),
+    language("Erlang", true, &["erl", "hrl"]),
+    language("Zig", true, &["zig"]),
+    language("R", true, &["r"]),
+    language("Haskell", false, &["hs", "lhs"]),
+    language("OCaml", false, &["ml", "mli"]),
+    language("Scala", false, &["scala", "sc"]),
+    language("Julia", false, &["jl"]),
+    language("Perl", false, &["pl", "pm"]),
+    language("F#", false, &["fs", "fsi", "fsx"]),
+    language("Visual Basic", false, &["vb"]),
+    language("Fortran", false, &["f", "f90", "f95"]),
+    language("COBOL", false, &["cob", "cbl"]),
+    language("Groovy", false, &["groovy"]),
+    language("Solidity", false, &["sol"]),
+    language("Nim", false, &["nim"]),
+    language("Crystal", false, &["cr"]),
+    language("Elm", false, &["elm"]),
+    language("PureScript", false, &["purs"]),
+    language("Racket", false, &["rkt"]),
+    language("Gleam", false, &["gleam"]),
+];
+
+/// Languages whose language server Serena downloads on first use, rather
+/// than bundling it or using one installed with the toolchain.
+const DOWNLOADED_SERVERS: [&str; 11] = [
+    "TypeScript/JavaScript",
+    "PHP",
+    "C/C++",
+    "Java",
+    "C#",
+    "Ruby",
+    "Kotlin",
+    "Clojure",
+    "Bash",
+    "Lua",
+    "Elixir",
+];
+
+/// Directories that hold dependencies or build output rather than sources.
+const SKIPPED_DIRS: [&str; 8] = [
+    "node_modules",
+    "target",
+    "build",
+    "dist",
+    "vendor",
+    "venv",
+    "__pycache__",
+    "site-packages",
+];
+
+/// Files looked at before the scan stops, so huge trees don't stall the panel.
+const MAX_FILES: usize = 20_000;
+
+/// How deep below the project root the scan descends.
+const MAX_DEPTH: usize = 8;
+
+/// Projects with fewer source files are small.
+const SMALL_FILES: usize = 200;
+
+/// Projects with at least this many source files are large.
+const LARGE_FILES: usize = 5_000;
+
+/// Symbol tools, which need a language server to return anything.
+pub(crate) const SYMBOL_TOOLS: [&str; 7] = [
+    "find_symbol",
+    "find_referencing_symbols",
+    "get_symbols_overview",
+    "insert_after_symbol",
+    "insert_before_symbol",
+    "replace_symbol_body",
+    "rename_symbol",
+];
+
+/// How big a project is, for choosing defaults.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Size {
+    /// Fewer than [`SMALL_FILES`] source files
+    Small,
+    /// At least [`LARGE_FILES`] source files, or too many to count
+    Large,
+}
+
+impl Size {
+    /// Serena modes a project of this size starts with unless `modes` is set.
+    ///
+    /// Onboarding a toy repository costs more than reading it, while large
+    /// ones are worth planning changes in before editing. Both keep Serena's
+    /// default `interactive` and `editing` modes.
+    pub(crate) fn modes(self) -> [&'static str; 3] {
+        match self {
+            Self::Small => ["interactive", "editing", "no-onboarding"],
+            Self::Large => ["interactive", "editing", "planning"],
+        }
+    }
+}
+
+/// Source files per language.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct Census {
+    /// Languages with at least one file, most files first
+    pub languages: Vec<(&'static Language, usize)>,
+    /// Whether the scan stopped at its file limit
+    pub truncated: bool,
+}
+
+impl Census {
+    /// Counts the source files below `root`.
+    ///
+    /// Hidden directories, dependencies and build output are skipped.
+    pub(crate) fn scan(root: &Path) -> Self {
+        let mut names = Vec::new();
+        let truncated = collect(root, 0, &mut names);
+        let mut census = Self::from_file_names(names.iter().map(String::as_str));
+        census.truncated = truncated;
+        census
+    }
+
+    /// Tallies file names by extension.
+    pub(crate) fn from_file_names<'a>(names: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut counts = [0usize; LANGUAGES.len()];
+        for name in names {
+            let Some((_, extension)) = name.rsplit_once('.') else {
+                continue;
+            };
+            let extension = extension.to_ascii_lowercase();
+            if let Some(index) = LANGUAGES
+                .iter()
+                .position(|language| language.extensions.contains(&extension.as_str()))
+            {
+                counts[index] += 1;
+            }
+        }
+        let mut languages: Vec<_> = LANGUAGES
+            .iter()
+            .zip(counts)
+            .filter(|(_, count)| *count > 0)
+            .collect();
+        // Stable, so ties keep table order
+        languages.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        Self {
+            languages,
+            truncated: false,
+        }
+    }
+
+    /// Languages Serena has a language server for, most files first.
+    pub(crate) fn supported(&self) -> impl Iterator<Item = &(&'static Language, usize)> {
+        self.languages
+            .iter()
+            .filter(|(language, _)| language.supported)
+    }
+
+    /// The project's size, or `None` for medium-sized projects and ones
+    /// without source files.
+    pub(crate) fn size(&self) -> Option<Size> {
+        let files: usize = self.languages.iter().map(|(_, count)| count).sum();
+        if self.truncated || files >= LARGE_FILES {
+            Some(Size::Large)
+        } else if (1..SMALL_FILES).contains(&files) {
+            Some(Size::Small)
+        } else {
+            None
+        }
+    }
+
+    /// Supported languages whose language server has to be downloaded first.
+    pub(crate) fn downloaded_servers(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.supported()
+            .map(|(language, _)| language.name)
+            .filter(|name| DOWNLOADED_SERVERS.contains(name))
+    }
+
+    /// True when the project has source files but none Serena can index.
+    pub(crate) fn only_unsupported(&self) -> bool {
+        !self.languages.is_empty() && self.supported().next().is_none()
+    }
+}
+
+/// The last census, reused while the project directory is unchanged.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct CachedCensus {
+    project: PathBuf,
+    /// Modification time of `project`, in milliseconds since the Unix epoch
+    modified: u64,
+    /// Files per language, by name
+    languages: Vec<(String, usize)>,
+    truncated: bool,
+}
+
+impl CachedCensus {
+    fn census(&self) -> Census {
+        Census {
+            languages: self
+                .languages
+                .iter()
+                .filter_map(|(name, count)| {
+                    let language = LANGUAGES.iter().find(|language| language.name == name)?;
+                    Some((language, *count))
+                })
+                .collect(),
+            truncated: self.truncated,
+        }
+    }
+}
+
+/// Scans `project`, or takes the census from `cache` when it was made for the
+/// same directory with the same modification time.
+///
+/// The modification time changes when entries are added to or removed from
+/// the directory itself, which is enough to notice a checkout or a new package.
+pub(crate) fn scan_cached(project: &Path, cache: &mut Option<CachedCensus>) -> Census {
+    let modified = std::fs::metadata(project)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map(|modified| modified.as_millis() as u64);
+    if let (Some(cached), Some(modified)) = (cache.as_ref(), modified) {
+        if cached.project == project && cached.modified == modified {
+            return cached.census();
+        }
+    }
+    let census = Census::scan(project);
+    *cache = modified.map(|modified| CachedCensus {
+        project: project.to_path_buf(),
+        modified,
+        languages: census
+            .languages
+            .iter()
+            .map(|(language, count)| (language.name.to_string(), *count))
+            .collect(),
+        truncated: census.truncated,
+    });
+    census
+}
+
+/// Collects file names below `dir`; returns true when [`MAX_FILES`] was hit.
+fn collect(dir: &Path, depth: usize, names: &mut Vec<String>) -> bool {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return false;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_dir() {
+            let skipped = name.starts_with('.') || SKIPPED_DIRS.contains(&name.as_str());
+            if !skipped && depth < MAX_DEPTH && collect(&entry.path(), depth + 1, names) {
+                return true;
+            }
+        } else if file_type.is_file() {
+            if names.len() >= MAX_FILES {
+                return true;
+            }
+            names.push(name);
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(census: &Census) -> Vec<(&str, usize)> {
+        census
+            .languages
+            .iter()
+            .map(|(language, count)| (language.name, *count))
+            .collect()
+    }
+
+    #[test]
+    fn test_from_file_names() {
+        let census = Census::from_file_names([
+            "main.rs",
+            "lib.rs",
+            "build.sh",
+            "README.md",
+            "Makefile",
+            "Schema.HS",
+        ]);
+        assert_eq!(names(&census), [("Rust", 2), ("Bash", 1), ("Haskell", 1)]);
+        assert_eq!(
+            census.supported().map(|(l, _)| l.name).collect::<Vec<_>>(),
+            ["Rust", "Bash"]
+        );
+        assert!(!census.only_unsupported());
+
+        assert_eq!(census.downloaded_servers().collect::<Vec<_>>(), ["Bash"]);
+
+        assert!(Census::from_file_names(["Main.hs", "Lib.hs"]).only_unsupported());
+        assert!(!Census::from_file_names(["README.md"]).only_unsupported());
+    }
+
+    #[test]
+    fn test_size() {
+        assert_eq!(Census::from_file_names(["README.md"]).size(), None);
+        assert_eq!(
+            Census::from_file_names(["main.rs"]).size(),
+            Some(Size::Small)
+        );
+        let names: Vec<String> = (0..SMALL_FILES).map(|n| format!("{}.py", n)).collect();
+        let medium = Census::from_file_names(names.iter().map(String::as_str));
+        assert_eq!(medium.size(), None);
+        let names: Vec<String> = (0..LARGE_FILES).map(|n| format!("{}.go", n)).collect();
+        let large = Census::from_file_names(names.iter().map(String::as_str));
+        assert_eq!(large.size(), Some(Size::Large));
+        let truncated = Census {
+            truncated: true,
+            ..Default::default()
+        };
+        assert_eq!(truncated.size(), Some(Size::Large));
+    }
+
+    #[test]
+    fn test_scan_skips_dependencies() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        for dir in ["src", "node_modules/left-pad", ".git"] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in [
+            "src/app.ts",
+            "src/util.ts",
+            "node_modules/left-pad/index.js",
+            ".git/hook.py",
+        ] {
+            std::fs::write(root.join(file), "").unwrap();
+        }
+
+        let census = Census::scan(root);
+        assert_eq!(names(&census), [("TypeScript/JavaScript", 2)]);
+        assert!(!census.truncated);
+    }
+
+    #[test]
+    fn test_scan_cached() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        std::fs::create_dir(root.join("src")).unwrap();
+        std::fs::write(root.join("src/app.ts"), "").unwrap();
+
+        let mut cache = None;
+        let census = scan_cached(root, &mut cache);
+        assert_eq!(names(&census), [("TypeScript/JavaScript", 1)]);
+
+        // A file deeper down leaves the root's modification time alone
+        std::fs::write(root.join("src/util.ts"), "").unwrap();
+        assert_eq!(scan_cached(root, &mut cache), census);
+
+        // Another directory, or a changed one, is scanned again
+        let other = tempfile::tempdir().unwrap();
+        assert_eq!(scan_cached(other.path(), &mut cache), Census::default());
+        cache.as_mut().unwrap().modified = 0;
+        cache.as_mut().unwrap().project = root.to_path_buf();
+        let census = scan_cached(root, &mut cache);
+        assert_eq!(names(&census), [("TypeScript/JavaScript", 2)]);
+    }
+}
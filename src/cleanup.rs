@@ -0,0 +1,162 @@
+//! Removing what the extension downloaded and generated.
+//!
+//! Managed environments, and the language servers Serena downloads into the
+//! per-project homes, easily reach gigabytes. `cleanup` deletes them together
+//! with the generated files and the diagnostics file, and reports how much
+//! space that freed. Memories in the per-project homes are kept.
+
+use std::path::{Path, PathBuf};
+
+use crate::diagnostics::DIAGNOSTICS_FILE;
+use crate::{generated, instances};
+
+/// Directory below a Serena home where Serena downloads language servers.
+pub(crate) const LANGUAGE_SERVERS_DIR: &str = "language_servers";
+
+/// Directory older versions wrote configuration overlays to.
+const LEGACY_OVERLAYS_DIR: &str = "overlays";
+
+/// Everything cleanup removes that exists, given the work dir, the root of
+/// the managed environments and any managed environment configured elsewhere.
+pub(crate) fn targets(
+    work_dir: &Path,
+    envs_root: &Path,
+    configured_env: Option<&Path>,
+) -> Vec<PathBuf> {
+    let mut targets = vec![envs_root.to_path_buf()];
+    // Only a directory that is a virtual environment is removed
+    if let Some(env) = configured_env.filter(|env| env.join("pyvenv.cfg").is_file()) {
+        targets.push(env.to_path_buf());
+    }
+    targets.push(generated::root(work_dir));
+    // Overlays were kept here before generated files moved
+    targets.push(work_dir.join(LEGACY_OVERLAYS_DIR));
+    targets.push(work_dir.join(DIAGNOSTICS_FILE));
+    targets.extend(language_server_dirs(work_dir));
+    targets.retain(|target| target.symlink_metadata().is_ok());
+    targets.dedup();
+    targets
+}
+
+/// Language server directories in the per-project Serena homes, sorted.
+pub(crate) fn language_server_dirs(work_dir: &Path) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = std::fs::read_dir(instances::homes_dir(work_dir))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|home| home.path().join(LANGUAGE_SERVERS_DIR))
+        .collect();
+    dirs.sort();
+    dirs
+}
+
+/// Size in bytes of a file or directory tree, not following symlinks.
+pub(crate) fn size(path: &Path) -> u64 {
+    let Ok(metadata) = path.symlink_metadata() else {
+        return 0;
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    std::fs::read_dir(path)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| size(&entry.path()))
+        .sum()
+}
+
+/// Formats a byte count, e.g. "1.5 GB".
+pub(crate) fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+/// Removes `targets` and describes what was freed as markdown.
+///
+/// Every target is attempted; the error lists the ones that couldn't be
+/// removed.
+pub(crate) fn remove(targets: &[PathBuf]) -> Result<String, String> {
+    let mut reclaimed = 0;
+    let mut out = String::new();
+    let mut failures = Vec::new();
+    for target in targets {
+        let bytes = size(target);
+        let removed = if target.is_dir() {
+            std::fs::remove_dir_all(target)
+        } else {
+            std::fs::remove_file(target)
+        };
+        match removed {
+            Ok(()) => {
+                reclaimed += bytes;
+                out.push_str(&format!(
+                    "- `{}` ({})\n",
+                    target.display(),
+                    format_size(bytes)
+                ));
+            }
+            Err(e) => failures.push(format!("- `{}`: {}\n", target.display(), e)),
+        }
+    }
+    let summary = if out.is_empty() {
+        "Nothing to clean up.\n".to_string()
+    } else {
+        format!("Reclaimed {}:\n\n{}", format_size(reclaimed), out)
+    };
+    if failures.is_empty() {
+        Ok(summary)
+    } else {
+        Err(format!(
+            "{}\nCouldn't remove:\n\n{}",
+            summary,
+            failures.concat()
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.5 KB");
+        assert_eq!(format_size(3 * 1024 * 1024 * 1024), "3.0 GB");
+    }
+
+    #[test]
+    fn test_cleanup_keeps_serena_homes() {
+        let temp = tempfile::tempdir().unwrap();
+        let work_dir = temp.path();
+        let envs = work_dir.join("envs");
+        let home = instances::homes_dir(work_dir).join("0123456789abcdef");
+        std::fs::create_dir_all(envs.join("stable/bin")).unwrap();
+        std::fs::write(envs.join("stable/bin/python"), [0u8; 2048]).unwrap();
+        std::fs::create_dir_all(home.join(LANGUAGE_SERVERS_DIR)).unwrap();
+        std::fs::write(home.join(LANGUAGE_SERVERS_DIR).join("pyright"), [0u8; 1024]).unwrap();
+        std::fs::create_dir_all(home.join("logs")).unwrap();
+        // Not a virtual environment, so it stays
+        let configured = work_dir.join("elsewhere");
+        std::fs::create_dir_all(&configured).unwrap();
+
+        let targets = targets(work_dir, &envs, Some(&configured));
+        assert_eq!(targets, [envs.clone(), home.join(LANGUAGE_SERVERS_DIR)]);
+        let report = remove(&targets).unwrap();
+        assert!(report.starts_with("Reclaimed 3.0 KB"), "{}", report);
+        assert!(!envs.exists());
+        assert!(home.join("logs").is_dir());
+        assert!(configured.is_dir());
+        assert_eq!(remove(&[]).unwrap(), "Nothing to clean up.\n");
+    }
+}
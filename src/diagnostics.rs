@@ -0,0 +1,237 @@
+//! Machine-readable diagnostics for bug reports.
+//!
+//! The configuration panel is meant for people. When `write_diagnostics_file`
+//! is on, each launch also writes what was detected, the effective settings
+//! and the launch command to a JSON file that support tooling can read.
+//! Secrets are redacted the same way as in the panel's debug output.
+
+use std::path::{Path, PathBuf};
+
+use python_discovery::detection::Interpreter;
+use python_discovery::discovery;
+use python_discovery::{Arch, Host};
+use zed_extension_api::serde_json::{self, json, Map, Value};
+use zed_extension_api::Command;
+
+use crate::config::LayeredConfig;
+use crate::generated;
+use crate::installation::Entry;
+use crate::instructions;
+use crate::secrets;
+use crate::state;
+
+/// File name of the report inside the extension work directory.
+pub(crate) const DIAGNOSTICS_FILE: &str = "serena-diagnostics.json";
+
+/// What a generic `python3` or `python` on `PATH` turned out to be.
+pub(crate) struct GenericCandidate {
+    pub name: &'static str,
+    /// Candidate after `PATH` resolution
+    pub path: String,
+    /// Version reported by `--version`, if it ran
+    pub version: Option<String>,
+    /// Why detection passed it over, if it did
+    pub verdict: Result<(), String>,
+}
+
+/// Everything known about one launch.
+pub(crate) struct Report<'a> {
+    pub host: Host,
+    /// Interpreter Serena runs with
+    pub python: &'a str,
+    pub python_version: Option<String>,
+    /// Architecture the interpreter was built for, e.g. "arm64"
+    pub machine: Option<&'a str>,
+    pub entry: &'a Entry,
+    /// Interpreters auto-detection found, best first
+    pub interpreters: &'a [Interpreter],
+    pub generic_candidates: &'a [GenericCandidate],
+    /// Whether conda base interpreters are passed over
+    pub skip_conda_base: bool,
+    pub config: &'a LayeredConfig,
+    pub command: &'a Command,
+}
+
+impl Report<'_> {
+    /// Renders the report; secret settings and environment values are redacted.
+    pub(crate) fn to_json(&self, timestamp: u64) -> Value {
+        let mut settings = self.config.merged().unwrap_or_default();
+        secrets::redact_settings(&mut settings);
+        let provenance: Map<String, Value> = self
+            .config
+            .provenance()
+            .into_iter()
+            .map(|(key, source)| (key, Value::from(source.to_string())))
+            .collect();
+        let env: Map<String, Value> = self
+            .command
+            .env
+            .iter()
+            .map(|(name, value)| {
+                let value = if secrets::is_secret_name(name) {
+                    secrets::REDACTED
+                } else {
+                    value
+                };
+                (name.clone(), Value::from(value))
+            })
+            .collect();
+        json!({
+            "extension_version": env!("CARGO_PKG_VERSION"),
+            "generated_at": state::format_timestamp(timestamp),
+            "host": {
+                "os": format!("{:?}", self.host.os),
+                "arch": format!("{:?}", self.host.arch),
+                "sandbox": self.host.sandbox.map(|sandbox| format!("{:?}", sandbox)),
+            },
+            "python": {
+                "executable": self.python,
+                "version": self.python_version,
+                "machine": self.machine,
+                "matches_host": self
+                    .machine
+                    .and_then(Arch::from_machine)
+                    .map(|arch| arch == self.host.arch),
+                "warning": self.machine.and_then(|machine| {
+                    instructions::architecture_warning(self.python, machine, self.host.arch)
+                }),
+                "serena_version": self.entry.version,
+                "entry_module": self.entry.module,
+            },
+            "interpreters": self
+                .interpreters
+                .iter()
+                .map(|interpreter| {
+                    let conda_base =
+                        discovery::is_conda_base(&interpreter.path, |dir| dir.is_dir());
+                    json!({
+                        "path": interpreter.path,
+                        "version": interpreter.version,
+                        "conda_base": conda_base,
+                        "note": (conda_base && self.skip_conda_base).then_some(
+                            "conda base installation; only used when no other interpreter \
+                             qualifies (skip_conda_base)",
+                        ),
+                    })
+                })
+                .collect::<Vec<_>>(),
+            "generic_candidates": self
+                .generic_candidates
+                .iter()
+                .map(|candidate| {
+                    json!({
+                        "name": candidate.name,
+                        "path": candidate.path,
+                        "version": candidate.version,
+                        "accepted": candidate.verdict.is_ok(),
+                        "reason": candidate.verdict.as_ref().err(),
+                    })
+                })
+                .collect::<Vec<_>>(),
+            "settings": settings,
+            "provenance": provenance,
+            "command": {
+                "command": self.command.command,
+                "args": secrets::redact_args(&self.command.args),
+                "env": env,
+            },
+        })
+    }
+
+    /// Writes the report to [`DIAGNOSTICS_FILE`] in `dir` and returns its path.
+    pub(crate) fn write(&self, dir: &Path, timestamp: u64) -> Result<PathBuf, String> {
+        let path = dir.join(DIAGNOSTICS_FILE);
+        let mut contents =
+            serde_json::to_string_pretty(&self.to_json(timestamp)).unwrap_or_default();
+        contents.push('\n');
+        generated::write_private(&path, &contents)?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use python_discovery::sandbox::Sandbox;
+    use python_discovery::{Arch, Os};
+
+    #[test]
+    fn test_to_json_redacts_secrets() {
+        let config = LayeredConfig::load(Some(json!({
+            "project_root": "/nonexistent/app",
+            "python_version": "3.12",
+            "api_keys": { "anthropic": "sk-ant-secret" },
+        })))
+        .unwrap();
+        let entry = Entry {
+            module: "serena.cli".to_string(),
+            version: Some("0.1.4".to_string()),
+        };
+        let env = vec![
+            ("ANTHROPIC_API_KEY".to_string(), "sk-ant-secret".to_string()),
+            ("DO_NOT_TRACK".to_string(), "1".to_string()),
+        ];
+        let argv = vec![
+            "/usr/bin/python3.12".to_string(),
+            "-m".to_string(),
+            "serena.cli".to_string(),
+        ];
+        // flatpak-spawn takes the environment as arguments
+        let mut argv = Sandbox::Flatpak.wrap(argv, &env).into_iter();
+        let command = Command {
+            command: argv.next().unwrap(),
+            args: argv.collect(),
+            env,
+        };
+        let interpreters = [Interpreter {
+            path: "/usr/bin/python3.12".to_string(),
+            version: "3.12.1".to_string(),
+        }];
+        let report = Report {
+            host: Host {
+                os: Os::Linux,
+                arch: Arch::X86_64,
+                sandbox: Some(Sandbox::Flatpak),
+            },
+            python: "/usr/bin/python3.12",
+            python_version: Some("3.12.1".to_string()),
+            machine: Some("arm64"),
+            entry: &entry,
+            interpreters: &interpreters,
+            generic_candidates: &[GenericCandidate {
+                name: "python",
+                path: "/usr/bin/python".to_string(),
+                version: Some("3.9.6".to_string()),
+                verdict: Err("Python 3.9.6 is too old".to_string()),
+            }],
+            skip_conda_base: true,
+            config: &config,
+            command: &command,
+        };
+
+        let value = report.to_json(0);
+        assert!(!value.to_string().contains("sk-ant-secret"), "{}", value);
+        assert_eq!(
+            value["settings"]["api_keys"]["anthropic"],
+            secrets::REDACTED
+        );
+        assert_eq!(value["command"]["env"]["DO_NOT_TRACK"], "1");
+        assert_eq!(
+            value["command"]["args"][1],
+            "--env=ANTHROPIC_API_KEY=<redacted>"
+        );
+        assert_eq!(value["python"]["serena_version"], "0.1.4");
+        assert_eq!(value["interpreters"][0]["version"], "3.12.1");
+        assert_eq!(value["interpreters"][0]["conda_base"], false);
+        assert!(value["interpreters"][0]["note"].is_null());
+        assert_eq!(value["generic_candidates"][0]["version"], "3.9.6");
+        assert_eq!(value["generic_candidates"][0]["accepted"], false);
+        assert_eq!(value["host"]["os"], "Linux");
+        assert_eq!(value["python"]["matches_host"], false);
+        assert!(value["python"]["warning"]
+            .as_str()
+            .unwrap()
+            .contains("built for arm64 but this machine is x86_64"));
+        assert_eq!(value["generated_at"], "1970-01-01 00:00 UTC");
+    }
+}
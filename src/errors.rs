@@ -0,0 +1,171 @@
+//! How much context launch errors carry.
+//!
+//! Zed shows a failed launch's error as is. Some users want one line, others
+//! want everything needed to debug it, so the `error_detail` setting shapes
+//! the error before it is returned.
+
+use serde::Deserialize;
+
+use crate::pipeline::Progress;
+use crate::secrets;
+use python_discovery::probe_cache::VersionProbe;
+use python_discovery::Host;
+
+/// Environment variables that decide which interpreter is found.
+const ENVIRONMENT: [&str; 9] = [
+    "PATH",
+    "PATHEXT",
+    "VIRTUAL_ENV",
+    "CONDA_PREFIX",
+    "PYENV_ROOT",
+    "PYENV_VERSION",
+    "PYTHONHOME",
+    "PYTHONPATH",
+    "SERENA_HOME",
+];
+
+/// The `error_detail` setting.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ErrorDetail {
+    /// Only what went wrong, in one line
+    Brief,
+    /// The error with its advice and the candidates tried
+    #[default]
+    Normal,
+    /// Also the launch stage, every interpreter probe and the environment
+    Verbose,
+}
+
+/// What the extension knew when the launch failed, for verbose errors.
+pub(crate) struct Context<'a> {
+    pub host: Host,
+    pub progress: Option<&'a Progress>,
+    /// Interpreter probes, by candidate
+    pub probes: Vec<(&'a str, &'a VersionProbe)>,
+    pub lookup_env: &'a dyn Fn(&str) -> Option<String>,
+}
+
+impl Context<'_> {
+    fn render(&self) -> String {
+        let mut text = format!(
+            "Host: {:?} {:?}{}",
+            self.host.os,
+            self.host.arch,
+            self.host
+                .sandbox
+                .map(|sandbox| format!(" in {:?}", sandbox))
+                .unwrap_or_default()
+        );
+        if let Some(progress) = self.progress {
+            text.push_str(&format!("\nLaunch stage: {}", progress.stage));
+            if let Some(stage) = progress.resumed_at {
+                text.push_str(&format!(" (resumed at {})", stage));
+            }
+        }
+        if !self.probes.is_empty() {
+            text.push_str("\nInterpreter probes:");
+            for (candidate, probe) in &self.probes {
+                let outcome = match &probe.banner {
+                    Ok(banner) => banner.trim(),
+                    Err(err) => err,
+                };
+                text.push_str(&format!("\n- {} → {}: {}", candidate, probe.path, outcome));
+            }
+        }
+        text.push_str("\nEnvironment:");
+        for name in ENVIRONMENT {
+            let value = match (self.lookup_env)(name) {
+                Some(_) if secrets::is_secret_name(name) => secrets::REDACTED.to_string(),
+                Some(value) => value,
+                None => "(unset)".to_string(),
+            };
+            text.push_str(&format!("\n- {}={}", name, value));
+        }
+        text
+    }
+}
+
+/// Shapes a launch `error` for `detail`; `context` is only gathered when verbose.
+pub(crate) fn shape<'a>(
+    error: &str,
+    detail: ErrorDetail,
+    context: impl FnOnce() -> Context<'a>,
+) -> String {
+    match detail {
+        ErrorDetail::Brief => {
+            let line = error
+                .lines()
+                .map(str::trim)
+                .find(|line| !line.is_empty())
+                .unwrap_or(error);
+            // Lists of candidates and tool output follow the first colon
+            let summary = line
+                .split_once(": ")
+                .map_or(line, |(summary, _)| summary)
+                .trim_end_matches(['.', ':']);
+            format!("{}. Set \"error_detail\": \"normal\" for details.", summary)
+        }
+        ErrorDetail::Normal => error.to_string(),
+        ErrorDetail::Verbose => format!("{}\n\n{}", error, context().render()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use python_discovery::{Arch, Os};
+
+    fn context<'a>(probes: Vec<(&'a str, &'a VersionProbe)>) -> Context<'a> {
+        Context {
+            host: Host {
+                os: Os::Linux,
+                arch: Arch::X86_64,
+                sandbox: None,
+            },
+            progress: None,
+            probes,
+            lookup_env: &|name| (name == "PATH").then(|| "/usr/bin".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_shape() {
+        let error =
+            "Python 3.11 or 3.12 not found in any of these locations: python3.11, python3. \n\n\
+                     Serena requires Python 3.11 OR 3.12 (either version works).";
+        assert_eq!(
+            shape(error, ErrorDetail::Brief, || unreachable!()),
+            "Python 3.11 or 3.12 not found in any of these locations. \
+             Set \"error_detail\": \"normal\" for details."
+        );
+        assert_eq!(
+            shape(
+                "Python executable path cannot be empty",
+                ErrorDetail::Brief,
+                || unreachable!()
+            ),
+            "Python executable path cannot be empty. Set \"error_detail\": \"normal\" for details."
+        );
+        assert_eq!(shape(error, ErrorDetail::Normal, || unreachable!()), error);
+
+        let probe = VersionProbe {
+            path: "/usr/bin/python3".to_string(),
+            banner: Ok("Python 3.9.6\n".to_string()),
+            checked_at: 0,
+        };
+        let verbose = shape(error, ErrorDetail::Verbose, || {
+            context(vec![("python3", &probe)])
+        });
+        assert!(verbose.starts_with(error), "{}", verbose);
+        assert!(verbose.contains("\nHost: Linux X86_64\n"), "{}", verbose);
+        assert!(
+            verbose.contains("\n- python3 → /usr/bin/python3: Python 3.9.6\n"),
+            "{}",
+            verbose
+        );
+        assert!(verbose.contains("\n- PATH=/usr/bin\n"), "{}", verbose);
+        assert!(verbose.contains("\n- VIRTUAL_ENV=(unset)\n"), "{}", verbose);
+    }
+}
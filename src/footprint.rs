@@ -0,0 +1,85 @@
+//! Disk space taken by what the extension and Serena download.
+//!
+//! Walking a multi-gigabyte environment takes a while, so the sizes are kept
+//! in the extension state and measured again after [`MAX_AGE_SECS`].
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::cleanup;
+
+/// How long measured sizes are shown before they are measured again.
+pub(crate) const MAX_AGE_SECS: u64 = 60 * 60;
+
+/// One measured location.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Item {
+    /// What lives there, e.g. "Managed environments"
+    pub label: String,
+    pub paths: Vec<PathBuf>,
+    pub bytes: u64,
+}
+
+/// Sizes measured at one point in time.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Footprint {
+    /// Seconds since the Unix epoch
+    pub checked_at: u64,
+    pub items: Vec<Item>,
+}
+
+impl Footprint {
+    /// Measures each labelled group of paths; missing paths count as empty.
+    pub(crate) fn measure(groups: Vec<(&str, Vec<PathBuf>)>, now: u64) -> Self {
+        let items = groups
+            .into_iter()
+            .map(|(label, paths)| Item {
+                label: label.to_string(),
+                bytes: paths.iter().map(|path| cleanup::size(path)).sum(),
+                paths,
+            })
+            .collect();
+        Self {
+            checked_at: now,
+            items,
+        }
+    }
+
+    /// Whether these sizes are recent enough to show at `now`.
+    pub(crate) fn is_fresh(&self, now: u64) -> bool {
+        now.saturating_sub(self.checked_at) < MAX_AGE_SECS
+    }
+
+    /// Bytes across all items.
+    pub(crate) fn total(&self) -> u64 {
+        self.items.iter().map(|item| item.bytes).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_measure() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        std::fs::create_dir_all(dir.join("env/lib")).unwrap();
+        std::fs::write(dir.join("env/lib/a"), [0u8; 1000]).unwrap();
+        std::fs::write(dir.join("env/b"), [0u8; 24]).unwrap();
+
+        let footprint = Footprint::measure(
+            vec![
+                ("Managed environments", vec![dir.join("env")]),
+                ("Language servers", vec![dir.join("missing")]),
+            ],
+            100,
+        );
+        assert_eq!(footprint.items[0].bytes, 1024);
+        assert_eq!(footprint.items[1].bytes, 0);
+        assert_eq!(footprint.total(), 1024);
+        assert!(footprint.is_fresh(100 + MAX_AGE_SECS - 1));
+        assert!(!footprint.is_fresh(100 + MAX_AGE_SECS));
+    }
+}
@@ -0,0 +1,170 @@
+//! Files the extension generates for launches.
+//!
+//! Configuration overlays and similar artifacts can carry settings that
+//! shouldn't be readable by other users. They are kept below `generated/` in
+//! the extension work dir, in one subdirectory per project. Native Unix
+//! builds restrict directories and files to the user; WASI can't change
+//! permissions, so in the extension they inherit the work dir's. Files are
+//! written under a unique temporary name and renamed into place, so a
+//! concurrent launch never reads a partial file, and generations nobody has
+//! rewritten for [`MAX_AGE`] are removed.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime};
+
+use crate::instances;
+
+/// Directory under the extension work dir holding generated files.
+const GENERATED_DIR: &str = "generated";
+
+/// Subdirectory for launches without a project root.
+const NO_PROJECT_DIR: &str = "default";
+
+/// Files written by this instance, to tell its temporary names apart.
+static WRITES: AtomicU64 = AtomicU64::new(0);
+
+/// Generated files untouched for this long are stale.
+pub(crate) const MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Directory holding every project's generated files.
+pub(crate) fn root(work_dir: &Path) -> PathBuf {
+    work_dir.join(GENERATED_DIR)
+}
+
+/// Directory for the generated files of `project_root`.
+pub(crate) fn project_dir(work_dir: &Path, project_root: Option<&Path>) -> PathBuf {
+    root(work_dir).join(
+        project_root
+            .map(instances::project_key)
+            .unwrap_or_else(|| NO_PROJECT_DIR.to_string()),
+    )
+}
+
+/// Creates `dir` and its parents, restricting `dir` to the user where
+/// permissions can be set.
+pub(crate) fn create_dir(dir: &Path) -> Result<(), String> {
+    std::fs::create_dir_all(dir)
+        .and_then(|()| restrict(dir, 0o700))
+        .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))
+}
+
+/// Writes `contents` to `path`, readable only by the user where permissions
+/// can be set, replacing any previous file atomically.
+pub(crate) fn write_private(path: &Path, contents: &str) -> Result<(), String> {
+    // WASI has no process ids, so the time and a counter make the name unique
+    let nanos = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    let count = WRITES.fetch_add(1, Ordering::Relaxed);
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let temp = path.with_file_name(format!(".{}.{}.{}.tmp", name, nanos, count));
+    // The file is private from the start, not just once it's complete
+    let written = create_private(&temp)
+        .and_then(|mut file| file.write_all(contents.as_bytes()))
+        .and_then(|()| std::fs::rename(&temp, path));
+    if written.is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+    written.map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+/// Removes files in `dir` last modified more than `max_age` before `now`,
+/// returning how many were removed.
+pub(crate) fn prune(dir: &Path, now: SystemTime, max_age: Duration) -> usize {
+    std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| {
+            entry.metadata().is_ok_and(|metadata| {
+                metadata.is_file()
+                    && metadata
+                        .modified()
+                        .ok()
+                        .and_then(|modified| now.duration_since(modified).ok())
+                        .is_some_and(|age| age > max_age)
+            })
+        })
+        .filter(|entry| std::fs::remove_file(entry.path()).is_ok())
+        .count()
+}
+
+#[cfg(unix)]
+fn create_private(path: &Path) -> std::io::Result<std::fs::File> {
+    use std::os::unix::fs::OpenOptionsExt;
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)
+}
+
+#[cfg(not(unix))]
+fn create_private(path: &Path) -> std::io::Result<std::fs::File> {
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+}
+
+#[cfg(unix)]
+fn restrict(path: &Path, mode: u32) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+}
+
+/// Other platforms, WASI included, rely on the work dir's inherited
+/// permissions.
+#[cfg(not(unix))]
+fn restrict(_path: &Path, _mode: u32) -> std::io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_project_dir() {
+        let work_dir = Path::new("/work");
+        let app = project_dir(work_dir, Some(Path::new("/src/app")));
+        assert!(app.starts_with("/work/generated"));
+        assert_ne!(app, project_dir(work_dir, Some(Path::new("/src/other"))));
+        assert_eq!(
+            project_dir(work_dir, None),
+            Path::new("/work/generated/default")
+        );
+    }
+
+    #[test]
+    fn test_write_private_and_prune() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().join("project");
+        create_dir(&dir).unwrap();
+        let path = dir.join("overlay.yml");
+        write_private(&path, "a").unwrap();
+        write_private(&path, "b").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "b");
+        // No temporary files are left behind
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+            assert_eq!(mode(&dir), 0o700);
+            assert_eq!(mode(&path), 0o600);
+        }
+
+        let now = SystemTime::now();
+        assert_eq!(prune(&dir, now, MAX_AGE), 0);
+        assert_eq!(prune(&dir, now + MAX_AGE * 2, MAX_AGE), 1);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,97 @@
+//! Ignore patterns forwarded to Serena.
+//!
+//! Serena indexes every file in its project for symbol search. Dependencies,
+//! build output and generated code only slow that down, so the project's
+//! `.gitignore` and Zed's `file_scan_exclusions` are passed on as Serena's
+//! `ignored_paths`.
+
+use std::path::Path;
+
+use zed_extension_api::serde_json::Value;
+
+use crate::jsonc;
+
+/// Dependency and build directories ignored even when nothing lists them.
+const DEFAULT_PATTERNS: [&str; 6] = [
+    "node_modules",
+    "target",
+    "build",
+    "dist",
+    "__pycache__",
+    ".venv",
+];
+
+/// Zed settings file relative to the project root.
+const ZED_SETTINGS: &str = ".zed/settings.json";
+
+/// Collects the ignore patterns for the project at `root`.
+///
+/// Patterns keep their order and appear once. Missing or unreadable files
+/// contribute nothing.
+pub(crate) fn patterns(root: &Path) -> Vec<String> {
+    let gitignore = std::fs::read_to_string(root.join(".gitignore")).unwrap_or_default();
+    let zed_settings = std::fs::read_to_string(root.join(ZED_SETTINGS))
+        .ok()
+        .and_then(|contents| jsonc::from_str::<Value>(&contents).ok());
+    combine(&gitignore, zed_settings.as_ref())
+}
+
+/// Merges the defaults, `.gitignore` contents and Zed settings.
+fn combine(gitignore: &str, zed_settings: Option<&Value>) -> Vec<String> {
+    let exclusions = zed_settings
+        .and_then(|settings| settings.get("file_scan_exclusions"))
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str);
+    let mut patterns: Vec<String> = Vec::new();
+    for pattern in DEFAULT_PATTERNS
+        .into_iter()
+        .chain(gitignore_patterns(gitignore))
+        .chain(exclusions)
+    {
+        if !patterns.iter().any(|existing| existing == pattern) {
+            patterns.push(pattern.to_string());
+        }
+    }
+    patterns
+}
+
+/// Patterns from `.gitignore` contents.
+///
+/// Negations are dropped: an ignore list can't re-include files, and
+/// forwarding the rest still only ever ignores what git ignores.
+fn gitignore_patterns(contents: &str) -> impl Iterator<Item = &str> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with('!'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zed_extension_api::serde_json::json;
+
+    #[test]
+    fn test_combine() {
+        let gitignore = "# Build output\n/target\n*.log\n\n!keep.log\nnode_modules\n";
+        let zed = json!({ "file_scan_exclusions": ["**/.git", "**/generated"] });
+        assert_eq!(
+            combine(gitignore, Some(&zed)),
+            [
+                "node_modules",
+                "target",
+                "build",
+                "dist",
+                "__pycache__",
+                ".venv",
+                "/target",
+                "*.log",
+                "**/.git",
+                "**/generated"
+            ]
+        );
+        assert_eq!(combine("", None), DEFAULT_PATTERNS);
+    }
+}
@@ -0,0 +1,945 @@
+//! Checking for and installing serena-agent.
+//!
+//! The stable server runs serena-agent from PyPI in the user's interpreter.
+//! The nightly server runs upstream git main from a virtual environment the
+//! extension manages in its work dir, so the two can be compared side by side.
+//! On Windows, auto-install also uses a managed environment when the
+//! interpreter lives below a path pip-installed scripts choke on.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use python_discovery::probe_cache::ProbeCache;
+use python_discovery::process::{Invocation, Runner};
+use python_discovery::version::Version;
+use zed_extension_api::Os;
+
+use crate::process::{self, output_within, RunError};
+use crate::retry::{self, Failure};
+use crate::scripts::{self, FileSystem};
+
+/// Name of the Serena distribution on PyPI.
+pub const PACKAGE_NAME: &str = "serena-agent";
+
+/// Upstream repository the nightly channel installs from.
+pub const NIGHTLY_SOURCE: &str = "git+https://github.com/oraios/serena";
+
+/// Module `python -m` runs when no entry module can be detected.
+pub const DEFAULT_MODULE: &str = "serena";
+
+/// Entry modules serena-agent releases have shipped, in order of preference:
+/// the `serena` package itself, the documented CLI module, and the
+/// `serena_agent` package of some older releases.
+const ENTRY_MODULES: [&str; 3] = ["serena", "serena.cli", "serena_agent"];
+
+/// Prints the first entry module `python -m` can run, then the installed
+/// serena-agent version. Packages only count when they have a `__main__`
+/// submodule.
+const ENTRY_PROBE: &str = "\
+import importlib.util as u, sys
+def runnable(name):
+    try:
+        spec = u.find_spec(name)
+        if spec is not None and spec.submodule_search_locations is not None:
+            spec = u.find_spec(name + '.__main__')
+        return spec is not None
+    except Exception:
+        return False
+def version():
+    try:
+        import importlib.metadata as m
+        return m.version('serena-agent')
+    except Exception:
+        return ''
+print(next((name for name in sys.argv[1:] if runnable(name)), ''))
+print(version())
+";
+
+/// First serena-agent release whose `start-mcp-server` takes `--transport`.
+const TRANSPORT_FLAG_SINCE: &str = "0.1.0";
+
+/// Seconds a pip install may run when `install_timeout_seconds` is unset.
+pub const DEFAULT_INSTALL_TIMEOUT_SECS: u64 = 600;
+
+/// Seconds a check may run when `preflight_timeout_seconds` is unset.
+pub const DEFAULT_PREFLIGHT_TIMEOUT_SECS: u64 = 30;
+
+/// Context server ids ending in this suffix run the nightly channel.
+pub const NIGHTLY_SUFFIX: &str = "-nightly";
+
+/// Directory under the extension work dir holding managed environments.
+const ENVS_DIR: &str = "envs";
+
+/// Directory below `%ProgramData%` used when the work dir isn't safe for pip.
+const SAFE_ENVS_DIR: &str = "zed-serena";
+
+/// How long installs and preflight checks may run before they are abandoned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timeouts {
+    /// Limit for each pip run and virtual environment creation
+    pub install: Duration,
+    /// Limit for each check of an interpreter's serena
+    pub preflight: Duration,
+}
+
+impl Timeouts {
+    /// Builds the limits from the settings, using the defaults for unset or
+    /// zero values.
+    pub fn from_secs(install: Option<u64>, preflight: Option<u64>) -> Self {
+        let secs = |value: Option<u64>, default| {
+            Duration::from_secs(value.filter(|secs| *secs > 0).unwrap_or(default))
+        };
+        Self {
+            install: secs(install, DEFAULT_INSTALL_TIMEOUT_SECS),
+            preflight: secs(preflight, DEFAULT_PREFLIGHT_TIMEOUT_SECS),
+        }
+    }
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self::from_secs(None, None)
+    }
+}
+
+/// Where a project can pin the transitive dependencies of auto-installs.
+pub const PROJECT_CONSTRAINTS: &str = ".serena/constraints.txt";
+
+/// What auto-install puts into an environment, and how long it may take.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstallOptions {
+    /// Optional dependency groups of serena-agent, e.g. "agno"
+    pub extras: Vec<String>,
+    /// pip constraints file pinning dependency versions
+    pub constraints: Option<PathBuf>,
+    /// Install into the user site-packages (`pip install --user`)
+    pub user: bool,
+    /// Reinstall even when serena-agent is already installed
+    pub reinstall: bool,
+    /// Limit for each pip run and virtual environment creation
+    pub timeout: Duration,
+}
+
+/// Resolves a path setting: a leading `~` expands to `home` and relative
+/// paths resolve against `project_root`.
+pub fn resolve_path(path: &str, project_root: Option<&Path>, home: Option<&str>) -> PathBuf {
+    let expanded = match (path.strip_prefix('~'), home) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with(['/', '\\']) => {
+            PathBuf::from(format!("{}{}", home, rest))
+        }
+        _ => PathBuf::from(path),
+    };
+    match project_root {
+        Some(root) if expanded.is_relative() => root.join(expanded),
+        _ => expanded,
+    }
+}
+
+/// Resolves the pip constraints file for auto-installs.
+///
+/// `configured` may start with `~` and resolves against `project_root` when
+/// relative; it must exist. Without it, the project's
+/// [`PROJECT_CONSTRAINTS`] is used when present.
+pub fn constraints_file(
+    configured: Option<&str>,
+    project_root: Option<&Path>,
+    home: Option<&str>,
+    fs: &impl FileSystem,
+) -> Result<Option<PathBuf>, String> {
+    let Some(configured) = configured.map(str::trim).filter(|path| !path.is_empty()) else {
+        return Ok(project_root
+            .map(|root| root.join(PROJECT_CONSTRAINTS))
+            .filter(|path| fs.is_file(path)));
+    };
+    let path = resolve_path(configured, project_root, home);
+    if fs.is_file(&path) {
+        Ok(Some(path))
+    } else {
+        Err(format!("constraints_file {} does not exist", path.display()))
+    }
+}
+
+/// Checks that every entry of `extras` is a valid extra name (letters,
+/// digits, `-`, `_` and `.`), so none can smuggle in another requirement.
+pub fn validate_extras(extras: &[String]) -> Result<(), String> {
+    match extras.iter().find(|extra| {
+        extra.is_empty()
+            || !extra
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    }) {
+        Some(extra) => Err(format!(
+            "Invalid entry \"{}\" in extras: expected a name such as \"agno\"",
+            extra
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Which Serena build a context server runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    /// The latest serena-agent release from PyPI
+    Stable,
+    /// Serena from the upstream git main branch
+    Nightly,
+}
+
+impl Channel {
+    /// The channel a context server id is registered for.
+    pub fn for_server(id: &str) -> Self {
+        if id.ends_with(NIGHTLY_SUFFIX) {
+            Self::Nightly
+        } else {
+            Self::Stable
+        }
+    }
+
+    /// pip requirement installing this channel's Serena with the optional
+    /// dependency `extras`, e.g. `serena-agent[agno,google]`.
+    pub fn requirement(self, extras: &[String]) -> String {
+        let mut package = PACKAGE_NAME.to_string();
+        if !extras.is_empty() {
+            package.push_str(&format!("[{}]", extras.join(",")));
+        }
+        match self {
+            Self::Stable => package,
+            Self::Nightly => format!("{} @ {}", package, NIGHTLY_SOURCE),
+        }
+    }
+
+    /// Directory of this channel's managed environment below `envs_root`.
+    pub fn managed_env(self, envs_root: &Path) -> PathBuf {
+        envs_root.join(match self {
+            Self::Stable => "stable",
+            Self::Nightly => "nightly",
+        })
+    }
+}
+
+/// True when `path` has characters that break pip-installed console scripts
+/// on Windows, such as the space in `C:\Users\John Smith`.
+pub fn has_unsafe_chars(path: &str) -> bool {
+    path.chars().any(|c| c.is_whitespace() || !c.is_ascii())
+}
+
+/// Directory holding managed environments.
+///
+/// This is `envs` in the work dir, unless that lies below an unsafe path on
+/// Windows; then `%ProgramData%\zed-serena` is used instead.
+pub fn envs_root(work_dir: &Path, os: Os, lookup_env: impl Fn(&str) -> Option<String>) -> PathBuf {
+    if os == Os::Windows && has_unsafe_chars(&work_dir.to_string_lossy()) {
+        let program_data = lookup_env("ProgramData")
+            .filter(|dir| !has_unsafe_chars(dir))
+            .unwrap_or_else(|| "C:\\ProgramData".to_string());
+        return PathBuf::from(format!("{}\\{}", program_data, SAFE_ENVS_DIR));
+    }
+    work_dir.join(ENVS_DIR)
+}
+
+/// Checks that files can be created in `dir`, creating it if needed.
+pub fn check_writable(dir: &Path) -> Result<(), String> {
+    let probe = dir.join(".zed-serena-write-test");
+    std::fs::create_dir_all(dir)
+        .and_then(|_| std::fs::write(&probe, b""))
+        .and_then(|_| std::fs::remove_file(&probe))
+        .map_err(|e| format!("Cannot write to {}: {}", dir.display(), e))
+}
+
+/// Interpreter inside the virtual environment at `env_dir`.
+pub fn env_python(env_dir: &Path, os: Os) -> PathBuf {
+    match os {
+        Os::Windows => env_dir.join("Scripts").join("python.exe"),
+        Os::Mac | Os::Linux => env_dir.join("bin").join("python"),
+    }
+}
+
+/// Creates the managed environment at `env_dir` from `base_python` and
+/// installs `channel` into it with `runner`, unless it already exists.
+///
+/// Returns the environment's interpreter. Delete the directory to pick up a
+/// newer upstream build. Installs retried after network errors are described
+/// in `retries`.
+pub fn ensure_managed_env(
+    base_python: &str,
+    env_dir: &Path,
+    os: Os,
+    channel: Channel,
+    options: &InstallOptions,
+    runner: &dyn Runner,
+    retries: &mut Vec<String>,
+) -> Result<String, String> {
+    let python = env_python(env_dir, os);
+    let python_str = python.to_string_lossy().to_string();
+    if python.is_file() {
+        return Ok(python_str);
+    }
+    check_writable(env_dir)?;
+
+    let output = output_within(
+        runner,
+        base_python,
+        Invocation::new(base_python).args([
+            "-m".to_string(),
+            "venv".to_string(),
+            env_dir.to_string_lossy().to_string(),
+        ]),
+        options.timeout,
+    )
+    .map_err(|e| {
+        format!(
+            "Failed to create {}: {}{}",
+            env_dir.display(),
+            e,
+            timeout_hint(&e, "install_timeout_seconds")
+        )
+    })?;
+    if !output.success() {
+        return Err(format!(
+            "Failed to create {}: {}",
+            env_dir.display(),
+            process::failure(&output)
+        ));
+    }
+
+    let installed = pip_install(&python_str, channel, options, runner, retries).and_then(|started| {
+        started
+            .then_some(())
+            .ok_or_else(|| "Failed to install Serena: pip could not be started".to_string())
+    });
+    if let Err(e) = installed {
+        // Leave no half-installed environment behind for the next attempt
+        let _ = std::fs::remove_dir_all(env_dir);
+        return Err(e);
+    }
+    Ok(python_str)
+}
+
+/// Tool running an install.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Installer {
+    /// `uv pip`, much faster than pip when it is on `PATH`
+    Uv,
+    /// The interpreter's own pip
+    Pip,
+}
+
+impl Installer {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Uv => "uv",
+            Self::Pip => "pip",
+        }
+    }
+}
+
+/// The run installing `channel` into `python`'s environment with `installer`.
+fn install_command(
+    installer: Installer,
+    python: &str,
+    channel: Channel,
+    options: &InstallOptions,
+) -> Invocation {
+    let (program, command) = match installer {
+        Installer::Uv => (
+            "uv".to_string(),
+            vec!["pip", "install", "--python", python],
+        ),
+        Installer::Pip => (python.to_string(), vec!["-m", "pip", "install"]),
+    };
+    let mut args: Vec<String> = command.into_iter().map(String::from).collect();
+    if options.user {
+        args.push("--user".to_string());
+    }
+    if options.reinstall {
+        args.push(
+            match installer {
+                Installer::Uv => "--reinstall",
+                Installer::Pip => "--force-reinstall",
+            }
+            .to_string(),
+        );
+    }
+    if let Some(constraints) = &options.constraints {
+        args.push("-c".to_string());
+        args.push(constraints.to_string_lossy().to_string());
+    }
+    args.push(channel.requirement(&options.extras));
+    Invocation::new(program).args(args)
+}
+
+/// Installs `channel` into `python`'s environment, retrying network failures.
+///
+/// Uses `uv pip` when uv is on `PATH` and `python -m pip` otherwise; user
+/// installs always use pip, since uv has no user scheme. Returns `Ok(false)`
+/// when neither could be started. A run that overruns the timeout is not
+/// retried; the others are described in `retries`.
+fn pip_install(
+    python: &str,
+    channel: Channel,
+    options: &InstallOptions,
+    runner: &dyn Runner,
+    retries: &mut Vec<String>,
+) -> Result<bool, String> {
+    let mut installer = if options.user {
+        Installer::Pip
+    } else {
+        Installer::Uv
+    };
+    retry::run(
+        "Failed to install Serena",
+        retry::INSTALL,
+        retries,
+        retry::sleep,
+        || {
+        let run = |installer| {
+            let invocation = install_command(installer, python, channel, options);
+            output_within(runner, python, invocation, options.timeout)
+        };
+        let output = match run(installer) {
+            Err(RunError::Start(_)) if installer == Installer::Uv => {
+                installer = Installer::Pip;
+                run(installer)
+            }
+            output => output,
+        };
+        match output {
+            Ok(output) if output.success() => Ok(true),
+            Ok(output) => Err(Failure::from_pip_run(&output)),
+            Err(e @ RunError::TimedOut(_)) => Err(Failure::Permanent(format!(
+                "{} {}{}",
+                installer.name(),
+                e,
+                timeout_hint(&e, "install_timeout_seconds")
+            ))),
+            Err(_) => Ok(false),
+        }
+    })
+}
+
+/// Points at the setting to raise when `error` is a timeout.
+fn timeout_hint(error: &RunError, setting: &str) -> String {
+    if matches!(error, RunError::TimedOut(_)) {
+        format!("; raise {} to wait longer", setting)
+    } else {
+        String::new()
+    }
+}
+
+/// Error for a check of `python` that overran `preflight_timeout_seconds`.
+fn preflight_timed_out(check: &str, python: &str, error: &RunError) -> String {
+    format!(
+        "{} with {} {}{}",
+        check,
+        python,
+        error,
+        timeout_hint(error, "preflight_timeout_seconds")
+    )
+}
+
+/// Reports the installed serena-agent version for an interpreter.
+pub fn serena_version(python: &str, timeout: Duration, runner: &dyn Runner) -> Option<String> {
+    let output = output_within(
+        runner,
+        python,
+        Invocation::new(python).args([
+            "-c",
+            "import importlib.metadata as m; print(m.version('serena-agent'))",
+        ]),
+        timeout,
+    )
+    .ok()?;
+    let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.success() && !version.is_empty()).then_some(version)
+}
+
+/// How to launch the serena installed for `python_exe`, consulting the probe
+/// cache.
+///
+/// The module falls back to [`DEFAULT_MODULE`] when none is found or the
+/// probe can't run. A probe overrunning `timeout` is an error.
+pub fn serena_entry(
+    python_exe: &str,
+    cache: &mut ProbeCache,
+    timeout: Duration,
+    runner: &dyn Runner,
+) -> Result<Entry, String> {
+    let probe = cache.serena_entry(python_exe, || probe_entry(python_exe, timeout, runner))?;
+    Ok(Entry {
+        module: probe.module.unwrap_or_else(|| DEFAULT_MODULE.to_string()),
+        version: probe.version,
+    })
+}
+
+/// The entry module and version of an installed serena.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// Module run with `python -m`
+    pub module: String,
+    /// Installed serena-agent version, if known
+    pub version: Option<String>,
+}
+
+/// Asks `python_exe` which of the known entry modules it can run, and which
+/// serena-agent version it has.
+///
+/// Only a probe overrunning `timeout` is an error.
+pub fn probe_entry(
+    python_exe: &str,
+    timeout: Duration,
+    runner: &dyn Runner,
+) -> Result<(Option<String>, Option<String>), String> {
+    match output_within(
+        runner,
+        python_exe,
+        Invocation::new(python_exe)
+            .args(["-c", ENTRY_PROBE])
+            .args(ENTRY_MODULES),
+        timeout,
+    ) {
+        Ok(output) if output.success() => Ok(parse_entry(&String::from_utf8_lossy(&output.stdout))),
+        Err(e @ RunError::TimedOut(_)) => Err(preflight_timed_out(
+            "Looking up the serena entry module",
+            python_exe,
+            &e,
+        )),
+        _ => Ok((None, None)),
+    }
+}
+
+/// Reads the probe output, accepting only the modules that were asked about
+/// and version-like strings.
+fn parse_entry(stdout: &str) -> (Option<String>, Option<String>) {
+    let mut lines = stdout.lines().map(str::trim);
+    let module = lines
+        .next()
+        .filter(|module| ENTRY_MODULES.contains(module))
+        .map(str::to_string);
+    let version = lines
+        .next()
+        .filter(|version| version.starts_with(|c: char| c.is_ascii_digit()))
+        .map(str::to_string);
+    (module, version)
+}
+
+/// True unless `version` is known to predate the `--transport` flag.
+pub fn supports_transport_flag(version: Option<&str>) -> bool {
+    match (
+        version.and_then(Version::parse),
+        Version::parse(TRANSPORT_FLAG_SINCE),
+    ) {
+        (Some(installed), Some(since)) => installed >= since,
+        _ => true,
+    }
+}
+
+/// Checks for serena with `python_exe`, consulting the probe cache.
+///
+/// The environment is inspected on disk first; importing serena pulls in
+/// heavy dependencies and can take seconds; an import overrunning `timeout`
+/// is an error.
+pub fn serena_installed(
+    python_exe: &str,
+    cache: &mut ProbeCache,
+    timeout: Duration,
+    runner: &dyn Runner,
+) -> Result<bool, String> {
+    cache.serena_installed(python_exe, || {
+        let python = Path::new(python_exe);
+        match serena_on_disk(python, &scripts::RealFs(os_of(python))) {
+            Some(installed) => Ok(installed),
+            None => is_serena_installed(python_exe, timeout, runner),
+        }
+    })
+}
+
+/// Looks for serena-agent in `python`'s environment without starting Python.
+///
+/// Only answers when the layout leaves no doubt: a `serena_agent` dist-info
+/// in the interpreter's own site-packages, or a `serena` script inside a
+/// virtual environment. Returns `None` otherwise, e.g. for user-site installs
+/// or an unversioned system `python3` next to several `lib/python3.X`.
+pub fn serena_on_disk(python: &Path, fs: &impl FileSystem) -> Option<bool> {
+    let bin = python.parent()?;
+    let prefix = bin.parent()?;
+
+    // Scripts in a venv's bin directory can only belong to its interpreter
+    let in_venv = fs.is_file(&prefix.join("pyvenv.cfg"));
+    let script = scripts::find_script(scripts::DEFAULT_SCRIPT, python, os_of(python), None, fs);
+    if in_venv && script.is_some() {
+        return Some(true);
+    }
+
+    let installed = site_packages(python, fs).iter().any(|dir| {
+        fs.list_dir(dir).iter().any(|name| {
+            name.to_lowercase().starts_with("serena_agent-") && name.ends_with(".dist-info")
+        })
+    });
+    installed.then_some(true)
+}
+
+/// Platform whose layout `python` follows, judged by its file name.
+fn os_of(python: &Path) -> Os {
+    match python.extension() {
+        Some(extension) if extension.eq_ignore_ascii_case("exe") => Os::Windows,
+        _ => Os::Linux,
+    }
+}
+
+/// site-packages directories belonging to `python`, where they can be told
+/// apart from other interpreters sharing the prefix.
+fn site_packages(python: &Path, fs: &impl FileSystem) -> Vec<PathBuf> {
+    let Some(bin) = python.parent() else {
+        return Vec::new();
+    };
+    // Windows base installs keep python.exe in the prefix, venvs in Scripts\
+    let prefixes = [Some(bin), bin.parent()];
+    let name = python
+        .file_name()
+        .map(|name| name.to_string_lossy().trim_end_matches(".exe").to_string())
+        .unwrap_or_default();
+    let mut dirs = Vec::new();
+    for prefix in prefixes.into_iter().flatten() {
+        dirs.push(prefix.join("Lib").join("site-packages"));
+
+        let lib = prefix.join("lib");
+        let versioned: Vec<String> = fs
+            .list_dir(&lib)
+            .into_iter()
+            .filter(|name| name.starts_with("python3."))
+            .collect();
+        let version = match versioned.as_slice() {
+            // A versioned interpreter name picks its own directory
+            _ if versioned.contains(&name) => Some(name.clone()),
+            [only] => Some(only.clone()),
+            _ => None,
+        };
+        if let Some(version) = version {
+            dirs.push(lib.join(version).join("site-packages"));
+        }
+    }
+    dirs
+}
+
+/// Checks whether `python_exe` can import serena.
+///
+/// Reports `true` when the check itself can't run, so restricted
+/// environments fall through to launching. Overrunning `timeout` is an error.
+pub fn is_serena_installed(
+    python_exe: &str,
+    timeout: Duration,
+    runner: &dyn Runner,
+) -> Result<bool, String> {
+    match output_within(
+        runner,
+        python_exe,
+        Invocation::new(python_exe).args(["-c", "import serena; print('installed')"]),
+        timeout,
+    ) {
+        Ok(output) => Ok(output.success()),
+        Err(e @ RunError::TimedOut(_)) => Err(preflight_timed_out(
+            "Checking for serena",
+            python_exe,
+            &e,
+        )),
+        Err(_) => {
+            // If we can't check, assume it's installed and let it fail later if not
+            // This handles restricted environments where process spawning is limited
+            Ok(true)
+        }
+    }
+}
+
+/// Installs serena-agent into `python_exe`'s environment with pip.
+///
+/// Network failures are retried with backoff and described in `retries`, and
+/// each run is stopped after the timeout in `options`. Only a failed pip run
+/// is an error; being unable to start pip is ignored so restricted
+/// environments fall through to launching.
+pub fn install_serena(
+    python_exe: &str,
+    options: &InstallOptions,
+    runner: &dyn Runner,
+    retries: &mut Vec<String>,
+) -> Result<(), String> {
+    // If pip can't start, just continue and hope serena is already installed.
+    // This handles restricted environments
+    pip_install(python_exe, Channel::Stable, options, runner, retries).map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_package_name_constant() {
+        assert_eq!(PACKAGE_NAME, "serena-agent");
+    }
+
+    #[test]
+    fn test_parse_entry() {
+        assert_eq!(
+            parse_entry("serena.cli\n0.1.4\n"),
+            (Some("serena.cli".to_string()), Some("0.1.4".to_string()))
+        );
+        assert_eq!(parse_entry("serena_agent\n\n"), (Some("serena_agent".to_string()), None));
+        assert_eq!(parse_entry("\n"), (None, None));
+        // Stray output from sitecustomize and the like is not a module name
+        assert_eq!(parse_entry("os; import evil\nnot a version"), (None, None));
+    }
+
+    /// A filesystem holding only the listed files.
+    struct VirtualFs(Vec<PathBuf>);
+
+    impl FileSystem for VirtualFs {
+        fn is_file(&self, path: &Path) -> bool {
+            self.0.iter().any(|file| file == path)
+        }
+
+        fn list_dir(&self, path: &Path) -> Vec<String> {
+            scripts::list_children(&self.0, path)
+        }
+    }
+
+    fn fs(files: &[&str]) -> VirtualFs {
+        VirtualFs(files.iter().map(PathBuf::from).collect())
+    }
+
+    #[test]
+    fn test_constraints_file() {
+        let root = Path::new("/src/app");
+        let fs = VirtualFs(vec![
+            PathBuf::from("/src/app/.serena/constraints.txt"),
+            PathBuf::from("/src/app/pins.txt"),
+            PathBuf::from("/home/me/pins.txt"),
+        ]);
+        let resolve = |configured| constraints_file(configured, Some(root), Some("/home/me"), &fs);
+
+        // The project's checked-in file is picked up without configuration
+        assert_eq!(
+            resolve(None),
+            Ok(Some(PathBuf::from("/src/app/.serena/constraints.txt")))
+        );
+        assert_eq!(
+            constraints_file(None, Some(Path::new("/src/other")), None, &fs),
+            Ok(None)
+        );
+        assert_eq!(
+            resolve(Some("pins.txt")),
+            Ok(Some(PathBuf::from("/src/app/pins.txt")))
+        );
+        assert_eq!(
+            resolve(Some("~/pins.txt")),
+            Ok(Some(PathBuf::from("/home/me/pins.txt")))
+        );
+        assert!(resolve(Some("missing.txt")).unwrap_err().contains("/src/app/missing.txt"));
+    }
+
+    #[test]
+    fn test_check_writable() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().join("env");
+        assert_eq!(check_writable(&dir), Ok(()));
+        assert!(dir.is_dir());
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+
+        // A file where a directory is needed can't hold an environment
+        let file = temp.path().join("not-a-dir");
+        std::fs::write(&file, b"").unwrap();
+        assert!(check_writable(&file.join("env")).is_err());
+    }
+
+    #[test]
+    fn test_install_command() {
+        let mut options = InstallOptions {
+            extras: vec!["agno".to_string()],
+            constraints: None,
+            user: false,
+            reinstall: false,
+            timeout: Duration::from_secs(1),
+        };
+        let python = "/usr/bin/python3.12";
+        assert_eq!(
+            install_command(Installer::Pip, python, Channel::Stable, &options),
+            Invocation::new(python).args(["-m", "pip", "install", "serena-agent[agno]"])
+        );
+        let user = InstallOptions {
+            user: true,
+            ..options.clone()
+        };
+        assert_eq!(
+            install_command(Installer::Pip, python, Channel::Stable, &user).args,
+            ["-m", "pip", "install", "--user", "serena-agent[agno]"]
+        );
+        let reinstall = InstallOptions {
+            reinstall: true,
+            ..options.clone()
+        };
+        assert_eq!(
+            install_command(Installer::Pip, python, Channel::Stable, &reinstall).args,
+            ["-m", "pip", "install", "--force-reinstall", "serena-agent[agno]"]
+        );
+        assert_eq!(
+            install_command(Installer::Uv, python, Channel::Stable, &reinstall).args[4],
+            "--reinstall"
+        );
+        options.constraints = Some(PathBuf::from("/src/app/.serena/constraints.txt"));
+        assert_eq!(
+            install_command(Installer::Uv, python, Channel::Stable, &options),
+            Invocation::new("uv").args([
+                "pip",
+                "install",
+                "--python",
+                python,
+                "-c",
+                "/src/app/.serena/constraints.txt",
+                "serena-agent[agno]"
+            ])
+        );
+    }
+
+    #[test]
+    fn test_serena_on_disk() {
+        let dist_info = "serena_agent-0.1.4.dist-info/METADATA";
+
+        // Virtual environments are decided by their script or site-packages
+        let venv = Path::new("/src/app/.venv/bin/python");
+        let script = fs(&["/src/app/.venv/pyvenv.cfg", "/src/app/.venv/bin/serena"]);
+        assert_eq!(serena_on_disk(venv, &script), Some(true));
+        let site = format!("/src/app/.venv/lib/python3.12/site-packages/{}", dist_info);
+        let venv_fs = fs(&["/src/app/.venv/pyvenv.cfg", &site]);
+        assert_eq!(serena_on_disk(venv, &venv_fs), Some(true));
+
+        // A versioned system interpreter only looks at its own directory
+        let system = fs(&[
+            &format!("/usr/lib/python3.11/site-packages/{}", dist_info),
+            "/usr/lib/python3.12/site-packages/six.py",
+        ]);
+        assert_eq!(serena_on_disk(Path::new("/usr/bin/python3.11"), &system), Some(true));
+        assert_eq!(serena_on_disk(Path::new("/usr/bin/python3.12"), &system), None);
+        // An unversioned one is ambiguous between them
+        assert_eq!(serena_on_disk(Path::new("/usr/bin/python3"), &system), None);
+
+        // A shared bin directory doesn't prove anything outside a venv
+        let shared = fs(&["/usr/local/bin/serena"]);
+        assert_eq!(serena_on_disk(Path::new("/usr/local/bin/python3.12"), &shared), None);
+
+        let windows = fs(&[&format!("C:/Python312/Lib/site-packages/{}", dist_info)]);
+        assert_eq!(
+            serena_on_disk(Path::new("C:/Python312/python.exe"), &windows),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn test_timeouts() {
+        let timeouts = Timeouts::from_secs(Some(120), Some(0));
+        assert_eq!(timeouts.install, Duration::from_secs(120));
+        assert_eq!(
+            timeouts.preflight,
+            Duration::from_secs(DEFAULT_PREFLIGHT_TIMEOUT_SECS)
+        );
+        assert_eq!(
+            Timeouts::default().install,
+            Duration::from_secs(DEFAULT_INSTALL_TIMEOUT_SECS)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_hanging_check_times_out() {
+        use python_discovery::process::StdRunner;
+        use std::os::unix::fs::PermissionsExt;
+
+        // An interpreter that starts, but hangs on whatever it is asked to run
+        let temp = tempfile::tempdir().unwrap();
+        let python = temp.path().join("python");
+        std::fs::write(
+            &python,
+            "#!/bin/sh\ncase \"$*\" in *serena-deadline*) exec python3 \"$@\";; esac\nsleep 10\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&python, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let python = python.to_string_lossy().to_string();
+
+        let timeout = Duration::from_millis(200);
+        let error = is_serena_installed(&python, timeout, &StdRunner).unwrap_err();
+        assert!(error.starts_with("Checking for serena with "));
+        assert!(
+            error.ends_with("timed out after 200ms; raise preflight_timeout_seconds to wait longer"),
+            "{}",
+            error
+        );
+        assert!(probe_entry(&python, timeout, &StdRunner).is_err());
+    }
+
+    #[test]
+    fn test_supports_transport_flag() {
+        assert!(supports_transport_flag(Some("0.1.4")));
+        assert!(supports_transport_flag(Some("1.0.0rc1")));
+        assert!(!supports_transport_flag(Some("0.0.9")));
+        // Unknown versions are assumed to be recent
+        assert!(supports_transport_flag(None));
+        assert!(supports_transport_flag(Some("main")));
+    }
+
+    #[test]
+    fn test_channels() {
+        assert_eq!(Channel::for_server("serena-context-server"), Channel::Stable);
+        assert_eq!(Channel::for_server("serena"), Channel::Stable);
+        assert_eq!(
+            Channel::for_server("serena-context-server-nightly"),
+            Channel::Nightly
+        );
+
+        assert_eq!(Channel::Stable.requirement(&[]), "serena-agent");
+        assert_eq!(
+            Channel::Nightly.requirement(&[]),
+            "serena-agent @ git+https://github.com/oraios/serena"
+        );
+        let extras = ["agno".to_string(), "google".to_string()];
+        assert_eq!(
+            Channel::Stable.requirement(&extras),
+            "serena-agent[agno,google]"
+        );
+        assert_eq!(
+            Channel::Nightly.requirement(&extras),
+            "serena-agent[agno,google] @ git+https://github.com/oraios/serena"
+        );
+        assert!(validate_extras(&extras).is_ok());
+        assert!(validate_extras(&["agno]; rm".to_string()]).is_err());
+        assert!(validate_extras(&[String::new()]).is_err());
+
+        let root = envs_root(Path::new("/work"), Os::Linux, |_| None);
+        assert_eq!(Channel::Stable.managed_env(&root), Path::new("/work/envs/stable"));
+        let env = Channel::Nightly.managed_env(&root);
+        assert_eq!(env, Path::new("/work/envs/nightly"));
+        assert_eq!(
+            env_python(&env, Os::Linux),
+            Path::new("/work/envs/nightly/bin/python")
+        );
+        assert!(env_python(&env, Os::Windows).ends_with("Scripts/python.exe"));
+    }
+
+    #[test]
+    fn test_envs_root_avoids_unsafe_paths_on_windows() {
+        assert!(has_unsafe_chars("C:\\Users\\John Smith\\python.exe"));
+        assert!(has_unsafe_chars("C:\\Users\\Jürgen\\python.exe"));
+        assert!(!has_unsafe_chars("C:\\Users\\jsmith\\python.exe"));
+
+        let program_data = |name: &str| (name == "ProgramData").then(|| "D:\\PD".to_string());
+        let unsafe_dir = Path::new("C:\\Users\\John Smith\\AppData\\Local\\Zed\\work");
+        assert_eq!(
+            envs_root(unsafe_dir, Os::Windows, program_data),
+            PathBuf::from("D:\\PD\\zed-serena")
+        );
+        assert_eq!(
+            envs_root(unsafe_dir, Os::Windows, |_| None),
+            PathBuf::from("C:\\ProgramData\\zed-serena")
+        );
+
+        // Only Windows needs the detour
+        let root = envs_root(Path::new("/home/John Smith/work"), Os::Linux, program_data);
+        assert_eq!(root, Path::new("/home/John Smith/work/envs"));
+    }
+}
@@ -0,0 +1,478 @@
+//! Sharing one Serena instance per project across Zed windows.
+//!
+//! Each Serena instance boots its own language servers, so opening the same
+//! worktree in several windows can cost gigabytes. With `share_instance`
+//! enabled, the first window starts Serena with the SSE transport and records
+//! it in a registry in the extension work dir; every window (including the
+//! first) talks to it through a stdio-to-SSE bridge.
+//!
+//! The registry has one tab-separated line per project: key, port, pid,
+//! start time and project root. Without a configured port, a free one is
+//! picked when the instance starts, preferring a port derived from the
+//! project root and skipping ports registered by other live instances. If
+//! Serena still loses the port to another process, the next attempt takes
+//! any free port.
+//!
+//! Liveness is checked by the generated wrapper at launch time, since the
+//! extension itself cannot inspect processes from inside WASM. The wrapper
+//! drops entries of exited instances whenever it registers a new one.
+//!
+//! Every window's bridge also records its pid in a clients file. An instance
+//! whose project has no live bridge left, e.g. after Zed crashed, is stale;
+//! with `reap_stale` the wrapper terminates such instances before it starts.
+
+use std::path::{Path, PathBuf};
+
+use crate::shell;
+
+/// Directory under the extension work dir holding the instance registry.
+const INSTANCES_DIR: &str = "instances";
+
+/// File in [`INSTANCES_DIR`] listing the running shared instances.
+const REGISTRY_FILE: &str = "registry.tsv";
+
+/// File in [`INSTANCES_DIR`] listing the bridges connected to each instance.
+const CLIENTS_FILE: &str = "clients.tsv";
+
+/// Directory under the extension work dir holding per-project Serena homes.
+const HOMES_DIR: &str = "homes";
+
+/// First port of the range used for automatically assigned instance ports.
+const PORT_RANGE_START: u16 = 24000;
+/// Number of ports in the automatically assigned range.
+const PORT_RANGE_LEN: u16 = 4000;
+
+/// Seconds the wrapper waits for a freshly started instance to accept connections.
+const STARTUP_TIMEOUT_SECS: u32 = 30;
+
+/// Attempts at starting an instance on an automatically picked port.
+const PORT_ATTEMPTS: u32 = 3;
+
+/// Prints the preferred port given as the first argument if it is free and
+/// not among the taken ports that follow, and any such free port otherwise.
+const FREE_PORT: &str = "\
+import socket, sys
+taken = {int(port) for port in sys.argv[2:] if port.isdigit()}
+def bind(port):
+    with socket.socket() as s:
+        try:
+            s.bind(('127.0.0.1', port))
+            return s.getsockname()[1]
+        except OSError:
+            return None
+preferred = int(sys.argv[1])
+port = preferred not in taken and bind(preferred) or bind(0)
+while port in taken:
+    port = bind(0)
+print(port)
+";
+
+/// Exits successfully once the port given as the argument accepts connections.
+const PORT_PROBE: &str = "import socket, sys; socket.create_connection(('127.0.0.1', int(sys.argv[1])), 1)";
+
+/// Location of a shared Serena instance for one project.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SharedInstance {
+    /// Configured port, or `None` to pick a free one when the instance starts
+    pub port: Option<u16>,
+    /// Port tried first when picking one, derived from the project root
+    pub preferred_port: u16,
+    /// Identifies the project in the registry
+    pub key: String,
+    /// Project root, recorded for people reading the registry
+    pub root: PathBuf,
+    /// The registry file
+    pub registry: PathBuf,
+    /// Terminate other projects' instances that no window uses anymore
+    pub reap_stale: bool,
+}
+
+/// A shared instance as recorded in the registry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegistryEntry {
+    /// Identifies the project, see [`project_key`]
+    pub key: String,
+    /// Port the instance serves SSE on
+    pub port: u16,
+    /// Process id of the instance
+    pub pid: u32,
+    /// Seconds since the Unix epoch
+    pub started_at: u64,
+    /// Project root the instance serves
+    pub root: PathBuf,
+}
+
+impl SharedInstance {
+    /// Resolves the shared instance for a project root.
+    ///
+    /// A port of 0 counts as unset. The preferred port is derived from the
+    /// project root, so every window tries the same port for the same project.
+    pub fn for_project(root: &Path, port: Option<u16>, work_dir: &Path) -> Self {
+        let key = project_key(root);
+        Self {
+            port: port.filter(|port| *port != 0),
+            preferred_port: derived_port(&key),
+            key,
+            root: root.to_path_buf(),
+            registry: registry_path(work_dir),
+            reap_stale: false,
+        }
+    }
+
+    /// SSE endpoint served on `port`, which may be a shell expansion.
+    pub fn sse_url(port: &str) -> String {
+        format!("http://127.0.0.1:{}/sse", port)
+    }
+
+    /// Generates a POSIX shell script that reuses or starts the instance and
+    /// then bridges Zed's stdio to it.
+    ///
+    /// `server` is the full Serena command including `--transport sse`, and
+    /// `bridge` the stdio-to-SSE bridge command; the script appends the port
+    /// and the SSE URL respectively. `python` picks free ports and polls the
+    /// port during startup.
+    pub fn wrapper_script(
+        &self,
+        server: &[String],
+        bridge: &[String],
+        python: &str,
+    ) -> String {
+        let registry = shell::quote(&self.registry.to_string_lossy());
+        let registry_dir = shell::quote(
+            &self
+                .registry
+                .parent()
+                .map(|p| p.to_string_lossy().to_string())
+                .unwrap_or_default(),
+        );
+        // Tabs and newlines in the root would break the line format
+        let root: String = self
+            .root
+            .to_string_lossy()
+            .chars()
+            .map(|c| if c == '\t' || c == '\n' { ' ' } else { c })
+            .collect();
+        let probe = format!("{} \"$port\"", shell::join(&[python, "-c", PORT_PROBE]));
+        let attempts = STARTUP_TIMEOUT_SECS * 2;
+        let wait = format!(
+            "i=0; until {probe} 2>/dev/null || ! kill -0 $pid 2>/dev/null || [ $i -ge {attempts} ]; do sleep 0.5; i=$((i+1)); done",
+            probe = probe,
+            attempts = attempts,
+        );
+        // Lines of other projects whose instance is still running
+        let others = "while IFS=\"$tab\" read -r k p i s r; do \
+             [ \"$k\" != \"$key\" ] && kill -0 \"$i\" 2>/dev/null && \
+             printf '%s\\t%s\\t%s\\t%s\\t%s\\n' \"$k\" \"$p\" \"$i\" \"$s\" \"$r\"; \
+             done < \"$registry\"";
+
+        // This window's bridge keeps the shell's pid once it is exec'd, so
+        // it counts as a client from here on; exited clients are dropped
+        let clients = format!(
+            "clients={clients}; touch \"$clients\"\n\
+             {{ while IFS=\"$tab\" read -r k c; do kill -0 \"$c\" 2>/dev/null && printf '%s\\t%s\\n' \"$k\" \"$c\"; done < \"$clients\"; \
+             printf '%s\\t%s\\n' \"$key\" \"$$\"; }} > \"$clients.$$\" && mv \"$clients.$$\" \"$clients\"\n",
+            clients = shell::quote(
+                &self
+                    .registry
+                    .with_file_name(CLIENTS_FILE)
+                    .to_string_lossy()
+            ),
+        );
+        // Other projects' live instances without a live client are stale
+        let reap = if self.reap_stale {
+            "while IFS=\"$tab\" read -r k p i s r; do \
+             if [ \"$k\" != \"$key\" ] && kill -0 \"$i\" 2>/dev/null && \
+             ! cut -f1 \"$clients\" | grep -qx \"$k\"; then kill \"$i\"; fi; \
+             done < \"$registry\"\n"
+        } else {
+            ""
+        };
+
+        // A configured port is used as is; otherwise pick one and retry on
+        // any free port if Serena exits before it listens
+        let (reuse_port, pick_port, retries) = match self.port {
+            Some(port) => (format!("port={}", port), format!("port={}", port), 1),
+            None => (
+                "port=$(printf '%s\\n' \"$entry\" | cut -f2)".to_string(),
+                format!(
+                    "port=$({} \"$preferred\" $taken); preferred=0",
+                    shell::join(&[python, "-c", FREE_PORT])
+                ),
+                PORT_ATTEMPTS,
+            ),
+        };
+
+        format!(
+            "registry={registry}; key={key}; tab=$(printf '\\t')\n\
+             mkdir -p {registry_dir}; touch \"$registry\"\n\
+             {clients}\
+             {reap}\
+             entry=$(awk -F'\\t' -v k=\"$key\" '$1 == k' \"$registry\" | tail -n 1)\n\
+             pid=$(printf '%s\\n' \"$entry\" | cut -f3)\n\
+             if [ -n \"$pid\" ] && kill -0 \"$pid\" 2>/dev/null; then\n\
+             \x20 {reuse_port}\n\
+             else\n\
+             \x20 taken=$({others} | cut -f2)\n\
+             \x20 preferred={preferred}; n=0\n\
+             \x20 while [ $n -lt {retries} ]; do\n\
+             \x20   {pick_port}\n\
+             \x20   nohup {server} --port \"$port\" >/dev/null 2>&1 &\n\
+             \x20   pid=$!\n\
+             \x20   {{ {others}; printf '%s\\t%s\\t%s\\t%s\\t%s\\n' \"$key\" \"$port\" \"$pid\" \"$(date +%s)\" {root}; }} > \"$registry.$$\" && mv \"$registry.$$\" \"$registry\"\n\
+             \x20   {wait}\n\
+             \x20   kill -0 $pid 2>/dev/null && break\n\
+             \x20   n=$((n+1))\n\
+             \x20 done\n\
+             fi\n\
+             port=${{port:-{preferred}}}\n\
+             i=0\n\
+             until {probe} 2>/dev/null || [ $i -ge {attempts} ]; do sleep 0.5; i=$((i+1)); done\n\
+             exec {bridge} \"{url}\"\n",
+            registry = registry,
+            key = self.key,
+            registry_dir = registry_dir,
+            clients = clients,
+            reap = reap,
+            reuse_port = reuse_port,
+            others = others,
+            pick_port = pick_port,
+            preferred = self.preferred_port,
+            retries = retries,
+            server = shell::join(server),
+            root = shell::quote(&root),
+            wait = wait,
+            probe = probe,
+            attempts = attempts,
+            bridge = shell::join(bridge),
+            url = Self::sse_url("$port"),
+        )
+    }
+}
+
+/// The instance registry in `work_dir`.
+pub fn registry_path(work_dir: &Path) -> PathBuf {
+    work_dir.join(INSTANCES_DIR).join(REGISTRY_FILE)
+}
+
+/// Reads the registry, skipping malformed lines.
+///
+/// Entries may belong to instances that have exited since; only the wrapper
+/// can tell.
+pub fn read_registry(work_dir: &Path) -> Vec<RegistryEntry> {
+    std::fs::read_to_string(registry_path(work_dir))
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(5, '\t');
+            Some(RegistryEntry {
+                key: fields.next()?.to_string(),
+                port: fields.next()?.parse().ok()?,
+                pid: fields.next()?.parse().ok()?,
+                started_at: fields.next()?.parse().ok()?,
+                root: PathBuf::from(fields.next()?),
+            })
+        })
+        .collect()
+}
+
+/// Serena home for a project root, keeping its configuration, logs and
+/// language servers apart from every other project's.
+pub fn project_home(root: &Path, work_dir: &Path) -> PathBuf {
+    homes_dir(work_dir).join(project_key(root))
+}
+
+/// Directory holding every project's Serena home.
+pub fn homes_dir(work_dir: &Path) -> PathBuf {
+    work_dir.join(HOMES_DIR)
+}
+
+/// Stable identifier for a project root (FNV-1a, hex encoded).
+pub fn project_key(root: &Path) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in root.to_string_lossy().as_bytes() {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", hash)
+}
+
+fn derived_port(key: &str) -> u16 {
+    let hash = u64::from_str_radix(key, 16).unwrap_or_default();
+    PORT_RANGE_START + (hash % u64::from(PORT_RANGE_LEN)) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(unix)]
+    use crate::testing::KillOnDrop;
+
+    #[test]
+    fn test_for_project_is_stable() {
+        let work_dir = Path::new("/work");
+        let a = SharedInstance::for_project(Path::new("/src/app"), None, work_dir);
+        let b = SharedInstance::for_project(Path::new("/src/app"), None, work_dir);
+        let other = SharedInstance::for_project(Path::new("/src/other"), None, work_dir);
+
+        assert_eq!(a, b);
+        assert_ne!(a.key, other.key);
+        assert_eq!(a.registry, other.registry);
+        assert_eq!(a.port, None);
+        assert!((PORT_RANGE_START..PORT_RANGE_START + PORT_RANGE_LEN).contains(&a.preferred_port));
+        assert_eq!(a.registry, Path::new("/work/instances/registry.tsv"));
+    }
+
+    #[test]
+    fn test_project_home() {
+        let work_dir = Path::new("/work");
+        let home = project_home(Path::new("/src/client-a"), work_dir);
+        assert!(home.starts_with("/work/homes"));
+        assert_eq!(home, project_home(Path::new("/src/client-a"), work_dir));
+        assert_ne!(home, project_home(Path::new("/src/client-b"), work_dir));
+    }
+
+    #[test]
+    fn test_explicit_port() {
+        let instance =
+            SharedInstance::for_project(Path::new("/src/app"), Some(9121), Path::new("/w"));
+        assert_eq!(instance.port, Some(9121));
+        assert_eq!(SharedInstance::sse_url("9121"), "http://127.0.0.1:9121/sse");
+
+        // Port 0 asks for a free port, as it does for a socket
+        let instance = SharedInstance::for_project(Path::new("/src/app"), Some(0), Path::new("/w"));
+        assert_eq!(instance.port, None);
+    }
+
+    fn instance(port: Option<u16>) -> SharedInstance {
+        SharedInstance {
+            port,
+            preferred_port: 24123,
+            key: "abc".to_string(),
+            root: PathBuf::from("/src/my app"),
+            registry: PathBuf::from("/w/instances/registry.tsv"),
+            reap_stale: false,
+        }
+    }
+
+    fn script(instance: &SharedInstance) -> String {
+        instance.wrapper_script(
+            &[
+                "serena".into(),
+                "start-mcp-server".into(),
+                "--transport".into(),
+                "sse".into(),
+            ],
+            &["mcp-proxy".into()],
+            "/usr/bin/python3.11",
+        )
+    }
+
+    #[test]
+    fn test_wrapper_script() {
+        let script = script(&instance(Some(9121)));
+
+        assert!(script.starts_with("registry=/w/instances/registry.tsv; key=abc;"));
+        assert!(script.contains("if [ -n \"$pid\" ] && kill -0 \"$pid\" 2>/dev/null; then\n  port=9121\n"));
+        assert!(script.contains(
+            "nohup serena start-mcp-server --transport sse --port \"$port\" >/dev/null 2>&1 &"
+        ));
+        // The new instance replaces the project's entry, with its root quoted
+        assert!(script.contains("\"$key\" \"$port\" \"$pid\" \"$(date +%s)\" '/src/my app'; }"));
+        assert!(script.contains("while [ $n -lt 1 ]"));
+        assert!(script.contains("printf '%s\\t%s\\n' \"$key\" \"$$\""));
+        assert!(!script.contains("then kill \"$i\""));
+        assert!(script.ends_with("exec mcp-proxy \"http://127.0.0.1:$port/sse\"\n"));
+    }
+
+    #[test]
+    fn test_wrapper_script_picks_free_port() {
+        let script = script(&instance(None));
+
+        // A running instance is found at the port it registered
+        assert!(script.contains("port=$(printf '%s\\n' \"$entry\" | cut -f2)"));
+        // New instances try the derived port first, then any free port, and
+        // skip ports other live instances registered
+        assert!(script.contains("preferred=24123; n=0"));
+        assert!(script.contains("\"$preferred\" $taken); preferred=0"));
+        assert!(script.contains(&format!("while [ $n -lt {} ]", PORT_ATTEMPTS)));
+        assert!(script.contains("port=${port:-24123}"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_reap_stale() {
+        let temp = tempfile::tempdir().unwrap();
+        let work_dir = temp.path();
+        let mut instance = SharedInstance::for_project(Path::new("/src/app"), None, work_dir);
+        instance.reap_stale = true;
+        std::fs::create_dir_all(instance.registry.parent().unwrap()).unwrap();
+        let spawn_sleep = || KillOnDrop(std::process::Command::new("sleep").arg("30").spawn().unwrap());
+        let mut stale = spawn_sleep();
+        let mut used = spawn_sleep();
+        std::fs::write(
+            &instance.registry,
+            format!(
+                "stale\t24001\t{}\t100\t/src/stale\nused\t24002\t{}\t100\t/src/used\n",
+                stale.0.id(),
+                used.0.id()
+            ),
+        )
+        .unwrap();
+        // A window of the used project is still connected (this process)
+        std::fs::write(
+            instance.registry.with_file_name(CLIENTS_FILE),
+            format!("used\t{}\n", std::process::id()),
+        )
+        .unwrap();
+
+        // Stop right after reaping, before an instance would be started
+        let script = instance.wrapper_script(&["true".into()], &["true".into()], "python3");
+        let end = script.find("entry=").unwrap();
+        let status = std::process::Command::new("sh")
+            .args(["-c", &script[..end]])
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        assert!(stale.0.wait().is_ok_and(|status| !status.success()));
+        assert!(used.0.try_wait().unwrap().is_none());
+        let clients =
+            std::fs::read_to_string(instance.registry.with_file_name(CLIENTS_FILE)).unwrap();
+        assert!(clients.contains(&format!("used\t{}\n", std::process::id())));
+        assert!(clients.contains(&instance.key));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_registry_round_trip() {
+        let temp = tempfile::tempdir().unwrap();
+        let work_dir = temp.path();
+        let instance = SharedInstance::for_project(Path::new("/src/app"), None, work_dir);
+        std::fs::create_dir_all(instance.registry.parent().unwrap()).unwrap();
+        // Another project's live instance (this process) and an exited one
+        std::fs::write(
+            &instance.registry,
+            format!(
+                "live\t24001\t{}\t100\t/src/live\nstale\t24002\t999999999\t100\t/src/stale\n",
+                std::process::id()
+            ),
+        )
+        .unwrap();
+
+        // Start a stand-in server that exits at once, so only registration runs
+        let script = instance.wrapper_script(&["true".into()], &["true".into()], "python3");
+        let script = script.replace("exec true", "exit 0");
+        let script = script.replace(&format!("[ $i -ge {} ]", STARTUP_TIMEOUT_SECS * 2), "true");
+        let status = std::process::Command::new("sh")
+            .args(["-c", &script])
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let entries = read_registry(work_dir);
+        let keys: Vec<&str> = entries.iter().map(|entry| entry.key.as_str()).collect();
+        assert_eq!(keys, ["live", &instance.key]);
+        assert_eq!(entries[1].root, Path::new("/src/app"));
+        assert_ne!(entries[1].port, 24001);
+    }
+}
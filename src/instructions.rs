@@ -0,0 +1,985 @@
+//! Markdown shown in the context server's configuration panel.
+//!
+//! New users get a guided setup that reports what was detected on their
+//! machine. Once a launch has succeeded, a compact status view replaces it.
+
+use crate::actions::{Action, ActionReport};
+use crate::census::{Census, Size};
+use crate::cleanup;
+use crate::conflicts::Duplicate;
+use crate::footprint::Footprint;
+use crate::instances::RegistryEntry;
+use crate::pipeline::{Progress, Stage};
+use crate::readiness::Readiness;
+use crate::state::{self, ExtensionState, LaunchRecord};
+use python_discovery::detection::Interpreter;
+use python_discovery::discovery::HomebrewPrefix;
+use python_discovery::Arch;
+
+/// Static setup guide shown during onboarding.
+const SETUP_GUIDE: &str = r#"
+### Setup Guide
+
+1. **Install Python 3.11 OR 3.12** (either version works):
+   ```bash
+   # Option A: Install Python 3.11
+   brew install python@3.11
+   python3.11 --version
+   
+   # Option B: Install Python 3.12
+   brew install python@3.12
+   python3.12 --version
+   ```
+
+2. **Install Serena Agent** (use the Python version you installed):
+   ```bash
+   # If you installed Python 3.11:
+   python3.11 -m pip install serena-agent
+   
+   # If you installed Python 3.12:
+   python3.12 -m pip install serena-agent
+   ```
+
+3. **Configure in Zed settings.json**:
+   ```json
+   {
+     "context_servers": {
+       "serena-context-server": {
+         "source": "extension",
+         "enabled": true,
+         "settings": {
+           "python_executable": "/opt/homebrew/bin/python3.11"
+         }
+       }
+     }
+   }
+   ```
+
+4. **Optional: share project defaults in pyproject.toml**:
+   ```toml
+   [tool.serena]
+   context = "ide-assistant"
+   modes = ["planning", "editing"]
+   python-version = "3.12"
+   ```
+   Non-Python projects can commit the same settings as JSON in `.zed/serena.json`
+   (or `.zed-serena.json`). Values from your Zed settings take precedence over both.
+
+The extension will automatically detect Python 3.11/3.12 installations, but you can specify a custom path using the `python_executable` setting.
+"#;
+
+/// Versions and launch details shown at the top of the panel.
+#[derive(Debug)]
+pub(crate) struct Banner {
+    pub extension_version: &'static str,
+    pub python_version: Option<String>,
+    pub serena_version: Option<String>,
+    /// Unix timestamp of the last successful launch
+    pub last_launch: Option<u64>,
+}
+
+/// Single-line summary suitable for bug report screenshots.
+pub(crate) fn banner(banner: &Banner) -> String {
+    let unknown = || "not detected".to_string();
+    format!(
+        "**Extension** {} · **Serena** {} · **Python** {} · **Last launch** {}\n\n",
+        banner.extension_version,
+        banner.serena_version.clone().unwrap_or_else(unknown),
+        banner.python_version.clone().unwrap_or_else(unknown),
+        banner
+            .last_launch
+            .map(state::format_timestamp)
+            .unwrap_or_else(|| "never".to_string()),
+    )
+}
+
+/// Explains how Serena starts for the folders open in Zed, when that needs saying.
+///
+/// Nothing is shown for a single folder, or for several when `project_root`
+/// picks one.
+pub(crate) fn workspace_notice(worktree_count: usize, has_project_root: bool) -> Option<String> {
+    match (worktree_count, has_project_root) {
+        (0, _) => Some(
+            "> 📂 **Open a folder to use Serena.** No folder is open, so Serena starts without a \
+             project and its code tools stay unavailable until one is activated.\n\n"
+                .to_string(),
+        ),
+        (1, _) | (_, true) => None,
+        (_, false) => Some(
+            "> 📂 Several folders are open. Serena works on one project at a time; set \
+             `\"project_root\"` to choose which one it starts with.\n\n"
+                .to_string(),
+        ),
+    }
+}
+
+/// Lists problems found while loading the configuration; `None` when there
+/// were none.
+pub(crate) fn config_warnings_notice(warnings: &[String]) -> Option<String> {
+    warning_list("Configuration warnings", warnings)
+}
+
+/// Warns when the interpreter comes from the Homebrew prefix that doesn't
+/// match the host architecture.
+pub(crate) fn homebrew_notice(python: &str, native: HomebrewPrefix) -> Option<String> {
+    let prefix = HomebrewPrefix::of(python).filter(|prefix| *prefix != native)?;
+    Some(format!(
+        "> ⚠️ Using `{}` from {}, which doesn't match this Mac. Install Python 3.11 or 3.12 with \
+         {} to run natively, or set `\"python_executable\"`.\n\n",
+        python,
+        prefix.describe(),
+        native.describe()
+    ))
+}
+
+/// Warns about manual Serena entries running next to the `own_id` server.
+pub(crate) fn duplicate_notice(duplicates: &[Duplicate], own_id: &str) -> String {
+    let entries: String = duplicates
+        .iter()
+        .map(|duplicate| format!("> - `{}`: `{}`\n", duplicate.name, duplicate.command))
+        .collect();
+    format!(
+        "> ⚠️ **Serena is registered more than once.** Besides `{}`, these `context_servers` \
+         entries start Serena with their own command:\n>\n{}>\n> Each server starts its own \
+         language servers for the project, doubling memory and CPU use, and the agent sees \
+         every tool twice. Remove the manual entries, or set `\"enabled\": false` on them, to \
+         keep the extension's detection, installs and updates. To keep a manual entry instead, \
+         disable `{}` in the Agent Panel's settings.\n\n",
+        own_id, entries, own_id
+    )
+}
+
+/// Warns that the configured interpreter is broken and `python` was
+/// auto-detected instead, because `fallback_to_autodetect` is on.
+pub(crate) fn fallback_notice(reason: &str, python: &str) -> String {
+    format!(
+        "> ⚠️ **python_executable is broken, so Serena runs with the auto-detected `{}`.** {}\n>\n\
+         > Fix or remove `\"python_executable\"`; `\"fallback_to_autodetect\"` only keeps \
+         Serena running until then.\n\n",
+        python,
+        reason.lines().next().unwrap_or(reason).trim()
+    )
+}
+
+/// Explains that `python`, built for `machine`, doesn't match the host's
+/// `arch`; `None` when it matches or the machine is unknown.
+///
+/// Serena downloads language servers for the interpreter's architecture,
+/// which then fail to start, or run emulated, on the host.
+pub(crate) fn architecture_warning(python: &str, machine: &str, arch: Arch) -> Option<String> {
+    let built_for = Arch::from_machine(machine).filter(|built_for| *built_for != arch)?;
+    let name = |arch: Arch| match arch {
+        Arch::Aarch64 => "arm64",
+        Arch::X86_64 => "x86_64",
+        Arch::X86 => "x86",
+    };
+    Some(format!(
+        "`{}` is built for {} but this machine is {}. Language servers Serena downloads for it \
+         may fail to start; use a native {} interpreter, e.g. via `python_executable`.",
+        python,
+        name(built_for),
+        name(arch),
+        name(arch)
+    ))
+}
+
+/// Panel form of [`architecture_warning`].
+pub(crate) fn architecture_notice(python: &str, machine: &str, arch: Arch) -> Option<String> {
+    architecture_warning(python, machine, arch).map(|warning| format!("> ⚠️ {}\n\n", warning))
+}
+
+/// Warns that Serena may not work with the Python `version` in use.
+pub(crate) fn unsupported_python_notice(version: &str) -> String {
+    format!(
+        "> ⚠️ Python {} is outside the 3.11–3.12 range Serena supports. It may work, but \
+         isn't tested; install Python 3.11 or 3.12 if Serena misbehaves.\n\n",
+        version
+    )
+}
+
+/// Advice from the compatibility table.
+pub(crate) fn compatibility_notice(advice: &str) -> String {
+    format!("> ⚠️ **Version mismatch:** {}\n\n", advice)
+}
+
+/// Summarizes the project's languages, warning when Serena can't index any
+/// of them.
+pub(crate) fn language_notice(census: &Census) -> Option<String> {
+    let (language, _) = census.languages.first()?;
+    if census.only_unsupported() {
+        return Some(format!(
+            "> ⚠️ This project looks like {}, which Serena has no language server for. Symbol \
+             tools such as `find_symbol` will come back empty; file, search and memory tools \
+             still work. The suggested settings exclude the symbol tools.\n\n",
+            language.name
+        ));
+    }
+    let supported: Vec<_> = census
+        .supported()
+        .map(|(language, count)| format!("{} ({})", language.name, count))
+        .collect();
+    let mut out = format!("**Languages:** {}", supported.join(", "));
+    if census.truncated {
+        out.push_str(" (partial scan)");
+    }
+    let unsupported: Vec<_> = census
+        .languages
+        .iter()
+        .filter(|(language, _)| !language.supported)
+        .map(|(language, _)| language.name)
+        .collect();
+    if !unsupported.is_empty() {
+        out.push_str(&format!(
+            ". Serena can't index the {} files.",
+            unsupported.join(" and ")
+        ));
+    }
+    out.push_str("\n\n");
+    Some(out)
+}
+
+/// Lists the interpreters detection found, with whether serena is installed
+/// in each, so the user can pick one with `preferred_python`.
+pub(crate) fn interpreter_choices(
+    choices: &[(&Interpreter, bool)],
+    in_use: Option<&str>,
+    preferred: Option<&str>,
+) -> String {
+    let mut out = String::from(
+        "### Detected Interpreters\n\n| Interpreter | Python | Serena |\n| --- | --- | --- |\n",
+    );
+    for (interpreter, serena_installed) in choices {
+        out.push_str(&format!(
+            "| `{}`{} | {} | {} |\n",
+            interpreter.path,
+            if in_use == Some(interpreter.path.as_str()) {
+                " (in use)"
+            } else {
+                ""
+            },
+            interpreter.version,
+            if *serena_installed {
+                "installed"
+            } else {
+                "missing"
+            }
+        ));
+    }
+    out.push('\n');
+    let missing = preferred.filter(|preferred| {
+        !choices
+            .iter()
+            .any(|(interpreter, _)| interpreter.path == *preferred)
+    });
+    if let Some(preferred) = missing {
+        out.push_str(&format!(
+            "> ⚠️ `preferred_python` `{}` was not detected, so another interpreter is used.\n\n",
+            preferred
+        ));
+    }
+    out.push_str("Set `\"preferred_python\"` to one of these paths to choose.\n\n");
+    out
+}
+
+/// Where Serena serves its web dashboard, unless that port is already taken.
+pub(crate) const DASHBOARD_URL: &str = "http://127.0.0.1:24282/dashboard/index.html";
+
+/// Points to the web dashboard of a running Serena.
+pub(crate) fn dashboard_notice() -> String {
+    format!(
+        "> 📊 **Dashboard:** [{url}]({url}) while Serena runs. If the port is taken, for example \
+         by another Serena, the next free port is used and the server log has the address.\n\n",
+        url = DASHBOARD_URL
+    )
+}
+
+/// Shows the registered shared instance for `key`, and warns when another
+/// project registered the configured `port`.
+pub(crate) fn shared_instance_notice(
+    entries: &[RegistryEntry],
+    key: &str,
+    port: Option<u16>,
+) -> Option<String> {
+    let mut out = String::new();
+    if let Some(entry) = entries.iter().rev().find(|entry| entry.key == key) {
+        out.push_str(&format!(
+            "> 🔗 **Shared instance:** port {} (pid {}), started {}. It may have exited since; \
+             the next launch checks.\n\n",
+            entry.port,
+            entry.pid,
+            state::format_timestamp(entry.started_at)
+        ));
+    }
+    let conflict = port.and_then(|port| {
+        entries
+            .iter()
+            .find(|entry| entry.key != key && entry.port == port)
+    });
+    if let Some(entry) = conflict {
+        out.push_str(&format!(
+            "> ⚠️ Port {} is also registered by the shared instance for `{}`. Unless that \
+             instance has exited, remove `port` so a free one is picked.\n\n",
+            entry.port,
+            entry.root.display()
+        ));
+    }
+    (!out.is_empty()).then_some(out)
+}
+
+/// Explains how an external agent reaches Serena.
+pub(crate) fn external_agent_notice(context_server_id: &str) -> String {
+    format!(
+        "> 🤝 **External agent:** Serena is a context server, not an agent server. Zed passes \
+         enabled context servers to external agents that accept MCP servers, so keep \
+         `{}` enabled and start the agent from the agent panel. Agents started outside Zed \
+         need Serena in their own MCP configuration.\n\n",
+        context_server_id
+    )
+}
+
+/// Warns that Serena's file and shell tools overlap Zed's agent tools in
+/// `context`, where `None` is Serena's default.
+pub(crate) fn tool_overlap_notice(context: Option<&str>) -> String {
+    let context = match context {
+        Some(context) => format!("the `{}` context", context),
+        None => "its default context".to_string(),
+    };
+    format!(
+        "> ⚠️ **Serena's file and shell tools overlap Zed's.** In {}, Serena offers tools such as \
+         `read_file`, `replace_regex` and `execute_shell_command` next to Zed's own agent tools, \
+         so the agent may edit a file twice or make conflicting edits. Set \
+         `\"enforce_ide_context\": true` to start Serena in the `ide-assistant` context, or \
+         `\"exclude_overlapping_tools\": true` to keep the context without those tools.\n\n",
+        context
+    )
+}
+
+/// Tells which defaults the project's `size` brings, given whether `modes`
+/// and `forward_ignores` are configured; `None` when it brings none.
+pub(crate) fn project_size_notice(
+    size: Size,
+    has_modes: bool,
+    has_forward_ignores: bool,
+) -> Option<String> {
+    let mut defaults = Vec::new();
+    if !has_modes {
+        defaults.push(format!("the modes `{}`", size.modes().join("`, `")));
+    }
+    if size == Size::Large && !has_modes && !has_forward_ignores {
+        defaults.push("`forward_ignores`".to_string());
+    }
+    let reindex = match size {
+        Size::Small => "",
+        Size::Large => {
+            " Serena indexes symbols as it first needs them, which takes a while here; set \
+             `\"action\": \"reindex\"` once to index up front."
+        }
+    };
+    if defaults.is_empty() && reindex.is_empty() {
+        return None;
+    }
+    let (name, files) = match size {
+        Size::Small => ("Small project", "fewer than 200 source files"),
+        Size::Large => ("Large project", "5,000 or more source files"),
+    };
+    let applied = if defaults.is_empty() {
+        String::new()
+    } else {
+        format!(
+            " Serena starts with {}; set them, or `\"size_defaults\": false`, to override.",
+            defaults.join(" and ")
+        )
+    };
+    Some(format!(
+        "> 📏 **{}** ({}).{}{}\n\n",
+        name, files, applied, reindex
+    ))
+}
+
+/// Explains what `restricted_environment` turns off, naming the project's
+/// languages whose language server can't be downloaded.
+pub(crate) fn restricted_notice(downloaded: &[&str], bundled: bool) -> String {
+    let mut notice = "> 🔒 **Restricted environment:** no update checks, installs, analytics or \
+                      downloads."
+        .to_string();
+    if !downloaded.is_empty() && bundled {
+        notice.push_str(&format!(
+            " The language servers for {} must come from `language_server_bundle`.",
+            downloaded.join(", ")
+        ));
+    } else if !downloaded.is_empty() {
+        notice.push_str(&format!(
+            " Serena can't download the language servers for {}, so symbol tools won't work \
+             for them unless the servers are already in `~/.serena/language_servers` or set \
+             `language_server_bundle` to a directory of pre-downloaded ones.",
+            downloaded.join(", ")
+        ));
+    }
+    notice.push_str("\n\n");
+    notice
+}
+
+/// Explains where the nightly server's Serena comes from.
+pub(crate) fn nightly_notice(env_dir: &std::path::Path) -> String {
+    format!(
+        "> 🧪 **Nightly:** Serena from git main, installed on first launch into `{}`. \
+         Delete that directory to pick up newer upstream changes.\n\n",
+        env_dir.display()
+    )
+}
+
+/// Tells the user that settings changed since the running server started.
+///
+/// `changed` lists the top-level keys; it is empty when only a redacted
+/// secret changed.
+pub(crate) fn settings_changed_notice(changed: &[String]) -> String {
+    let what = if changed.is_empty() {
+        "a secret value".to_string()
+    } else {
+        changed
+            .iter()
+            .map(|key| format!("`{}`", key))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    format!(
+        "> 🔄 **Settings changed since the last launch:** {}. The running server still uses \
+         the old ones; restart the Serena context server, e.g. by turning it off and on in the \
+         agent panel's settings, for the changes to take effect.\n\n",
+        what
+    )
+}
+
+/// Reports the readiness check after the last launch.
+pub(crate) fn readiness_notice(readiness: &Readiness) -> String {
+    match (readiness.millis, &readiness.error) {
+        (Some(millis), _) => format!(
+            "> ✅ **Serena ready** in {:.1} s with {} tools after the launch at {}.\n\n",
+            millis as f64 / 1000.0,
+            readiness.tools.unwrap_or_default(),
+            state::format_timestamp(readiness.launch)
+        ),
+        (None, error) => format!(
+            "> ⚠️ **Serena didn't pass the readiness check** after the launch at {}: {}. \
+             Zed's log (`zed: open log`) has the server's output.\n\n",
+            state::format_timestamp(readiness.launch),
+            error.as_deref().unwrap_or("unknown error")
+        ),
+    }
+}
+
+/// Where a launch that hasn't finished stopped, or why it failed.
+pub(crate) fn launch_progress_notice(progress: &Progress) -> Option<String> {
+    if progress.stage == Stage::Ready {
+        return None;
+    }
+    let stages = [
+        Stage::Detect,
+        Stage::EnsureInstalled,
+        Stage::Preflight,
+        Stage::BuildCommand,
+    ]
+    .map(|stage| {
+        if stage == progress.stage {
+            format!("**{}**", stage)
+        } else {
+            stage.to_string()
+        }
+    })
+    .join(" → ");
+    Some(match &progress.error {
+        Some(error) => format!(
+            "> ⚠️ **The last launch failed at {}** ({}):\n>\n{}>\n> {}\n\n",
+            progress.stage,
+            state::format_timestamp(progress.stage_started_at),
+            error
+                .lines()
+                .map(|line| format!("> {}\n", line))
+                .collect::<String>(),
+            stages
+        ),
+        None => format!(
+            "> ⏳ **Launching: {}** since {}. If Zed gave up on the launch, \
+             the next one resumes there.\n>\n> {}\n\n",
+            progress.stage,
+            state::format_timestamp(progress.stage_started_at),
+            stages
+        ),
+    })
+}
+
+/// Tells that Serena runs with the `good` launch's command after the last
+/// launch failed, or offers to when `enabled` is off.
+pub(crate) fn last_known_good_notice(good: &LaunchRecord, enabled: bool) -> String {
+    let when = state::format_timestamp(good.timestamp);
+    if enabled {
+        format!(
+            "> ♻️ **Serena runs with the last-known-good configuration** from {} with `{}` until \
+             the failure above is fixed. Settings changed since then don't apply.\n\n",
+            when, good.python
+        )
+    } else {
+        format!(
+            "> 💡 The launch on {} with `{}` worked. Set `\"use_last_known_good\": true` to start \
+             Serena that way while a launch fails.\n\n",
+            when, good.python
+        )
+    }
+}
+
+/// Problems the last launch got past; `None` when there were none.
+pub(crate) fn launch_warnings_notice(warnings: &[String]) -> Option<String> {
+    warning_list("Warnings from the last launch", warnings)
+}
+
+fn warning_list(heading: &str, warnings: &[String]) -> Option<String> {
+    if warnings.is_empty() {
+        return None;
+    }
+    let mut out = format!("> ⚠️ **{}:**\n>\n", heading);
+    for warning in warnings {
+        out.push_str(&format!("> - {}\n", warning));
+    }
+    out.push('\n');
+    Some(out)
+}
+
+/// Outcome of the last maintenance action.
+pub(crate) fn action_notice(report: &ActionReport) -> String {
+    let (icon, outcome) = if report.succeeded {
+        ("✅", "succeeded")
+    } else {
+        ("⚠️", "failed")
+    };
+    let setting = match report.action {
+        Action::Cleanup => "`cleanup` setting",
+        _ => "`action` setting",
+    };
+    format!(
+        "> {} **The `{}` action {}** at {}. \
+         Remove the {} to run it again later.\n>\n{}\n",
+        icon,
+        report.action.name(),
+        outcome,
+        state::format_timestamp(report.timestamp),
+        setting,
+        report
+            .details
+            .lines()
+            .map(|line| format!("> {}\n", line))
+            .collect::<String>()
+    )
+}
+
+/// Disk space taken by managed environments and downloads; `None` when
+/// nothing was found.
+pub(crate) fn footprint_notice(footprint: &Footprint) -> Option<String> {
+    if footprint.total() == 0 {
+        return None;
+    }
+    let mut out = format!(
+        "**Disk usage:** {} (measured {})\n\n",
+        cleanup::format_size(footprint.total()),
+        state::format_timestamp(footprint.checked_at)
+    );
+    for item in footprint.items.iter().filter(|item| item.bytes > 0) {
+        let paths: Vec<String> = item
+            .paths
+            .iter()
+            .filter(|path| path.exists())
+            .map(|path| format!("`{}`", path.display()))
+            .collect();
+        out.push_str(&format!(
+            "- {}: {} in {}\n",
+            item.label,
+            cleanup::format_size(item.bytes),
+            paths.join(", ")
+        ));
+    }
+    out.push_str(
+        "\nSet `venv_dir` to move the managed environment to another disk, or \
+         `\"cleanup\": true` to remove it and the downloaded language servers.\n\n",
+    );
+    Some(out)
+}
+
+/// What the extension found on this machine.
+#[derive(Debug)]
+pub(crate) struct Detection {
+    /// The interpreter that would be used, or why none was found
+    pub python: Result<String, String>,
+    /// Whether serena-agent is importable, when it could be checked
+    pub serena_installed: Option<bool>,
+}
+
+/// Full first-run guide including the detection results.
+pub(crate) fn onboarding(detection: &Detection) -> String {
+    let mut out = String::from(
+        "## Welcome to Serena\n\nHere's what the extension found on this machine:\n\n",
+    );
+
+    match &detection.python {
+        Ok(python) => out.push_str(&format!("- ✅ Python: `{}`\n", python)),
+        Err(_) => out.push_str("- ❌ Python 3.11 or 3.12 was not found\n"),
+    }
+    match detection.serena_installed {
+        Some(true) => out.push_str("- ✅ serena-agent is installed\n"),
+        Some(false) => out.push_str("- ❌ serena-agent is not installed\n"),
+        None => out.push_str("- ❔ serena-agent could not be checked\n"),
+    }
+
+    if let (Ok(python), Some(false)) = (&detection.python, detection.serena_installed) {
+        out.push_str(&format!(
+            "\n### Install serena-agent\n\nRun `{} -m pip install serena-agent`, or add \
+             `\"auto_install\": true` to the settings below and the extension will install it \
+             on the next launch.\n",
+            python
+        ));
+    }
+
+    out.push('\n');
+    out.push_str(SETUP_GUIDE);
+    out
+}
+
+/// Compact view shown once setup has completed.
+pub(crate) fn status_view(state: &ExtensionState) -> String {
+    let mut out = String::from("## Serena Context Server\n\n✅ Set up");
+    if let Some(launch) = &state.last_launch {
+        out.push_str(&format!(
+            " — last launched {} with `{}`",
+            state::format_timestamp(launch.timestamp),
+            launch.python
+        ));
+    }
+    out.push_str(".\n\nSet `\"show_setup_guide\": true` to see the full setup guide again.\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_onboarding_offers_install() {
+        let guide = onboarding(&Detection {
+            python: Ok("/usr/bin/python3.12".to_string()),
+            serena_installed: Some(false),
+        });
+        assert!(guide.contains("✅ Python: `/usr/bin/python3.12`"));
+        assert!(guide.contains("/usr/bin/python3.12 -m pip install serena-agent"));
+        assert!(guide.contains("\"auto_install\": true"));
+        assert!(guide.contains("### Setup Guide"));
+    }
+
+    #[test]
+    fn test_duplicate_notice() {
+        let notice = duplicate_notice(
+            &[Duplicate {
+                name: "serena".to_string(),
+                command: "uvx serena start-mcp-server".to_string(),
+            }],
+            "serena-context-server",
+        );
+        assert!(notice.starts_with("> ⚠️ **Serena is registered more than once.**"));
+        assert!(notice.contains("\n> - `serena`: `uvx serena start-mcp-server`\n"));
+        assert!(notice.contains("disable `serena-context-server`"));
+    }
+
+    #[test]
+    fn test_project_size_notice() {
+        assert_eq!(
+            project_size_notice(Size::Small, false, false).unwrap(),
+            "> 📏 **Small project** (fewer than 200 source files). Serena starts with the modes \
+             `interactive`, `editing`, `no-onboarding`; set them, or `\"size_defaults\": false`, \
+             to override.\n\n"
+        );
+        assert_eq!(project_size_notice(Size::Small, true, false), None);
+        let large = project_size_notice(Size::Large, false, false).unwrap();
+        assert!(
+            large.contains("`planning` and `forward_ignores`;"),
+            "{}",
+            large
+        );
+        // Configured modes turn the size defaults off
+        let large = project_size_notice(Size::Large, true, false).unwrap();
+        assert!(!large.contains("Serena starts with"), "{}", large);
+        assert!(large.contains("`\"action\": \"reindex\"`"), "{}", large);
+    }
+
+    #[test]
+    fn test_restricted_notice() {
+        assert_eq!(
+            restricted_notice(&[], false),
+            "> 🔒 **Restricted environment:** no update checks, installs, analytics or \
+             downloads.\n\n"
+        );
+        assert!(restricted_notice(&["Java", "C#"], false)
+            .contains("download the language servers for Java, C#, so symbol tools"));
+        assert!(restricted_notice(&["Java"], true)
+            .contains("The language servers for Java must come from `language_server_bundle`."));
+    }
+
+    #[test]
+    fn test_tool_overlap_notice() {
+        assert!(tool_overlap_notice(None).contains("In its default context, Serena offers"));
+        assert!(tool_overlap_notice(Some("desktop-app")).contains("In the `desktop-app` context"));
+    }
+
+    #[test]
+    fn test_fallback_notice() {
+        let notice = fallback_notice(
+            "python_executable `/old/venv/bin/python` does not exist or can't be executed\nmore",
+            "/usr/bin/python3.12",
+        );
+        assert!(notice.starts_with("> ⚠️ **python_executable is broken"));
+        assert!(notice.contains("`/usr/bin/python3.12`"));
+        assert!(notice.contains("`/old/venv/bin/python` does not exist or can't be executed\n>\n"));
+        assert!(!notice.contains("more"));
+    }
+
+    #[test]
+    fn test_homebrew_notice() {
+        let intel_python = "/usr/local/bin/python3.12";
+        let notice = homebrew_notice(intel_python, HomebrewPrefix::AppleSilicon).unwrap();
+        assert!(notice.contains("Intel Homebrew"));
+        assert!(notice.contains("Apple Silicon Homebrew"));
+
+        assert!(homebrew_notice(intel_python, HomebrewPrefix::Intel).is_none());
+        assert!(homebrew_notice("/usr/bin/python3", HomebrewPrefix::AppleSilicon).is_none());
+    }
+
+    #[test]
+    fn test_architecture_notice() {
+        let python = "/usr/local/bin/python3.12";
+        let notice = architecture_notice(python, "x86_64", Arch::Aarch64).unwrap();
+        assert!(
+            notice.contains("built for x86_64 but this machine is arm64"),
+            "{}",
+            notice
+        );
+        assert!(architecture_notice(python, "arm64", Arch::Aarch64).is_none());
+        // Unknown machines aren't guessed at
+        assert!(architecture_notice(python, "ppc64le", Arch::X86_64).is_none());
+    }
+
+    #[test]
+    fn test_language_notice() {
+        assert_eq!(language_notice(&Census::default()), None);
+        assert_eq!(
+            language_notice(&Census::from_file_names(["main.rs", "lib.rs", "Gen.hs"])).unwrap(),
+            "**Languages:** Rust (2). Serena can't index the Haskell files.\n\n"
+        );
+        let notice = language_notice(&Census::from_file_names(["Main.hs"])).unwrap();
+        assert!(notice.contains("looks like Haskell"), "{}", notice);
+    }
+
+    #[test]
+    fn test_settings_changed_notice() {
+        let notice = settings_changed_notice(&["python_version".to_string(), "modes".to_string()]);
+        assert!(notice.contains("last launch:** `python_version`, `modes`."));
+        assert!(settings_changed_notice(&[]).contains("a secret value"));
+    }
+
+    #[test]
+    fn test_readiness_notice() {
+        let ready = Readiness {
+            launch: 0,
+            millis: Some(4_250),
+            tools: Some(24),
+            error: None,
+        };
+        assert_eq!(
+            readiness_notice(&ready),
+            "> ✅ **Serena ready** in 4.2 s with 24 tools after the launch at 1970-01-01 00:00 UTC.\n\n"
+        );
+        let failed = Readiness {
+            millis: None,
+            tools: None,
+            error: Some("no answer within 30s".to_string()),
+            ..ready
+        };
+        assert!(readiness_notice(&failed).contains(": no answer within 30s."));
+    }
+
+    #[test]
+    fn test_action_notice() {
+        let report = ActionReport {
+            action: Action::ClearCache,
+            timestamp: 0,
+            succeeded: true,
+            details: "Cleared cached interpreter probes.".to_string(),
+        };
+        assert_eq!(
+            action_notice(&report),
+            "> ✅ **The `clear-cache` action succeeded** at 1970-01-01 00:00 UTC. \
+             Remove the `action` setting to run it again later.\n>\n\
+             > Cleared cached interpreter probes.\n\n"
+        );
+    }
+
+    #[test]
+    fn test_launch_warnings_notice() {
+        assert_eq!(launch_warnings_notice(&[]), None);
+        assert_eq!(
+            launch_warnings_notice(&["The diagnostics file wasn't written: disk full".to_string()])
+                .unwrap(),
+            "> ⚠️ **Warnings from the last launch:**\n>\n\
+             > - The diagnostics file wasn't written: disk full\n\n"
+        );
+    }
+
+    #[test]
+    fn test_config_warnings_notice() {
+        assert_eq!(config_warnings_notice(&[]), None);
+        assert_eq!(
+            config_warnings_notice(&["repo config file sets `venv_dir`".to_string()]).unwrap(),
+            "> ⚠️ **Configuration warnings:**\n>\n> - repo config file sets `venv_dir`\n\n"
+        );
+    }
+
+    #[test]
+    fn test_launch_progress_notice() {
+        let mut progress = Progress::resume(None, "a".to_string(), 0);
+        progress.detected("/usr/bin/python3.12", 0);
+        assert_eq!(
+            launch_progress_notice(&progress).unwrap(),
+            "> ⏳ **Launching: Ensure Installed** since 1970-01-01 00:00 UTC. \
+             If Zed gave up on the launch, the next one resumes there.\n>\n\
+             > Detect → **Ensure Installed** → Preflight → Build Command\n\n"
+        );
+        progress.fail("pip failed");
+        assert!(launch_progress_notice(&progress)
+            .unwrap()
+            .starts_with("> ⚠️ **The last launch failed at Ensure Installed**"));
+        progress.error = None;
+        progress.enter(Stage::Ready, 0);
+        assert_eq!(launch_progress_notice(&progress), None);
+    }
+
+    #[test]
+    fn test_footprint_notice() {
+        let footprint = Footprint::measure(Vec::new(), 0);
+        assert_eq!(footprint_notice(&footprint), None);
+        let footprint = Footprint {
+            checked_at: 0,
+            items: vec![
+                crate::footprint::Item {
+                    label: "Managed environments".to_string(),
+                    paths: vec!["/nonexistent/envs".into()],
+                    bytes: 3 * 1024 * 1024 * 1024,
+                },
+                crate::footprint::Item {
+                    label: "Language servers".to_string(),
+                    paths: Vec::new(),
+                    bytes: 0,
+                },
+            ],
+        };
+        let notice = footprint_notice(&footprint).unwrap();
+        assert!(notice.starts_with("**Disk usage:** 3.0 GB"), "{}", notice);
+        assert!(
+            notice.contains("- Managed environments: 3.0 GB"),
+            "{}",
+            notice
+        );
+        assert!(!notice.contains("Language servers"), "{}", notice);
+    }
+
+    #[test]
+    fn test_shared_instance_notice() {
+        let entry = |key: &str, port| RegistryEntry {
+            key: key.to_string(),
+            port,
+            pid: 4242,
+            started_at: 0,
+            root: std::path::PathBuf::from(format!("/src/{}", key)),
+        };
+        let entries = [entry("app", 24001), entry("other", 9121)];
+        assert_eq!(shared_instance_notice(&entries, "new", None), None);
+        let notice = shared_instance_notice(&entries, "app", Some(9121)).unwrap();
+        assert!(notice.contains("port 24001 (pid 4242)"), "{}", notice);
+        assert!(
+            notice.contains("Port 9121 is also registered by the shared instance for `/src/other`")
+        );
+    }
+
+    #[test]
+    fn test_interpreter_choices() {
+        let homebrew = Interpreter {
+            path: "/opt/homebrew/bin/python3.12".to_string(),
+            version: "3.12.1".to_string(),
+        };
+        let venv = Interpreter {
+            path: "/src/app/.venv/bin/python".to_string(),
+            version: "3.11.9".to_string(),
+        };
+        let choices = [(&homebrew, false), (&venv, true)];
+
+        let text = interpreter_choices(&choices, Some(venv.path.as_str()), None);
+        assert!(text.contains("| `/opt/homebrew/bin/python3.12` | 3.12.1 | missing |"));
+        assert!(text.contains("| `/src/app/.venv/bin/python` (in use) | 3.11.9 | installed |"));
+        assert!(!text.contains("was not detected"));
+
+        let text =
+            interpreter_choices(&choices, Some(homebrew.path.as_str()), Some("/gone/python"));
+        assert!(text.contains("`/gone/python` was not detected"));
+    }
+
+    #[test]
+    fn test_dashboard_notice() {
+        assert!(dashboard_notice().contains(
+            "[http://127.0.0.1:24282/dashboard/index.html](http://127.0.0.1:24282/dashboard/index.html)"
+        ));
+    }
+
+    #[test]
+    fn test_banner() {
+        let text = banner(&Banner {
+            extension_version: "0.0.1",
+            python_version: Some("3.12.1".to_string()),
+            serena_version: None,
+            last_launch: None,
+        });
+        assert_eq!(
+            text,
+            "**Extension** 0.0.1 · **Serena** not detected · **Python** 3.12.1 · **Last launch** never\n\n"
+        );
+    }
+
+    #[test]
+    fn test_last_known_good_notice() {
+        let good = LaunchRecord {
+            timestamp: 0,
+            python: "/usr/bin/python3.12".to_string(),
+            command: None,
+            check: None,
+            settings_hash: None,
+            settings: None,
+        };
+        assert!(last_known_good_notice(&good, true).starts_with(
+            "> ♻️ **Serena runs with the last-known-good configuration** from \
+             1970-01-01 00:00 UTC with `/usr/bin/python3.12`"
+        ));
+        assert!(last_known_good_notice(&good, false).contains("`\"use_last_known_good\": true`"));
+    }
+
+    #[test]
+    fn test_status_view() {
+        let state = ExtensionState {
+            onboarding_completed: true,
+            last_launch: Some(LaunchRecord {
+                timestamp: 0,
+                python: "python3.11".to_string(),
+                command: None,
+                check: None,
+                settings_hash: None,
+                settings: None,
+            }),
+            ..Default::default()
+        };
+        let view = status_view(&state);
+        assert!(view.contains("last launched 1970-01-01 00:00 UTC with `python3.11`"));
+        assert!(!view.contains("Setup Guide"));
+    }
+}
@@ -0,0 +1,128 @@
+//! Tolerant JSON parsing for hand-written configuration.
+//!
+//! Accepts the JSON-with-comments dialect used by Zed's settings.json: `//` and
+//! `/* */` comments and trailing commas in arrays and objects. The input is
+//! normalized to strict JSON and then handed to serde.
+
+use zed_extension_api::serde_json;
+
+/// Parses JSON that may contain comments and trailing commas.
+pub(crate) fn from_str<T: serde::de::DeserializeOwned>(input: &str) -> Result<T, String> {
+    let normalized = normalize(input)?;
+    serde_json::from_str(&normalized).map_err(|e| e.to_string())
+}
+
+/// Strips comments and trailing commas, producing strict JSON.
+///
+/// Comment bytes are replaced with spaces (newlines are kept) so that serde's
+/// line and column numbers still point at the original input.
+pub(crate) fn normalize(input: &str) -> Result<String, String> {
+    let mut out = String::with_capacity(input.len());
+    let mut chars = input.chars().peekable();
+    let mut in_string = false;
+    // Position in `out` of a comma that may turn out to be trailing
+    let mut pending_comma: Option<usize> = None;
+
+    while let Some(c) = chars.next() {
+        if in_string {
+            out.push(c);
+            match c {
+                '\\' => {
+                    if let Some(escaped) = chars.next() {
+                        out.push(escaped);
+                    }
+                }
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+
+        match c {
+            '/' if chars.peek() == Some(&'/') => {
+                out.push(' ');
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        out.push('\n');
+                        break;
+                    }
+                    out.push(' ');
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                out.push_str("  ");
+                let mut closed = false;
+                while let Some(c) = chars.next() {
+                    if c == '*' && chars.peek() == Some(&'/') {
+                        chars.next();
+                        out.push_str("  ");
+                        closed = true;
+                        break;
+                    }
+                    out.push(if c == '\n' { '\n' } else { ' ' });
+                }
+                if !closed {
+                    return Err("unterminated block comment".into());
+                }
+            }
+            ',' => {
+                pending_comma = Some(out.len());
+                out.push(c);
+            }
+            '}' | ']' => {
+                if let Some(position) = pending_comma.take() {
+                    out.replace_range(position..position + 1, " ");
+                }
+                out.push(c);
+            }
+            c if c.is_whitespace() => out.push(c),
+            c => {
+                pending_comma = None;
+                in_string = c == '"';
+                out.push(c);
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zed_extension_api::serde_json::{json, Value};
+
+    #[test]
+    fn test_comments_and_trailing_commas() {
+        let input = r#"
+        {
+            // Line comment
+            "context": "ide-assistant", /* block
+               comment */
+            "modes": ["planning", "editing",],
+            "url": "http://example.com/*not-a-comment*/",
+        }
+        "#;
+        let value: Value = from_str(input).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "context": "ide-assistant",
+                "modes": ["planning", "editing"],
+                "url": "http://example.com/*not-a-comment*/"
+            })
+        );
+    }
+
+    #[test]
+    fn test_escaped_quotes_in_strings() {
+        let value: Value = from_str(r#"{ "a": "say \"hi\", // ok", }"#).unwrap();
+        assert_eq!(value, json!({ "a": "say \"hi\", // ok" }));
+    }
+
+    #[test]
+    fn test_unterminated_block_comment() {
+        assert!(normalize("{ /* oops }").is_err());
+    }
+}
@@ -0,0 +1,81 @@
+//! Launch commands built the way the extension builds them.
+//!
+//! The extension assembles its inputs from Zed, the settings and the
+//! installed release; this module takes them as plain values instead, so the
+//! golden tests in `tests/` and other tools can see exactly what would run.
+
+use std::path::Path;
+
+use python_discovery::sandbox::Sandbox;
+use zed_extension_api::{serde_json::Value, Command, Os, Result};
+
+use crate::capabilities::Capabilities;
+use crate::scripts::FileSystem;
+use crate::{parse_settings, Workspace};
+
+/// Everything a launch command depends on apart from the filesystem and the
+/// process environment.
+#[derive(Debug, Clone)]
+pub struct Launch<'a> {
+    /// Context server settings as JSON, validated like Zed's
+    pub settings: Option<Value>,
+    /// Resolved interpreter, already in host form
+    pub python: &'a Path,
+    /// Module run with `python -m` when no console script is used
+    pub module: &'a str,
+    /// Installed serena-agent version, if known
+    pub serena_version: Option<&'a str>,
+    /// `start-mcp-server --help` output of the installed release, if read
+    pub help: Option<&'a str>,
+    /// Serena configuration overlay written for this launch
+    pub config_overlay: Option<&'a Path>,
+    /// Platform Zed runs on
+    pub os: Os,
+    /// User home directory, for user-site and pipx scripts
+    pub home: Option<&'a Path>,
+    /// Number of folders open in the Zed project
+    pub worktrees: usize,
+    /// Extension work directory
+    pub work_dir: Option<&'a Path>,
+    /// Sandbox Zed runs in
+    pub sandbox: Option<Sandbox>,
+}
+
+/// Builds the command Zed runs to start Serena.
+pub fn build_command(
+    launch: &Launch,
+    lookup_env: impl Fn(&str) -> Option<String>,
+    fs: &impl FileSystem,
+) -> Result<Command> {
+    with_launch(launch, |inner| crate::build_command(inner, lookup_env, fs))
+}
+
+/// Builds the bare server command the readiness check starts.
+pub fn check_command(
+    launch: &Launch,
+    lookup_env: impl Fn(&str) -> Option<String>,
+    fs: &impl FileSystem,
+) -> Result<Command> {
+    with_launch(launch, |inner| crate::check_command(inner, lookup_env, fs))
+}
+
+/// Parses the settings and help text and hands the extension's own launch
+/// description to `build`.
+fn with_launch<T>(launch: &Launch, build: impl FnOnce(&crate::Launch) -> Result<T>) -> Result<T> {
+    let settings = launch.settings.clone().map(parse_settings).transpose()?;
+    let capabilities = launch.help.map(Capabilities::parse);
+    build(&crate::Launch {
+        settings: settings.as_ref(),
+        python: launch.python,
+        module: launch.module,
+        serena_version: launch.serena_version,
+        capabilities: capabilities.as_ref(),
+        config_overlay: launch.config_overlay,
+        os: launch.os,
+        home: launch.home,
+        workspace: Workspace::from_worktree_count(launch.worktrees),
+        project_root: settings.as_ref().and_then(crate::project_root),
+        work_dir: launch.work_dir,
+        sandbox: launch.sandbox,
+    })
+}
@@ -1,409 +1,2872 @@
-use schemars::JsonSchema;
+//! Zed extension that runs the Serena MCP server as a context server.
+//!
+//! Interpreter discovery lives in the `python-discovery` crate. With the `lib`
+//! feature, the serena installation and launcher modules are public too, so
+//! other extensions for Python-based servers, and this crate's integration
+//! tests, can use them directly.
+
+#![cfg_attr(feature = "lib", warn(missing_docs))]
+
+use python_discovery::process::{Invocation, Runner};
+use python_discovery::{detection, discovery, probe_cache, sandbox, timings, version};
 use serde::Deserialize;
-use std::process::Command as StdCommand;
 use zed::settings::ContextServerSettings;
 use zed_extension_api::{
     self as zed, serde_json, Command, ContextServerConfiguration, ContextServerId, Project, Result,
 };
 
-#[allow(dead_code)]
-const PACKAGE_NAME: &str = "serena-agent";
+/// Declares modules that form the library surface: public with the `lib`
+/// feature, private to the extension otherwise.
+macro_rules! library_modules {
+    ($($module:ident),* $(,)?) => {
+        $(
+            #[cfg(feature = "lib")]
+            pub mod $module;
+            #[cfg(not(feature = "lib"))]
+            mod $module;
+        )*
+    };
+}
+
+library_modules!(
+    installation,
+    instances,
+    pythonpath,
+    readiness,
+    scripts,
+    shell,
+    wrapper
+);
+
+mod actions;
+mod capabilities;
+mod census;
+mod cleanup;
+mod compatibility;
+mod config;
+mod conflicts;
+mod diagnostics;
+mod errors;
+mod footprint;
+mod generated;
+mod ignores;
+mod instructions;
+mod jsonc;
+#[cfg(feature = "lib")]
+pub mod launch;
+mod overlay;
+mod paths;
+mod pipeline;
+mod process;
+mod pyproject;
+mod reference;
+mod repo_config;
+mod retry;
+mod secrets;
+mod state;
+#[cfg(test)]
+mod testing;
+mod updates;
+mod validation;
+mod zed_python;
+
+/// Work shared across calls is done once, on first use.
+#[derive(Default)]
+struct SerenaContextServerExtension {
+    /// Platform Zed runs on
+    platform: std::sync::OnceLock<(zed::Os, zed::Architecture)>,
+    /// The platform in python-discovery's terms, including any sandbox
+    host: std::sync::OnceLock<python_discovery::Host>,
+    /// Persisted state, loaded from the work dir by the first call that needs it
+    state: Option<LoadedState>,
+}
+
+/// Extension state together with the directory it persists to.
+struct LoadedState {
+    work_dir: Option<std::path::PathBuf>,
+    state: state::ExtensionState,
+}
+
+impl LoadedState {
+    /// Persists the state; failures only cost a re-probe or a repeated check.
+    fn save(&self) {
+        if let Some(work_dir) = &self.work_dir {
+            let _ = self.state.save(work_dir);
+        }
+    }
+
+    /// Updates the launch progress and persists it, so an interrupted launch
+    /// can resume.
+    fn advance(&mut self, update: impl FnOnce(&mut pipeline::Progress)) {
+        if let Some(progress) = &mut self.state.launch_progress {
+            update(progress);
+        }
+        self.save();
+    }
+
+    /// Records that a requested reinstall failed, before the error aborts the launch.
+    fn record_reinstall_failure(&mut self, reinstall: bool, err: &str) {
+        if reinstall {
+            self.state.action_report = Some(actions::ActionReport::new(
+                actions::Action::Reinstall,
+                Err(err.to_string()),
+            ));
+            self.save();
+        }
+    }
+}
+
+impl SerenaContextServerExtension {
+    fn platform(&self) -> (zed::Os, zed::Architecture) {
+        *self.platform.get_or_init(zed::current_platform)
+    }
+
+    fn host(&self) -> python_discovery::Host {
+        *self
+            .host
+            .get_or_init(|| discovery_host(self.platform(), &process::ZedRunner))
+    }
+
+    fn loaded_state(&mut self) -> &mut LoadedState {
+        self.state.get_or_insert_with(|| {
+            let work_dir = state::work_dir().ok();
+            let state = work_dir
+                .as_deref()
+                .map(state::ExtensionState::load)
+                .unwrap_or_default();
+            LoadedState { work_dir, state }
+        })
+    }
+
+    /// Runs the launch pipeline and returns the command for Zed.
+    fn launch(
+        &mut self,
+        context_server_id: &ContextServerId,
+        project: &Project,
+    ) -> Result<Command> {
+        // Get settings from the entry Zed is starting, which users may have renamed
+        let settings = ContextServerSettings::for_project(context_server_id.as_ref(), project)?;
+
+        // Layer Zed settings with repo config files, pyproject.toml and the environment
+        let layered = config::LayeredConfig::load(settings.settings)?;
+        let merged = layered.merged();
+        let mut user_settings = merged.clone().map(parse_settings).transpose()?;
+
+        // Find Python executable, reusing recent probe results
+        let os = self.platform().0;
+        let host = self.host();
+        let runner = process::ZedRunner;
+        let loaded = self.loaded_state();
+        loaded.state.launch_warnings.clear();
+        if let Some(settings) = &mut user_settings {
+            if let Some(size) = project_size(settings, &mut loaded.state.census) {
+                apply_size_defaults(settings, size);
+            }
+        }
+
+        // Maintenance requested through the `action` setting runs once
+        let requested = user_settings.as_ref().and_then(|settings| {
+            let cleanup = settings.cleanup == Some(true);
+            settings
+                .action
+                .or(cleanup.then_some(actions::Action::Cleanup))
+        });
+        let action = actions::pending(requested, &mut loaded.state.action_done);
+        if action == Some(actions::Action::Cleanup) {
+            loaded.state.launch_progress = None;
+            return Err(cleanup(
+                loaded,
+                user_settings.as_ref(),
+                context_server_id,
+                layered.project_root(),
+                os,
+            ));
+        }
+        if action == Some(actions::Action::ClearCache) {
+            loaded.state.probe_cache = Default::default();
+            loaded.state.update_check = None;
+            loaded.state.readiness = None;
+            loaded.state.cli_capabilities.clear();
+            loaded.state.action_report = Some(actions::ActionReport::new(
+                actions::Action::ClearCache,
+                Ok(
+                    "Cleared cached interpreter probes, Serena's options and the update check."
+                        .to_string(),
+                ),
+            ));
+        }
+        prepare_probe_cache(&mut loaded.state, merged.as_ref(), user_settings.as_ref());
+
+        // Detect, unless an interrupted launch with the same settings already did
+        let progress = pipeline::Progress::resume(
+            loaded
+                .state
+                .launch_progress
+                .as_ref()
+                .filter(|_| action.is_none()),
+            probe_cache::settings_hash(merged.as_ref()),
+            state::now(),
+        );
+        let resumed_install = progress.installed.clone();
+        let resolved = match progress.python.clone() {
+            Some(path) => Ok(ResolvedPython {
+                path,
+                fallback: progress.fallback.clone(),
+            }),
+            None => resolve_python_executable(
+                user_settings.as_ref(),
+                layered.project_root(),
+                host,
+                &runner,
+                &mut loaded.state.probe_cache,
+            ),
+        };
+        loaded.state.launch_progress = Some(progress);
+        loaded.save();
+        let ResolvedPython {
+            path: python_exe,
+            fallback,
+        } = resolved?;
+
+        // Validate the Python executable path for basic security
+        if python_exe.is_empty() {
+            return Err("Python executable path cannot be empty".into());
+        }
+        // The configuration panel warns about a fallback; the progress keeps its reason
+        loaded.advance(|progress| {
+            progress.fallback = fallback.clone();
+            progress.detected(&python_exe, state::now());
+        });
+
+        let home = match os {
+            zed::Os::Windows => std::env::var("USERPROFILE").ok(),
+            zed::Os::Mac | zed::Os::Linux => std::env::var("HOME").ok(),
+        };
+
+        let timeouts = timeouts(user_settings.as_ref());
+        let extras = user_settings
+            .as_ref()
+            .and_then(|settings| settings.extras.clone())
+            .unwrap_or_default();
+        installation::validate_extras(&extras)?;
+        let install_scope = user_settings
+            .as_ref()
+            .and_then(|settings| settings.install_scope);
+        let reinstall = action == Some(actions::Action::Reinstall);
+        let install_options = installation::InstallOptions {
+            extras,
+            constraints: installation::constraints_file(
+                user_settings
+                    .as_ref()
+                    .and_then(|settings| settings.constraints_file.as_deref()),
+                layered.project_root(),
+                home.as_deref(),
+                &scripts::RealFs(os),
+            )?,
+            user: install_scope == Some(InstallScope::User),
+            reinstall,
+            timeout: timeouts.install,
+        };
+        let auto_install = user_settings
+            .as_ref()
+            .and_then(|settings| settings.auto_install)
+            .unwrap_or(false);
+
+        // Ensure Installed, unless an interrupted launch got past it
+        let python_exe = match resumed_install {
+            Some(installed) => installed,
+            None => {
+                // The nightly server runs upstream Serena from an environment it manages,
+                // built from the detected or configured interpreter. Auto-install uses
+                // one too when install_scope asks for it. Without a scope, Windows
+                // installs below paths with spaces or non-ASCII characters go there as
+                // well, since pip produces broken scripts for them.
+                let channel = installation::Channel::for_server(context_server_id.as_ref());
+                let restricted = user_settings
+                    .as_ref()
+                    .is_some_and(|settings| settings.restricted_environment == Some(true));
+                if restricted && channel == installation::Channel::Nightly {
+                    return Err("The nightly server installs Serena from git, which restricted_environment rules out; use serena-context-server instead".into());
+                }
+                // Installs retried after network errors are listed in the panel
+                let mut retries = Vec::new();
+                let managed = match (channel, install_scope) {
+                    (installation::Channel::Nightly, _) => true,
+                    (installation::Channel::Stable, _) if !auto_install => false,
+                    (installation::Channel::Stable, Some(scope)) => scope == InstallScope::Venv,
+                    (installation::Channel::Stable, None) => {
+                        os == zed::Os::Windows
+                            && installation::has_unsafe_chars(&python_exe)
+                            && !installation::serena_installed(
+                                &python_exe,
+                                &mut loaded.state.probe_cache,
+                                timeouts.preflight,
+                                &runner,
+                            )?
+                    }
+                };
+                let python_exe = if managed {
+                    let env_dir = managed_env_dir(
+                        user_settings.as_ref(),
+                        channel,
+                        loaded.work_dir.as_deref(),
+                        layered.project_root(),
+                        os,
+                        home.as_deref(),
+                    )?
+                    .ok_or("A managed environment needs the extension work directory")?;
+                    // A reinstall rebuilds the managed environment from scratch
+                    if reinstall && env_dir.exists() {
+                        std::fs::remove_dir_all(&env_dir)
+                            .map_err(|e| format!("Failed to remove {}: {}", env_dir.display(), e))
+                            .inspect_err(|err| loaded.record_reinstall_failure(reinstall, err))?;
+                    }
+                    installation::ensure_managed_env(
+                        &python_exe,
+                        &env_dir,
+                        os,
+                        channel,
+                        &install_options,
+                        &runner,
+                        &mut retries,
+                    )
+                    .inspect_err(|err| loaded.record_reinstall_failure(reinstall, err))?
+                } else {
+                    python_exe
+                };
+
+                // Only check for serena-agent when auto-install is requested; otherwise
+                // assume it is installed to avoid issues in restricted environments.
+                // An explicit reinstall always runs.
+                let install = reinstall
+                    || auto_install
+                        && !managed
+                        && !installation::serena_installed(
+                            &python_exe,
+                            &mut loaded.state.probe_cache,
+                            timeouts.preflight,
+                            &runner,
+                        )?;
+                // A configured interpreter gets the same serena-agent check as
+                // a detected one, so a missing install isn't an import error later
+                let configured = user_settings
+                    .as_ref()
+                    .and_then(|settings| settings.python_executable.as_deref())
+                    .filter(|_| fallback.is_none());
+                if let (Some(configured), false) = (
+                    configured,
+                    install || managed || has_custom_command(user_settings.as_ref()),
+                ) {
+                    let installed = installation::serena_installed(
+                        &python_exe,
+                        &mut loaded.state.probe_cache,
+                        timeouts.preflight,
+                        &runner,
+                    )?;
+                    if !installed {
+                        return Err(format!(
+                            "python_executable `{}` doesn't have serena-agent installed; \
+                             run `{} -m pip install serena-agent` or set \"auto_install\": true",
+                            configured, python_exe
+                        ));
+                    }
+                }
+                if install && !managed {
+                    installation::install_serena(
+                        &python_exe,
+                        &install_options,
+                        &runner,
+                        &mut retries,
+                    )
+                    .inspect_err(|err| loaded.record_reinstall_failure(reinstall, err))?;
+                    loaded
+                        .state
+                        .probe_cache
+                        .set_serena_installed(&python_exe, true);
+                    loaded.save();
+                }
+                loaded.state.launch_warnings.append(&mut retries);
+                if reinstall {
+                    // Cached versions and entry points describe the old install
+                    loaded.state.probe_cache = Default::default();
+                    loaded.state.footprint = None;
+                    loaded.state.action_report = Some(actions::ActionReport::new(
+                        actions::Action::Reinstall,
+                        Ok(format!("Reinstalled serena-agent for `{}`.", python_exe)),
+                    ));
+                }
+
+                python_exe
+            }
+        };
+        loaded.advance(|progress| progress.installed(&python_exe, state::now()));
+
+        // Releases have moved the entry point and grown flags, so ask the
+        // interpreter which module to run and which version it has
+        let entry = installation::serena_entry(
+            &python_exe,
+            &mut loaded.state.probe_cache,
+            timeouts.preflight,
+            &runner,
+        );
+        loaded.save();
+        let entry = entry?;
+
+        // Leave out flags the installed release would reject; a custom
+        // serena_command may not be the same release, so it gets them all
+        let custom_command = has_custom_command(user_settings.as_ref());
+        let capabilities = (!custom_command)
+            .then(|| {
+                capabilities::lookup(
+                    &mut loaded.state.cli_capabilities,
+                    &python_exe,
+                    &entry,
+                    timeouts.preflight,
+                    &runner,
+                )
+            })
+            .flatten();
+
+        if let Some(settings) = &user_settings {
+            check_project_subpath(settings, |path| path.is_dir())?;
+        }
+
+        // Options Serena only reads from YAML go into a regenerated overlay
+        let config_overlay = user_settings
+            .as_ref()
+            .map(|settings| {
+                overlay::write(
+                    settings,
+                    &ignored_paths(settings),
+                    loaded.work_dir.as_deref(),
+                )
+            })
+            .transpose()?
+            .flatten();
+
+        // Serena downloads language servers for the interpreter's architecture;
+        // the panel and the diagnostics file warn about a mismatch
+        let machine = loaded.state.probe_cache.machine(&python_exe, || {
+            detection::python_machine(&python_exe, &runner)
+        });
+
+        loaded.advance(|progress| progress.enter(pipeline::Stage::BuildCommand, state::now()));
+
+        // Commands take host paths, which differ from the guest's on Windows
+        let python_path = paths::to_host(std::path::Path::new(&python_exe), os);
+        let launch = Launch {
+            settings: user_settings.as_ref(),
+            python: &python_path,
+            module: &entry.module,
+            serena_version: entry.version.as_deref(),
+            capabilities: capabilities.as_ref(),
+            config_overlay: config_overlay.as_deref(),
+            os,
+            home: home.as_deref().map(std::path::Path::new),
+            workspace: Workspace::from_worktree_count(project.worktree_ids().len()),
+            project_root: layered.project_root(),
+            work_dir: loaded.work_dir.as_deref(),
+            sandbox: host.sandbox,
+        };
+        let lookup_env = |name: &str| std::env::var(name).ok();
+        let command = build_command(&launch, lookup_env, &scripts::RealFs(os))?;
+        let check = readiness::LaunchCommand::from_command(&check_command(
+            &launch,
+            lookup_env,
+            &scripts::RealFs(os),
+        )?);
+        loaded.advance(|progress| progress.enter(pipeline::Stage::Ready, state::now()));
+
+        // Reindexing and the doctor report on the launch but never block it
+        match action {
+            Some(actions::Action::Reindex) => {
+                let result = match user_settings.as_ref().and_then(serena_project) {
+                    Some(project) => actions::reindex(
+                        &python_path.to_string_lossy(),
+                        &entry.module,
+                        &project,
+                        timeouts.install,
+                        &runner,
+                    ),
+                    None => Err("Reindexing needs project_root.".to_string()),
+                };
+                loaded.state.action_report =
+                    Some(actions::ActionReport::new(actions::Action::Reindex, result));
+            }
+            Some(actions::Action::Doctor) => {
+                let python = python_path.to_string_lossy();
+                let readiness = readiness::Readiness::check(
+                    state::now(),
+                    &python,
+                    &check,
+                    host.sandbox,
+                    timeouts.preflight,
+                    &runner,
+                );
+                let result = actions::doctor(
+                    &python,
+                    detection::python_version(&python, &runner).as_deref(),
+                    &entry,
+                    &readiness,
+                );
+                loaded.state.action_report =
+                    Some(actions::ActionReport::new(actions::Action::Doctor, result));
+            }
+            _ => {}
+        }
+
+        let write_diagnostics = user_settings
+            .as_ref()
+            .and_then(|settings| settings.write_diagnostics_file)
+            .unwrap_or(false);
+        if let (true, Some(work_dir)) = (write_diagnostics, loaded.work_dir.as_deref()) {
+            let interpreters = detect_interpreters(
+                user_settings.as_ref(),
+                layered.project_root(),
+                host,
+                &runner,
+                &mut loaded.state.probe_cache,
+            )
+            .unwrap_or_default();
+            let python = python_path.to_string_lossy();
+            let report = diagnostics::Report {
+                host,
+                python: &python,
+                python_version: detection::python_version(&python, &runner),
+                machine: machine.as_deref(),
+                entry: &entry,
+                interpreters: &interpreters,
+                generic_candidates: &generic_candidates(
+                    user_settings.as_ref(),
+                    &loaded.state.probe_cache,
+                ),
+                skip_conda_base: skips_conda_base(user_settings.as_ref()),
+                config: &layered,
+                command: &command,
+            };
+            // Like the launch record, the report must never block the server
+            if let Err(err) = report.write(work_dir, state::now()) {
+                loaded
+                    .state
+                    .launch_warnings
+                    .push(format!("The diagnostics file wasn't written: {}", err));
+            }
+        }
 
-struct SerenaContextServerExtension;
+        // Failing to persist the launch must never prevent the server from starting
+        loaded.state.record_launch(
+            &python_path.to_string_lossy(),
+            readiness::LaunchCommand::from_command(&command),
+            check,
+            merged.as_ref(),
+        );
+        // Without a readiness check, a launch that got this far counts as healthy
+        let readiness_check = user_settings
+            .as_ref()
+            .and_then(|settings| settings.readiness_check)
+            .unwrap_or(false);
+        if !readiness_check {
+            loaded.state.mark_healthy();
+        }
+        loaded.save();
+        Ok(command)
+    }
+}
 
-#[derive(Debug, Deserialize, JsonSchema)]
+#[derive(Debug, Default, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 struct SerenaContextServerSettings {
     /// Python executable to use (optional, defaults to auto-detection)
     python_executable: Option<String>,
+    /// If python_executable is broken, e.g. a moved virtual environment or a deleted pyenv version, auto-detect an interpreter instead of failing, with a warning in the configuration panel
+    fallback_to_autodetect: Option<bool>,
+    /// Interpreter to use when auto-detection finds several, e.g. "/src/app/.venv/bin/python"; the configuration panel lists them
+    preferred_python: Option<String>,
+    /// pyenv-virtualenv environment to run Serena from, by name, e.g. "serena-3.12"; takes precedence over auto-detection
+    pyenv_virtualenv: Option<String>,
+    /// Pass over the Python of a conda base installation, e.g. Anaconda's bundled one, unless no other interpreter qualifies
+    #[cfg_attr(feature = "schema", schemars(extend("default" = true)))]
+    skip_conda_base: Option<bool>,
+    /// Interpreters auto-detection never picks, as paths or globs where `*` matches any characters, e.g. ["/usr/bin/*"]
+    excluded_pythons: Option<Vec<String>>,
+    /// Python version to require during auto-detection ("3.11" or "3.12")
+    python_version: Option<String>,
+    /// Accept pre-release interpreters (e.g. 3.13 betas and release candidates) during auto-detection
+    allow_prereleases: Option<bool>,
+    /// Accept free-threaded interpreters (e.g. python3.13t) during auto-detection
+    allow_free_threaded: Option<bool>,
+    /// Accept Python releases Serena doesn't support, e.g. 3.10 or 3.14, with a warning in the configuration panel
+    allow_unsupported_python: Option<bool>,
+    /// Regular expression that reads the version from interpreter banners standard parsing can't, e.g. "Python (\\d+\\.\\d+\\.\\d+)" for vendor builds; the first group, or else the whole match, is the version
+    version_accept_regex: Option<String>,
+    /// Prefer the interpreter pyright or basedpyright are configured with in the project's .zed/settings.json
+    #[cfg_attr(feature = "schema", schemars(extend("default" = true)))]
+    use_zed_python_settings: Option<bool>,
+    /// Minutes interpreter probe results are cached between restarts; 0 disables the cache
+    #[cfg_attr(feature = "schema", schemars(extend("default" = 60)))]
+    probe_cache_ttl_minutes: Option<u64>,
+    /// Discard cached interpreter probes once and detect again
+    clear_cache: Option<bool>,
+    /// Which interpreters win during auto-detection: "venv-first", "system-first" or "managed-only"
+    #[cfg_attr(feature = "schema", schemars(extend("default" = "venv-first")))]
+    python_preference: Option<discovery::PythonPreference>,
     /// Additional environment variables for Serena
     environment: Option<std::collections::HashMap<String, String>>,
+    /// Command that starts Serena in place of the `serena` console script, e.g. ["serena-corp"] or ["uv", "run", "serena"]
+    serena_command: Option<Vec<String>>,
+    /// Start Serena through the login shell ($SHELL -lc) so shims from shell profiles work, e.g. pyenv, conda or direnv (Unix only)
+    launch_via_shell: Option<bool>,
+    /// Run Python with -I, ignoring user site-packages and PYTHON* environment variables
+    isolated: Option<bool>,
+    /// Directories placed at the front of PYTHONPATH, e.g. for Serena plugins or patches
+    pythonpath: Option<Vec<String>>,
+    /// Directories appended to the end of PYTHONPATH for extra packages
+    site_packages_extra: Option<Vec<String>>,
+    /// API keys for Serena's agent features; values may be "env:VAR" or "keychain:SERVICE" references
+    api_keys: Option<secrets::ApiKeySettings>,
+    /// Serena context to start with (e.g. "ide-assistant")
+    context: Option<String>,
+    /// Agent using Serena's tools: "zed" for Zed's own agent, or "external" for an agent Zed runs as an agent server, such as Claude Code or Gemini CLI; "external" starts Serena in the ide-assistant context unless context is set
+    #[cfg_attr(feature = "schema", schemars(extend("default" = "zed")))]
+    agent: Option<Agent>,
+    /// Serena modes to activate (e.g. ["planning", "editing"])
+    modes: Option<Vec<String>>,
+    /// When modes isn't set, choose it by the number of source files: small projects skip onboarding, large ones get the planning mode and, unless it's set, forward_ignores
+    #[cfg_attr(feature = "schema", schemars(extend("default" = true)))]
+    size_defaults: Option<bool>,
+    /// Serena tools to exclude from the tool set
+    excluded_tools: Option<Vec<String>>,
+    /// Always start Serena in the ide-assistant context, whatever context and agent say, so its file-editing and shell tools don't compete with Zed's agent tools
+    #[cfg_attr(feature = "schema", schemars(extend("default" = false)))]
+    enforce_ide_context: Option<bool>,
+    /// Exclude Serena's tools that overlap Zed's agent tools, such as read_file, replace_regex and execute_shell_command, in any context
+    #[cfg_attr(feature = "schema", schemars(extend("default" = false)))]
+    exclude_overlapping_tools: Option<bool>,
+    /// Pass the project's .gitignore patterns, Zed's file_scan_exclusions and common build directories to Serena as ignored_paths
+    forward_ignores: Option<bool>,
+    /// Entries for Serena's YAML configuration that have no setting of their own, e.g. {"ls_specific_settings": {...}}
+    serena_config: Option<serde_json::Map<String, serde_json::Value>>,
+    /// Project root used to locate pyproject.toml and repo config files, which are not read without it
+    project_root: Option<String>,
+    /// Subdirectory of project_root Serena works on, e.g. "services/billing", so a large monorepo isn't indexed as a whole
+    project_subpath: Option<String>,
+    /// Directory Serena runs in; relative paths resolve against project_root (defaults to the project Serena works on)
+    working_directory: Option<String>,
+    /// After each launch, start Serena once more from the configuration panel, complete the MCP handshake and report how long it took
+    readiness_check: Option<bool>,
+    /// Index the project's symbols before Serena starts instead of as tools first need them; suits workstations, while laptops may prefer the lazy default
+    #[cfg_attr(feature = "schema", schemars(extend("default" = false)))]
+    precompute_symbols: Option<bool>,
+    /// Seconds precompute_symbols may index before Serena starts anyway and indexes the rest lazily
+    #[cfg_attr(feature = "schema", schemars(range(min = 1), extend("default" = 45)))]
+    precompute_timeout_seconds: Option<u64>,
+    /// When a launch fails, e.g. on a transient environment problem, start Serena with the command of the last launch that proved healthy and show the failure in the configuration panel
+    #[cfg_attr(feature = "schema", schemars(extend("default" = false)))]
+    use_last_known_good: Option<bool>,
+    /// On each launch, write the detection results, effective settings and launch command to serena-diagnostics.json in the extension work directory, with secrets redacted
+    write_diagnostics_file: Option<bool>,
+    /// On Linux, set LC_ALL and LANG to C.UTF-8 when Zed's locale is missing, C or POSIX
+    #[cfg_attr(feature = "schema", schemars(extend("default" = true)))]
+    utf8_locale: Option<bool>,
+    /// Show the effective merged configuration in the configuration panel
+    debug: Option<bool>,
+    /// How much context launch errors include: "brief" (one line), "normal" or "verbose" (also the launch stage, interpreter probes and environment)
+    #[cfg_attr(feature = "schema", schemars(extend("default" = "normal")))]
+    error_detail: Option<errors::ErrorDetail>,
+    /// Maintenance to run once on the next launch: "reinstall", "clear-cache", "reindex" or "doctor"; the configuration panel shows the outcome, and removing the setting lets the same action run again
+    action: Option<actions::Action>,
+    /// Once, on the next launch, remove the managed environments, cached probes, generated overlays and the language servers Serena downloaded, report the space reclaimed and don't start Serena; memories are kept
+    cleanup: Option<bool>,
+    /// Install serena-agent with pip on launch when it is missing
+    auto_install: Option<bool>,
+    /// Where auto-install puts serena-agent: "venv" (an environment the extension manages), "user" (pip install --user) or "system" (the interpreter's site-packages)
+    install_scope: Option<InstallScope>,
+    /// Directory of the virtual environment the extension manages, e.g. on a faster disk; relative paths resolve against project_root (defaults to the extension work directory)
+    venv_dir: Option<String>,
+    /// Optional serena-agent dependency groups to install, e.g. ["agno", "google"] installs serena-agent[agno,google]
+    extras: Option<Vec<String>>,
+    /// pip constraints file pinning dependency versions during auto-install; relative paths resolve against project_root (defaults to .serena/constraints.txt in project_root when present)
+    constraints_file: Option<String>,
+    /// Seconds each pip install or environment creation may run before it is stopped
+    #[cfg_attr(feature = "schema", schemars(range(min = 1), extend("default" = 600)))]
+    install_timeout_seconds: Option<u64>,
+    /// Seconds each check of the interpreter's serena may run before it is stopped
+    #[cfg_attr(feature = "schema", schemars(range(min = 1), extend("default" = 30)))]
+    preflight_timeout_seconds: Option<u64>,
+    /// Check PyPI once a day for serena-agent updates
+    #[cfg_attr(feature = "schema", schemars(extend("default" = false)))]
+    check_for_updates: Option<bool>,
+    /// For locked-down machines: no network access at all, so no update checks, installs, analytics or language server downloads; the configuration panel lists the languages that lose their language server
+    restricted_environment: Option<bool>,
+    /// Show the full setup guide even after setup has completed
+    show_setup_guide: Option<bool>,
+    /// Allow Serena and its dependencies to send usage analytics
+    #[cfg_attr(feature = "schema", schemars(extend("default" = false)))]
+    analytics: Option<bool>,
+    /// Transport between the extension and Serena: "stdio", or "sse" together with share_instance
+    #[cfg_attr(feature = "schema", schemars(extend("default" = "stdio")))]
+    transport: Option<Transport>,
+    /// Serve Serena's web dashboard (Serena's default is on); the configuration panel links to it
+    web_dashboard: Option<bool>,
+    /// Directory Serena's downloaded language servers are kept in, e.g. a shared team cache or a directory that isn't backed up; Serena's language_servers directory becomes a link to it
+    language_server_cache: Option<String>,
+    /// Directory of pre-downloaded language servers, e.g. on a network share, linked into Serena's language_servers directory wherever it lacks them, so Serena needs no downloads; the bundle is only read
+    language_server_bundle: Option<String>,
+    /// Give each project root its own Serena home (SERENA_HOME) in the extension work dir, so projects never share Serena's configuration, project list, logs or language servers; memories always stay in the project's .serena/memories
+    isolate_memories: Option<bool>,
+    /// Share one Serena instance per project across Zed windows via the SSE transport (Unix only)
+    share_instance: Option<bool>,
+    /// Port for the shared SSE instance; unset or 0 picks a free port, preferring one derived from the project path
+    port: Option<u16>,
+    /// With share_instance, terminate other projects' shared instances that no Zed window is connected to anymore, e.g. after Zed crashed
+    #[cfg_attr(feature = "schema", schemars(extend("default" = false)))]
+    reap_stale_instances: Option<bool>,
+    /// Stop the language servers Serena started when Zed stops Serena, using a Job Object on Windows and a separate session on Unix
+    #[cfg_attr(feature = "schema", schemars(extend("default" = false)))]
+    kill_process_tree: Option<bool>,
+    /// Seconds Serena gets to save its state and stop its language servers after being asked to stop, before it is killed (Unix only)
+    #[cfg_attr(feature = "schema", schemars(extend("default" = 10)))]
+    shutdown_grace_seconds: Option<u64>,
+    /// Scheduling priority adjustment for the Serena process, 0-19 (Unix only)
+    #[cfg_attr(feature = "schema", schemars(range(min = 0, max = 19)))]
+    niceness: Option<i32>,
+    /// Address-space ceiling for the Serena process in megabytes (Unix only)
+    memory_limit_mb: Option<u64>,
+    /// Stop Serena after this many minutes without MCP traffic
+    idle_timeout_minutes: Option<u64>,
+    /// Command run before Serena starts, e.g. ["direnv", "allow"]; Serena is not started if it fails
+    pre_launch_command: Option<Vec<String>>,
+    /// Command run after Serena exits, e.g. to clean up temporary state
+    post_exit_command: Option<Vec<String>>,
+    /// Seconds a pre-launch or post-exit command may run before it is stopped
+    #[cfg_attr(feature = "schema", schemars(range(min = 1), extend("default" = 30)))]
+    hook_timeout_seconds: Option<u64>,
+    /// Command bridging Zed's stdio to the shared instance; the SSE URL is appended
+    #[cfg_attr(feature = "schema", schemars(extend("default" = ["mcp-proxy"])))]
+    sse_bridge_command: Option<Vec<String>>,
 }
 
 impl zed::Extension for SerenaContextServerExtension {
     fn new() -> Self {
-        Self
+        Self::default()
     }
 
     fn context_server_command(
         &mut self,
-        _context_server_id: &ContextServerId,
+        context_server_id: &ContextServerId,
         project: &Project,
     ) -> Result<Command> {
-        // Get settings from project configuration
-        let settings = ContextServerSettings::for_project("serena-context-server", project)?;
-        let user_settings: Option<SerenaContextServerSettings> = settings
-            .settings
-            .map(serde_json::from_value)
-            .transpose()
-            .map_err(|e| format!("Invalid settings: {}", e))?;
-
-        // Find Python executable
-        let python_exe = match &user_settings {
-            Some(settings) if settings.python_executable.is_some() => settings
-                .python_executable
+        let result = self.launch(context_server_id, project);
+        let Err(err) = result else {
+            return result;
+        };
+        let os = self.platform().0;
+        let host = self.host();
+        let loaded = self.loaded_state();
+        // A failed launch leaves its stage and the full reason for the panel
+        if let Some(progress) = &mut loaded.state.launch_progress {
+            progress.fail(&err);
+            loaded.save();
+        }
+        let settings = current_settings(context_server_id, project);
+        // The panel shows the failure and that the last-known-good command runs
+        if let Some(command) = last_known_good_command(settings.as_ref(), &loaded.state, os) {
+            return Ok(command);
+        }
+        Err(errors::shape(
+            &err,
+            settings
+                .and_then(|settings| settings.error_detail)
+                .unwrap_or_default(),
+            || errors::Context {
+                host,
+                progress: loaded.state.launch_progress.as_ref(),
+                probes: loaded.state.probe_cache.version_probes().collect(),
+                lookup_env: &|name| std::env::var(name).ok(),
+            },
+        ))
+    }
+
+    fn context_server_configuration(
+        &mut self,
+        context_server_id: &ContextServerId,
+        project: &Project,
+    ) -> Result<Option<ContextServerConfiguration>> {
+        let settings = ContextServerSettings::for_project(context_server_id.as_ref(), project)
+            .ok()
+            .and_then(|settings| config::LayeredConfig::load(settings.settings).ok());
+        let merged = settings.as_ref().and_then(|layered| layered.merged());
+        let user_settings = merged
+            .clone()
+            .and_then(|merged| parse_settings(merged).ok());
+
+        let os = self.platform().0;
+        let host = self.host();
+        let runner = process::ZedRunner;
+        let loaded = self.loaded_state();
+        let state = &mut loaded.state;
+        prepare_probe_cache(state, merged.as_ref(), user_settings.as_ref());
+        let timeouts = timeouts(user_settings.as_ref());
+        let resolved = resolve_python_executable(
+            user_settings.as_ref(),
+            settings.as_ref().and_then(|layered| layered.project_root()),
+            host,
+            &runner,
+            &mut state.probe_cache,
+        );
+        let fallback = resolved
+            .as_ref()
+            .ok()
+            .and_then(|resolved| resolved.fallback.clone());
+        let python = resolved.map(|resolved| resolved.path);
+
+        // Prefer a managed environment once a launch has created it
+        let channel = installation::Channel::for_server(context_server_id.as_ref());
+        let home = match os {
+            zed::Os::Windows => std::env::var("USERPROFILE").ok(),
+            zed::Os::Mac | zed::Os::Linux => std::env::var("HOME").ok(),
+        };
+        let managed_env = managed_env_dir(
+            user_settings.as_ref(),
+            channel,
+            loaded.work_dir.as_deref(),
+            settings.as_ref().and_then(|layered| layered.project_root()),
+            os,
+            home.as_deref(),
+        )
+        .ok()
+        .flatten();
+        let python = match &managed_env {
+            Some(env_dir) if installation::env_python(env_dir, os).is_file() => {
+                Ok(installation::env_python(env_dir, os)
+                    .to_string_lossy()
+                    .to_string())
+            }
+            _ => python,
+        };
+        let mut timings = timings::Timings::default();
+        timings.append(&mut state.probe_cache.timings);
+        let installed_serena = timings.time("serena version lookup", || {
+            python.as_deref().ok().and_then(|python| {
+                installation::serena_version(python, timeouts.preflight, &runner)
+            })
+        });
+        let installed_python = timings.time("python version lookup", || {
+            python
                 .as_deref()
-                .unwrap_or_default()
-                .to_string(),
-            _ => find_python_executable()?,
+                .ok()
+                .and_then(|python| detection::python_version(python, &runner))
+        });
+
+        // The compatibility table knows which release to switch to, so its
+        // advice replaces the generic interpreter warning
+        let unsupported_python = match installed_serena
+            .as_deref()
+            .and_then(|serena| compatibility::advice(serena, installed_python.as_deref()))
+        {
+            Some(advice) => Some(instructions::compatibility_notice(&advice)),
+            None => installed_python
+                .as_deref()
+                .filter(|version| !detection::is_supported_python(version))
+                .map(instructions::unsupported_python_notice),
         };
+        let mut installation_instructions = instructions::banner(&instructions::Banner {
+            extension_version: env!("CARGO_PKG_VERSION"),
+            python_version: installed_python,
+            serena_version: installed_serena.clone(),
+            last_launch: state.last_launch.as_ref().map(|launch| launch.timestamp),
+        });
 
-        // Validate the Python executable path for basic security
-        if python_exe.is_empty() {
-            return Err("Python executable path cannot be empty".into());
+        let has_project_root = user_settings
+            .as_ref()
+            .is_some_and(|settings| settings.project_root.is_some());
+        if let Some(notice) =
+            instructions::workspace_notice(project.worktree_ids().len(), has_project_root)
+        {
+            installation_instructions.push_str(&notice);
+        }
+        let config_warnings = settings.as_ref().map(|layered| layered.warnings());
+        if let Some(notice) =
+            instructions::config_warnings_notice(config_warnings.unwrap_or_default())
+        {
+            installation_instructions.push_str(&notice);
+        }
+        let duplicates = conflicts::duplicates(context_server_id.as_ref(), |name| {
+            ContextServerSettings::for_project(name, project).ok()
+        });
+        if !duplicates.is_empty() {
+            installation_instructions.push_str(&instructions::duplicate_notice(
+                &duplicates,
+                context_server_id.as_ref(),
+            ));
+        }
+        if let (Some(reason), Ok(python)) = (&fallback, &python) {
+            installation_instructions.push_str(&instructions::fallback_notice(reason, python));
+        }
+        // Mixed Homebrew installs easily pick an interpreter for the wrong architecture
+        let native_homebrew = (host.os == python_discovery::Os::Mac)
+            .then(|| discovery::HomebrewPrefix::native(host.arch));
+        let homebrew = native_homebrew
+            .zip(python.as_deref().ok())
+            .and_then(|(_, python)| discovery::HomebrewPrefix::of(python));
+        if let (Some(native), Ok(python)) = (native_homebrew, &python) {
+            if let Some(notice) = instructions::homebrew_notice(python, native) {
+                installation_instructions.push_str(&notice);
+            }
+        }
+        if let Ok(python) = &python {
+            let machine = timings.time("python architecture lookup", || {
+                state
+                    .probe_cache
+                    .machine(python, || detection::python_machine(python, &runner))
+            });
+            if let Some(notice) = machine
+                .and_then(|machine| instructions::architecture_notice(python, &machine, host.arch))
+            {
+                installation_instructions.push_str(&notice);
+            }
+        }
+        // Zed keeps the running server on the settings it was started with
+        let launched_with = state
+            .last_launch
+            .as_ref()
+            .and_then(|launch| Some((launch.settings_hash.as_ref()?, &launch.settings)));
+        if let Some((hash, launched)) = launched_with {
+            if *hash != probe_cache::settings_hash(merged.as_ref()) {
+                let mut current = merged.clone();
+                if let Some(current) = &mut current {
+                    secrets::redact_settings(current);
+                }
+                let changed = config::changed_keys(launched.as_ref(), current.as_ref());
+                installation_instructions
+                    .push_str(&instructions::settings_changed_notice(&changed));
+            }
+        }
+        if let Some(notice) = unsupported_python {
+            installation_instructions.push_str(&notice);
+        }
+        // Only projects in a supported language benefit from the symbol tools;
+        // without project_root there is no folder to scan
+        let census = user_settings
+            .as_ref()
+            .and_then(serena_project)
+            .map(|project| census::scan_cached(&project, &mut state.census));
+        if let Some(notice) = census.as_ref().and_then(instructions::language_notice) {
+            installation_instructions.push_str(&notice);
+        }
+        let restricted = user_settings
+            .as_ref()
+            .is_some_and(|settings| settings.restricted_environment == Some(true));
+        if restricted {
+            let downloaded: Vec<_> = census
+                .iter()
+                .flat_map(census::Census::downloaded_servers)
+                .collect();
+            let bundled = user_settings
+                .as_ref()
+                .is_some_and(|settings| settings.language_server_bundle.is_some());
+            installation_instructions
+                .push_str(&instructions::restricted_notice(&downloaded, bundled));
+        }
+        // Launches only size projects Serena is pointed at, so the panel does too
+        let size = user_settings
+            .as_ref()
+            .filter(|settings| settings.size_defaults != Some(false))
+            .filter(|settings| serena_project(settings).is_some())
+            .and(census.as_ref())
+            .and_then(census::Census::size);
+        if let (Some(size), Some(settings)) = (size, &user_settings) {
+            if let Some(notice) = instructions::project_size_notice(
+                size,
+                settings.modes.is_some(),
+                settings.forward_ignores.is_some(),
+            ) {
+                installation_instructions.push_str(&notice);
+            }
+        }
+        if let (installation::Channel::Nightly, Some(env_dir)) = (channel, &managed_env) {
+            installation_instructions.push_str(&instructions::nightly_notice(env_dir));
+        }
+        // Let the user choose when detection found several interpreters, or
+        // a replacement for a broken python_executable
+        let configured = fallback.is_none()
+            && user_settings
+                .as_ref()
+                .is_some_and(|settings| settings.python_executable.is_some());
+        let detected = if configured {
+            Vec::new()
+        } else {
+            detect_interpreters(
+                user_settings.as_ref(),
+                settings.as_ref().and_then(|layered| layered.project_root()),
+                host,
+                &runner,
+                &mut state.probe_cache,
+            )
+            .unwrap_or_default()
+        };
+        if detected.len() > 1 {
+            let choices: Vec<_> = detected
+                .iter()
+                .map(|interpreter| {
+                    let installed = installation::serena_installed(
+                        &interpreter.path,
+                        &mut state.probe_cache,
+                        timeouts.preflight,
+                        &runner,
+                    )
+                    .unwrap_or(false);
+                    (interpreter, installed)
+                })
+                .collect();
+            installation_instructions.push_str(&instructions::interpreter_choices(
+                &choices,
+                python.as_deref().ok(),
+                user_settings
+                    .as_ref()
+                    .and_then(|settings| settings.preferred_python.as_deref()),
+            ));
+        }
+        // Instances from other projects may hold the configured port
+        let shared_root = user_settings
+            .as_ref()
+            .filter(|settings| settings.share_instance == Some(true))
+            .zip(settings.as_ref().and_then(|layered| layered.project_root()));
+        if let (Some((shared, root)), Some(work_dir)) = (shared_root, loaded.work_dir.as_deref()) {
+            let notice = instructions::shared_instance_notice(
+                &instances::read_registry(work_dir),
+                &instances::project_key(root),
+                shared.port.filter(|port| *port != 0),
+            );
+            if let Some(notice) = notice {
+                installation_instructions.push_str(&notice);
+            }
+        }
+        // Outside ide-assistant, Serena's file and shell tools compete with Zed's
+        let context = user_settings.as_ref().and_then(serena_context);
+        let overlapping = context.as_deref() != Some(IDE_ASSISTANT_CONTEXT)
+            && user_settings
+                .as_ref()
+                .and_then(|settings| settings.exclude_overlapping_tools)
+                != Some(true);
+        if overlapping {
+            installation_instructions
+                .push_str(&instructions::tool_overlap_notice(context.as_deref()));
+        }
+        let external_agent = user_settings
+            .as_ref()
+            .is_some_and(|settings| settings.agent == Some(Agent::External));
+        if external_agent {
+            installation_instructions.push_str(&instructions::external_agent_notice(
+                context_server_id.as_ref(),
+            ));
+        }
+        let dashboard = user_settings
+            .as_ref()
+            .and_then(|settings| settings.web_dashboard)
+            != Some(false);
+        if dashboard {
+            installation_instructions.push_str(&instructions::dashboard_notice());
         }
 
-        // Skip installation check - assume serena-agent is already installed
-        // This avoids potential issues with restricted environments
-
-        // Prepare environment variables
-        let mut env_vars = Vec::new();
-        if let Some(settings) = &user_settings {
-            if let Some(env) = &settings.environment {
-                for (key, value) in env {
-                    env_vars.push((key.clone(), value.clone()));
+        let check_for_updates = user_settings
+            .as_ref()
+            .and_then(|settings| settings.check_for_updates)
+            .unwrap_or(false);
+        if let (true, Some(installed), Ok(python)) = (check_for_updates, &installed_serena, &python)
+        {
+            let latest = timings.time("PyPI update check", || {
+                updates::latest_version(state, state::now())
+            });
+            if let Some(latest) = latest {
+                if let Some(notice) = updates::notice(installed, &latest, python) {
+                    installation_instructions.push_str(&notice);
                 }
             }
         }
 
-        // Sanitize paths for Windows compatibility
-        let python_path = zed_ext::sanitize_windows_path(python_exe.into());
+        if let Some(notice) = state
+            .launch_progress
+            .as_ref()
+            .and_then(instructions::launch_progress_notice)
+        {
+            installation_instructions.push_str(&notice);
+        }
+        let failed = state
+            .launch_progress
+            .as_ref()
+            .is_some_and(|progress| progress.error.is_some());
+        // Records from before commands were stored have nothing to fall back on
+        let good = state
+            .last_known_good
+            .as_ref()
+            .filter(|good| good.command.is_some());
+        if let (true, Some(good)) = (failed, good) {
+            installation_instructions.push_str(&instructions::last_known_good_notice(
+                good,
+                uses_last_known_good(user_settings.as_ref()),
+            ));
+        }
+        if let Some(notice) = instructions::launch_warnings_notice(&state.launch_warnings) {
+            installation_instructions.push_str(&notice);
+        }
+        if let Some(report) = &state.action_report {
+            installation_instructions.push_str(&instructions::action_notice(report));
+        }
+
+        // Sizes are measured at most hourly, since environments can be huge
+        if let Some(work_dir) = loaded.work_dir.as_deref() {
+            let now = state::now();
+            if !state.footprint.as_ref().is_some_and(|f| f.is_fresh(now)) {
+                let cache = user_settings
+                    .as_ref()
+                    .and_then(|settings| settings.language_server_cache.as_deref())
+                    .map(|dir| {
+                        installation::resolve_path(
+                            dir,
+                            settings.as_ref().and_then(|layered| layered.project_root()),
+                            home.as_deref(),
+                        )
+                    });
+                let footprint = timings.time("disk usage", || {
+                    measure_footprint(
+                        work_dir,
+                        managed_env.as_deref(),
+                        cache.as_deref(),
+                        os,
+                        home.as_deref(),
+                        now,
+                    )
+                });
+                state.footprint = Some(footprint);
+            }
+            if let Some(notice) = state
+                .footprint
+                .as_ref()
+                .and_then(instructions::footprint_notice)
+            {
+                installation_instructions.push_str(&notice);
+            }
+        }
 
-        // Use the serena console script directly or call the CLI properly
-        // First try to find the serena script in the same directory as python
-        let python_dir = std::path::Path::new(&python_path)
-            .parent()
-            .ok_or("Could not determine Python directory")?;
-        let serena_script = python_dir.join("serena");
+        // Check a new launch once, then keep showing the result
+        let readiness_check = user_settings
+            .as_ref()
+            .and_then(|settings| settings.readiness_check)
+            .unwrap_or(false);
+        if let (true, Some(launch)) = (readiness_check, &state.last_launch) {
+            let checked = state
+                .readiness
+                .as_ref()
+                .is_some_and(|readiness| readiness.launch == launch.timestamp);
+            if let (false, Some(check)) = (checked, &launch.check) {
+                let readiness = timings.time("readiness check", || {
+                    readiness::Readiness::check(
+                        launch.timestamp,
+                        &launch.python,
+                        check,
+                        host.sandbox,
+                        timeouts.preflight,
+                        &runner,
+                    )
+                });
+                if readiness.error.is_none() {
+                    state.last_known_good = Some(launch.clone());
+                }
+                state.readiness = Some(readiness);
+            }
+            let current = state
+                .readiness
+                .as_ref()
+                .filter(|readiness| readiness.launch == launch.timestamp);
+            if let Some(readiness) = current {
+                installation_instructions.push_str(&instructions::readiness_notice(readiness));
+            }
+        }
 
-        let (command, args) = if serena_script.exists() {
-            // Use the serena console script directly
-            (
-                serena_script.to_string_lossy().to_string(),
-                vec!["start-mcp-server".to_string()],
-            )
+        // Walk new users through setup; afterwards show a compact status view
+        let show_guide = user_settings
+            .as_ref()
+            .and_then(|settings| settings.show_setup_guide)
+            .unwrap_or(false);
+        if state.onboarding_completed && !show_guide {
+            installation_instructions.push_str(&instructions::status_view(state));
         } else {
-            // Use proper module invocation instead of inline code manipulation
-            (
-                python_path.to_string_lossy().to_string(),
-                vec![
-                    "-m".to_string(),
-                    "serena".to_string(),
-                    "start-mcp-server".to_string(),
-                ],
-            )
-        };
+            let serena_installed = python.as_ref().ok().and_then(|python| {
+                installation::serena_installed(
+                    python,
+                    &mut state.probe_cache,
+                    timeouts.preflight,
+                    &runner,
+                )
+                .ok()
+            });
+            timings.append(&mut state.probe_cache.timings);
+            installation_instructions.push_str(&instructions::onboarding(
+                &instructions::Detection {
+                    python,
+                    serena_installed,
+                },
+            ));
+        }
+        if let Ok(schema) = settings_schema() {
+            installation_instructions.push('\n');
+            installation_instructions.push_str(&reference::render(schema));
+        }
+        // Persist probe results and the update check
+        loaded.save();
 
-        Ok(Command {
-            command,
-            args,
-            env: env_vars,
-        })
-    }
+        // Append the effective configuration when debug output is enabled
+        if let (Some(layered), Some(settings)) = (&settings, &user_settings) {
+            if settings.debug == Some(true) && !layered.is_empty() {
+                installation_instructions.push('\n');
+                installation_instructions.push_str(&layered.describe());
+            }
+            if let (Some(true), Some(homebrew)) = (settings.debug, homebrew) {
+                installation_instructions.push_str(&format!(
+                    "\n**Interpreter source:** {}\n",
+                    homebrew.describe()
+                ));
+            }
+            if let (Some(true), true) = (settings.debug, dashboard) {
+                installation_instructions.push_str(&format!(
+                    "\n**Dashboard URL:** {}\n",
+                    instructions::DASHBOARD_URL
+                ));
+            }
+            if settings.debug == Some(true) {
+                installation_instructions.push('\n');
+                installation_instructions.push_str(&describe_environment(
+                    Some(settings),
+                    os,
+                    std::env::vars(),
+                ));
+            }
+            if settings.debug == Some(true) && !timings.is_empty() {
+                installation_instructions.push('\n');
+                installation_instructions.push_str(&timings.describe());
+            }
+        }
+        let diagnostics_file = loaded
+            .work_dir
+            .as_deref()
+            .filter(|_| {
+                user_settings
+                    .as_ref()
+                    .and_then(|settings| settings.write_diagnostics_file)
+                    == Some(true)
+            })
+            .map(|work_dir| work_dir.join(diagnostics::DIAGNOSTICS_FILE));
+        if let Some(path) = diagnostics_file {
+            installation_instructions.push_str(&format!(
+                "\n**Diagnostics file:** `{}`, rewritten on each launch\n",
+                path.display()
+            ));
+        }
 
-    fn context_server_configuration(
-        &mut self,
-        _context_server_id: &ContextServerId,
-        _project: &Project,
-    ) -> Result<Option<ContextServerConfiguration>> {
-        let installation_instructions = r#"
-## Serena Context Server Setup
-
-1. **Install Python 3.11 OR 3.12** (either version works):
-   ```bash
-   # Option A: Install Python 3.11
-   brew install python@3.11
-   python3.11 --version
-   
-   # Option B: Install Python 3.12
-   brew install python@3.12
-   python3.12 --version
-   ```
-
-2. **Install Serena Agent** (use the Python version you installed):
-   ```bash
-   # If you installed Python 3.11:
-   python3.11 -m pip install serena-agent
-   
-   # If you installed Python 3.12:
-   python3.12 -m pip install serena-agent
-   ```
-
-3. **Configure in Zed settings.json**:
-   ```json
-   {
-     "context_servers": {
-       "serena-context-server": {
-         "source": "extension",
-         "enabled": true,
-         "settings": {
-           "python_executable": "/opt/homebrew/bin/python3.11"
-         }
-       }
-     }
-   }
-   ```
-
-The extension will automatically detect Python 3.11/3.12 installations, but you can specify a custom path using the `python_executable` setting.
-"#.to_string();
-
-        let default_settings = r#"
-{
-  "python_executable": null
-}
-"#
-        .to_string();
-
-        let settings_schema =
-            serde_json::to_string(&schemars::schema_for!(SerenaContextServerSettings))
-                .map_err(|e| format!("Failed to generate schema: {}", e))?;
+        let default_settings = default_settings(census.as_ref());
 
         Ok(Some(ContextServerConfiguration {
             installation_instructions,
             default_settings,
-            settings_schema,
+            settings_schema: SETTINGS_SCHEMA.to_string(),
         }))
     }
 }
 
-/// Validates a Python path for security checks
-fn validate_python_path(path: &str) -> bool {
-    // Enhanced security checks
-    if path.is_empty() || path.len() >= 1000 || path.contains('\0') {
-        return false;
+/// Settings suggested in the configuration panel.
+///
+/// Projects with no language Serena can index get the symbol tools excluded,
+/// since they would only ever come back empty.
+fn default_settings(census: Option<&census::Census>) -> String {
+    let mut settings = serde_json::json!({
+        "python_executable": null,
+        "analytics": false,
+    });
+    if census.is_some_and(census::Census::only_unsupported) {
+        settings["excluded_tools"] = serde_json::json!(census::SYMBOL_TOOLS);
     }
+    format!(
+        "\n{}\n",
+        serde_json::to_string_pretty(&settings).unwrap_or_default()
+    )
+}
 
-    // Prevent path traversal attempts
-    if path.contains("..") || path.contains("//") {
-        return false;
-    }
+/// JSON schema of [`SerenaContextServerSettings`].
+///
+/// Generated ahead of time so schemars stays out of the WASM binary; a test
+/// run with `--features schema` keeps it in sync with the struct.
+const SETTINGS_SCHEMA: &str = include_str!("settings-schema.json");
 
-    // Only allow reasonable executable names/paths
-    let path_lower = path.to_lowercase();
-    path_lower.contains("python")
-        || path_lower.starts_with("/usr/")
-        || path_lower.starts_with("/opt/")
+/// [`SETTINGS_SCHEMA`], parsed.
+fn settings_schema() -> Result<&'static serde_json::Value> {
+    // Parsed once per process; the schema is compiled in, so it never changes
+    static SCHEMA: std::sync::OnceLock<std::result::Result<serde_json::Value, String>> =
+        std::sync::OnceLock::new();
+    SCHEMA
+        .get_or_init(|| {
+            serde_json::from_str(SETTINGS_SCHEMA)
+                .map_err(|e| format!("Invalid settings schema: {}", e))
+        })
+        .as_ref()
+        .map_err(Clone::clone)
 }
 
-/// Validates Python version string to ensure it's 3.11 or 3.12
-fn is_valid_python_version(version_str: &str) -> bool {
-    // Use regex-like matching to precisely identify 3.11.x or 3.12.x versions
-    let version_str = version_str.trim();
-
-    // Match "Python 3.11" followed by end, space, or dot
-    if let Some(rest) = version_str.strip_prefix("Python 3.11") {
-        return rest.is_empty() || rest.starts_with('.') || rest.starts_with(' ');
+/// Validates merged settings against the schema and deserializes them.
+///
+/// All schema violations are reported together rather than stopping at the first.
+fn parse_settings(value: serde_json::Value) -> Result<SerenaContextServerSettings> {
+    let schema = settings_schema()?;
+    let errors = validation::validate(schema, &value);
+    if !errors.is_empty() {
+        return Err(validation::format_errors(&errors));
     }
 
-    // Match "Python 3.12" followed by end, space, or dot
-    if let Some(rest) = version_str.strip_prefix("Python 3.12") {
-        return rest.is_empty() || rest.starts_with('.') || rest.starts_with(' ');
+    let mut settings: SerenaContextServerSettings =
+        serde_json::from_value(value).map_err(|e| format!("Invalid settings: {}", e))?;
+    if settings.restricted_environment == Some(true) {
+        restrict(&mut settings);
     }
+    Ok(settings)
+}
 
-    false
+/// Turns off everything that goes online for `restricted_environment`,
+/// whatever the individual settings say.
+fn restrict(settings: &mut SerenaContextServerSettings) {
+    settings.analytics = Some(false);
+    settings.check_for_updates = Some(false);
+    settings.auto_install = Some(false);
 }
 
-fn find_python_executable() -> Result<String> {
-    // First try using which to find Python executables in PATH
-    let which_candidates = vec!["python3.11", "python3.12"];
+/// The interpreter Serena runs with.
+struct ResolvedPython {
+    path: String,
+    /// Why the configured `python_executable` was passed over for auto-detection
+    fallback: Option<String>,
+}
 
-    for candidate in &which_candidates {
-        if let Ok(output) = StdCommand::new("which").arg(candidate).output() {
-            if output.status.success() {
-                let python_path = String::from_utf8_lossy(&output.stdout).trim().to_string();
-                if !python_path.is_empty() && validate_python_path(&python_path) {
-                    // Verify it's the correct version
-                    if let Ok(version_output) =
-                        StdCommand::new(&python_path).arg("--version").output()
-                    {
-                        if version_output.status.success() {
-                            let version_str = String::from_utf8_lossy(&version_output.stdout);
-                            if is_valid_python_version(&version_str) {
-                                return Ok(python_path);
-                            }
-                        }
-                    }
-                }
-            }
+/// Uses the configured interpreter, or picks one of the detected ones.
+///
+/// With `fallback_to_autodetect`, a broken configured interpreter falls back
+/// to auto-detection instead of failing the launch.
+fn resolve_python_executable(
+    settings: Option<&SerenaContextServerSettings>,
+    project_root: Option<&std::path::Path>,
+    host: python_discovery::Host,
+    runner: &dyn Runner,
+    cache: &mut probe_cache::ProbeCache,
+) -> Result<ResolvedPython> {
+    let mut fallback = None;
+    if let Some(python) = settings.and_then(|settings| settings.python_executable.as_deref()) {
+        let home = match host.os {
+            python_discovery::Os::Windows => std::env::var("USERPROFILE").ok(),
+            _ => std::env::var("HOME").ok(),
+        };
+        let checked = check_configured_python(
+            python,
+            settings,
+            project_root,
+            home.as_deref(),
+            host,
+            runner,
+            cache,
+        );
+        let falls_back = settings
+            .and_then(|settings| settings.fallback_to_autodetect)
+            .unwrap_or(false);
+        match checked {
+            Err(err) if falls_back => fallback = Some(err),
+            checked => return checked.map(|path| ResolvedPython { path, fallback }),
         }
     }
+    let path =
+        detect_python_executable(settings, project_root, host, runner, cache).map_err(|err| {
+            match &fallback {
+                Some(reason) => format!(
+                    "{}\n\nfallback_to_autodetect found no interpreter either: {}",
+                    reason, err
+                ),
+                None => err,
+            }
+        })?;
+    Ok(ResolvedPython { path, fallback })
+}
 
-    // Fallback to hardcoded paths
-    let python_candidates = vec![
-        "/opt/homebrew/bin/python3.11",
-        "/opt/homebrew/bin/python3.12",
-        "/usr/local/bin/python3.11",
-        "/usr/local/bin/python3.12",
-        "python3.11",
-        "python3.12",
-        "python3",
-        "python",
-    ];
+/// Picks the pyenv-virtualenv interpreter or one of the detected ones.
+fn detect_python_executable(
+    settings: Option<&SerenaContextServerSettings>,
+    project_root: Option<&std::path::Path>,
+    host: python_discovery::Host,
+    runner: &dyn Runner,
+    cache: &mut probe_cache::ProbeCache,
+) -> Result<String> {
+    if let Some(name) = settings
+        .and_then(|settings| settings.pyenv_virtualenv.as_deref())
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+    {
+        return pyenv_virtualenv_python(name, host.os, |name| std::env::var(name).ok());
+    }
 
-    for candidate in &python_candidates {
-        if !validate_python_path(candidate) {
-            continue;
-        }
+    let found = detect_interpreters(settings, project_root, host, runner, cache)?;
+    let found = if skips_conda_base(settings) {
+        skip_conda_base(found, |dir| dir.is_dir())
+    } else {
+        found
+    };
+    let zed_python = zed_python_path(settings, project_root, host.os);
+    let preferred = settings
+        .and_then(|settings| settings.preferred_python.as_deref())
+        .or(zed_python.as_deref());
+    let timeout = timeouts(settings).preflight;
+    let chosen = choose_interpreter(&found, preferred, |python| {
+        installation::serena_installed(python, cache, timeout, runner).unwrap_or(false)
+    });
+    Ok(chosen.path.clone())
+}
 
-        match StdCommand::new(candidate).args(["--version"]).output() {
-            Ok(output) => {
-                if output.status.success() {
-                    let version_output = String::from_utf8_lossy(&output.stdout);
-                    // Check for Python 3.11 or 3.12 specifically (Serena requirement)
-                    if is_valid_python_version(&version_output) {
-                        return Ok(candidate.to_string());
-                    }
-                }
-            }
-            Err(_) => {
-                // Skip candidates that can't be executed
-                continue;
-            }
-        }
+/// Checks the configured `python_executable` like a detected interpreter and
+/// returns it resolved: `~` and relative paths are expanded and bare names
+/// looked up on `PATH`. The error names the configured path and what's wrong.
+fn check_configured_python(
+    configured: &str,
+    settings: Option<&SerenaContextServerSettings>,
+    project_root: Option<&std::path::Path>,
+    home: Option<&str>,
+    host: python_discovery::Host,
+    runner: &dyn Runner,
+    cache: &mut probe_cache::ProbeCache,
+) -> Result<String> {
+    let trimmed = configured.trim();
+    if trimmed.is_empty() {
+        return Err(
+            "python_executable is empty; set it to an interpreter or remove it to auto-detect"
+                .to_string(),
+        );
     }
+    let candidate = if trimmed.contains(['/', '\\']) || trimmed.starts_with('~') {
+        detection::normalize_path(&installation::resolve_path(trimmed, project_root, home))
+            .to_string_lossy()
+            .to_string()
+    } else {
+        trimmed.to_string()
+    };
+    let resolved = if candidate == configured {
+        String::new()
+    } else {
+        format!(" (resolved to `{}`)", candidate)
+    };
 
-    let attempted_paths = python_candidates.join(", ");
-    Err(format!(
-        "Python 3.11 or 3.12 not found in any of these locations: {}. 
+    let probe = detection::probe_candidate(&candidate, host, runner, cache);
+    if let Err(reason) = &probe.banner {
+        let problem = if reason == "not a valid Python path" {
+            "doesn't look like a Python interpreter; point it at a python executable, \
+             e.g. `/path/to/.venv/bin/python`"
+                .to_string()
+        } else if reason.starts_with("could not run") {
+            let missing = if candidate.contains(['/', '\\']) {
+                "does not exist or can't be executed"
+            } else {
+                "was not found on PATH"
+            };
+            format!(
+                "{}; fix the path or remove the setting to auto-detect ({})",
+                missing, reason
+            )
+        } else {
+            format!("didn't report its version: {}", reason)
+        };
+        return Err(format!(
+            "python_executable `{}`{} {}",
+            configured, resolved, problem
+        ));
+    }
+    let python_version = settings.and_then(|settings| settings.python_version.as_deref());
+    detection::judge(&probe, python_version, python_policy(settings)).map_err(|reason| {
+        format!(
+            "python_executable `{}`{} can't run Serena: {}",
+            configured, resolved, reason
+        )
+    })?;
+    Ok(probe.path)
+}
 
-Serena requires Python 3.11 OR 3.12 (either version works).
+/// Whether `serena_command` replaces the `python -m` launch.
+fn has_custom_command(settings: Option<&SerenaContextServerSettings>) -> bool {
+    settings
+        .and_then(|settings| settings.serena_command.as_ref())
+        .is_some_and(|command| !command.is_empty())
+}
 
-To fix this issue:
-1. Install Python 3.11: brew install python@3.11
-2. Or install Python 3.12: brew install python@3.12  
-3. Or specify custom path in Zed settings: {{\"python_executable\": \"/path/to/python3.11\"}}",
-        attempted_paths
-    ))
+/// Whether conda base interpreters are passed over.
+fn skips_conda_base(settings: Option<&SerenaContextServerSettings>) -> bool {
+    settings.and_then(|settings| settings.skip_conda_base) != Some(false)
 }
 
-#[allow(dead_code)]
-fn is_serena_installed(python_exe: &str) -> Result<bool> {
-    match StdCommand::new(python_exe)
-        .args(["-c", "import serena; print('installed')"])
-        .output()
-    {
-        Ok(output) => Ok(output.status.success()),
-        Err(_) => {
-            // If we can't check, assume it's installed and let it fail later if not
-            // This handles restricted environments where process spawning is limited
-            Ok(true)
-        }
+/// Drops conda base interpreters from `found`, unless nothing else is left.
+///
+/// They are often years old or broken by packages installed into them.
+fn skip_conda_base(
+    found: Vec<detection::Interpreter>,
+    is_dir: impl Fn(&std::path::Path) -> bool,
+) -> Vec<detection::Interpreter> {
+    let (base, others): (Vec<_>, Vec<_>) = found
+        .into_iter()
+        .partition(|interpreter| discovery::is_conda_base(&interpreter.path, &is_dir));
+    if others.is_empty() {
+        base
+    } else {
+        others
     }
 }
 
-#[allow(dead_code)]
-fn install_serena(python_exe: &str) -> Result<()> {
-    match StdCommand::new(python_exe)
-        .args(["-m", "pip", "install", PACKAGE_NAME])
-        .output()
-    {
-        Ok(output) => {
-            if !output.status.success() {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                return Err(format!("Failed to install Serena: {}", stderr));
-            }
-            Ok(())
-        }
-        Err(_) => {
-            // If we can't install, just continue and hope it's already installed
-            // This handles restricted environments
-            Ok(())
-        }
+/// Interpreter of the pyenv-virtualenv environment `name`; the error lists
+/// the environments that exist.
+fn pyenv_virtualenv_python(
+    name: &str,
+    os: python_discovery::Os,
+    lookup_env: impl Fn(&str) -> Option<String>,
+) -> Result<String> {
+    let root = discovery::pyenv_root(lookup_env)
+        .ok_or("pyenv_virtualenv is set, but neither PYENV_ROOT nor HOME is")?;
+    if let Some(python) = discovery::pyenv_virtualenv_python(&root, name, os, detection::list_dir) {
+        return Ok(python);
+    }
+    let available: Vec<String> = discovery::pyenv_virtualenvs(&root, detection::list_dir)
+        .into_iter()
+        .map(|(env, _)| format!("`{}`", env))
+        .collect();
+    if available.is_empty() {
+        return Err(format!(
+            "pyenv_virtualenv `{}` not found: there are no pyenv-virtualenv environments in {}",
+            name,
+            root.join("versions").display()
+        ));
     }
+    Err(format!(
+        "pyenv_virtualenv `{}` not found; available: {}",
+        name,
+        available.join(", ")
+    ))
 }
 
-zed::register_extension!(SerenaContextServerExtension);
+/// The effective settings for a server, or `None` when they are invalid.
+fn current_settings(
+    context_server_id: &ContextServerId,
+    project: &Project,
+) -> Option<SerenaContextServerSettings> {
+    ContextServerSettings::for_project(context_server_id.as_ref(), project)
+        .ok()
+        .and_then(|settings| config::LayeredConfig::load(settings.settings).ok())
+        .and_then(|layered| layered.merged())
+        .and_then(|merged| parse_settings(merged).ok())
+}
 
-/// Extensions to the Zed extension API that have not yet stabilized.
-mod zed_ext {
-    /// Sanitizes the given path to remove the leading `/` on Windows.
-    ///
-    /// On macOS and Linux this is a no-op.
-    ///
-    /// This is a workaround for https://github.com/bytecodealliance/wasmtime/issues/10415.
-    pub fn sanitize_windows_path(path: std::path::PathBuf) -> std::path::PathBuf {
-        use zed_extension_api::{current_platform, Os};
+/// Whether a failed launch falls back to the last-known-good command.
+fn uses_last_known_good(settings: Option<&SerenaContextServerSettings>) -> bool {
+    settings
+        .and_then(|settings| settings.use_last_known_good)
+        .unwrap_or(false)
+}
 
-        let (os, _arch) = current_platform();
-        match os {
-            Os::Mac | Os::Linux => path,
-            Os::Windows => path
-                .to_string_lossy()
-                .to_string()
-                .trim_start_matches('/')
-                .into(),
-        }
+/// The command of the last healthy launch, when `use_last_known_good` is on.
+///
+/// The persisted command lacks secrets, so API keys come from the current
+/// settings; if they can't be resolved, Serena starts without them.
+fn last_known_good_command(
+    settings: Option<&SerenaContextServerSettings>,
+    state: &state::ExtensionState,
+    os: zed::Os,
+) -> Option<Command> {
+    if !uses_last_known_good(settings) {
+        return None;
     }
+    let command = state.last_known_good.as_ref()?.command.as_ref()?;
+    let secrets =
+        build_environment(settings, os, |name| std::env::var(name).ok()).unwrap_or_default();
+    Some(command.to_command(secrets))
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use zed_extension_api::Extension;
-
-    #[test]
-    fn test_validate_python_path() {
-        // Valid paths
-        assert!(validate_python_path("/usr/bin/python3.11"));
-        assert!(validate_python_path("/opt/homebrew/bin/python3.12"));
-        assert!(validate_python_path("python3.11"));
-        assert!(validate_python_path("python3.12"));
-        assert!(validate_python_path("python"));
-
-        // Invalid paths
-        assert!(!validate_python_path(""));
-        assert!(!validate_python_path("path\0with\0null"));
-        assert!(!validate_python_path(&"x".repeat(1001))); // Too long
-        assert!(!validate_python_path("/etc/../passwd")); // Path traversal
-        assert!(!validate_python_path("//malicious//path")); // Double slashes
-        assert!(!validate_python_path("malicious-executable")); // Suspicious name
+/// Which interpreter builds the settings let through.
+fn python_policy(settings: Option<&SerenaContextServerSettings>) -> detection::PythonPolicy<'_> {
+    detection::PythonPolicy {
+        allow_prereleases: settings
+            .and_then(|settings| settings.allow_prereleases)
+            .unwrap_or(false),
+        allow_free_threaded: settings
+            .and_then(|settings| settings.allow_free_threaded)
+            .unwrap_or(false),
+        allow_unsupported: settings
+            .and_then(|settings| settings.allow_unsupported_python)
+            .unwrap_or(false),
+        version_accept_regex: settings
+            .and_then(|settings| settings.version_accept_regex.as_deref()),
     }
+}
 
-    #[test]
-    fn test_is_valid_python_version() {
-        // Valid Python 3.11 versions (system needs 3.11 OR 3.12, not both)
-        assert!(is_valid_python_version("Python 3.11.0"));
-        assert!(is_valid_python_version("Python 3.11.5"));
-        assert!(is_valid_python_version(
-            "Python 3.11 (default, Oct  5 2023)"
-        ));
-        assert!(is_valid_python_version("Python 3.11"));
-        assert!(is_valid_python_version("  Python 3.11.7  ")); // With whitespace
+/// What the generic `python3` and `python` on `PATH` were found to be,
+/// including the ones detection rejected.
+fn generic_candidates(
+    settings: Option<&SerenaContextServerSettings>,
+    cache: &probe_cache::ProbeCache,
+) -> Vec<diagnostics::GenericCandidate> {
+    let python_version = settings.and_then(|settings| settings.python_version.as_deref());
+    let policy = python_policy(settings);
+    discovery::GENERIC_NAMES
+        .iter()
+        .filter_map(|&name| {
+            let probe = cache.cached_version(name)?;
+            Some(diagnostics::GenericCandidate {
+                name,
+                path: probe.path.clone(),
+                version: probe
+                    .banner
+                    .as_ref()
+                    .ok()
+                    .and_then(|banner| version::Version::from_python_banner(banner))
+                    .map(|version| version.to_string()),
+                verdict: detection::judge(probe, python_version, policy).map(|_| ()),
+            })
+        })
+        .collect()
+}
 
-        // Valid Python 3.12 versions
-        assert!(is_valid_python_version("Python 3.12.0"));
-        assert!(is_valid_python_version("Python 3.12.1"));
-        assert!(is_valid_python_version("Python 3.12 (main, Dec  7 2023)"));
+/// Auto-detects every interpreter that satisfies Serena's requirements.
+fn detect_interpreters(
+    settings: Option<&SerenaContextServerSettings>,
+    project_root: Option<&std::path::Path>,
+    host: python_discovery::Host,
+    runner: &dyn Runner,
+    cache: &mut probe_cache::ProbeCache,
+) -> Result<Vec<detection::Interpreter>> {
+    let policy = python_policy(settings);
+    let python_version = settings.and_then(|settings| settings.python_version.as_deref());
+    let excluded = settings
+        .and_then(|settings| settings.excluded_pythons.as_deref())
+        .unwrap_or_default();
+    let found = detection::find_python_interpreters(
+        python_version,
+        policy,
+        settings
+            .and_then(|settings| settings.python_preference)
+            .unwrap_or_default(),
+        excluded,
+        project_root,
+        host,
+        runner,
+        cache,
+    );
 
-        // Invalid versions - should NOT match
-        assert!(!is_valid_python_version("Python 3.10.0"));
-        assert!(!is_valid_python_version("Python 3.13.0"));
-        assert!(!is_valid_python_version("Python 2.7.0"));
-        assert!(!is_valid_python_version("Python 3.9.0"));
-        assert!(!is_valid_python_version("Python 3.110.0")); // Edge case - should not match
-        assert!(!is_valid_python_version("Python 3.120.0")); // Edge case - should not match
-        assert!(!is_valid_python_version("Some Python 3.11.0 thing")); // Doesn't start with "Python 3.11"
+    // The interpreter Zed's language servers use goes first when it qualifies
+    let zed = zed_python_path(settings, project_root, host.os).and_then(|path| {
+        let version = detection::python_version(&path, runner)?;
+        let banner = format!("Python {}", version);
+        let usable = !detection::is_excluded(&path, excluded)
+            && detection::check_python_build(&banner, &path, python_version, policy).is_ok();
+        usable.then_some(detection::Interpreter { path, version })
+    });
+    match (zed, found) {
+        (Some(zed), Ok(mut found)) => {
+            found.retain(|interpreter| interpreter.path != zed.path);
+            found.insert(0, zed);
+            Ok(found)
+        }
+        (Some(zed), Err(_)) => Ok(vec![zed]),
+        (None, found) => found,
     }
+}
 
-    #[test]
-    fn test_extension_initialization() {
-        let _extension = SerenaContextServerExtension::new();
-        // Extension should initialize without panicking
+/// The interpreter configured for pyright or basedpyright in the project's
+/// Zed settings, unless `use_zed_python_settings` is off.
+fn zed_python_path(
+    settings: Option<&SerenaContextServerSettings>,
+    project_root: Option<&std::path::Path>,
+    os: python_discovery::Os,
+) -> Option<String> {
+    let enabled = settings
+        .and_then(|settings| settings.use_zed_python_settings)
+        .unwrap_or(true);
+    if !enabled {
+        return None;
     }
+    let python = zed_python::configured_python(project_root?, os)?;
+    Some(python.to_string_lossy().into_owned())
+}
 
-    #[test]
-    fn test_serena_context_server_settings_deserialization() {
-        // Test valid JSON settings
-        let json_str = r#"
-        {
-            "python_executable": "/usr/bin/python3.11",
-            "environment": {
-                "SERENA_LOG_LEVEL": "debug"
+/// Picks `preferred` when it was detected, then the first interpreter that
+/// already has serena, and the first interpreter otherwise.
+///
+/// Checking for serena before auto-install keeps a clean system Python from
+/// winning over a virtual environment that is ready to go. `found` must not
+/// be empty.
+fn choose_interpreter<'a>(
+    found: &'a [detection::Interpreter],
+    preferred: Option<&str>,
+    mut has_serena: impl FnMut(&str) -> bool,
+) -> &'a detection::Interpreter {
+    found
+        .iter()
+        .find(|interpreter| Some(interpreter.path.as_str()) == preferred)
+        .or_else(|| {
+            found
+                .iter()
+                .find(|interpreter| has_serena(&interpreter.path))
+        })
+        .unwrap_or(&found[0])
+}
+
+/// The Zed platform in python-discovery's terms, including any sandbox Zed
+/// runs in.
+fn discovery_host(
+    (os, arch): (zed::Os, zed::Architecture),
+    runner: &dyn Runner,
+) -> python_discovery::Host {
+    let os = match os {
+        zed::Os::Mac => python_discovery::Os::Mac,
+        // Zed reports other Unix-likes as Linux; the kernel name tells them apart
+        zed::Os::Linux => runner
+            .run(&Invocation::new("uname").args(["-s"]))
+            .ok()
+            .filter(|output| output.success())
+            .map(|output| {
+                python_discovery::Os::from_uname(&String::from_utf8_lossy(&output.stdout))
+            })
+            .unwrap_or(python_discovery::Os::Linux),
+        zed::Os::Windows => python_discovery::Os::Windows,
+    };
+    let arch = match arch {
+        zed::Architecture::Aarch64 => python_discovery::Arch::Aarch64,
+        zed::Architecture::X8664 => python_discovery::Arch::X86_64,
+        zed::Architecture::X86 => python_discovery::Arch::X86,
+    };
+    python_discovery::Host {
+        os,
+        arch,
+        sandbox: sandbox::Sandbox::current(os).or_else(|| sandbox::Sandbox::probe(os, runner)),
+    }
+}
+
+/// Directory of the managed environment for `channel`: `venv_dir` when set,
+/// otherwise below the extension work dir.
+///
+/// `None` means neither is available.
+fn managed_env_dir(
+    settings: Option<&SerenaContextServerSettings>,
+    channel: installation::Channel,
+    work_dir: Option<&std::path::Path>,
+    project_root: Option<&std::path::Path>,
+    os: zed::Os,
+    home: Option<&str>,
+) -> Result<Option<std::path::PathBuf>> {
+    let configured = settings
+        .and_then(|settings| settings.venv_dir.as_deref())
+        .map(str::trim)
+        .filter(|dir| !dir.is_empty());
+    match configured {
+        Some(dir) => {
+            let dir = installation::resolve_path(dir, project_root, home);
+            if os == zed::Os::Windows && installation::has_unsafe_chars(&dir.to_string_lossy()) {
+                return Err(format!(
+                    "venv_dir {} contains spaces or non-ASCII characters, which break pip-installed scripts on Windows",
+                    dir.display()
+                ));
             }
+            Ok(Some(dir))
         }
-        "#;
+        None => Ok(work_dir.map(|work_dir| {
+            let envs_root = installation::envs_root(work_dir, os, |name| std::env::var(name).ok());
+            channel.managed_env(&envs_root)
+        })),
+    }
+}
 
-        let settings: Result<SerenaContextServerSettings, _> = serde_json::from_str(json_str);
-        assert!(settings.is_ok());
+/// Measures the managed environments and the language servers Serena
+/// downloaded, per project, in the shared Serena home and in
+/// `language_server_cache`.
+fn measure_footprint(
+    work_dir: &std::path::Path,
+    managed_env: Option<&std::path::Path>,
+    language_server_cache: Option<&std::path::Path>,
+    os: zed::Os,
+    home: Option<&str>,
+    now: u64,
+) -> footprint::Footprint {
+    let envs_root = installation::envs_root(work_dir, os, |name| std::env::var(name).ok());
+    let mut envs = vec![envs_root.clone()];
+    envs.extend(
+        managed_env
+            .filter(|env| !env.starts_with(&envs_root))
+            .map(std::path::Path::to_path_buf),
+    );
+    let mut servers = cleanup::language_server_dirs(work_dir);
+    let serena_home = std::env::var("SERENA_HOME")
+        .ok()
+        .map(std::path::PathBuf::from)
+        .or_else(|| home.map(|home| std::path::Path::new(home).join(".serena")));
+    servers.extend(serena_home.map(|home| home.join(cleanup::LANGUAGE_SERVERS_DIR)));
+    // Links to the cache aren't followed, so it is only counted here
+    servers.extend(language_server_cache.map(std::path::Path::to_path_buf));
+    footprint::Footprint::measure(
+        vec![
+            ("Managed environments", envs),
+            ("Language servers and runtimes", servers),
+        ],
+        now,
+    )
+}
+
+/// Runs the `cleanup` action and returns the message that stops the launch,
+/// so the environment isn't rebuilt right away.
+fn cleanup(
+    loaded: &mut LoadedState,
+    settings: Option<&SerenaContextServerSettings>,
+    context_server_id: &ContextServerId,
+    project_root: Option<&std::path::Path>,
+    os: zed::Os,
+) -> String {
+    let result = match loaded.work_dir.as_deref() {
+        Some(work_dir) => {
+            let home = match os {
+                zed::Os::Windows => std::env::var("USERPROFILE").ok(),
+                zed::Os::Mac | zed::Os::Linux => std::env::var("HOME").ok(),
+            };
+            let configured_env = settings
+                .filter(|settings| settings.venv_dir.is_some())
+                .and_then(|_| {
+                    let channel = installation::Channel::for_server(context_server_id.as_ref());
+                    managed_env_dir(
+                        settings,
+                        channel,
+                        Some(work_dir),
+                        project_root,
+                        os,
+                        home.as_deref(),
+                    )
+                    .ok()
+                    .flatten()
+                });
+            let envs_root = installation::envs_root(work_dir, os, |name| std::env::var(name).ok());
+            cleanup::remove(&cleanup::targets(
+                work_dir,
+                &envs_root,
+                configured_env.as_deref(),
+            ))
+        }
+        None => Err("Cleanup needs the extension work directory.".to_string()),
+    };
+    let state = &mut loaded.state;
+    state.probe_cache = Default::default();
+    state.cli_capabilities.clear();
+    state.update_check = None;
+    state.readiness = None;
+    state.footprint = None;
+    state.action_report = Some(actions::ActionReport::new(
+        actions::Action::Cleanup,
+        result.clone(),
+    ));
+    loaded.save();
+    format!(
+        "Serena wasn't started because cleanup ran. {}\nRestart the server to launch Serena again. To clean up another time, turn `cleanup` off and on.",
+        result.unwrap_or_else(|err| err)
+    )
+}
+
+/// Limits for installs and preflight checks from the settings.
+fn timeouts(settings: Option<&SerenaContextServerSettings>) -> installation::Timeouts {
+    installation::Timeouts::from_secs(
+        settings.and_then(|settings| settings.install_timeout_seconds),
+        settings.and_then(|settings| settings.preflight_timeout_seconds),
+    )
+}
+
+/// Readies the probe cache in `state` for the current settings.
+fn prepare_probe_cache(
+    state: &mut state::ExtensionState,
+    merged: Option<&serde_json::Value>,
+    settings: Option<&SerenaContextServerSettings>,
+) {
+    let ttl_minutes = settings
+        .and_then(|settings| settings.probe_cache_ttl_minutes)
+        .unwrap_or(probe_cache::DEFAULT_TTL_MINUTES);
+    state.probe_cache.prepare(
+        probe_cache::settings_hash(merged),
+        settings.and_then(|settings| settings.clear_cache) == Some(true),
+        ttl_minutes.saturating_mul(60),
+        state::now(),
+    );
+}
+
+/// Transport Serena serves MCP over.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "lowercase")]
+enum Transport {
+    /// Standard input and output, which Zed talks to directly
+    Stdio,
+    /// Server-sent events, bridged to Zed's stdio by share_instance
+    Sse,
+}
+
+/// Agent that calls Serena's tools.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "lowercase")]
+enum Agent {
+    /// Zed's own agent in the agent panel
+    Zed,
+    /// An external agent Zed runs as an agent server and passes its context servers to
+    External,
+}
+
+/// Serena context for agents that bring their own file and shell tools.
+const IDE_ASSISTANT_CONTEXT: &str = "ide-assistant";
+
+/// Serena tools that duplicate Zed's agent tools for reading and editing
+/// files and running commands.
+pub(crate) const OVERLAPPING_TOOLS: [&str; 8] = [
+    "create_text_file",
+    "read_file",
+    "execute_shell_command",
+    "replace_regex",
+    "replace_content",
+    "delete_lines",
+    "replace_lines",
+    "insert_at_line",
+];
+
+/// The context Serena starts in, or `None` for Serena's default.
+///
+/// `enforce_ide_context` wins over `context`, which wins over the context
+/// for external agents.
+fn serena_context(settings: &SerenaContextServerSettings) -> Option<String> {
+    if settings.enforce_ide_context == Some(true) {
+        return Some(IDE_ASSISTANT_CONTEXT.to_string());
+    }
+    // External agents already edit files and run commands themselves
+    settings.context.clone().or_else(|| {
+        (settings.agent == Some(Agent::External)).then(|| IDE_ASSISTANT_CONTEXT.to_string())
+    })
+}
+
+/// Where auto-install puts serena-agent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "lowercase")]
+enum InstallScope {
+    /// A virtual environment in the extension work dir, built from the interpreter
+    Venv,
+    /// The user site-packages, with `pip install --user`
+    User,
+    /// The interpreter's own site-packages
+    System,
+}
+
+/// Rejects transport settings Zed can't talk to.
+///
+/// Zed only speaks stdio to context servers. Serena serving SSE that nothing
+/// bridges would look like a server that never answers.
+fn check_transport(settings: &SerenaContextServerSettings) -> Result<()> {
+    let shared = settings.share_instance == Some(true);
+    match settings.transport {
+        Some(Transport::Sse) if !shared => Err(
+            "transport \"sse\" needs share_instance, which bridges Zed's stdio to Serena's SSE server; turn on share_instance or use \"stdio\""
+                .into(),
+        ),
+        Some(Transport::Sse) if project_root(settings).is_none() => Err(
+            "transport \"sse\" needs project_root, which the shared instance and its port are derived from"
+                .into(),
+        ),
+        Some(Transport::Stdio) if shared => Err(
+            "share_instance serves Serena over SSE; remove transport or set it to \"sse\"".into(),
+        ),
+        _ => Ok(()),
+    }
+}
+
+/// Everything the launch command depends on apart from the filesystem and
+/// the process environment.
+struct Launch<'a> {
+    /// Parsed and layered settings, if any were given
+    settings: Option<&'a SerenaContextServerSettings>,
+    /// Resolved interpreter, already in host form
+    python: &'a std::path::Path,
+    /// Module run with `python -m` when no console script is used
+    module: &'a str,
+    /// Installed serena-agent version, if known
+    serena_version: Option<&'a str>,
+    /// Options the installed release accepts, when its help could be read
+    capabilities: Option<&'a capabilities::Capabilities>,
+    /// Serena configuration overlay written for this launch
+    config_overlay: Option<&'a std::path::Path>,
+    /// Platform Zed runs on
+    os: zed::Os,
+    /// User home directory, for user-site and pipx scripts
+    home: Option<&'a std::path::Path>,
+    /// Folders open in the Zed project
+    workspace: Workspace,
+    /// Project root from the settings or the repo config
+    project_root: Option<&'a std::path::Path>,
+    /// Extension work directory, for the shared instance registry
+    work_dir: Option<&'a std::path::Path>,
+    /// Sandbox Zed runs in; Serena then has to be started on the host
+    sandbox: Option<sandbox::Sandbox>,
+}
+
+/// Builds the command Zed runs to start Serena.
+fn build_command(
+    launch: &Launch,
+    lookup_env: impl Fn(&str) -> Option<String>,
+    fs: &impl scripts::FileSystem,
+) -> Result<Command> {
+    let settings = launch.settings;
+    let os = launch.os;
+    let env_vars = server_environment(launch, &lookup_env)?;
+    let language_server_cache = settings
+        .and_then(|settings| settings.language_server_cache.as_deref())
+        .map(|dir| language_server_cache(dir, launch, &env_vars, &lookup_env))
+        .transpose()?;
+    let language_server_bundle = settings
+        .and_then(|settings| settings.language_server_bundle.as_deref())
+        .map(|dir| language_server_bundle(dir, launch, &env_vars, &lookup_env))
+        .transpose()?;
+    let login_shell = match settings.and_then(|settings| settings.launch_via_shell) {
+        Some(true) if os == zed::Os::Windows => {
+            return Err("launch_via_shell is not supported on Windows".into());
+        }
+        Some(true) => Some(
+            lookup_env("SHELL")
+                .filter(|shell| !shell.is_empty())
+                .unwrap_or_else(|| "/bin/sh".to_string()),
+        ),
+        _ => None,
+    };
+
+    let python = launch.python.to_string_lossy().to_string();
+    let mut server = serena_program(launch, fs)?;
+    // Precomputing symbols runs the same Serena's `project index` first
+    let precompute = settings
+        .filter(|settings| settings.precompute_symbols == Some(true))
+        .filter(|_| launch.workspace != Workspace::Empty)
+        .and_then(serena_project);
+    let index_command = precompute.map(|project| {
+        let mut index = server.clone();
+        index.extend([
+            "project".to_string(),
+            "index".to_string(),
+            paths::to_host(&project, os).to_string_lossy().to_string(),
+        ]);
+        index
+    });
+    server.extend(server_arguments(launch));
+
+    let launch_options = wrapper::LaunchOptions {
+        index_command,
+        language_server_cache,
+        language_server_bundle,
+        ..settings.map(launch_options).unwrap_or_default()
+    };
+
+    // Without an open folder there is no project to share an instance for
+    let shared_instance = settings
+        .filter(|settings| settings.share_instance == Some(true))
+        .filter(|_| launch.workspace != Workspace::Empty);
+    if let Some(settings) = shared_instance {
+        if os == zed::Os::Windows {
+            return Err("share_instance is not supported on Windows".into());
+        }
+        // Instances are registered, and stale ones reaped, by project root,
+        // so without one unrelated projects would share a single instance
+        let root = project_root(settings).ok_or(
+            "share_instance needs project_root, which the shared instance and its port are derived from; set project_root or turn off share_instance",
+        )?;
+
+        let work_dir = launch
+            .work_dir
+            .ok_or("Could not determine extension work directory")?;
+        let instance = instances::SharedInstance {
+            reap_stale: settings.reap_stale_instances == Some(true),
+            ..instances::SharedInstance::for_project(root, settings.port, work_dir)
+        };
+
+        if launch
+            .capabilities
+            .is_some_and(|capabilities| !capabilities.supports_transport("sse"))
+        {
+            return Err(
+                "share_instance needs the SSE transport, which the installed Serena doesn't support; upgrade serena-agent or turn off share_instance"
+                    .into(),
+            );
+        }
+        server.extend(["--transport".to_string(), "sse".to_string()]);
+        // Idle time can't be observed from the SSE side, so only limits and hooks apply
+        let server = wrapper::LaunchOptions {
+            idle_timeout_seconds: None,
+            ..launch_options
+        }
+        .wrap(&python, server);
+        let bridge = settings
+            .sse_bridge_command
+            .clone()
+            .unwrap_or_else(|| vec!["mcp-proxy".to_string()]);
+
+        let script = instance.wrapper_script(&server, &bridge, &python);
+        let argv = vec!["/bin/sh".to_string(), "-c".to_string(), script];
+        return Ok(host_command(
+            via_login_shell(argv, login_shell.as_deref()),
+            env_vars,
+            launch.sandbox,
+        ));
+    }
+
+    server.extend(stdio_arguments(launch));
+    Ok(host_command(
+        via_login_shell(launch_options.wrap(&python, server), login_shell.as_deref()),
+        env_vars,
+        launch.sandbox,
+    ))
+}
+
+/// Builds the command the readiness check starts: Serena alone over stdio,
+/// without hooks, resource limits, precomputation, a login shell or a
+/// shared instance, so running it has no side effects.
+///
+/// The command isn't wrapped for the sandbox; the check wraps the helper
+/// that starts it instead.
+fn check_command(
+    launch: &Launch,
+    lookup_env: impl Fn(&str) -> Option<String>,
+    fs: &impl scripts::FileSystem,
+) -> Result<Command> {
+    let mut argv = serena_program(launch, fs)?;
+    argv.extend(server_arguments(launch));
+    argv.extend(stdio_arguments(launch));
+    Ok(host_command(
+        argv,
+        server_environment(launch, lookup_env)?,
+        None,
+    ))
+}
+
+/// The environment Serena runs with, including the SERENA_HOME
+/// isolate_memories derives from the `project_root` setting.
+fn server_environment(
+    launch: &Launch,
+    lookup_env: impl Fn(&str) -> Option<String>,
+) -> Result<Vec<(String, String)>> {
+    let settings = launch.settings;
+    let mut env_vars = build_environment(settings, launch.os, &lookup_env)?;
+    let isolate_memories = settings.and_then(|settings| settings.isolate_memories) == Some(true);
+    if isolate_memories && launch.workspace != Workspace::Empty {
+        // Only an explicit root keeps projects apart; anything else would
+        // give every project the same home
+        let root = settings.and_then(project_root).ok_or(
+            "isolate_memories needs project_root, which the project's Serena home is derived from",
+        )?;
+        let work_dir = launch
+            .work_dir
+            .ok_or("Could not determine extension work directory")?;
+        // A SERENA_HOME from `environment` wins
+        if !env_vars.iter().any(|(key, _)| key == "SERENA_HOME") {
+            let home = paths::to_host(&instances::project_home(root, work_dir), launch.os);
+            env_vars.push((
+                "SERENA_HOME".to_string(),
+                home.to_string_lossy().to_string(),
+            ));
+            env_vars.sort();
+        }
+    }
+    Ok(env_vars)
+}
+
+/// The program that runs Serena, without its arguments: serena_command, the
+/// console script, or `python -m` with the entry module.
+fn serena_program(launch: &Launch, fs: &impl scripts::FileSystem) -> Result<Vec<String>> {
+    let settings = launch.settings;
+    let os = launch.os;
+    // Use the serena console script directly or call the CLI properly
+    let serena_script = scripts::find_serena_script(launch.python, os, launch.home, fs);
+
+    let isolated = match settings {
+        Some(settings) => check_isolated(settings)?,
+        None => false,
+    };
+    if let Some(settings) = settings {
+        check_transport(settings)?;
+    }
+    let serena_command = settings
+        .and_then(|settings| settings.serena_command.as_deref())
+        .filter(|command| !command.is_empty());
+    if isolated && serena_command.is_some() {
+        return Err(
+            "isolated mode always runs `python -I -m`, so serena_command would have no effect; remove it or turn off isolated"
+                .into(),
+        );
+    }
+
+    Ok(if let Some(command) = serena_command {
+        scripts::resolve_command(command, launch.python, os, launch.home, fs)
+    } else if let (Some(serena_script), false) = (&serena_script, isolated) {
+        // Use the serena console script directly
+        vec![serena_script.to_string_lossy().to_string()]
+    } else {
+        // Use proper module invocation instead of inline code manipulation;
+        // isolated mode needs it so the interpreter flag can be passed
+        let mut server = vec![launch.python.to_string_lossy().to_string()];
+        if isolated {
+            server.push("-I".to_string());
+        }
+        server.extend(["-m".to_string(), launch.module.to_string()]);
+        server
+    })
+}
+
+/// Serena's `start-mcp-server` arguments with the configuration overlay,
+/// keeping only the options the installed release accepts.
+fn server_arguments(launch: &Launch) -> Vec<String> {
+    let mut server_args = serena_server_args(launch.settings, launch.workspace, launch.os);
+    if let Some(path) = launch.config_overlay {
+        server_args.extend([
+            "--serena-config".to_string(),
+            paths::to_host(path, launch.os)
+                .to_string_lossy()
+                .to_string(),
+        ]);
+    }
+    match launch.capabilities {
+        Some(capabilities) => capabilities.retain_supported(server_args),
+        None => server_args,
+    }
+}
+
+/// Selects the stdio transport when the installed release has the flag.
+fn stdio_arguments(launch: &Launch) -> Vec<String> {
+    // Say stdio explicitly so a changed default can't leave Zed waiting
+    let stdio_flag = match launch.capabilities {
+        Some(capabilities) => capabilities.supports_transport("stdio"),
+        None => installation::supports_transport_flag(launch.serena_version),
+    };
+    if stdio_flag {
+        vec!["--transport".to_string(), "stdio".to_string()]
+    } else {
+        Vec::new()
+    }
+}
+
+/// Links the `language_servers` directory of the Serena home the server runs
+/// with, from `environment`, isolate_memories or Zed's environment, to `dir`.
+///
+/// `dir` may start with `~` and resolves against `project_root` when relative.
+fn language_server_cache(
+    dir: &str,
+    launch: &Launch,
+    env_vars: &[(String, String)],
+    lookup_env: impl Fn(&str) -> Option<String>,
+) -> Result<wrapper::LanguageServerCache> {
+    Ok(wrapper::LanguageServerCache {
+        link: language_servers_dir("language_server_cache", launch, env_vars, lookup_env)?,
+        target: resolve_host_path(dir, launch),
+    })
+}
+
+/// Links the language servers in `dir` into the `language_servers` directory
+/// of the Serena home the server runs with, resolving `dir` like
+/// [`language_server_cache`].
+fn language_server_bundle(
+    dir: &str,
+    launch: &Launch,
+    env_vars: &[(String, String)],
+    lookup_env: impl Fn(&str) -> Option<String>,
+) -> Result<wrapper::LanguageServerBundle> {
+    Ok(wrapper::LanguageServerBundle {
+        directory: language_servers_dir("language_server_bundle", launch, env_vars, lookup_env)?,
+        bundle: resolve_host_path(dir, launch),
+    })
+}
+
+/// The host path of Serena's `language_servers` directory: SERENA_HOME from
+/// `env_vars` or Zed's environment, else `~/.serena`.
+fn language_servers_dir(
+    setting: &str,
+    launch: &Launch,
+    env_vars: &[(String, String)],
+    lookup_env: impl Fn(&str) -> Option<String>,
+) -> Result<String> {
+    let serena_home = env_vars
+        .iter()
+        .find(|(key, _)| key == "SERENA_HOME")
+        .map(|(_, value)| value.clone())
+        .or_else(|| lookup_env("SERENA_HOME"))
+        .map(std::path::PathBuf::from)
+        .or_else(|| launch.home.map(|home| home.join(".serena")))
+        .ok_or_else(|| {
+            format!(
+                "{} needs SERENA_HOME or a home directory to find Serena's language servers",
+                setting
+            )
+        })?;
+    Ok(
+        paths::to_host(&serena_home.join(cleanup::LANGUAGE_SERVERS_DIR), launch.os)
+            .to_string_lossy()
+            .to_string(),
+    )
+}
+
+/// Resolves a directory setting that may start with `~` or be relative to
+/// `project_root`, as a host path.
+fn resolve_host_path(dir: &str, launch: &Launch) -> String {
+    let home = launch.home.and_then(|home| home.to_str());
+    let path = installation::resolve_path(dir, launch.project_root, home);
+    paths::to_host(&path, launch.os)
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Runs `argv` through the user's login shell when `launch_via_shell` is on,
+/// so shims set up in shell profiles resolve. Without `$SHELL`, `/bin/sh` is used.
+fn via_login_shell(argv: Vec<String>, login_shell: Option<&str>) -> Vec<String> {
+    match login_shell {
+        Some(shell) => shell::login_shell_command(shell, &argv),
+        None => argv,
+    }
+}
+
+/// Turns an argument vector into a command, run on the host when Zed is
+/// sandboxed.
+fn host_command(
+    argv: Vec<String>,
+    env: Vec<(String, String)>,
+    sandbox: Option<sandbox::Sandbox>,
+) -> Command {
+    let argv = match sandbox {
+        Some(sandbox) => sandbox.wrap(argv, &env),
+        None => argv,
+    };
+    let mut argv = argv.into_iter();
+    Command {
+        command: argv.next().unwrap_or_default(),
+        args: argv.collect(),
+        env,
+    }
+}
+
+/// Collects the wrapper's resource controls and hooks from the settings.
+fn launch_options(settings: &SerenaContextServerSettings) -> wrapper::LaunchOptions {
+    wrapper::LaunchOptions {
+        niceness: settings.niceness,
+        memory_limit_mb: settings.memory_limit_mb,
+        idle_timeout_seconds: settings.idle_timeout_minutes.map(|m| m * 60),
+        pre_launch_command: settings.pre_launch_command.clone(),
+        post_exit_command: settings.post_exit_command.clone(),
+        hook_timeout_seconds: settings.hook_timeout_seconds,
+        working_directory: working_directory(settings),
+        kill_process_tree: settings.kill_process_tree == Some(true),
+        shutdown_grace_seconds: settings.shutdown_grace_seconds,
+        index_command: None,
+        index_timeout_seconds: settings.precompute_timeout_seconds,
+        language_server_cache: None,
+        language_server_bundle: None,
+    }
+}
+
+/// Returns whether isolated mode is on, rejecting settings it would silently ignore.
+fn check_isolated(settings: &SerenaContextServerSettings) -> Result<bool> {
+    if settings.isolated != Some(true) {
+        return Ok(false);
+    }
+    let ignored: Vec<&str> = [
+        ("pythonpath", &settings.pythonpath),
+        ("site_packages_extra", &settings.site_packages_extra),
+    ]
+    .into_iter()
+    .filter(|(_, paths)| paths.as_ref().is_some_and(|paths| !paths.is_empty()))
+    .map(|(name, _)| name)
+    .collect();
+
+    if settings.install_scope == Some(InstallScope::User) {
+        return Err(
+            "isolated mode ignores the user site-packages, so Serena installed with install_scope \"user\" could not be imported; choose another install_scope or turn off isolated"
+                .into(),
+        );
+    }
+    if ignored.is_empty() {
+        Ok(true)
+    } else {
+        Err(format!(
+            "isolated mode ignores PYTHONPATH, so {} would have no effect; remove it or turn off isolated",
+            ignored.join(" and ")
+        ))
+    }
+}
+
+/// Resolves the directory Serena runs in.
+///
+/// Without either setting the process keeps the directory the extension host
+/// launches it in.
+fn working_directory(settings: &SerenaContextServerSettings) -> Option<String> {
+    let root = settings.project_root.as_deref().map(std::path::Path::new);
+    match (settings.working_directory.as_deref(), root) {
+        (Some(dir), Some(root)) => Some(root.join(dir).to_string_lossy().to_string()),
+        (Some(dir), None) => Some(dir.to_string()),
+        (None, _) => serena_project(settings).map(|project| project.to_string_lossy().to_string()),
+    }
+}
+
+/// The configured `project_root`.
+///
+/// Shared instances and isolated Serena homes are keyed on it, so they must
+/// never fall back to a directory every project has in common.
+fn project_root(settings: &SerenaContextServerSettings) -> Option<&std::path::Path> {
+    settings.project_root.as_deref().map(std::path::Path::new)
+}
+
+/// The project Serena works on: `project_root`, narrowed to `project_subpath`
+/// when that is set.
+fn serena_project(settings: &SerenaContextServerSettings) -> Option<std::path::PathBuf> {
+    let root = std::path::Path::new(settings.project_root.as_deref()?);
+    Some(match settings.project_subpath.as_deref() {
+        Some(subpath) => root.join(subpath),
+        None => root.to_path_buf(),
+    })
+}
+
+/// Size of the project Serena works on, when `size_defaults` applies; the
+/// tree isn't walked when `modes` is set.
+fn project_size(
+    settings: &SerenaContextServerSettings,
+    cache: &mut Option<census::CachedCensus>,
+) -> Option<census::Size> {
+    if settings.size_defaults == Some(false) || settings.modes.is_some() {
+        return None;
+    }
+    census::scan_cached(&serena_project(settings)?, cache).size()
+}
+
+/// Fills in the modes and options a project of `size` gets by default;
+/// configured ones are kept.
+fn apply_size_defaults(settings: &mut SerenaContextServerSettings, size: census::Size) {
+    if settings.modes.is_none() {
+        settings.modes = Some(size.modes().map(str::to_string).to_vec());
+    }
+    if size == census::Size::Large && settings.forward_ignores.is_none() {
+        settings.forward_ignores = Some(true);
+    }
+}
+
+/// Ignore patterns forwarded to Serena when `forward_ignores` is on.
+fn ignored_paths(settings: &SerenaContextServerSettings) -> Vec<String> {
+    match (settings.forward_ignores, serena_project(settings)) {
+        (Some(true), Some(project)) => ignores::patterns(&project),
+        _ => Vec::new(),
+    }
+}
+
+/// Rejects a `project_subpath` that doesn't name a directory inside
+/// `project_root`.
+fn check_project_subpath(
+    settings: &SerenaContextServerSettings,
+    is_dir: impl Fn(&std::path::Path) -> bool,
+) -> Result<()> {
+    let Some(subpath) = settings.project_subpath.as_deref() else {
+        return Ok(());
+    };
+    if settings.project_root.is_none() {
+        return Err("project_subpath needs project_root, which it is relative to".into());
+    }
+    let escapes = std::path::Path::new(subpath).components().any(|component| {
+        !matches!(
+            component,
+            std::path::Component::Normal(_) | std::path::Component::CurDir
+        )
+    });
+    if subpath.is_empty() || escapes {
+        return Err(format!(
+            "project_subpath \"{}\" must be a relative path inside project_root, e.g. \"services/billing\"",
+            subpath
+        ));
+    }
+    match serena_project(settings) {
+        Some(project) if !is_dir(&project) => Err(format!(
+            "project_subpath \"{}\" does not exist: {} is not a directory",
+            subpath,
+            project.display()
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Offline switches of pip, uv, npm and Hugging Face, set under
+/// `restricted_environment`.
+const OFFLINE_ENV: [(&str, &str); 4] = [
+    ("HF_HUB_OFFLINE", "1"),
+    ("PIP_NO_INDEX", "1"),
+    ("npm_config_offline", "true"),
+    ("UV_OFFLINE", "1"),
+];
+
+/// Best-effort network block under `restricted_environment`: HTTP clients
+/// that honour the proxy variables are sent to an address nothing listens
+/// on, so downloads fail at once instead of hanging. Clients that connect
+/// directly aren't stopped. Local addresses, such as the dashboard and
+/// shared instances, bypass the proxy.
+const PROXY_BLOCK_ENV: [(&str, &str); 6] = [
+    ("HTTP_PROXY", "http://127.0.0.1:9"),
+    ("HTTPS_PROXY", "http://127.0.0.1:9"),
+    ("http_proxy", "http://127.0.0.1:9"),
+    ("https_proxy", "http://127.0.0.1:9"),
+    ("NO_PROXY", "localhost,127.0.0.1,::1"),
+    ("no_proxy", "localhost,127.0.0.1,::1"),
+];
+
+/// Environment switches that keep usage data from leaving the machine.
+const ANALYTICS_OPT_OUT_ENV: [(&str, &str); 4] = [
+    ("DO_NOT_TRACK", "1"),
+    ("AGNO_TELEMETRY", "false"),
+    ("ANONYMIZED_TELEMETRY", "false"),
+    ("HF_HUB_DISABLE_TELEMETRY", "1"),
+];
+
+/// Environment that keeps Serena and the language servers it spawns from
+/// writing colors, progress bars or buffered output into the stdio stream
+/// Zed parses as MCP.
+const STDIO_HYGIENE_ENV: [(&str, &str); 7] = [
+    ("NO_COLOR", "1"),
+    ("PYTHONUNBUFFERED", "1"),
+    ("TERM", "dumb"),
+    ("CLICOLOR", "0"),
+    ("PY_COLORS", "0"),
+    ("PIP_PROGRESS_BAR", "off"),
+    ("TQDM_DISABLE", "1"),
+];
+
+/// Environment that makes Python use UTF-8 on Windows instead of the ANSI
+/// code page, e.g. cp1252, which can't decode many source files.
+const WINDOWS_UTF8_ENV: [(&str, &str); 2] = [("PYTHONUTF8", "1"), ("PYTHONIOENCODING", "utf-8")];
+
+/// Where a variable in Serena's environment comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum EnvSource {
+    /// Zed's own environment, passed through unchanged
+    Inherited,
+    /// Analytics opt-outs, offline settings, stdio hygiene and Windows UTF-8 mode
+    Defaults,
+    /// The `environment` setting
+    Settings,
+    /// The `api_keys` setting
+    ApiKeys,
+    /// Computed by the extension, e.g. a combined `PYTHONPATH`
+    Derived,
+}
+
+impl EnvSource {
+    fn describe(self) -> &'static str {
+        match self {
+            Self::Inherited => "Inherited from Zed",
+            Self::Defaults => "Defaults",
+            Self::Settings => "`environment` setting",
+            Self::ApiKeys => "`api_keys` setting",
+            Self::Derived => "Derived by the extension",
+        }
+    }
+}
+
+/// Builds the environment for the Serena process.
+///
+/// Later sources win: analytics opt-outs and, unless Serena serves SSE for
+/// `share_instance`, stdio hygiene, then `environment`, then `api_keys`.
+/// `pythonpath` and `site_packages_extra` are combined with any configured
+/// or inherited `PYTHONPATH`.
+fn build_environment(
+    settings: Option<&SerenaContextServerSettings>,
+    os: zed::Os,
+    lookup_env: impl Fn(&str) -> Option<String>,
+) -> Result<Vec<(String, String)>> {
+    let env = sourced_environment(settings, os, &lookup_env, |keys| {
+        secrets::api_key_env(keys, os, &lookup_env)
+    })?;
+    Ok(env
+        .into_iter()
+        .map(|(key, (value, _))| (key, value))
+        .collect())
+}
+
+/// Like [`build_environment`], but records where each variable comes from
+/// and takes the `api_keys` resolution from `api_keys`.
+fn sourced_environment(
+    settings: Option<&SerenaContextServerSettings>,
+    os: zed::Os,
+    lookup_env: impl Fn(&str) -> Option<String>,
+    api_keys: impl FnOnce(&secrets::ApiKeySettings) -> Result<Vec<(String, String)>>,
+) -> Result<std::collections::BTreeMap<String, (String, EnvSource)>> {
+    let mut env = std::collections::BTreeMap::new();
+
+    if !settings.and_then(|s| s.analytics).unwrap_or(false) {
+        for (key, value) in ANALYTICS_OPT_OUT_ENV {
+            env.insert(key.to_string(), (value.to_string(), EnvSource::Defaults));
+        }
+    }
+    let restricted = settings.and_then(|s| s.restricted_environment) == Some(true);
+    if restricted {
+        for (key, value) in OFFLINE_ENV.into_iter().chain(PROXY_BLOCK_ENV) {
+            env.insert(key.to_string(), (value.to_string(), EnvSource::Defaults));
+        }
+    }
+    if os == zed::Os::Windows {
+        for (key, value) in WINDOWS_UTF8_ENV {
+            env.insert(key.to_string(), (value.to_string(), EnvSource::Defaults));
+        }
+    }
+    // Over SSE the stdio stream carries only logs, so terminals may keep colors
+    if settings.and_then(|s| s.share_instance) != Some(true) {
+        for (key, value) in STDIO_HYGIENE_ENV {
+            env.insert(key.to_string(), (value.to_string(), EnvSource::Defaults));
+        }
+    }
+
+    if let Some(settings) = settings {
+        for (key, value) in settings.environment.iter().flatten() {
+            // A proxy of the user's own, or a NO_PROXY of `*`, would lift the block
+            if restricted && PROXY_BLOCK_ENV.iter().any(|(blocked, _)| blocked == key) {
+                continue;
+            }
+            env.insert(key.clone(), (value.clone(), EnvSource::Settings));
+        }
+        if let Some(keys) = &settings.api_keys {
+            for (key, value) in api_keys(keys)? {
+                env.insert(key, (value, EnvSource::ApiKeys));
+            }
+        }
+    }
+
+    // Language servers mangle non-ASCII files under an ASCII locale
+    let utf8_locale = settings.and_then(|s| s.utf8_locale).unwrap_or(true);
+    if utf8_locale && os == zed::Os::Linux && !has_utf8_capable_locale(&env, &lookup_env) {
+        for key in ["LC_ALL", "LANG"] {
+            env.insert(key.to_string(), ("C.UTF-8".to_string(), EnvSource::Derived));
+        }
+    }
+
+    if let Some(settings) = settings {
+        let existing = env
+            .get("PYTHONPATH")
+            .map(|(value, _)| value.clone())
+            .or_else(|| lookup_env("PYTHONPATH"));
+        let python_path = pythonpath::build(
+            settings.pythonpath.as_deref().unwrap_or_default(),
+            settings.site_packages_extra.as_deref().unwrap_or_default(),
+            existing.as_deref(),
+            settings.project_root.as_deref().map(std::path::Path::new),
+            os,
+            &lookup_env,
+        )?;
+        if let Some(python_path) = python_path {
+            env.insert("PYTHONPATH".to_string(), (python_path, EnvSource::Derived));
+        }
+    }
+
+    Ok(env)
+}
+
+/// False when the effective locale is unset or the ASCII-only C/POSIX one.
+///
+/// The locale follows POSIX precedence: `LC_ALL`, then `LC_CTYPE`, then `LANG`,
+/// with configured values ahead of inherited ones.
+fn has_utf8_capable_locale(
+    env: &std::collections::BTreeMap<String, (String, EnvSource)>,
+    lookup_env: impl Fn(&str) -> Option<String>,
+) -> bool {
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"].into_iter().find_map(|key| {
+        env.get(key)
+            .map(|(value, _)| value.clone())
+            .or_else(|| lookup_env(key))
+            .filter(|value| !value.is_empty())
+    });
+    !matches!(locale.as_deref(), None | Some("C") | Some("POSIX"))
+}
+
+/// Renders the environment Serena will receive, grouped by source, for debug
+/// output.
+///
+/// `inherited` is Zed's environment; variables the extension sets replace
+/// inherited ones. API keys are never resolved, and secret-looking values
+/// are redacted.
+fn describe_environment(
+    settings: Option<&SerenaContextServerSettings>,
+    os: zed::Os,
+    inherited: impl IntoIterator<Item = (String, String)>,
+) -> String {
+    let inherited: std::collections::BTreeMap<_, _> = inherited.into_iter().collect();
+    let env = sourced_environment(
+        settings,
+        os,
+        |name| inherited.get(name).cloned(),
+        |keys| {
+            Ok(secrets::api_key_names(keys)
+                .into_iter()
+                .map(|name| (name.to_string(), secrets::REDACTED.to_string()))
+                .collect())
+        },
+    );
+    let mut out = String::from("### Serena Environment\n\n");
+    let mut env = match env {
+        Ok(env) => env,
+        Err(err) => {
+            out.push_str(&format!("Could not build the environment: {}\n", err));
+            return out;
+        }
+    };
+    for (key, value) in inherited {
+        env.entry(key).or_insert((value, EnvSource::Inherited));
+    }
+    let mut grouped: Vec<_> = env.into_iter().collect();
+    grouped.sort_by_key(|(key, (_, source))| (*source, key.clone()));
+    let mut current = None;
+    for (key, (value, source)) in grouped {
+        if current != Some(source) {
+            out.push_str(&format!("\n**{}**\n\n", source.describe()));
+            current = Some(source);
+        }
+        let value = if secrets::is_secret_name(&key) {
+            secrets::REDACTED
+        } else {
+            &value
+        };
+        out.push_str(&format!("- `{}={}`\n", key, value));
+    }
+    out
+}
+
+/// What the Zed project gives Serena to work on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Workspace {
+    /// Zed was opened without a folder
+    Empty,
+    /// One folder is open
+    Single,
+    /// Several folders are open; Serena activates one project at a time
+    Multiple,
+}
+
+impl Workspace {
+    fn from_worktree_count(count: usize) -> Self {
+        match count {
+            0 => Self::Empty,
+            1 => Self::Single,
+            _ => Self::Multiple,
+        }
+    }
+}
+
+/// Builds the `start-mcp-server` arguments from the resolved settings.
+///
+/// `--project` is only passed when a folder is open and `project_root` names
+/// it, narrowed to `project_subpath`; without one Serena starts project-less
+/// and can activate a project later.
+fn serena_server_args(
+    settings: Option<&SerenaContextServerSettings>,
+    workspace: Workspace,
+    os: zed::Os,
+) -> Vec<String> {
+    let mut args = vec!["start-mcp-server".to_string()];
+    let Some(settings) = settings else {
+        return args;
+    };
+
+    if let (Some(project), false) = (serena_project(settings), workspace == Workspace::Empty) {
+        args.push("--project".to_string());
+        args.push(paths::to_host(&project, os).to_string_lossy().to_string());
+    }
+    if let Some(context) = serena_context(settings) {
+        args.push("--context".to_string());
+        args.push(context);
+    }
+    for mode in settings.modes.iter().flatten() {
+        args.push("--mode".to_string());
+        args.push(mode.clone());
+    }
+    if let Some(enabled) = settings.web_dashboard {
+        args.push("--enable-web-dashboard".to_string());
+        args.push(enabled.to_string());
+    }
+    args
+}
+
+// The generated WASM entry point isn't part of the documented library surface
+#[allow(missing_docs)]
+mod entry_point {
+    zed_extension_api::register_extension!(super::SerenaContextServerExtension);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zed_extension_api::Extension;
+
+    #[test]
+    fn test_extension_initialization() {
+        let _extension = SerenaContextServerExtension::new();
+        // Extension should initialize without panicking
+    }
+
+    #[test]
+    fn test_choose_interpreter() {
+        let found = ["/opt/homebrew/bin/python3.12", "/src/app/.venv/bin/python"].map(|path| {
+            detection::Interpreter {
+                path: path.to_string(),
+                version: "3.12.1".to_string(),
+            }
+        });
+        let venv_has_serena = |python: &str| python.contains(".venv");
+
+        // An interpreter with serena wins over an earlier one without
+        assert_eq!(
+            choose_interpreter(&found, None, venv_has_serena).path,
+            "/src/app/.venv/bin/python"
+        );
+        assert_eq!(
+            choose_interpreter(&found, None, |_| false).path,
+            "/opt/homebrew/bin/python3.12"
+        );
+        // An explicit choice wins over both
+        assert_eq!(
+            choose_interpreter(
+                &found,
+                Some("/opt/homebrew/bin/python3.12"),
+                venv_has_serena
+            )
+            .path,
+            "/opt/homebrew/bin/python3.12"
+        );
+        // A preferred interpreter that went away falls back to the others
+        assert_eq!(
+            choose_interpreter(&found, Some("/gone/python"), |_| false).path,
+            "/opt/homebrew/bin/python3.12"
+        );
+    }
+
+    #[test]
+    fn test_state_is_loaded_once() {
+        let mut extension = SerenaContextServerExtension::new();
+        assert!(extension.state.is_none());
+
+        // Later calls see earlier changes instead of reloading from disk
+        extension.loaded_state().state.onboarding_completed = true;
+        assert!(extension.loaded_state().state.onboarding_completed);
+    }
+
+    #[test]
+    fn test_serena_context_server_settings_deserialization() {
+        // Test valid JSON settings
+        let json_str = r#"
+        {
+            "python_executable": "/usr/bin/python3.11",
+            "environment": {
+                "SERENA_LOG_LEVEL": "debug"
+            }
+        }
+        "#;
+
+        let settings: Result<SerenaContextServerSettings, _> = serde_json::from_str(json_str);
+        assert!(settings.is_ok());
 
         let settings = settings.unwrap();
         assert_eq!(
@@ -420,7 +2883,664 @@ mod tests {
     }
 
     #[test]
-    fn test_package_name_constant() {
-        assert_eq!(PACKAGE_NAME, "serena-agent");
+    fn test_parse_settings_reports_all_errors() {
+        let err = parse_settings(serde_json::json!({
+            "python_executable": 3,
+            "modes": "planning",
+            "environment": { "A": true }
+        }))
+        .unwrap_err();
+
+        assert!(err.starts_with("Invalid settings (3 problem(s)):"));
+        assert!(err.contains("`/python_executable`"));
+        assert!(err.contains("`/modes`"));
+        assert!(err.contains("`/environment/A`"));
+    }
+
+    #[test]
+    fn test_build_environment_analytics() {
+        let env = build_environment(None, zed::Os::Linux, |_| None).unwrap();
+        assert!(env.contains(&("DO_NOT_TRACK".to_string(), "1".to_string())));
+
+        let settings = SerenaContextServerSettings {
+            analytics: Some(true),
+            environment: Some([("A".to_string(), "1".to_string())].into()),
+            ..Default::default()
+        };
+        let env = build_environment(Some(&settings), zed::Os::Linux, |_| None).unwrap();
+        assert!(env.contains(&("A".to_string(), "1".to_string())));
+        assert!(!env.iter().any(|(key, _)| key == "DO_NOT_TRACK"));
+    }
+
+    #[test]
+    fn test_build_environment_restricted() {
+        let mut settings = SerenaContextServerSettings {
+            restricted_environment: Some(true),
+            environment: Some(
+                [
+                    ("NO_PROXY", "*"),
+                    ("no_proxy", "internal.example.com"),
+                    ("HF_HUB_OFFLINE", "0"),
+                ]
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .into(),
+            ),
+            ..Default::default()
+        };
+        let env = build_environment(Some(&settings), zed::Os::Linux, |_| None).unwrap();
+        for key in ["NO_PROXY", "no_proxy"] {
+            assert!(env.contains(&(key.to_string(), "localhost,127.0.0.1,::1".to_string())));
+        }
+        assert!(env.contains(&("HTTPS_PROXY".to_string(), "http://127.0.0.1:9".to_string())));
+        // Only the proxy block can't be overridden
+        assert!(env.contains(&("HF_HUB_OFFLINE".to_string(), "0".to_string())));
+
+        settings.restricted_environment = None;
+        let env = build_environment(Some(&settings), zed::Os::Linux, |_| None).unwrap();
+        assert!(env.contains(&("NO_PROXY".to_string(), "*".to_string())));
+        assert!(!env.iter().any(|(key, _)| key == "HTTPS_PROXY"));
+    }
+
+    #[test]
+    fn test_build_environment_stdio_hygiene() {
+        let env = build_environment(None, zed::Os::Linux, |_| None).unwrap();
+        assert!(env.contains(&("NO_COLOR".to_string(), "1".to_string())));
+        assert!(env.contains(&("PYTHONUNBUFFERED".to_string(), "1".to_string())));
+
+        let mut settings = SerenaContextServerSettings {
+            environment: Some([("TERM".to_string(), "xterm-256color".to_string())].into()),
+            ..Default::default()
+        };
+        let env = build_environment(Some(&settings), zed::Os::Linux, |_| None).unwrap();
+        assert!(env.contains(&("TERM".to_string(), "xterm-256color".to_string())));
+
+        settings.share_instance = Some(true);
+        let env = build_environment(Some(&settings), zed::Os::Linux, |_| None).unwrap();
+        assert!(!env.iter().any(|(key, _)| key == "NO_COLOR"));
+    }
+
+    #[test]
+    fn test_server_environment_isolates_by_project_root_setting() {
+        fn launch(settings: &SerenaContextServerSettings) -> Launch<'_> {
+            Launch {
+                settings: Some(settings),
+                python: std::path::Path::new("/usr/bin/python3"),
+                module: "serena",
+                serena_version: None,
+                capabilities: None,
+                config_overlay: None,
+                os: zed::Os::Linux,
+                home: None,
+                workspace: Workspace::Single,
+                project_root: Some(std::path::Path::new("/work")),
+                work_dir: Some(std::path::Path::new("/work")),
+                sandbox: None,
+            }
+        }
+        let mut settings = SerenaContextServerSettings {
+            isolate_memories: Some(true),
+            ..Default::default()
+        };
+        // A root that doesn't come from the setting would give every project one home
+        let err = server_environment(&launch(&settings), |_| None).unwrap_err();
+        assert!(
+            err.contains("isolate_memories needs project_root"),
+            "{}",
+            err
+        );
+
+        settings.project_root = Some("/src/app".to_string());
+        let env = server_environment(&launch(&settings), |_| None).unwrap();
+        let home = instances::project_home(
+            std::path::Path::new("/src/app"),
+            std::path::Path::new("/work"),
+        );
+        assert!(env.contains(&(
+            "SERENA_HOME".to_string(),
+            home.to_string_lossy().to_string()
+        )));
+    }
+
+    #[test]
+    fn test_build_environment_user_overrides_defaults() {
+        let settings = SerenaContextServerSettings {
+            environment: Some([("DO_NOT_TRACK".to_string(), "0".to_string())].into()),
+            ..Default::default()
+        };
+        let env = build_environment(Some(&settings), zed::Os::Linux, |_| None).unwrap();
+        assert!(env.contains(&("DO_NOT_TRACK".to_string(), "0".to_string())));
+    }
+
+    #[test]
+    fn test_build_environment_pythonpath() {
+        let settings = SerenaContextServerSettings {
+            pythonpath: Some(vec!["/plugins".to_string()]),
+            site_packages_extra: Some(vec!["/extra".to_string()]),
+            environment: Some([("PYTHONPATH".to_string(), "/configured".to_string())].into()),
+            ..Default::default()
+        };
+        let env = build_environment(Some(&settings), zed::Os::Linux, |_| {
+            Some("/inherited".to_string())
+        })
+        .unwrap();
+        assert!(env.contains(&(
+            "PYTHONPATH".to_string(),
+            "/plugins:/configured:/extra".to_string()
+        )));
+    }
+
+    #[test]
+    fn test_describe_environment() {
+        let settings: SerenaContextServerSettings = serde_json::from_value(serde_json::json!({
+            "environment": { "SERENA_LOG_LEVEL": "debug", "GITHUB_TOKEN": "ghp_secret" },
+            "api_keys": { "openai": "keychain:openai" },
+            "pythonpath": ["/plugins"],
+        }))
+        .unwrap();
+        let inherited = [
+            ("LANG".to_string(), "en_US.UTF-8".to_string()),
+            ("PATH".to_string(), "/usr/bin".to_string()),
+            ("PYTHONPATH".to_string(), "/inherited".to_string()),
+        ];
+        let described = describe_environment(Some(&settings), zed::Os::Linux, inherited);
+
+        assert!(described
+            .contains("**Inherited from Zed**\n\n- `LANG=en_US.UTF-8`\n- `PATH=/usr/bin`\n"));
+        assert!(described.contains("**Defaults**\n\n- `AGNO_TELEMETRY=false`"));
+        assert!(described.contains("- `SERENA_LOG_LEVEL=debug`"));
+        assert!(described.contains("- `GITHUB_TOKEN=<redacted>`"));
+        assert!(described.contains("**`api_keys` setting**\n\n- `OPENAI_API_KEY=<redacted>`"));
+        assert!(described
+            .contains("**Derived by the extension**\n\n- `PYTHONPATH=/plugins:/inherited`"));
+        assert!(!described.contains("ghp_secret"));
+    }
+
+    #[test]
+    fn test_build_environment_utf8_locale() {
+        let locale = |settings: &SerenaContextServerSettings, os, lang: Option<&str>| {
+            let env = build_environment(Some(settings), os, |name| {
+                lang.filter(|_| name == "LANG").map(String::from)
+            })
+            .unwrap();
+            env.into_iter()
+                .find(|(key, _)| key == "LC_ALL")
+                .map(|(_, value)| value)
+        };
+        let mut settings = SerenaContextServerSettings::default();
+        assert_eq!(
+            locale(&settings, zed::Os::Linux, None).as_deref(),
+            Some("C.UTF-8")
+        );
+        assert_eq!(
+            locale(&settings, zed::Os::Linux, Some("POSIX")).as_deref(),
+            Some("C.UTF-8")
+        );
+        assert_eq!(locale(&settings, zed::Os::Linux, Some("de_DE.UTF-8")), None);
+        assert_eq!(locale(&settings, zed::Os::Mac, None), None);
+        // Users without any settings get the default too
+        let env = build_environment(None, zed::Os::Linux, |_| None).unwrap();
+        assert!(env.contains(&("LANG".to_string(), "C.UTF-8".to_string())));
+
+        settings.utf8_locale = Some(false);
+        assert_eq!(locale(&settings, zed::Os::Linux, None), None);
+    }
+
+    #[test]
+    fn test_build_environment_windows_utf8() {
+        let utf8 = ("PYTHONUTF8".to_string(), "1".to_string());
+        let env = build_environment(None, zed::Os::Windows, |_| None).unwrap();
+        assert!(env.contains(&utf8));
+        assert!(env.contains(&("PYTHONIOENCODING".to_string(), "utf-8".to_string())));
+        for os in [zed::Os::Linux, zed::Os::Mac] {
+            let env = build_environment(None, os, |_| None).unwrap();
+            assert!(!env
+                .iter()
+                .any(|(key, _)| key == "PYTHONUTF8" || key == "PYTHONIOENCODING"));
+        }
+    }
+
+    #[test]
+    fn test_check_isolated() {
+        let mut settings = SerenaContextServerSettings::default();
+        assert_eq!(check_isolated(&settings), Ok(false));
+
+        settings.isolated = Some(true);
+        assert_eq!(check_isolated(&settings), Ok(true));
+
+        settings.pythonpath = Some(vec!["/plugins".to_string()]);
+        let err = check_isolated(&settings).unwrap_err();
+        assert!(err.contains("pythonpath would have no effect"), "{}", err);
+
+        settings.pythonpath = None;
+        settings.install_scope = Some(InstallScope::User);
+        let err = check_isolated(&settings).unwrap_err();
+        assert!(err.contains("user site-packages"), "{}", err);
+    }
+
+    #[test]
+    fn test_managed_env_dir() {
+        let work_dir = std::path::Path::new("/work");
+        let root = std::path::Path::new("/src/app");
+        let dir = |settings: &SerenaContextServerSettings, os| {
+            managed_env_dir(
+                Some(settings),
+                installation::Channel::Stable,
+                Some(work_dir),
+                Some(root),
+                os,
+                Some("/home/me"),
+            )
+        };
+
+        let mut settings = SerenaContextServerSettings::default();
+        assert_eq!(
+            dir(&settings, zed::Os::Linux),
+            Ok(Some("/work/envs/stable".into()))
+        );
+        settings.venv_dir = Some("~/fast/serena".to_string());
+        assert_eq!(
+            dir(&settings, zed::Os::Linux),
+            Ok(Some("/home/me/fast/serena".into()))
+        );
+        settings.venv_dir = Some(".serena/venv".to_string());
+        assert_eq!(
+            dir(&settings, zed::Os::Linux),
+            Ok(Some("/src/app/.serena/venv".into()))
+        );
+        settings.venv_dir = Some("D:\\Shared Envs\\serena".to_string());
+        assert!(dir(&settings, zed::Os::Windows).is_err());
+    }
+
+    #[test]
+    fn test_zed_python_path() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        std::fs::create_dir_all(root.join(".zed")).unwrap();
+        std::fs::write(
+            root.join(".zed/settings.json"),
+            r#"{
+                // Shared with the team
+                "lsp": { "pyright": { "settings": { "python": { "venv": ".venv" } } } },
+            }"#,
+        )
+        .unwrap();
+
+        let mut settings = SerenaContextServerSettings::default();
+        let python = |settings: &SerenaContextServerSettings| {
+            zed_python_path(Some(settings), Some(root), python_discovery::Os::Linux)
+        };
+        assert_eq!(
+            python(&settings),
+            Some(root.join(".venv/bin/python").to_string_lossy().into_owned())
+        );
+        settings.use_zed_python_settings = Some(false);
+        assert_eq!(python(&settings), None);
+        assert_eq!(
+            zed_python_path(None, None, python_discovery::Os::Linux),
+            None
+        );
+    }
+
+    #[test]
+    fn test_default_settings() {
+        let defaults = default_settings(None);
+        assert!(defaults.contains("\"analytics\": false"), "{}", defaults);
+        assert!(!defaults.contains("excluded_tools"));
+
+        let rust = census::Census::from_file_names(["main.rs"]);
+        assert!(!default_settings(Some(&rust)).contains("excluded_tools"));
+        let haskell = census::Census::from_file_names(["Main.hs"]);
+        let defaults = default_settings(Some(&haskell));
+        let value: serde_json::Value = serde_json::from_str(&defaults).unwrap();
+        assert_eq!(value["excluded_tools"][0], serde_json::json!("find_symbol"));
+    }
+
+    #[test]
+    fn test_working_directory() {
+        assert_eq!(
+            working_directory(&SerenaContextServerSettings::default()),
+            None
+        );
+
+        let mut settings = SerenaContextServerSettings {
+            project_root: Some("/src/app".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(working_directory(&settings).as_deref(), Some("/src/app"));
+
+        settings.working_directory = Some("backend".to_string());
+        assert_eq!(
+            working_directory(&settings).as_deref(),
+            Some("/src/app/backend")
+        );
+
+        settings.working_directory = Some("/tmp/serena".to_string());
+        assert_eq!(working_directory(&settings).as_deref(), Some("/tmp/serena"));
+    }
+
+    #[test]
+    fn test_project_subpath() {
+        let mut settings = SerenaContextServerSettings {
+            project_root: Some("/src/monorepo".to_string()),
+            project_subpath: Some("services/billing".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            serena_server_args(Some(&settings), Workspace::Single, zed::Os::Linux)[1..3],
+            ["--project", "/src/monorepo/services/billing"]
+        );
+        // Windows gets the drive path without the guest's leading slash
+        let windows = SerenaContextServerSettings {
+            project_root: Some("/C:/src/monorepo".to_string()),
+            project_subpath: Some("services/billing".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            serena_server_args(Some(&windows), Workspace::Single, zed::Os::Windows)[1..3],
+            ["--project", "C:/src/monorepo/services/billing"]
+        );
+        assert_eq!(
+            working_directory(&settings).as_deref(),
+            Some("/src/monorepo/services/billing")
+        );
+        assert_eq!(check_project_subpath(&settings, |_| true), Ok(()));
+        let err = check_project_subpath(&settings, |_| false).unwrap_err();
+        assert!(err.contains("does not exist"), "{}", err);
+
+        for subpath in ["../elsewhere", "/etc", ""] {
+            settings.project_subpath = Some(subpath.to_string());
+            assert!(
+                check_project_subpath(&settings, |_| true).is_err(),
+                "{}",
+                subpath
+            );
+        }
+
+        settings.project_root = None;
+        settings.project_subpath = Some("services/billing".to_string());
+        let err = check_project_subpath(&settings, |_| true).unwrap_err();
+        assert!(err.contains("needs project_root"), "{}", err);
+    }
+
+    #[test]
+    fn test_check_transport() {
+        let mut settings = SerenaContextServerSettings {
+            transport: Some(Transport::Sse),
+            share_instance: Some(true),
+            project_root: Some("/src/app".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(check_transport(&settings), Ok(()));
+        // The extension's own directory must not key a shared instance
+        settings.project_root = None;
+        let err = check_transport(&settings).unwrap_err();
+        assert!(err.contains("needs project_root"), "{}", err);
+        settings.share_instance = None;
+        let err = check_transport(&settings).unwrap_err();
+        assert!(err.contains("needs share_instance"), "{}", err);
+    }
+
+    #[test]
+    fn test_serena_server_args() {
+        assert_eq!(
+            serena_server_args(None, Workspace::Single, zed::Os::Linux),
+            vec!["start-mcp-server"]
+        );
+
+        let settings = SerenaContextServerSettings {
+            context: Some("ide-assistant".to_string()),
+            modes: Some(vec!["planning".to_string(), "editing".to_string()]),
+            ..Default::default()
+        };
+        assert_eq!(
+            serena_server_args(Some(&settings), Workspace::Single, zed::Os::Linux),
+            vec![
+                "start-mcp-server",
+                "--context",
+                "ide-assistant",
+                "--mode",
+                "planning",
+                "--mode",
+                "editing"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_skip_conda_base() {
+        let interpreter = |path: &str| detection::Interpreter {
+            path: path.to_string(),
+            version: "3.12.1".to_string(),
+        };
+        let is_dir = |dir: &std::path::Path| {
+            dir == std::path::Path::new("/opt/anaconda3/conda-meta")
+                || dir == std::path::Path::new("/opt/anaconda3/condabin")
+        };
+        let anaconda = interpreter("/opt/anaconda3/bin/python3.12");
+        let system = interpreter("/usr/bin/python3.12");
+
+        assert_eq!(
+            skip_conda_base(vec![anaconda.clone(), system.clone()], is_dir),
+            [system]
+        );
+        // Better than nothing
+        assert_eq!(skip_conda_base(vec![anaconda.clone()], is_dir), [anaconda]);
+    }
+
+    #[test]
+    fn test_pyenv_virtualenv_python() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        std::fs::create_dir_all(root.join("versions/3.12.1/envs/serena-env")).unwrap();
+        let pyenv_root = root.to_string_lossy().to_string();
+        let lookup_env = |name: &str| (name == "PYENV_ROOT").then(|| pyenv_root.clone());
+
+        assert_eq!(
+            pyenv_virtualenv_python("serena-env", python_discovery::Os::Linux, lookup_env).unwrap(),
+            root.join("versions/3.12.1/envs/serena-env/bin/python")
+                .to_string_lossy()
+        );
+        let err =
+            pyenv_virtualenv_python("other", python_discovery::Os::Linux, lookup_env).unwrap_err();
+        assert!(err.ends_with("available: `serena-env`"), "{}", err);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_check_configured_python() {
+        use python_discovery::process::StdRunner;
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        std::fs::create_dir_all(root.join(".venv/bin")).unwrap();
+        for (name, version) in [("python", "3.12.1"), ("python3.9", "3.9.18")] {
+            let path = root.join(".venv/bin").join(name);
+            std::fs::write(&path, format!("#!/bin/sh\necho 'Python {}'\n", version)).unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        let host = python_discovery::Host {
+            os: python_discovery::Os::Linux,
+            arch: python_discovery::Arch::X86_64,
+            sandbox: None,
+        };
+        let mut cache = probe_cache::ProbeCache::default();
+        let mut check = |configured: &str| {
+            check_configured_python(
+                configured,
+                None,
+                Some(root),
+                Some("/home/dev"),
+                host,
+                &StdRunner,
+                &mut cache,
+            )
+        };
+
+        assert_eq!(
+            check("./.venv/lib/../bin/python").unwrap(),
+            root.join(".venv/bin/python").to_string_lossy()
+        );
+        let err = check("  ").unwrap_err();
+        assert!(err.contains("is empty"), "{}", err);
+        let err = check(".venv/bin/python3.9").unwrap_err();
+        assert!(
+            err.starts_with("python_executable `.venv/bin/python3.9` (resolved to `"),
+            "{}",
+            err
+        );
+        assert!(
+            err.contains("can't run Serena: Python 3.9.18 is not supported"),
+            "{}",
+            err
+        );
+        let err = check("~/moved/.venv/bin/python").unwrap_err();
+        assert!(
+            err.contains("(resolved to `/home/dev/moved/.venv/bin/python`) does not exist"),
+            "{}",
+            err
+        );
+        let err = check("python3.99-missing").unwrap_err();
+        assert!(err.contains("was not found on PATH"), "{}", err);
+        let err = check("/home/dev/bin/ruby").unwrap_err();
+        assert!(
+            err.contains("doesn't look like a Python interpreter"),
+            "{}",
+            err
+        );
+
+        // fallback_to_autodetect passes a broken interpreter over
+        let settings = |fallback: bool| {
+            parse_settings(serde_json::json!({
+                "python_executable": "/moved/.venv/bin/python",
+                "fallback_to_autodetect": fallback,
+            }))
+            .unwrap()
+        };
+        let err =
+            resolve_python_executable(Some(&settings(false)), None, host, &StdRunner, &mut cache)
+                .err()
+                .unwrap();
+        assert!(err.contains("does not exist"), "{}", err);
+        match resolve_python_executable(Some(&settings(true)), None, host, &StdRunner, &mut cache) {
+            Ok(resolved) => {
+                assert_ne!(resolved.path, "/moved/.venv/bin/python");
+                assert!(resolved.fallback.unwrap().contains("does not exist"));
+            }
+            Err(err) => assert!(
+                err.contains("fallback_to_autodetect found no interpreter either"),
+                "{}",
+                err
+            ),
+        }
+    }
+
+    #[test]
+    fn test_external_agent_context() {
+        let mut settings = SerenaContextServerSettings {
+            agent: Some(Agent::External),
+            ..Default::default()
+        };
+        assert_eq!(
+            serena_server_args(Some(&settings), Workspace::Single, zed::Os::Linux),
+            vec!["start-mcp-server", "--context", "ide-assistant"]
+        );
+
+        // An explicit context wins
+        settings.context = Some("agent".to_string());
+        assert_eq!(
+            serena_server_args(Some(&settings), Workspace::Single, zed::Os::Linux),
+            vec!["start-mcp-server", "--context", "agent"]
+        );
+
+        settings.context = None;
+        settings.agent = Some(Agent::Zed);
+        assert_eq!(
+            serena_server_args(Some(&settings), Workspace::Single, zed::Os::Linux),
+            vec!["start-mcp-server"]
+        );
+
+        // enforce_ide_context wins over everything
+        settings.context = Some("desktop-app".to_string());
+        settings.enforce_ide_context = Some(true);
+        assert_eq!(
+            serena_server_args(Some(&settings), Workspace::Single, zed::Os::Linux),
+            vec!["start-mcp-server", "--context", "ide-assistant"]
+        );
+    }
+
+    #[test]
+    fn test_apply_size_defaults() {
+        let mut settings = SerenaContextServerSettings::default();
+        apply_size_defaults(&mut settings, census::Size::Large);
+        assert_eq!(
+            settings.modes.as_deref(),
+            Some(&["interactive", "editing", "planning"].map(String::from)[..])
+        );
+        assert_eq!(settings.forward_ignores, Some(true));
+
+        // Configured values win
+        let mut settings = SerenaContextServerSettings {
+            modes: Some(vec!["editing".to_string()]),
+            forward_ignores: Some(false),
+            ..Default::default()
+        };
+        apply_size_defaults(&mut settings, census::Size::Large);
+        assert_eq!(settings.modes, Some(vec!["editing".to_string()]));
+        assert_eq!(settings.forward_ignores, Some(false));
+        apply_size_defaults(&mut settings, census::Size::Small);
+        assert_eq!(settings.modes, Some(vec!["editing".to_string()]));
+    }
+
+    #[test]
+    fn test_serena_server_args_without_worktrees() {
+        assert_eq!(Workspace::from_worktree_count(0), Workspace::Empty);
+        let settings = SerenaContextServerSettings {
+            project_root: Some("/src/app".to_string()),
+            ..Default::default()
+        };
+
+        // No folder open: start project-less even when project_root is set
+        assert_eq!(
+            serena_server_args(Some(&settings), Workspace::Empty, zed::Os::Linux),
+            vec!["start-mcp-server"]
+        );
+        assert!(instructions::workspace_notice(0, true).is_some());
+    }
+
+    #[test]
+    fn test_serena_server_args_multiple_worktrees() {
+        assert_eq!(Workspace::from_worktree_count(3), Workspace::Multiple);
+        let mut settings = SerenaContextServerSettings::default();
+
+        // Serena activates one project at a time, so nothing is guessed
+        assert_eq!(
+            serena_server_args(Some(&settings), Workspace::Multiple, zed::Os::Linux),
+            vec!["start-mcp-server"]
+        );
+        assert!(instructions::workspace_notice(3, false).is_some());
+
+        settings.project_root = Some("/src/app".to_string());
+        assert_eq!(
+            serena_server_args(Some(&settings), Workspace::Multiple, zed::Os::Linux),
+            vec!["start-mcp-server", "--project", "/src/app"]
+        );
+        assert!(instructions::workspace_notice(3, true).is_none());
+    }
+
+    #[cfg(feature = "schema")]
+    #[test]
+    fn test_settings_schema_is_up_to_date() {
+        let schema = schemars::schema_for!(SerenaContextServerSettings);
+        let generated = format!("{}\n", serde_json::to_string_pretty(&schema).unwrap());
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/src/settings-schema.json");
+        if std::env::var_os("UPDATE_SCHEMA").is_some() {
+            std::fs::write(path, &generated).unwrap();
+        }
+        assert!(
+            SETTINGS_SCHEMA == generated,
+            "src/settings-schema.json is stale; regenerate it with \
+             `UPDATE_SCHEMA=1 cargo test --features schema settings_schema`"
+        );
     }
 }
@@ -0,0 +1,187 @@
+//! Serena configuration overlays.
+//!
+//! Some Serena options, such as excluded tools or per-language server choices,
+//! can only be set in its YAML configuration. They are written to an overlay
+//! file among the project's generated files on every launch and passed to
+//! Serena with `--serena-config`. JSON is valid YAML, so the overlay is
+//! written as JSON.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+use zed_extension_api::serde_json::{self, Map, Value};
+
+use crate::{generated, SerenaContextServerSettings, OVERLAPPING_TOOLS};
+
+/// Collects the overlay entries from the settings and the forwarded
+/// `ignored_paths`, or `None` when there are none.
+///
+/// Dedicated settings win over the same key in `serena_config`; forwarded
+/// ignore patterns are added to any listed there. `exclude_overlapping_tools`
+/// adds the tools that overlap Zed's to `excluded_tools`.
+pub(crate) fn entries(
+    settings: &SerenaContextServerSettings,
+    ignored_paths: &[String],
+) -> Option<Map<String, Value>> {
+    let mut entries = settings.serena_config.clone().unwrap_or_default();
+    let mut tools = settings.excluded_tools.clone().unwrap_or_default();
+    if settings.exclude_overlapping_tools == Some(true) {
+        for tool in OVERLAPPING_TOOLS {
+            if !tools.iter().any(|excluded| excluded == tool) {
+                tools.push(tool.to_string());
+            }
+        }
+    }
+    if !tools.is_empty() {
+        entries.insert("excluded_tools".to_string(), Value::from(tools));
+    }
+    if !ignored_paths.is_empty() {
+        let mut paths = match entries.remove("ignored_paths") {
+            Some(Value::Array(paths)) => paths,
+            _ => Vec::new(),
+        };
+        for path in ignored_paths {
+            let path = Value::from(path.as_str());
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+        entries.insert("ignored_paths".to_string(), Value::Array(paths));
+    }
+    (!entries.is_empty()).then_some(entries)
+}
+
+/// Renders the overlay file contents.
+pub(crate) fn render(entries: &Map<String, Value>) -> String {
+    let mut contents = serde_json::to_string_pretty(entries).unwrap_or_default();
+    contents.push('\n');
+    contents
+}
+
+/// Path of the overlay with `contents` for `project_root`.
+///
+/// Files are named after their contents, so windows launching with different
+/// settings never overwrite each other's overlay.
+pub(crate) fn path(work_dir: &Path, project_root: Option<&Path>, contents: &str) -> PathBuf {
+    let mut hasher = DefaultHasher::new();
+    contents.hash(&mut hasher);
+    generated::project_dir(work_dir, project_root)
+        .join(format!("overlay-{:016x}.yml", hasher.finish()))
+}
+
+/// Writes the overlay for `settings` and `ignored_paths` and returns its
+/// path, or `None` when there is nothing to overlay.
+///
+/// The file is rewritten on every launch, so deleting it is always safe.
+/// Overlays of settings no launch has used for a while are removed.
+pub(crate) fn write(
+    settings: &SerenaContextServerSettings,
+    ignored_paths: &[String],
+    work_dir: Option<&Path>,
+) -> Result<Option<PathBuf>, String> {
+    let Some(entries) = entries(settings, ignored_paths) else {
+        return Ok(None);
+    };
+    let work_dir =
+        work_dir.ok_or("A Serena configuration overlay needs the extension work directory")?;
+    let contents = render(&entries);
+    let path = path(
+        work_dir,
+        settings.project_root.as_deref().map(Path::new),
+        &contents,
+    );
+    let dir = path.parent().unwrap_or(work_dir);
+    generated::create_dir(dir)?;
+    generated::write_private(&path, &contents)?;
+    generated::prune(dir, std::time::SystemTime::now(), generated::MAX_AGE);
+    Ok(Some(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zed_extension_api::serde_json::json;
+
+    fn settings(value: Value) -> SerenaContextServerSettings {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_entries() {
+        assert_eq!(entries(&settings(json!({})), &[]), None);
+        assert_eq!(
+            entries(&settings(json!({ "excluded_tools": [] })), &[]),
+            None
+        );
+
+        let entries = entries(
+            &settings(json!({
+                "excluded_tools": ["execute_shell_command"],
+                "serena_config": {
+                    "excluded_tools": ["ignored"],
+                    "web_dashboard_open_on_launch": false,
+                },
+            })),
+            &[],
+        )
+        .unwrap();
+        assert_eq!(
+            Value::Object(entries),
+            json!({
+                "excluded_tools": ["execute_shell_command"],
+                "web_dashboard_open_on_launch": false,
+            })
+        );
+    }
+
+    #[test]
+    fn test_entries_overlapping_tools() {
+        let entries = entries(
+            &settings(json!({
+                "excluded_tools": ["read_file", "onboarding"],
+                "exclude_overlapping_tools": true,
+            })),
+            &[],
+        )
+        .unwrap();
+        let tools = entries["excluded_tools"].as_array().unwrap();
+        assert_eq!(tools.len(), OVERLAPPING_TOOLS.len() + 1);
+        assert_eq!(tools[..2], [json!("read_file"), json!("onboarding")]);
+        assert!(tools.contains(&json!("execute_shell_command")));
+    }
+
+    #[test]
+    fn test_entries_ignored_paths() {
+        let forwarded = ["node_modules".to_string(), "*.log".to_string()];
+        assert_eq!(
+            entries(&settings(json!({})), &forwarded).map(Value::Object),
+            Some(json!({ "ignored_paths": ["node_modules", "*.log"] }))
+        );
+        let entries = entries(
+            &settings(json!({ "serena_config": { "ignored_paths": ["fixtures", "*.log"] } })),
+            &forwarded,
+        );
+        assert_eq!(
+            entries.map(Value::Object),
+            Some(json!({ "ignored_paths": ["fixtures", "*.log", "node_modules"] }))
+        );
+    }
+
+    #[test]
+    fn test_write() {
+        let temp = tempfile::tempdir().unwrap();
+        let work_dir = temp.path().to_path_buf();
+        let overlay = settings(json!({ "excluded_tools": ["execute_shell_command"] }));
+
+        let path = write(&overlay, &[], Some(&work_dir)).unwrap().unwrap();
+        assert!(path.starts_with(generated::project_dir(&work_dir, None)));
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(contents.contains("\"execute_shell_command\""));
+        // The same settings map to the same file
+        assert_eq!(write(&overlay, &[], Some(&work_dir)).unwrap(), Some(path));
+
+        assert_eq!(write(&settings(json!({})), &[], None), Ok(None));
+        assert!(write(&overlay, &[], None).is_err());
+    }
+}
@@ -0,0 +1,143 @@
+//! Translating paths between the host and the WASI guest.
+//!
+//! Zed runs extensions under WASI. On Windows the guest names host files
+//! with forward slashes and a leading slash before the drive, e.g.
+//! `/C:/Users/dev` for `C:\Users\dev`, and that is also how it reports the
+//! work dir (see <https://github.com/bytecodealliance/wasmtime/issues/10415>).
+//! Paths going into a command need the host form; paths the extension reads
+//! itself need the guest form. On every other platform, including Unix-likes
+//! Zed reports as Linux, both forms are the same.
+
+use std::path::{Path, PathBuf};
+
+use zed_extension_api::Os;
+
+/// Prefix Windows puts before verbatim paths, e.g. `\\?\C:\python`.
+const VERBATIM_PREFIX: &str = r"\\?\";
+
+/// Host form of `path`, for commands and environment variables.
+///
+/// On Windows the leading slash of a guest drive path is dropped. Relative
+/// paths, UNC paths and paths already in host form are left alone.
+pub(crate) fn to_host(path: &Path, os: Os) -> PathBuf {
+    if os != Os::Windows {
+        return path.to_path_buf();
+    }
+    let text = path.to_string_lossy();
+    match text.strip_prefix('/') {
+        Some(rest) if starts_with_drive(rest) => PathBuf::from(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Guest form of `path`, for reading it from the extension.
+///
+/// On Windows, drive paths get a leading slash, UNC paths keep their double
+/// slash and every backslash becomes a forward slash. Verbatim drive paths
+/// lose their `\\?\` prefix, which the guest doesn't understand.
+pub(crate) fn to_guest(path: &Path, os: Os) -> PathBuf {
+    if os != Os::Windows {
+        return path.to_path_buf();
+    }
+    let text = path.to_string_lossy();
+    let text = match text.strip_prefix(VERBATIM_PREFIX) {
+        Some(rest) if starts_with_drive(rest) => rest,
+        _ => &text,
+    };
+    let text = text.replace('\\', "/");
+    if starts_with_drive(&text) {
+        PathBuf::from(format!("/{}", text))
+    } else {
+        PathBuf::from(text)
+    }
+}
+
+/// Whether `text` starts with a drive such as `C:`.
+fn starts_with_drive(text: &str) -> bool {
+    let bytes = text.as_bytes();
+    bytes.len() >= 2
+        && bytes[0].is_ascii_alphabetic()
+        && bytes[1] == b':'
+        && bytes
+            .get(2)
+            .is_none_or(|separator| matches!(separator, b'/' | b'\\'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn host(path: &str) -> String {
+        to_host(Path::new(path), Os::Windows)
+            .to_string_lossy()
+            .to_string()
+    }
+
+    fn guest(path: &str) -> String {
+        to_guest(Path::new(path), Os::Windows)
+            .to_string_lossy()
+            .to_string()
+    }
+
+    #[test]
+    fn test_windows_paths() {
+        assert_eq!(
+            host("/C:/Users/dev/.venv/Scripts/python.exe"),
+            "C:/Users/dev/.venv/Scripts/python.exe"
+        );
+        assert_eq!(host("/C:"), "C:");
+        assert_eq!(
+            host("C:\\Python312\\python.exe"),
+            "C:\\Python312\\python.exe"
+        );
+        // Only a drive loses its slash; the old workaround trimmed UNC paths too
+        assert_eq!(
+            host("//server/share/python.exe"),
+            "//server/share/python.exe"
+        );
+        assert_eq!(host("/work"), "/work");
+        assert_eq!(host("/Cache:/x"), "/Cache:/x");
+
+        assert_eq!(guest("C:\\Users\\dev"), "/C:/Users/dev");
+        assert_eq!(guest("d:/projects"), "/d:/projects");
+        assert_eq!(guest("\\\\?\\C:\\python"), "/C:/python");
+        assert_eq!(guest("\\\\server\\share"), "//server/share");
+        assert_eq!(guest("..\\..\\Windows\\System32"), "../../Windows/System32");
+        assert_eq!(guest("/C:/already/guest"), "/C:/already/guest");
+    }
+
+    #[test]
+    fn test_round_trips() {
+        let hosts = [
+            "C:/Users/dev/.venv/Scripts/python.exe",
+            "C:\\Users\\dev\\.venv\\Scripts\\python.exe",
+            "c:\\ünïcødé\\パイソン",
+            "D:",
+            "\\\\server\\share\\serena",
+            "relative\\dir",
+            "NUL",
+        ];
+        for path in hosts {
+            // The guest only writes forward slashes
+            assert_eq!(host(&guest(path)), path.replace('\\', "/"), "{}", path);
+        }
+
+        let guests = [
+            "/C:/Users/dev",
+            "/z:/",
+            "//server/share/serena",
+            "relative/dir",
+            "",
+        ];
+        for path in guests {
+            assert_eq!(guest(&host(path)), path, "{}", path);
+        }
+
+        for os in [Os::Mac, Os::Linux] {
+            for path in hosts.iter().chain(&guests) {
+                assert_eq!(to_host(Path::new(path), os), Path::new(path));
+                assert_eq!(to_guest(Path::new(path), os), Path::new(path));
+            }
+        }
+    }
+}
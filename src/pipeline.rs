@@ -0,0 +1,200 @@
+//! The launch pipeline: Detect → Ensure Installed → Preflight → Build Command.
+//!
+//! Zed may give up on a launch while pip is still installing, and the next
+//! call used to start over from detection. The stage a launch is in, and what
+//! the finished stages produced, are persisted with the extension state
+//! instead. An interrupted launch resumes where it stopped, and the
+//! configuration panel shows which stage the extension is in or which one
+//! failed.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// How long an interrupted launch may be resumed, in seconds.
+const RESUME_WINDOW_SECS: u64 = 60 * 60;
+
+/// A step of the launch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Stage {
+    /// Finding the interpreter
+    Detect,
+    /// Installing serena-agent or building a managed environment
+    EnsureInstalled,
+    /// Asking the installed release for its entry point and options
+    Preflight,
+    /// Assembling the command Zed runs
+    BuildCommand,
+    /// The command was handed to Zed
+    Ready,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Detect => "Detect",
+            Self::EnsureInstalled => "Ensure Installed",
+            Self::Preflight => "Preflight",
+            Self::BuildCommand => "Build Command",
+            Self::Ready => "Ready",
+        })
+    }
+}
+
+/// Where the current or last launch got to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Progress {
+    /// Fingerprint of the effective settings the launch runs with
+    pub settings_hash: String,
+    /// Stage running, or the one that failed
+    pub stage: Stage,
+    /// Seconds since the Unix epoch when the launch started
+    pub started_at: u64,
+    /// When the current stage started
+    pub stage_started_at: u64,
+    /// Interpreter detection settled on
+    #[serde(default)]
+    pub python: Option<String>,
+    /// Why the configured interpreter was passed over for auto-detection
+    #[serde(default)]
+    pub fallback: Option<String>,
+    /// Interpreter serena-agent is installed for, which may be a managed environment's
+    #[serde(default)]
+    pub installed: Option<String>,
+    /// The stage this launch resumed at, if it continued an interrupted one
+    #[serde(default)]
+    pub resumed_at: Option<Stage>,
+    /// Why the stage failed
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+impl Progress {
+    /// Starts a launch, continuing `previous` when it was interrupted recently
+    /// with the same settings.
+    ///
+    /// Failed launches start over, and so does anything after Ensure
+    /// Installed, since preflight results are cached anyway.
+    pub(crate) fn resume(previous: Option<&Progress>, settings_hash: String, now: u64) -> Self {
+        let interrupted = previous.filter(|previous| {
+            previous.stage != Stage::Ready
+                && previous.error.is_none()
+                && previous.settings_hash == settings_hash
+                && now.saturating_sub(previous.started_at) < RESUME_WINDOW_SECS
+        });
+        let python = interrupted.and_then(|previous| previous.python.clone());
+        let fallback = interrupted.and_then(|previous| previous.fallback.clone());
+        let installed = interrupted.and_then(|previous| previous.installed.clone());
+        let stage = match (&python, &installed) {
+            (_, Some(_)) => Stage::Preflight,
+            (Some(_), None) => Stage::EnsureInstalled,
+            (None, None) => Stage::Detect,
+        };
+        Self {
+            settings_hash,
+            stage,
+            started_at: interrupted.map_or(now, |previous| previous.started_at),
+            stage_started_at: now,
+            python,
+            fallback,
+            installed,
+            resumed_at: (stage != Stage::Detect).then_some(stage),
+            error: None,
+        }
+    }
+
+    /// Moves on to `stage`.
+    pub(crate) fn enter(&mut self, stage: Stage, now: u64) {
+        self.stage = stage;
+        self.stage_started_at = now;
+    }
+
+    /// Records the detected interpreter and moves on to Ensure Installed.
+    pub(crate) fn detected(&mut self, python: &str, now: u64) {
+        self.python = Some(python.to_string());
+        self.enter(Stage::EnsureInstalled, now);
+    }
+
+    /// Records the interpreter serena-agent is installed for and moves on to Preflight.
+    pub(crate) fn installed(&mut self, python: &str, now: u64) {
+        self.installed = Some(python.to_string());
+        self.enter(Stage::Preflight, now);
+    }
+
+    /// Records why the current stage failed; a finished launch stays finished.
+    pub(crate) fn fail(&mut self, error: &str) {
+        if self.stage != Stage::Ready {
+            self.error = Some(error.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resume() {
+        let mut progress = Progress::resume(None, "a".to_string(), 1_000);
+        assert_eq!(progress.stage, Stage::Detect);
+        assert_eq!(progress.resumed_at, None);
+
+        progress.detected("/usr/bin/python3.12", 1_010);
+        // Zed gave up while pip was running
+        let resumed = Progress::resume(Some(&progress), "a".to_string(), 1_100);
+        assert_eq!(resumed.stage, Stage::EnsureInstalled);
+        assert_eq!(resumed.resumed_at, Some(Stage::EnsureInstalled));
+        assert_eq!(resumed.python.as_deref(), Some("/usr/bin/python3.12"));
+        assert_eq!(resumed.started_at, 1_000);
+
+        progress.installed("/work/serena-venv/bin/python", 1_200);
+        progress.enter(Stage::BuildCommand, 1_210);
+        let resumed = Progress::resume(Some(&progress), "a".to_string(), 1_300);
+        assert_eq!(resumed.stage, Stage::Preflight);
+        assert_eq!(
+            resumed.installed.as_deref(),
+            Some("/work/serena-venv/bin/python")
+        );
+
+        // Changed settings, stale launches, failures and finished launches start over
+        for (previous, hash, now) in [
+            (progress.clone(), "b", 1_300),
+            (progress.clone(), "a", 1_000 + RESUME_WINDOW_SECS),
+            (
+                Progress {
+                    error: Some("pip failed".to_string()),
+                    ..progress.clone()
+                },
+                "a",
+                1_300,
+            ),
+            (
+                Progress {
+                    stage: Stage::Ready,
+                    ..progress.clone()
+                },
+                "a",
+                1_300,
+            ),
+        ] {
+            let fresh = Progress::resume(Some(&previous), hash.to_string(), now);
+            assert_eq!(fresh.stage, Stage::Detect);
+            assert_eq!(fresh.python, None);
+            assert_eq!(fresh.started_at, now);
+        }
+    }
+
+    #[test]
+    fn test_fail() {
+        let mut progress = Progress::resume(None, "a".to_string(), 1_000);
+        progress.fail("No suitable Python interpreter found");
+        assert_eq!(progress.stage, Stage::Detect);
+        assert!(progress.error.is_some());
+
+        let mut progress = Progress::resume(None, "a".to_string(), 1_000);
+        progress.enter(Stage::Ready, 1_010);
+        progress.fail("too late");
+        assert_eq!(progress.error, None);
+    }
+}
@@ -0,0 +1,157 @@
+//! Reads project-level Serena configuration from `pyproject.toml`.
+//!
+//! Projects can declare defaults in a `[tool.serena]` or `[tool.zed-serena]`
+//! table.
+
+use std::path::Path;
+use toml_edit::{Document, Item, Table, Value as TomlValue};
+use zed_extension_api::serde_json::{Map, Number, Value};
+
+/// Tables recognised in `pyproject.toml`, in increasing order of precedence.
+const TOOL_TABLES: [&str; 2] = ["serena", "zed-serena"];
+
+/// Reads the Serena tool tables from `<root>/pyproject.toml`.
+///
+/// Returns `Ok(None)` when the file doesn't exist or declares no Serena table.
+pub(crate) fn read_tool_settings(root: &Path) -> Result<Option<Value>, String> {
+    let path = root.join("pyproject.toml");
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(_) => return Ok(None),
+    };
+
+    parse_tool_settings(&contents).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Extracts and merges the Serena tool tables from `pyproject.toml` contents.
+///
+/// Top-level keys are normalized to snake_case so `python-version` and
+/// `python_version` are equivalent. `[tool.zed-serena]` wins over `[tool.serena]`.
+pub(crate) fn parse_tool_settings(contents: &str) -> Result<Option<Value>, String> {
+    let document = Document::parse(contents).map_err(|e| {
+        let line = e
+            .span()
+            .map_or(1, |span| contents[..span.start].lines().count().max(1));
+        format!("line {}: {}", line, e.message())
+    })?;
+    let Some(tool) = document.as_table().get("tool") else {
+        return Ok(None);
+    };
+
+    let mut merged = None;
+    for name in TOOL_TABLES {
+        let Some(Value::Object(table)) = tool.get(name).map(item_to_json) else {
+            continue;
+        };
+        let settings = merged.get_or_insert_with(Map::new);
+        for (key, value) in table {
+            settings.insert(key.replace('-', "_"), value);
+        }
+    }
+    Ok(merged.map(Value::Object))
+}
+
+fn item_to_json(item: &Item) -> Value {
+    match item {
+        Item::None => Value::Null,
+        Item::Value(value) => value_to_json(value),
+        Item::Table(table) => table_to_json(table),
+        Item::ArrayOfTables(tables) => Value::Array(tables.iter().map(table_to_json).collect()),
+    }
+}
+
+fn table_to_json(table: &Table) -> Value {
+    Value::Object(
+        table
+            .iter()
+            .map(|(key, item)| (key.to_string(), item_to_json(item)))
+            .collect(),
+    )
+}
+
+fn value_to_json(value: &TomlValue) -> Value {
+    match value {
+        TomlValue::String(text) => Value::String(text.value().clone()),
+        TomlValue::Integer(int) => Value::Number((*int.value()).into()),
+        TomlValue::Float(float) => {
+            Number::from_f64(*float.value()).map_or(Value::Null, Value::Number)
+        }
+        TomlValue::Boolean(flag) => Value::Bool(*flag.value()),
+        TomlValue::Datetime(datetime) => Value::String(datetime.value().to_string()),
+        TomlValue::Array(items) => Value::Array(items.iter().map(value_to_json).collect()),
+        TomlValue::InlineTable(table) => Value::Object(
+            table
+                .iter()
+                .map(|(key, value)| (key.to_string(), value_to_json(value)))
+                .collect(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zed_extension_api::serde_json::json;
+
+    #[test]
+    fn test_parse_tool_settings() {
+        let contents = r#"
+[project]
+name = "demo"
+dependencies = [
+    "requests>=2",  # [not.a.header]
+]
+description = """
+A "demo" project é
+"""
+urls = { homepage = "https://example.com" }
+
+[tool.serena]
+context = "ide-assistant"
+modes = ["planning", "editing"]
+python-version = "3.11"
+
+[tool.zed-serena]
+python-version = "3.12"
+excluded_tools = [
+    "execute_shell_command",
+]
+serena_config = { ls_specific_settings = { python = { timeout = 1.5 } } }
+
+[tool.zed-serena.environment]
+SERENA_LOG_LEVEL = "debug"  # inline comment
+GREETING = "caf\u00e9"
+
+[tool.ruff]
+line-length = 100
+"#;
+
+        let settings = parse_tool_settings(contents).unwrap().unwrap();
+        assert_eq!(
+            settings,
+            json!({
+                "context": "ide-assistant",
+                "modes": ["planning", "editing"],
+                "python_version": "3.12",
+                "excluded_tools": ["execute_shell_command"],
+                "serena_config": { "ls_specific_settings": { "python": { "timeout": 1.5 } } },
+                "environment": { "SERENA_LOG_LEVEL": "debug", "GREETING": "café" }
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_tool_settings_without_table() {
+        let contents = "[project]\nname = \"demo\"\n";
+        assert_eq!(parse_tool_settings(contents).unwrap(), None);
+        let contents = "[tool.ruff]\nline-length = 100\n";
+        assert_eq!(parse_tool_settings(contents).unwrap(), None);
+    }
+
+    #[test]
+    fn test_parse_tool_settings_reports_line() {
+        let contents = "[tool.serena]\ncontext = ide-assistant\n";
+        let err = parse_tool_settings(contents).unwrap_err();
+        assert!(err.starts_with("line 2:"), "{}", err);
+    }
+}
@@ -0,0 +1,139 @@
+//! `PYTHONPATH` assembly for Serena plugins and local patches.
+//!
+//! `pythonpath` entries go first so they can shadow installed packages;
+//! `site_packages_extra` entries go last so they only add packages. Any
+//! `PYTHONPATH` already configured sits between the two.
+
+use std::path::Path;
+use zed_extension_api::Os;
+
+/// Separator between `PYTHONPATH` entries on `os`.
+fn separator(os: Os) -> char {
+    match os {
+        Os::Windows => ';',
+        Os::Mac | Os::Linux => ':',
+    }
+}
+
+/// Builds the `PYTHONPATH` value, or `None` when there is nothing to add.
+///
+/// Entries are trimmed, `~` is expanded from the home directory and relative
+/// entries resolve against `project_root`. `existing` is a `PYTHONPATH` that
+/// is already in effect and is kept as is.
+pub fn build(
+    pythonpath: &[String],
+    site_packages_extra: &[String],
+    existing: Option<&str>,
+    project_root: Option<&Path>,
+    os: Os,
+    lookup_env: impl Fn(&str) -> Option<String>,
+) -> Result<Option<String>, String> {
+    if pythonpath.is_empty() && site_packages_extra.is_empty() {
+        return Ok(None);
+    }
+
+    let home = match os {
+        Os::Windows => lookup_env("USERPROFILE"),
+        Os::Mac | Os::Linux => lookup_env("HOME"),
+    };
+    let normalize = |setting: &str, entry: &String| -> Result<Option<String>, String> {
+        let entry = entry.trim();
+        if entry.is_empty() {
+            return Ok(None);
+        }
+        if entry.contains(separator(os)) {
+            return Err(format!(
+                "{}: \"{}\" contains the path separator '{}'; list each directory separately",
+                setting,
+                entry,
+                separator(os)
+            ));
+        }
+
+        let expanded = match (entry.strip_prefix('~'), &home) {
+            (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with(['/', '\\']) => {
+                format!("{}{}", home, rest)
+            }
+            _ => entry.to_string(),
+        };
+        let path = match project_root {
+            Some(root) if Path::new(&expanded).is_relative() => root.join(&expanded),
+            _ => Path::new(&expanded).to_path_buf(),
+        };
+        let path = path.to_string_lossy().to_string();
+        Ok(Some(match os {
+            Os::Windows => path.replace('/', "\\"),
+            Os::Mac | Os::Linux => path,
+        }))
+    };
+
+    let mut entries = Vec::new();
+    for entry in pythonpath {
+        entries.extend(normalize("pythonpath", entry)?);
+    }
+    entries.extend(
+        existing
+            .filter(|existing| !existing.is_empty())
+            .map(str::to_string),
+    );
+    for entry in site_packages_extra {
+        entries.extend(normalize("site_packages_extra", entry)?);
+    }
+
+    Ok((!entries.is_empty()).then(|| entries.join(&separator(os).to_string())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn home(name: &str) -> Option<String> {
+        (name == "HOME").then(|| "/home/dev".to_string())
+    }
+
+    #[test]
+    fn test_build_order_and_normalization() {
+        let path = build(
+            &[
+                "plugins".to_string(),
+                "~/patches".to_string(),
+                " ".to_string(),
+            ],
+            &["/opt/extra".to_string()],
+            Some("/existing"),
+            Some(Path::new("/src/app")),
+            Os::Linux,
+            home,
+        )
+        .unwrap();
+        assert_eq!(
+            path.as_deref(),
+            Some("/src/app/plugins:/home/dev/patches:/existing:/opt/extra")
+        );
+
+        assert_eq!(
+            build(&[], &[], Some("/existing"), None, Os::Linux, home),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn test_build_windows() {
+        let path = build(
+            &["C:/serena/plugins".to_string()],
+            &["D:\\extra".to_string()],
+            None,
+            None,
+            Os::Windows,
+            |_| None,
+        )
+        .unwrap();
+        assert_eq!(path.as_deref(), Some("C:\\serena\\plugins;D:\\extra"));
+    }
+
+    #[test]
+    fn test_build_rejects_separator() {
+        let err = build(&["/a:/b".to_string()], &[], None, None, Os::Mac, |_| None).unwrap_err();
+        assert!(err.starts_with("pythonpath:"), "{}", err);
+    }
+}
@@ -0,0 +1,417 @@
+//! Readiness check for a launched server.
+//!
+//! A command Zed accepts can still produce a server that never answers. After
+//! a launch, the configuration panel can start Serena once more, complete the
+//! MCP initialize handshake over stdio and list the tools, so users see that
+//! the integration works rather than assume it.
+//!
+//! The check starts the bare server, without hooks, resource limits,
+//! precomputation or a shared instance, so it has no side effects. The
+//! extension can't talk to a process itself, so a small Python script run
+//! through Zed does the handshake and reports the outcome.
+
+use std::time::Duration;
+
+use python_discovery::process::{Invocation, Runner};
+use python_discovery::sandbox::Sandbox;
+use serde::{Deserialize, Serialize};
+use zed_extension_api::serde_json::{self, json};
+
+use crate::{process, secrets};
+
+/// MCP protocol revision offered in the handshake.
+const PROTOCOL_VERSION: &str = "2024-11-05";
+
+/// Starts the server in `sys.argv[3:]`, sends it the initialize request in
+/// `sys.argv[2]`, lists its tools and prints the outcome as one JSON line,
+/// killing the server with everything it started when done or once
+/// `sys.argv[1]` seconds have passed.
+const HANDSHAKE_SOURCE: &str = "\
+import json, os, queue, signal, subprocess, sys, threading, time
+posix = os.name == 'posix'
+started = time.monotonic()
+deadline = started + float(sys.argv[1])
+def report(**result):
+    sys.stdout.write(json.dumps(result) + '\\n')
+    sys.exit(0)
+try:
+    server = subprocess.Popen(sys.argv[3:], stdin=subprocess.PIPE, stdout=subprocess.PIPE,
+                              stderr=subprocess.DEVNULL, start_new_session=posix)
+except OSError as error:
+    report(error='start', message=str(error))
+def stop(**result):
+    try:
+        if posix:
+            os.killpg(server.pid, signal.SIGKILL)
+        else:
+            subprocess.call(['taskkill', '/F', '/T', '/PID', str(server.pid)],
+                            stdout=subprocess.DEVNULL, stderr=subprocess.DEVNULL)
+    except OSError:
+        pass
+    server.wait()
+    report(**result)
+lines = queue.Queue()
+def read():
+    for line in server.stdout:
+        lines.put(line)
+    lines.put(None)
+threading.Thread(target=read, daemon=True).start()
+def send(message):
+    try:
+        server.stdin.write(json.dumps(message).encode() + b'\\n')
+        server.stdin.flush()
+    except BrokenPipeError:
+        stop(error='exited')
+    except OSError as error:
+        stop(error='write', message=str(error))
+def response(id):
+    while True:
+        try:
+            line = lines.get(timeout=max(deadline - time.monotonic(), 0))
+        except queue.Empty:
+            stop(error='timeout')
+        if line is None:
+            stop(error='exited')
+        try:
+            message = json.loads(line)
+        except ValueError:
+            continue
+        if not isinstance(message, dict) or message.get('id') != id:
+            continue
+        error = message.get('error')
+        if error is not None:
+            reason = error.get('message') if isinstance(error, dict) else None
+            stop(error='rejected', message=str(reason or 'unknown error'))
+        return message.get('result') or {}
+send(json.loads(sys.argv[2]))
+response(1)
+send({'jsonrpc': '2.0', 'method': 'notifications/initialized'})
+send({'jsonrpc': '2.0', 'id': 2, 'method': 'tools/list'})
+tools = response(2).get('tools')
+stop(millis=int((time.monotonic() - started) * 1000),
+     tools=len(tools) if isinstance(tools, list) else 0)
+";
+
+/// A launch command as persisted for later checks.
+///
+/// Secret-looking environment variables are left out, and the values of
+/// secret `--env=` arguments of a Flatpak command are redacted, so keys never
+/// land in the state file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LaunchCommand {
+    /// Program Zed starts
+    pub command: String,
+    /// Its arguments
+    pub args: Vec<String>,
+    /// Environment, without secrets
+    pub env: Vec<(String, String)>,
+}
+
+impl LaunchCommand {
+    /// Copies `command`, dropping secrets from its environment and arguments.
+    pub fn from_command(command: &zed_extension_api::Command) -> Self {
+        Self {
+            command: command.command.clone(),
+            args: secrets::redact_args(&command.args),
+            env: command
+                .env
+                .iter()
+                .filter(|(name, _)| !secrets::is_secret_name(name))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Turns the persisted command back into one for Zed, with the secrets
+    /// `from_command` dropped taken from `secrets`.
+    pub fn to_command(&self, secrets: Vec<(String, String)>) -> zed_extension_api::Command {
+        let secrets: Vec<_> = secrets
+            .into_iter()
+            .filter(|(name, _)| secrets::is_secret_name(name))
+            .collect();
+        zed_extension_api::Command {
+            command: self.command.clone(),
+            args: secrets::restore_args(&self.args, &secrets),
+            env: self.env.iter().cloned().chain(secrets).collect(),
+        }
+    }
+}
+
+/// Outcome of the check after one launch.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Readiness {
+    /// Timestamp of the launch that was checked
+    pub launch: u64,
+    /// Milliseconds until the tool list arrived
+    pub millis: Option<u64>,
+    /// Tools the server offered
+    pub tools: Option<usize>,
+    /// Why the check failed
+    pub error: Option<String>,
+}
+
+impl Readiness {
+    /// Has `python` run `command` through `runner` and records how the
+    /// handshake went.
+    ///
+    /// `python` must start on the host, outside `sandbox`.
+    pub fn check(
+        launch: u64,
+        python: &str,
+        command: &LaunchCommand,
+        sandbox: Option<Sandbox>,
+        timeout: Duration,
+        runner: &dyn Runner,
+    ) -> Self {
+        match probe(python, command, sandbox, timeout, runner) {
+            Ok((millis, tools)) => Self {
+                launch,
+                millis: Some(millis),
+                tools: Some(tools),
+                error: None,
+            },
+            Err(error) => Self {
+                launch,
+                millis: None,
+                tools: None,
+                error: Some(error),
+            },
+        }
+    }
+}
+
+/// What the handshake script printed.
+#[derive(Debug, Default, Deserialize)]
+struct Report {
+    millis: Option<u64>,
+    tools: Option<usize>,
+    error: Option<String>,
+    message: Option<String>,
+}
+
+/// Runs the handshake against `command` and returns how many milliseconds it
+/// took and how many tools were listed.
+fn probe(
+    python: &str,
+    command: &LaunchCommand,
+    sandbox: Option<Sandbox>,
+    timeout: Duration,
+    runner: &dyn Runner,
+) -> Result<(u64, usize), String> {
+    let initialize = json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "initialize",
+        "params": {
+            "protocolVersion": PROTOCOL_VERSION,
+            "capabilities": {},
+            "clientInfo": { "name": "zed-serena-readiness", "version": env!("CARGO_PKG_VERSION") },
+        },
+    });
+    let argv: Vec<String> = [
+        python.to_string(),
+        "-c".to_string(),
+        HANDSHAKE_SOURCE.to_string(),
+        timeout.as_secs_f64().to_string(),
+        initialize.to_string(),
+        command.command.clone(),
+    ]
+    .into_iter()
+    .chain(command.args.iter().cloned())
+    .collect();
+    let argv = match sandbox {
+        Some(sandbox) => sandbox.wrap(argv, &command.env),
+        None => argv,
+    };
+    let mut argv = argv.into_iter();
+    let invocation = Invocation::new(argv.next().unwrap_or_default())
+        .args(argv)
+        .envs(command.env.iter().cloned());
+    let output = runner
+        .run(&invocation)
+        .map_err(|e| format!("Failed to start {}: {}", python, e))?;
+    let report = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .last()
+        .and_then(|line| serde_json::from_str::<Report>(line).ok())
+        .ok_or_else(|| format!("the check failed: {}", process::failure(&output)))?;
+    let message = report.message.unwrap_or_default();
+    match (report.error.as_deref(), report.millis, report.tools) {
+        (None, Some(millis), Some(tools)) => Ok((millis, tools)),
+        (Some("start"), _, _) => Err(format!("Failed to start {}: {}", command.command, message)),
+        (Some("write"), _, _) => Err(format!("Failed to write to the server: {}", message)),
+        (Some("timeout"), _, _) => Err(format!("no answer within {:?}", timeout)),
+        (Some("exited"), _, _) => Err("the server exited before answering".into()),
+        (Some("rejected"), _, _) => Err(format!("the server rejected the request: {}", message)),
+        _ => Err(format!("the check failed: {}", process::failure(&output))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use python_discovery::process::{Output, StdRunner};
+    use std::cell::RefCell;
+
+    fn command(script: &str) -> LaunchCommand {
+        LaunchCommand {
+            command: "sh".to_string(),
+            args: vec!["-c".to_string(), script.to_string()],
+            env: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_from_command_drops_secrets() {
+        let launch = LaunchCommand::from_command(&zed_extension_api::Command {
+            command: "serena".to_string(),
+            args: vec!["start-mcp-server".to_string()],
+            env: vec![
+                ("ANTHROPIC_API_KEY".to_string(), "sk-ant".to_string()),
+                ("DO_NOT_TRACK".to_string(), "1".to_string()),
+            ],
+        });
+        assert_eq!(launch.env, [("DO_NOT_TRACK".to_string(), "1".to_string())]);
+
+        // Reusing the command takes the secrets from the current settings
+        let command = launch.to_command(vec![
+            ("ANTHROPIC_API_KEY".to_string(), "sk-new".to_string()),
+            ("PATH".to_string(), "/tmp".to_string()),
+        ]);
+        assert_eq!(
+            command.env,
+            [
+                ("DO_NOT_TRACK".to_string(), "1".to_string()),
+                ("ANTHROPIC_API_KEY".to_string(), "sk-new".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_from_command_redacts_flatpak_secrets() {
+        let env = vec![
+            ("ANTHROPIC_API_KEY".to_string(), "sk-ant".to_string()),
+            ("DO_NOT_TRACK".to_string(), "1".to_string()),
+        ];
+        let argv = vec!["/usr/bin/serena".to_string()];
+        let wrapped = Sandbox::Flatpak.wrap(argv, &env);
+        let mut wrapped = wrapped.into_iter();
+        let launch = LaunchCommand::from_command(&zed_extension_api::Command {
+            command: wrapped.next().unwrap(),
+            args: wrapped.collect(),
+            env,
+        });
+        assert_eq!(
+            launch.args,
+            [
+                "--host",
+                "--env=ANTHROPIC_API_KEY=<redacted>",
+                "--env=DO_NOT_TRACK=1",
+                "/usr/bin/serena"
+            ]
+        );
+        assert!(!serde_json::to_string(&launch).unwrap().contains("sk-ant"));
+
+        let secret = ("ANTHROPIC_API_KEY".to_string(), "sk-new".to_string());
+        let command = launch.to_command(vec![secret]);
+        assert_eq!(command.args[1], "--env=ANTHROPIC_API_KEY=sk-new");
+        // Without the key, Serena starts without it
+        let command = launch.to_command(Vec::new());
+        assert_eq!(command.args, ["--host", "--env=DO_NOT_TRACK=1", "/usr/bin/serena"]);
+    }
+
+    /// Answers every run with `stdout` and records what was run.
+    struct Recorder {
+        stdout: &'static str,
+        runs: RefCell<Vec<Invocation>>,
+    }
+
+    impl Runner for Recorder {
+        fn run(&self, invocation: &Invocation) -> Result<Output, String> {
+            self.runs.borrow_mut().push(invocation.clone());
+            Ok(Output {
+                status: Some(0),
+                stdout: self.stdout.as_bytes().to_vec(),
+                stderr: Vec::new(),
+            })
+        }
+    }
+
+    #[test]
+    fn test_check_on_host() {
+        let runner = Recorder {
+            stdout: "{\"millis\": 850, \"tools\": 31}\n",
+            runs: RefCell::new(Vec::new()),
+        };
+        let server = LaunchCommand {
+            command: "/usr/bin/python3".to_string(),
+            args: vec!["-m".to_string(), "serena".to_string()],
+            env: vec![("DO_NOT_TRACK".to_string(), "1".to_string())],
+        };
+        let readiness = Readiness::check(
+            7,
+            "/usr/bin/python3",
+            &server,
+            Some(Sandbox::Flatpak),
+            Duration::from_secs(5),
+            &runner,
+        );
+        assert_eq!((readiness.millis, readiness.tools), (Some(850), Some(31)));
+        let runs = runner.runs.into_inner();
+        assert_eq!(runs[0].program, "flatpak-spawn");
+        let args = &runs[0].args;
+        assert!(args.contains(&"--env=DO_NOT_TRACK=1".to_string()));
+        assert_eq!(args[args.len() - 5], "5");
+        assert_eq!(args[args.len() - 3..], ["/usr/bin/python3", "-m", "serena"]);
+
+        let rejected = Recorder {
+            stdout: "{\"error\": \"rejected\", \"message\": \"unsupported protocol\"}\n",
+            runs: RefCell::new(Vec::new()),
+        };
+        let readiness = Readiness::check(7, "python3", &server, None, Duration::ZERO, &rejected);
+        assert_eq!(
+            readiness.error.as_deref(),
+            Some("the server rejected the request: unsupported protocol")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_check() {
+        let server = command(
+            r#"read request
+echo 'INFO starting'
+echo '{"jsonrpc":"2.0","id":1,"result":{"protocolVersion":"2024-11-05"}}'
+read notification
+read request
+echo '{"jsonrpc":"2.0","id":2,"result":{"tools":[{"name":"find_symbol"},{"name":"read_file"}]}}'
+sleep 10"#,
+        );
+        let check = |server: &LaunchCommand, timeout| {
+            Readiness::check(7, "python3", server, None, timeout, &StdRunner)
+        };
+        let started = std::time::Instant::now();
+        let readiness = check(&server, Duration::from_secs(5));
+        assert_eq!(readiness.error, None);
+        assert_eq!(readiness.tools, Some(2));
+        assert_eq!(readiness.launch, 7);
+        // The server is stopped once the tools are listed
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        let silent = check(&command("sleep 10"), Duration::from_millis(200));
+        assert_eq!(silent.error.as_deref(), Some("no answer within 200ms"));
+        let exits = check(&command("exit 1"), Duration::from_secs(5));
+        assert_eq!(
+            exits.error.as_deref(),
+            Some("the server exited before answering")
+        );
+        let missing = LaunchCommand {
+            command: "/nonexistent/serena".to_string(),
+            ..command("")
+        };
+        assert!(check(&missing, Duration::from_secs(5))
+            .error
+            .unwrap()
+            .starts_with("Failed to start /nonexistent/serena: "));
+    }
+}
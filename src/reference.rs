@@ -0,0 +1,209 @@
+//! The settings reference in the configuration panel.
+//!
+//! Rendered from the compiled-in settings schema rather than written by hand,
+//! so it lists exactly the settings the struct has, described by their doc
+//! comments, with the `default` values the schema records.
+
+use zed_extension_api::serde_json::Value;
+
+/// Renders the reference for the settings `schema`.
+pub(crate) fn render(schema: &Value) -> String {
+    let mut out = String::from("### Settings Reference\n\n");
+    for (name, property) in properties(schema) {
+        out.push_str(&entry(schema, name, property, ""));
+    }
+    out
+}
+
+/// The properties of an object `schema`.
+fn properties(schema: &Value) -> impl Iterator<Item = (&String, &Value)> {
+    schema
+        .get("properties")
+        .and_then(Value::as_object)
+        .into_iter()
+        .flatten()
+}
+
+/// One list item, with nested items for the fields of an object setting.
+fn entry(root: &Value, name: &str, property: &Value, indent: &str) -> String {
+    let target = resolve(root, property);
+    let description = property
+        .get("description")
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    let mut summary = describe_type(target);
+    if let Some(default) = property.get("default") {
+        summary.push_str(&format!(", default `{}`", default));
+    }
+    let mut out = format!("{}- `{}` ({})", indent, name, summary);
+    if !description.is_empty() {
+        out.push_str(&format!(": {}", description));
+    }
+    out.push('\n');
+    for (field, schema) in properties(target) {
+        out.push_str(&entry(root, field, schema, &format!("{}  ", indent)));
+    }
+    out
+}
+
+/// The schema a setting's value follows, past `$ref`s and the `null`
+/// alternative every optional setting has.
+fn resolve<'a>(root: &'a Value, schema: &'a Value) -> &'a Value {
+    let schema = schema
+        .get("anyOf")
+        .and_then(Value::as_array)
+        .and_then(|options| {
+            options
+                .iter()
+                .find(|option| option.get("type").and_then(Value::as_str) != Some("null"))
+        })
+        .unwrap_or(schema);
+    match schema.get("$ref").and_then(Value::as_str) {
+        Some(reference) => reference
+            .strip_prefix("#/")
+            .and_then(|path| root.pointer(&format!("/{}", path)))
+            .unwrap_or(schema),
+        None => schema,
+    }
+}
+
+/// The string constants an enum schema allows.
+fn variants(schema: &Value) -> Option<Vec<&str>> {
+    schema
+        .get("oneOf")
+        .or_else(|| schema.get("enum"))
+        .and_then(Value::as_array)
+        .map(|options| {
+            options
+                .iter()
+                .filter_map(|option| option.get("const").unwrap_or(option).as_str())
+                .collect()
+        })
+}
+
+/// A short name for the type of values `schema` accepts.
+fn describe_type(schema: &Value) -> String {
+    if let Some(variants) = variants(schema) {
+        return variants
+            .iter()
+            .map(|variant| format!("\"{}\"", variant))
+            .collect::<Vec<_>>()
+            .join(" | ");
+    }
+    let kind = match schema.get("type") {
+        Some(Value::Array(types)) => types
+            .iter()
+            .filter_map(Value::as_str)
+            .find(|kind| *kind != "null"),
+        Some(kind) => kind.as_str(),
+        None => None,
+    };
+    match kind {
+        Some("array") => match schema.pointer("/items/type").and_then(Value::as_str) {
+            Some(item) => format!("list of {}s", item),
+            None => "list".to_string(),
+        },
+        Some("object") => match schema.pointer("/additionalProperties/type") {
+            Some(Value::String(value)) => format!("map of {}s", value),
+            _ => "object".to_string(),
+        },
+        Some("integer") => {
+            let bound = |key| schema.get(key).and_then(Value::as_i64);
+            match (bound("minimum"), bound("maximum")) {
+                (Some(minimum), Some(maximum)) => format!("integer, {}-{}", minimum, maximum),
+                (Some(minimum), None) if minimum > 0 => format!("integer, at least {}", minimum),
+                _ => "integer".to_string(),
+            }
+        }
+        Some(kind) => kind.to_string(),
+        None => "any".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zed_extension_api::serde_json::{self, json};
+
+    #[test]
+    fn test_render() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "analytics": {
+                    "description": "Allow usage analytics",
+                    "default": false,
+                    "type": ["boolean", "null"]
+                },
+                "niceness": {
+                    "description": "Scheduling priority adjustment, 0-19 (Unix only)",
+                    "type": ["integer", "null"],
+                    "minimum": 0,
+                    "maximum": 19
+                },
+                "shutdown_grace_seconds": {
+                    "description": "Seconds to stop (Unix only)",
+                    "default": 10,
+                    "type": ["integer", "null"],
+                    "minimum": 0
+                },
+                "transport": {
+                    "description": "Transport: \"stdio\", or \"sse\"",
+                    "default": "stdio",
+                    "anyOf": [{ "$ref": "#/$defs/Transport" }, { "type": "null" }]
+                },
+                "api_keys": {
+                    "description": "API keys",
+                    "anyOf": [{ "$ref": "#/$defs/ApiKeys" }, { "type": "null" }]
+                },
+                "environment": {
+                    "type": ["object", "null"],
+                    "additionalProperties": { "type": "string" }
+                }
+            },
+            "$defs": {
+                "Transport": {
+                    "oneOf": [
+                        { "type": "string", "const": "stdio" },
+                        { "type": "string", "const": "sse" }
+                    ]
+                },
+                "ApiKeys": {
+                    "type": "object",
+                    "properties": {
+                        "openai": { "description": "OpenAI API key", "type": ["string", "null"] }
+                    }
+                }
+            }
+        });
+        assert_eq!(
+            render(&schema),
+            "### Settings Reference\n\n\
+             - `analytics` (boolean, default `false`): Allow usage analytics\n\
+             - `api_keys` (object): API keys\n\
+             \x20 - `openai` (string): OpenAI API key\n\
+             - `environment` (map of strings)\n\
+             - `niceness` (integer, 0-19): Scheduling priority adjustment, 0-19 (Unix only)\n\
+             - `shutdown_grace_seconds` (integer, default `10`): Seconds to stop (Unix only)\n\
+             - `transport` (\"stdio\" | \"sse\", default `\"stdio\"`): Transport: \"stdio\", or \"sse\"\n"
+        );
+    }
+
+    #[test]
+    fn test_render_lists_every_setting() {
+        let schema: Value = serde_json::from_str(crate::SETTINGS_SCHEMA).unwrap();
+        let reference = render(&schema);
+        for (name, _) in properties(&schema) {
+            assert!(
+                reference.contains(&format!("\n- `{}` (", name)),
+                "{} is missing from the reference",
+                name
+            );
+        }
+        assert!(reference
+            .contains("\n- `install_timeout_seconds` (integer, at least 1, default `600`): "));
+        assert!(reference.contains(
+            "\n- `python_preference` (\"venv-first\" | \"system-first\" | \"managed-only\", default `\"venv-first\"`): "
+        ));
+    }
+}
@@ -0,0 +1,61 @@
+//! Reads checked-in Serena configuration files from the project root.
+//!
+//! Repositories can commit `.zed/serena.json` (or `.zed-serena.json`) using the
+//! same schema as the `settings` block in Zed's settings.json. Like settings.json,
+//! these files may contain comments and trailing commas.
+
+use std::path::Path;
+use zed_extension_api::serde_json::Value;
+
+use crate::jsonc;
+
+/// Candidate file names relative to the project root, in lookup order.
+const CONFIG_FILES: [&str; 2] = [".zed/serena.json", ".zed-serena.json"];
+
+/// Reads the first repository configuration file found under `root`.
+///
+/// Returns `Ok(None)` when no configuration file exists.
+pub(crate) fn read_repo_settings(root: &Path) -> Result<Option<Value>, String> {
+    for name in CONFIG_FILES {
+        let path = root.join(name);
+        let Ok(contents) = std::fs::read_to_string(&path) else {
+            continue;
+        };
+        return parse_repo_settings(&contents)
+            .map(Some)
+            .map_err(|e| format!("{}: {}", path.display(), e));
+    }
+    Ok(None)
+}
+
+/// Parses repository configuration contents, which must be a JSON object.
+pub(crate) fn parse_repo_settings(contents: &str) -> Result<Value, String> {
+    let value: Value = jsonc::from_str(contents)?;
+    if !value.is_object() {
+        return Err("expected a JSON object".into());
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_repo_settings() {
+        let settings = parse_repo_settings(r#"{ "context": "ide-assistant" }"#).unwrap();
+        assert_eq!(settings["context"], "ide-assistant");
+
+        let commented = r#"
+        {
+            // Shared team defaults
+            "modes": ["planning", "editing",],
+        }
+        "#;
+        let settings = parse_repo_settings(commented).unwrap();
+        assert_eq!(settings["modes"][1], "editing");
+
+        assert!(parse_repo_settings(r#"["not", "an", "object"]"#).is_err());
+        assert!(parse_repo_settings("{ invalid").is_err());
+    }
+}
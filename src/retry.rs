@@ -0,0 +1,233 @@
+//! Bounded retries for installs and downloads.
+//!
+//! Flaky networks make one-shot pip runs unreliable. Failures that look like
+//! network trouble are retried with exponential backoff; anything else, such
+//! as an unknown version or a failed build, is reported right away.
+
+use std::time::Duration;
+
+use python_discovery::process::Output;
+
+use crate::process;
+
+/// How often and how patiently an operation is retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Backoff {
+    /// Attempts in total, including the first
+    pub attempts: u32,
+    /// Delay before the first retry; doubled for each later one
+    pub initial: Duration,
+}
+
+/// For pip runs, which already take a while.
+pub(crate) const INSTALL: Backoff = Backoff {
+    attempts: 4,
+    initial: Duration::from_secs(2),
+};
+
+/// For lookups the configuration panel waits on.
+pub(crate) const DOWNLOAD: Backoff = Backoff {
+    attempts: 2,
+    initial: Duration::from_millis(500),
+};
+
+/// pip, urllib3 and uv messages that point at the network rather than the
+/// package.
+const TRANSIENT_MARKERS: [&str; 16] = [
+    "connection aborted",
+    "connection refused",
+    "connection reset",
+    "connecttimeouterror",
+    "dns error",
+    "error sending request",
+    "incompleteread",
+    "max retries exceeded",
+    "name or service not known",
+    "newconnectionerror",
+    "proxyerror",
+    "readtimeouterror",
+    "remotedisconnected",
+    "temporary failure in name resolution",
+    "timed out",
+    "503 service unavailable",
+];
+
+/// Why an attempt failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Failure {
+    /// Worth another attempt, e.g. a dropped connection
+    Transient(String),
+    /// Fails the same way every time, e.g. no matching version
+    Permanent(String),
+}
+
+impl Failure {
+    /// Classifies a failed pip or uv run, reporting how it ended and the end
+    /// of its error output.
+    pub(crate) fn from_pip_run(output: &Output) -> Self {
+        let details = process::failure(output);
+        match Self::from_pip_output(&String::from_utf8_lossy(&output.stderr)) {
+            Self::Transient(_) => Self::Transient(details),
+            Self::Permanent(_) => Self::Permanent(details),
+        }
+    }
+
+    /// Classifies a failed pip or uv run by its output.
+    pub(crate) fn from_pip_output(stderr: &str) -> Self {
+        let lowercase = stderr.to_lowercase();
+        if TRANSIENT_MARKERS
+            .iter()
+            .any(|marker| lowercase.contains(marker))
+        {
+            Self::Transient(stderr.to_string())
+        } else {
+            Self::Permanent(stderr.to_string())
+        }
+    }
+}
+
+/// Runs `attempt` until it succeeds, fails permanently or runs out of
+/// attempts, sleeping with `sleep` between attempts.
+///
+/// Errors start with `what`, e.g. "Failed to install Serena", and say whether
+/// the failure was retried. Each retried failure is added to `retries`, for
+/// the caller to show.
+pub(crate) fn run<T>(
+    what: &str,
+    backoff: Backoff,
+    retries: &mut Vec<String>,
+    mut sleep: impl FnMut(Duration),
+    mut attempt: impl FnMut() -> Result<T, Failure>,
+) -> Result<T, String> {
+    let mut delay = backoff.initial;
+    for number in 1..=backoff.attempts.max(1) {
+        match attempt() {
+            Ok(value) => return Ok(value),
+            Err(Failure::Permanent(message)) => {
+                return Err(format!("{}: {}", what, message));
+            }
+            Err(Failure::Transient(message)) if number >= backoff.attempts => {
+                return Err(format!(
+                    "{} after {} attempts because of network errors: {}",
+                    what, number, message
+                ));
+            }
+            Err(Failure::Transient(message)) => {
+                retries.push(format!(
+                    "{} (attempt {}/{}), retried after {:?}: {}",
+                    what,
+                    number,
+                    backoff.attempts,
+                    delay,
+                    message.trim()
+                ));
+                sleep(delay);
+                delay *= 2;
+            }
+        }
+    }
+    unreachable!("the last attempt always returns")
+}
+
+/// Sleeps the current thread.
+pub(crate) fn sleep(delay: Duration) {
+    std::thread::sleep(delay);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BACKOFF: Backoff = Backoff {
+        attempts: 3,
+        initial: Duration::from_secs(1),
+    };
+
+    #[test]
+    fn test_classifies_pip_output() {
+        let timeout = "WARNING: Retrying (Retry(total=4)) after connection broken by \
+                       'ReadTimeoutError(\"HTTPSConnectionPool(host='pypi.org', port=443): Read timed out.\")'";
+        assert!(matches!(
+            Failure::from_pip_output(timeout),
+            Failure::Transient(_)
+        ));
+        let uv = "error: Request failed after 3 retries\n  Caused by: error sending request for url (https://pypi.org/simple/serena-agent/)";
+        assert!(matches!(
+            Failure::from_pip_output(uv),
+            Failure::Transient(_)
+        ));
+        let missing = "ERROR: No matching distribution found for serena-agent==9.9";
+        assert!(matches!(
+            Failure::from_pip_output(missing),
+            Failure::Permanent(_)
+        ));
+    }
+
+    #[test]
+    fn test_retries_transient_failures_with_backoff() {
+        let mut delays = Vec::new();
+        let mut retries = Vec::new();
+        let mut calls = 0;
+        let result = run(
+            "Failed to install Serena",
+            BACKOFF,
+            &mut retries,
+            |delay| delays.push(delay),
+            || {
+                calls += 1;
+                if calls < 3 {
+                    Err(Failure::Transient("connection reset".to_string()))
+                } else {
+                    Ok(calls)
+                }
+            },
+        );
+        assert_eq!(result, Ok(3));
+        assert_eq!(delays, [Duration::from_secs(1), Duration::from_secs(2)]);
+        assert_eq!(
+            retries,
+            [
+                "Failed to install Serena (attempt 1/3), retried after 1s: connection reset",
+                "Failed to install Serena (attempt 2/3), retried after 2s: connection reset"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reports_why_it_gave_up() {
+        let mut calls = 0;
+        let result: Result<(), String> = run(
+            "Failed to install Serena",
+            BACKOFF,
+            &mut Vec::new(),
+            |_| {},
+            || {
+                calls += 1;
+                Err(Failure::Transient("timed out".to_string()))
+            },
+        );
+        assert_eq!(
+            result.unwrap_err(),
+            "Failed to install Serena after 3 attempts because of network errors: timed out"
+        );
+        assert_eq!(calls, 3);
+
+        // Genuine failures are not retried
+        let mut calls = 0;
+        let result: Result<(), String> = run(
+            "Failed to install Serena",
+            BACKOFF,
+            &mut Vec::new(),
+            |_| {},
+            || {
+                calls += 1;
+                Err(Failure::Permanent("No matching distribution".to_string()))
+            },
+        );
+        assert_eq!(
+            result.unwrap_err(),
+            "Failed to install Serena: No matching distribution"
+        );
+        assert_eq!(calls, 1);
+    }
+}
@@ -0,0 +1,344 @@
+//! Locating the `serena` console script for an interpreter.
+//!
+//! Running the console script directly avoids an extra `-m` import hop and
+//! matches what users run in a terminal. When no script is found the
+//! extension falls back to `python -m serena`. A `serena_command` setting
+//! replaces the script, for shims and renamed installs. Filesystem access goes
+//! through [`FileSystem`] so the layouts below can be tested without
+//! touching disk.
+
+use std::path::{Path, PathBuf};
+use zed_extension_api::Os;
+
+use crate::paths;
+
+/// Python minor versions whose macOS and Windows user-site script
+/// directories are checked.
+const USER_SITE_MINORS: [u64; 2] = [11, 12];
+
+/// The filesystem queries script and package discovery need.
+pub trait FileSystem {
+    /// Returns true when `path` exists and is a file.
+    fn is_file(&self, path: &Path) -> bool;
+
+    /// Names of the entries in the directory `path`; empty when it can't be read.
+    fn list_dir(&self, path: &Path) -> Vec<String>;
+}
+
+/// The real filesystem, seen from the extension on platform `0`.
+///
+/// Host paths are translated to the paths the WASI guest reads.
+pub struct RealFs(pub Os);
+
+impl FileSystem for RealFs {
+    fn is_file(&self, path: &Path) -> bool {
+        paths::to_guest(path, self.0).is_file()
+    }
+
+    fn list_dir(&self, path: &Path) -> Vec<String> {
+        std::fs::read_dir(paths::to_guest(path, self.0))
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok())
+                    .map(|entry| entry.file_name().to_string_lossy().to_string())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// Name of the console script serena-agent installs.
+pub const DEFAULT_SCRIPT: &str = "serena";
+
+/// Finds the `serena` console script belonging to `python`.
+///
+/// See [`find_script`] for the locations checked.
+pub fn find_serena_script(
+    python: &Path,
+    os: Os,
+    home: Option<&Path>,
+    fs: &impl FileSystem,
+) -> Option<PathBuf> {
+    find_script(DEFAULT_SCRIPT, python, os, home, fs)
+}
+
+/// Resolves a `serena_command` setting to the command to run.
+///
+/// A single bare name, e.g. a renamed script, is looked up like the default
+/// script and left for `PATH` lookup when it isn't found. Paths and longer
+/// commands such as `["uv", "run", "serena"]` are used as given.
+pub fn resolve_command(
+    command: &[String],
+    python: &Path,
+    os: Os,
+    home: Option<&Path>,
+    fs: &impl FileSystem,
+) -> Vec<String> {
+    match command {
+        [name] if !name.contains(['/', '\\']) => {
+            let script = find_script(name, python, os, home, fs)
+                .map(|script| script.to_string_lossy().to_string());
+            vec![script.unwrap_or_else(|| name.clone())]
+        }
+        _ => command.to_vec(),
+    }
+}
+
+/// Finds the console script `name` belonging to `python`.
+///
+/// On Windows `.exe` is appended unless `name` has an extension. Checked in
+/// order:
+/// 1. next to the interpreter (virtual environments, Homebrew, conda)
+/// 2. `Scripts\` below a Windows base install
+/// 3. the user-site scripts directory (`pip install --user`)
+/// 4. a pipx-managed `serena-agent` environment
+pub fn find_script(
+    name: &str,
+    python: &Path,
+    os: Os,
+    home: Option<&Path>,
+    fs: &impl FileSystem,
+) -> Option<PathBuf> {
+    let script = match os {
+        Os::Windows if Path::new(name).extension().is_none() => format!("{}.exe", name),
+        _ => name.to_string(),
+    };
+    let script = script.as_str();
+    let mut candidates = Vec::new();
+
+    if let Some(python_dir) = python.parent() {
+        candidates.push(python_dir.join(script));
+        if os == Os::Windows && !python_dir.ends_with("Scripts") {
+            candidates.push(python_dir.join("Scripts").join(script));
+        }
+    }
+
+    if let Some(home) = home {
+        match os {
+            Os::Linux => candidates.push(home.join(".local").join("bin").join(script)),
+            Os::Mac => {
+                for minor in USER_SITE_MINORS {
+                    candidates.push(
+                        home.join("Library")
+                            .join("Python")
+                            .join(format!("3.{}", minor))
+                            .join("bin")
+                            .join(script),
+                    );
+                }
+            }
+            Os::Windows => {
+                for minor in USER_SITE_MINORS {
+                    candidates.push(
+                        home.join("AppData")
+                            .join("Roaming")
+                            .join("Python")
+                            .join(format!("Python3{}", minor))
+                            .join("Scripts")
+                            .join(script),
+                    );
+                }
+            }
+        }
+
+        let pipx_venv = match os {
+            Os::Windows => home.join("pipx").join("venvs").join("serena-agent"),
+            Os::Mac | Os::Linux => home
+                .join(".local")
+                .join("pipx")
+                .join("venvs")
+                .join("serena-agent"),
+        };
+        candidates.push(match os {
+            Os::Windows => pipx_venv.join("Scripts").join(script),
+            Os::Mac | Os::Linux => pipx_venv.join("bin").join(script),
+        });
+    }
+
+    candidates.into_iter().find(|candidate| fs.is_file(candidate))
+}
+
+/// Names of the direct children of `dir` implied by a set of file paths,
+/// for filesystems simulated in tests.
+#[cfg(any(test, feature = "lib"))]
+pub fn list_children<'a>(
+    files: impl IntoIterator<Item = &'a PathBuf>,
+    dir: &Path,
+) -> Vec<String> {
+    let mut names: Vec<String> = files
+        .into_iter()
+        .filter_map(|file| file.strip_prefix(dir).ok()?.components().next())
+        .map(|name| name.as_os_str().to_string_lossy().to_string())
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    /// A filesystem holding only the listed files.
+    struct VirtualFs(HashSet<PathBuf>);
+
+    impl VirtualFs {
+        fn with(files: impl IntoIterator<Item = PathBuf>) -> Self {
+            Self(files.into_iter().collect())
+        }
+    }
+
+    impl FileSystem for VirtualFs {
+        fn is_file(&self, path: &Path) -> bool {
+            self.0.contains(path)
+        }
+
+        fn list_dir(&self, path: &Path) -> Vec<String> {
+            list_children(&self.0, path)
+        }
+    }
+
+    fn path(parts: &[&str]) -> PathBuf {
+        parts.iter().collect()
+    }
+
+    #[test]
+    fn test_venv_script_next_to_python() {
+        let python = path(&["/src", "app", ".venv", "bin", "python"]);
+        let script = path(&["/src", "app", ".venv", "bin", "serena"]);
+        let pipx = path(&[
+            "/home/dev",
+            ".local",
+            "pipx",
+            "venvs",
+            "serena-agent",
+            "bin",
+            "serena",
+        ]);
+        let fs = VirtualFs::with([script.clone(), pipx]);
+
+        assert_eq!(
+            find_serena_script(&python, Os::Linux, Some(Path::new("/home/dev")), &fs),
+            Some(script)
+        );
+    }
+
+    #[test]
+    fn test_user_site_and_pipx() {
+        let python = path(&["/usr", "bin", "python3.12"]);
+        let home = Path::new("/home/dev");
+
+        let user_site = path(&["/home/dev", ".local", "bin", "serena"]);
+        let fs = VirtualFs::with([user_site.clone()]);
+        assert_eq!(
+            find_serena_script(&python, Os::Linux, Some(home), &fs),
+            Some(user_site)
+        );
+
+        let mac_user_site = path(&["/home/dev", "Library", "Python", "3.12", "bin", "serena"]);
+        let fs = VirtualFs::with([mac_user_site.clone()]);
+        assert_eq!(
+            find_serena_script(&python, Os::Mac, Some(home), &fs),
+            Some(mac_user_site)
+        );
+
+        let pipx = path(&[
+            "/home/dev",
+            ".local",
+            "pipx",
+            "venvs",
+            "serena-agent",
+            "bin",
+            "serena",
+        ]);
+        let fs = VirtualFs::with([pipx.clone()]);
+        assert_eq!(
+            find_serena_script(&python, Os::Linux, Some(home), &fs),
+            Some(pipx)
+        );
+    }
+
+    #[test]
+    fn test_windows_scripts_dir() {
+        let python = path(&["C:", "Python312", "python.exe"]);
+        let script = path(&["C:", "Python312", "Scripts", "serena.exe"]);
+        let fs = VirtualFs::with([script.clone()]);
+        assert_eq!(
+            find_serena_script(&python, Os::Windows, None, &fs),
+            Some(script)
+        );
+
+        let home = path(&["C:", "Users", "dev"]);
+        let user_site = path(&[
+            "C:",
+            "Users",
+            "dev",
+            "AppData",
+            "Roaming",
+            "Python",
+            "Python312",
+            "Scripts",
+            "serena.exe",
+        ]);
+        let fs = VirtualFs::with([user_site.clone()]);
+        assert_eq!(
+            find_serena_script(&python, Os::Windows, Some(&home), &fs),
+            Some(user_site)
+        );
+
+        // A venv's python.exe already lives in Scripts
+        let venv_python = path(&["C:", "app", ".venv", "Scripts", "python.exe"]);
+        let venv_script = path(&["C:", "app", ".venv", "Scripts", "serena.exe"]);
+        let fs = VirtualFs::with([venv_script.clone()]);
+        assert_eq!(
+            find_serena_script(&venv_python, Os::Windows, None, &fs),
+            Some(venv_script)
+        );
+    }
+
+    #[test]
+    fn test_falls_back_to_module() {
+        let python = path(&["/usr", "bin", "python3.11"]);
+        let fs = VirtualFs::with([]);
+        assert_eq!(
+            find_serena_script(&python, Os::Linux, Some(Path::new("/home/dev")), &fs),
+            None
+        );
+    }
+
+    #[test]
+    fn test_custom_command() {
+        let python = path(&["/src", "app", ".venv", "bin", "python"]);
+        let shim = path(&["/src", "app", ".venv", "bin", "serena-corp"]);
+        let fs = VirtualFs::with([shim.clone()]);
+        let command = |parts: &[&str]| parts.iter().map(|part| part.to_string()).collect::<Vec<_>>();
+
+        // A renamed script is found where the default one would be
+        assert_eq!(
+            resolve_command(&command(&["serena-corp"]), &python, Os::Linux, None, &fs),
+            [shim.to_string_lossy()]
+        );
+        // Names that aren't found are left to PATH
+        assert_eq!(
+            resolve_command(&command(&["serena-shim"]), &python, Os::Linux, None, &fs),
+            ["serena-shim"]
+        );
+        assert_eq!(
+            resolve_command(&command(&["uv", "run", "serena"]), &python, Os::Linux, None, &fs),
+            ["uv", "run", "serena"]
+        );
+        assert_eq!(
+            resolve_command(&command(&["/opt/bin/serena"]), &python, Os::Linux, None, &fs),
+            ["/opt/bin/serena"]
+        );
+
+        // Windows scripts get .exe unless they name their extension
+        let python = path(&["C:", "Python312", "python.exe"]);
+        let exe = path(&["C:", "Python312", "Scripts", "serena-corp.exe"]);
+        let cmd = path(&["C:", "Python312", "Scripts", "serena.cmd"]);
+        let fs = VirtualFs::with([exe.clone(), cmd.clone()]);
+        assert_eq!(find_script("serena-corp", &python, Os::Windows, None, &fs), Some(exe));
+        assert_eq!(find_script("serena.cmd", &python, Os::Windows, None, &fs), Some(cmd));
+    }
+}
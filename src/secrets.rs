@@ -0,0 +1,241 @@
+//! API key passthrough and secret redaction.
+//!
+//! Keys in the `api_keys` setting can be literal values, but users are
+//! encouraged to use references so raw keys never land in settings.json:
+//!
+//! - `env:NAME` reads the key from the environment variable `NAME`
+//! - `keychain:SERVICE` reads a generic password from the OS keychain
+//!   (`security` on macOS, `secret-tool` on Linux)
+//!
+//! Keychain tools run through Zed's process API, since the extension's WASM
+//! sandbox can't start processes itself; `extension.toml` grants the two
+//! lookups as `process:exec` capabilities.
+
+use serde::Deserialize;
+use zed_extension_api::process::Command as ZedCommand;
+use zed_extension_api::serde_json::Value;
+use zed_extension_api::Os;
+
+/// Placeholder shown in diagnostics instead of secret values.
+pub(crate) const REDACTED: &str = "<redacted>";
+
+/// API keys for the model providers used by Serena's agent features.
+#[derive(Debug, Default, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub(crate) struct ApiKeySettings {
+    /// Anthropic API key, exported as ANTHROPIC_API_KEY
+    pub anthropic: Option<String>,
+    /// OpenAI API key, exported as OPENAI_API_KEY
+    pub openai: Option<String>,
+    /// Google API key, exported as GOOGLE_API_KEY
+    pub google: Option<String>,
+}
+
+impl ApiKeySettings {
+    fn entries(&self) -> [(&'static str, &'static str, Option<&str>); 3] {
+        [
+            ("anthropic", "ANTHROPIC_API_KEY", self.anthropic.as_deref()),
+            ("openai", "OPENAI_API_KEY", self.openai.as_deref()),
+            ("google", "GOOGLE_API_KEY", self.google.as_deref()),
+        ]
+    }
+}
+
+/// Resolves configured API keys into the environment variables Serena expects.
+pub(crate) fn api_key_env(
+    keys: &ApiKeySettings,
+    os: Os,
+    lookup_env: impl Fn(&str) -> Option<String>,
+) -> Result<Vec<(String, String)>, String> {
+    let mut env = Vec::new();
+    for (name, var, value) in keys.entries() {
+        let Some(value) = value else {
+            continue;
+        };
+        let resolved = resolve_secret(value, os, &lookup_env)
+            .map_err(|e| format!("api_keys.{}: {}", name, e))?;
+        env.push((var.to_string(), resolved));
+    }
+    Ok(env)
+}
+
+/// Names of the environment variables the configured keys are exported as,
+/// without resolving them.
+pub(crate) fn api_key_names(keys: &ApiKeySettings) -> Vec<&'static str> {
+    keys.entries()
+        .into_iter()
+        .filter(|(_, _, value)| value.is_some())
+        .map(|(_, var, _)| var)
+        .collect()
+}
+
+/// Resolves a literal value or an `env:` / `keychain:` reference.
+pub(crate) fn resolve_secret(
+    value: &str,
+    os: Os,
+    lookup_env: impl Fn(&str) -> Option<String>,
+) -> Result<String, String> {
+    if let Some(name) = value.strip_prefix("env:") {
+        return lookup_env(name)
+            .filter(|v| !v.is_empty())
+            .ok_or_else(|| format!("environment variable {} is not set", name));
+    }
+
+    if let Some(service) = value.strip_prefix("keychain:") {
+        return read_keychain(service, os);
+    }
+
+    Ok(value.to_string())
+}
+
+fn read_keychain(service: &str, os: Os) -> Result<String, String> {
+    let mut command = match os {
+        Os::Mac => ZedCommand::new("security").args(["find-generic-password", "-w", "-s", service]),
+        Os::Linux => ZedCommand::new("secret-tool").args(["lookup", "service", service]),
+        Os::Windows => {
+            return Err("keychain references are not supported on Windows; use env: instead".into())
+        }
+    };
+
+    let output = command
+        .output()
+        .map_err(|e| format!("could not query keychain for {}: {}", service, e))?;
+    let secret = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if output.status != Some(0) || secret.is_empty() {
+        return Err(format!("no keychain entry found for service {}", service));
+    }
+    Ok(secret)
+}
+
+/// Returns true when an environment variable name looks like it holds a secret.
+pub(crate) fn is_secret_name(name: &str) -> bool {
+    let upper = name.to_uppercase();
+    python_discovery::stderr::SECRET_MARKERS
+        .iter()
+        .any(|marker| upper.contains(marker))
+}
+
+/// Splits a `--env=NAME=VALUE` argument, the form `flatpak-spawn` takes a
+/// command's environment in, into the name and value.
+fn env_arg(arg: &str) -> Option<(&str, &str)> {
+    arg.strip_prefix("--env=")?.split_once('=')
+}
+
+/// Redacts the values of secret-looking `--env=` arguments, so a command
+/// run through `flatpak-spawn` can be stored or shown.
+pub(crate) fn redact_args(args: &[String]) -> Vec<String> {
+    args.iter()
+        .map(|arg| match env_arg(arg) {
+            Some((name, _)) if is_secret_name(name) => format!("--env={}={}", name, REDACTED),
+            _ => arg.clone(),
+        })
+        .collect()
+}
+
+/// Puts the values in `env` back into the `--env=` arguments
+/// [`redact_args`] redacted; arguments `env` has no value for are dropped.
+pub(crate) fn restore_args(args: &[String], env: &[(String, String)]) -> Vec<String> {
+    args.iter()
+        .filter_map(|arg| match env_arg(arg) {
+            Some((name, REDACTED)) if is_secret_name(name) => env
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(key, value)| format!("--env={}={}", key, value)),
+            _ => Some(arg.clone()),
+        })
+        .collect()
+}
+
+/// Redacts secrets in a settings value for display.
+///
+/// Literal `api_keys` and secret-looking `environment` entries are replaced,
+/// while `env:`/`keychain:` references are kept since they reveal nothing.
+pub(crate) fn redact_settings(settings: &mut Value) {
+    if let Some(keys) = settings.get_mut("api_keys").and_then(Value::as_object_mut) {
+        for value in keys.values_mut() {
+            redact_literal(value);
+        }
+    }
+    if let Some(env) = settings
+        .get_mut("environment")
+        .and_then(Value::as_object_mut)
+    {
+        for (name, value) in env.iter_mut() {
+            if is_secret_name(name) {
+                redact_literal(value);
+            }
+        }
+    }
+}
+
+fn redact_literal(value: &mut Value) {
+    if let Some(text) = value.as_str() {
+        if !text.starts_with("env:") && !text.starts_with("keychain:") {
+            *value = Value::String(REDACTED.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zed_extension_api::serde_json::json;
+
+    fn lookup(name: &str) -> Option<String> {
+        (name == "MY_ANTHROPIC_KEY").then(|| "sk-ant-test".to_string())
+    }
+
+    #[test]
+    fn test_api_key_env() {
+        let keys = ApiKeySettings {
+            anthropic: Some("env:MY_ANTHROPIC_KEY".to_string()),
+            openai: Some("sk-literal".to_string()),
+            google: None,
+        };
+        assert_eq!(
+            api_key_env(&keys, Os::Linux, lookup).unwrap(),
+            vec![
+                ("ANTHROPIC_API_KEY".to_string(), "sk-ant-test".to_string()),
+                ("OPENAI_API_KEY".to_string(), "sk-literal".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_api_key_names() {
+        let keys = ApiKeySettings {
+            google: Some("keychain:gemini".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(api_key_names(&keys), ["GOOGLE_API_KEY"]);
+    }
+
+    #[test]
+    fn test_api_key_env_missing_reference() {
+        let keys = ApiKeySettings {
+            openai: Some("env:UNSET_VAR".to_string()),
+            ..Default::default()
+        };
+        let err = api_key_env(&keys, Os::Linux, lookup).unwrap_err();
+        assert_eq!(
+            err,
+            "api_keys.openai: environment variable UNSET_VAR is not set"
+        );
+    }
+
+    #[test]
+    fn test_redact_settings() {
+        let mut settings = json!({
+            "api_keys": { "anthropic": "sk-ant-secret", "openai": "env:OPENAI_KEY" },
+            "environment": { "GITHUB_TOKEN": "ghp_secret", "SERENA_LOG_LEVEL": "debug" }
+        });
+        redact_settings(&mut settings);
+        assert_eq!(
+            settings,
+            json!({
+                "api_keys": { "anthropic": REDACTED, "openai": "env:OPENAI_KEY" },
+                "environment": { "GITHUB_TOKEN": REDACTED, "SERENA_LOG_LEVEL": "debug" }
+            })
+        );
+    }
+}
@@ -0,0 +1,106 @@
+//! POSIX shell helpers for generated wrapper commands.
+
+/// Quotes a string for safe use as a single word in a POSIX shell.
+pub fn quote(word: &str) -> String {
+    if !word.is_empty()
+        && word
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:=@%+,".contains(c))
+    {
+        return word.to_string();
+    }
+    format!("'{}'", word.replace('\'', r"'\''"))
+}
+
+/// Quotes a command and its arguments into a single shell command line.
+pub fn join<S: AsRef<str>>(words: &[S]) -> String {
+    words
+        .iter()
+        .map(|word| quote(word.as_ref()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Runs `argv` through `shell` as a login shell, e.g. `zsh -lc 'exec serena …'`.
+///
+/// `exec` replaces the shell so signals from Zed reach the command. The
+/// quoting also holds in fish, whose single quotes accept `\'`.
+pub fn login_shell_command<S: AsRef<str>>(shell: &str, argv: &[S]) -> Vec<String> {
+    vec![
+        shell.to_string(),
+        "-lc".to_string(),
+        format!("exec {}", join(argv)),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("serena"), "serena");
+        assert_eq!(quote("/usr/bin/python3.11"), "/usr/bin/python3.11");
+        assert_eq!(quote(""), "''");
+        assert_eq!(quote("John Smith"), "'John Smith'");
+        assert_eq!(quote("it's"), r"'it'\''s'");
+        assert_eq!(quote("$(rm -rf /)"), "'$(rm -rf /)'");
+    }
+
+    #[test]
+    fn test_join() {
+        assert_eq!(
+            join(&["serena", "start-mcp-server", "--mode", "a b"]),
+            "serena start-mcp-server --mode 'a b'"
+        );
+    }
+
+    #[test]
+    fn test_login_shell_command() {
+        assert_eq!(
+            login_shell_command("/bin/zsh", &["/home/dev/.pyenv/shims/serena", "--mode", "it's"]),
+            [
+                "/bin/zsh",
+                "-lc",
+                r"exec /home/dev/.pyenv/shims/serena --mode 'it'\''s'"
+            ]
+        );
+    }
+
+    /// Reads back a single word produced by [`quote`] the way `sh` would.
+    fn unquote(word: &str) -> Option<String> {
+        let mut out = String::new();
+        let mut chars = word.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\'' => loop {
+                    match chars.next()? {
+                        '\'' => break,
+                        c => out.push(c),
+                    }
+                },
+                '\\' => out.push(chars.next()?),
+                c if c.is_whitespace() || "$`\"|;&<>()*?[]{}#~!".contains(c) => return None,
+                c => out.push(c),
+            }
+        }
+        Some(out)
+    }
+
+    #[test]
+    fn test_quote_round_trips() {
+        let pieces = [
+            "", "a", " ", "'", "''", "\"", "\\", "$HOME", "$(id)", "`id`", ";", "&&", "|", "\n",
+            "\t", "*", "~", "#", "!", "--mode", "ünï", "パイ", "\u{202e}", "..", "/",
+        ];
+        for a in pieces {
+            for b in pieces {
+                for c in pieces {
+                    let word = format!("{}{}{}", a, b, c);
+                    assert_eq!(unquote(&quote(&word)).as_deref(), Some(word.as_str()));
+                }
+            }
+        }
+    }
+}
+
@@ -0,0 +1,225 @@
+//! Persistent extension state kept in the extension work directory.
+//!
+//! Zed gives every extension its own work directory, which is also the
+//! extension's current directory. State is stored there as a small JSON file
+//! and is always optional: a missing or corrupt file means "first run".
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use zed_extension_api::serde_json::{self, Value};
+
+use crate::actions::{Action, ActionReport};
+use crate::capabilities::Capabilities;
+use crate::census::CachedCensus;
+use crate::footprint::Footprint;
+use crate::pipeline::Progress;
+use crate::readiness::{LaunchCommand, Readiness};
+use crate::secrets;
+use crate::updates::UpdateCheck;
+use python_discovery::probe_cache::{self, ProbeCache};
+
+/// File name of the state file inside the work directory.
+const STATE_FILE: &str = "serena-state.json";
+
+/// State persisted between extension invocations.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct ExtensionState {
+    /// Set once a context server command has been built successfully
+    pub onboarding_completed: bool,
+    /// The most recent successful launch
+    pub last_launch: Option<LaunchRecord>,
+    /// The last launch whose server proved healthy, to fall back on
+    pub last_known_good: Option<LaunchRecord>,
+    /// Stage the current or last launch reached
+    pub launch_progress: Option<Progress>,
+    /// Cached result of the last serena-agent update check
+    pub update_check: Option<UpdateCheck>,
+    /// Recent interpreter probe results
+    pub probe_cache: ProbeCache,
+    /// Result of the readiness check after the last launch
+    pub readiness: Option<Readiness>,
+    /// Options each serena-agent release accepts, keyed by entry module and version
+    pub cli_capabilities: BTreeMap<String, Capabilities>,
+    /// The `action` setting as of the last launch, so it runs only once
+    pub action_done: Option<Action>,
+    /// Outcome of the last maintenance action
+    pub action_report: Option<ActionReport>,
+    /// Disk space last measured for managed environments and language servers
+    pub footprint: Option<Footprint>,
+    /// Problems the last launch got past, such as retried installs
+    pub launch_warnings: Vec<String>,
+    /// Source files the project had when last counted
+    pub census: Option<CachedCensus>,
+}
+
+/// Details about a successful launch.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct LaunchRecord {
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+    /// Interpreter the server was launched with
+    pub python: String,
+    /// The command, to fall back on after a failed launch
+    #[serde(default)]
+    pub command: Option<LaunchCommand>,
+    /// The bare server the readiness check starts
+    #[serde(default)]
+    pub check: Option<LaunchCommand>,
+    /// Fingerprint of the effective settings the server started with
+    #[serde(default)]
+    pub settings_hash: Option<String>,
+    /// Those settings with secrets redacted, to tell what changed since
+    #[serde(default)]
+    pub settings: Option<Value>,
+}
+
+impl ExtensionState {
+    /// Loads the state from `work_dir`, falling back to the default state.
+    pub(crate) fn load(work_dir: &Path) -> Self {
+        std::fs::read_to_string(state_path(work_dir))
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    /// Writes the state to `work_dir`.
+    pub(crate) fn save(&self, work_dir: &Path) -> Result<(), String> {
+        let contents = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("Failed to serialize state: {}", e))?;
+        std::fs::write(state_path(work_dir), contents)
+            .map_err(|e| format!("Failed to write extension state: {}", e))
+    }
+
+    /// Records a successful launch with the effective `settings` and marks
+    /// onboarding as complete.
+    pub(crate) fn record_launch(
+        &mut self,
+        python: &str,
+        command: LaunchCommand,
+        check: LaunchCommand,
+        settings: Option<&Value>,
+    ) {
+        let mut redacted = settings.cloned();
+        if let Some(redacted) = &mut redacted {
+            secrets::redact_settings(redacted);
+        }
+        self.onboarding_completed = true;
+        self.last_launch = Some(LaunchRecord {
+            timestamp: now(),
+            python: python.to_string(),
+            command: Some(command),
+            check: Some(check),
+            settings_hash: Some(probe_cache::settings_hash(settings)),
+            settings: redacted,
+        });
+    }
+
+    /// Keeps the last launch as the one to fall back on when a later launch fails.
+    pub(crate) fn mark_healthy(&mut self) {
+        self.last_known_good = self.last_launch.clone();
+    }
+}
+
+fn state_path(work_dir: &Path) -> PathBuf {
+    work_dir.join(STATE_FILE)
+}
+
+/// The extension work directory.
+pub(crate) fn work_dir() -> Result<PathBuf, String> {
+    std::env::current_dir()
+        .map_err(|e| format!("Could not determine extension work directory: {}", e))
+}
+
+/// Current time in seconds since the Unix epoch.
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Formats a Unix timestamp as `YYYY-MM-DD HH:MM UTC`.
+pub(crate) fn format_timestamp(timestamp: u64) -> String {
+    let days = (timestamp / 86_400) as i64;
+    let seconds = timestamp % 86_400;
+
+    // Civil-from-days conversion (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        seconds / 3_600,
+        seconds % 3_600 / 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00 UTC");
+        assert_eq!(format_timestamp(951_782_400), "2000-02-29 00:00 UTC");
+        assert_eq!(format_timestamp(1_760_531_655), "2025-10-15 12:34 UTC");
+    }
+
+    #[test]
+    fn test_state_round_trip() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+
+        assert_eq!(ExtensionState::load(dir), ExtensionState::default());
+
+        let mut state = ExtensionState::default();
+        let command = LaunchCommand {
+            command: "serena".to_string(),
+            args: vec!["start-mcp-server".to_string()],
+            env: Vec::new(),
+        };
+        let settings = serde_json::json!({ "api_keys": { "openai": "sk-secret" } });
+        state.record_launch(
+            "/usr/bin/python3.12",
+            command.clone(),
+            command.clone(),
+            Some(&settings),
+        );
+        state.save(dir).unwrap();
+
+        let loaded = ExtensionState::load(dir);
+        assert!(loaded.onboarding_completed);
+        let launch = loaded.last_launch.unwrap();
+        assert_eq!(launch.python, "/usr/bin/python3.12");
+        assert_eq!(launch.command, Some(command.clone()));
+        assert_eq!(launch.check, Some(command.clone()));
+        assert_eq!(
+            launch.settings_hash,
+            Some(probe_cache::settings_hash(Some(&settings)))
+        );
+        assert!(!launch.settings.unwrap().to_string().contains("sk-secret"));
+        assert_eq!(loaded.last_known_good, None);
+
+        state.mark_healthy();
+        state.record_launch("/usr/bin/python3.11", command.clone(), command, None);
+        state.save(dir).unwrap();
+        let loaded = ExtensionState::load(dir);
+        assert_eq!(
+            loaded.last_known_good.unwrap().python,
+            "/usr/bin/python3.12"
+        );
+    }
+}
@@ -0,0 +1,28 @@
+//! Helpers shared by the unit tests.
+
+use std::process::{Child, Command, Stdio};
+
+/// A child process killed when dropped, so a failed assertion leaves nothing
+/// running.
+pub(crate) struct KillOnDrop(pub Child);
+
+impl Drop for KillOnDrop {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+/// A process some other process started, killed by pid when dropped.
+#[cfg(unix)]
+pub(crate) struct KillPidOnDrop(pub String);
+
+#[cfg(unix)]
+impl Drop for KillPidOnDrop {
+    fn drop(&mut self) {
+        let _ = Command::new("kill")
+            .args(["-9", self.0.trim()])
+            .stderr(Stdio::null())
+            .status();
+    }
+}
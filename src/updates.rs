@@ -0,0 +1,142 @@
+//! Opt-in, rate-limited update checks for serena-agent.
+//!
+//! When `check_for_updates` is enabled, the latest release is looked up on
+//! PyPI at most once per [`CHECK_INTERVAL_SECS`]; the result is cached in the
+//! extension state so opening the configuration panel stays fast and offline.
+
+use serde::{Deserialize, Serialize};
+use zed_extension_api::http_client::{HttpMethod, HttpRequest, RedirectPolicy};
+use zed_extension_api::serde_json::{self, Value};
+
+use crate::retry::{self, Failure};
+use crate::state::ExtensionState;
+use python_discovery::version::Version;
+
+/// PyPI JSON API endpoint for serena-agent.
+const PYPI_URL: &str = "https://pypi.org/pypi/serena-agent/json";
+
+/// Minimum time between PyPI lookups.
+const CHECK_INTERVAL_SECS: u64 = 24 * 60 * 60;
+
+/// Cached result of the last PyPI lookup.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct UpdateCheck {
+    /// Unix timestamp of the lookup
+    pub checked_at: u64,
+    pub latest_version: String,
+}
+
+/// Returns the latest serena-agent release, using the cache when it is fresh.
+///
+/// Updates `state` after a successful lookup; the caller persists it.
+pub(crate) fn latest_version(state: &mut ExtensionState, now: u64) -> Option<String> {
+    if let Some(check) = &state.update_check {
+        if now.saturating_sub(check.checked_at) < CHECK_INTERVAL_SECS {
+            return Some(check.latest_version.clone());
+        }
+    }
+
+    match fetch_latest_version() {
+        Ok(latest_version) => {
+            state.update_check = Some(UpdateCheck {
+                checked_at: now,
+                latest_version: latest_version.clone(),
+            });
+            Some(latest_version)
+        }
+        // Fall back to a stale result rather than showing nothing
+        Err(_) => state
+            .update_check
+            .as_ref()
+            .map(|check| check.latest_version.clone()),
+    }
+}
+
+fn fetch_latest_version() -> Result<String, String> {
+    let request = HttpRequest::builder()
+        .method(HttpMethod::Get)
+        .url(PYPI_URL)
+        .header("Accept", "application/json")
+        .redirect_policy(RedirectPolicy::FollowAll)
+        .build()?;
+    let response = retry::run(
+        "Failed to reach PyPI",
+        retry::DOWNLOAD,
+        // The panel reports a failed check; retries that succeeded don't matter
+        &mut Vec::new(),
+        retry::sleep,
+        || request.fetch().map_err(Failure::Transient),
+    )?;
+    parse_latest_version(&String::from_utf8_lossy(&response.body))
+        .ok_or_else(|| "Unexpected response from PyPI".to_string())
+}
+
+/// Extracts `info.version` from a PyPI JSON API response.
+pub(crate) fn parse_latest_version(body: &str) -> Option<String> {
+    let value: Value = serde_json::from_str(body).ok()?;
+    value
+        .get("info")?
+        .get("version")?
+        .as_str()
+        .map(|version| version.to_string())
+}
+
+/// Returns true when `latest` is a newer release than `installed`.
+pub(crate) fn is_newer(latest: &str, installed: &str) -> bool {
+    match (Version::parse(latest), Version::parse(installed)) {
+        (Some(latest), Some(installed)) => latest > installed,
+        _ => false,
+    }
+}
+
+/// Markdown notice shown when an update is available.
+pub(crate) fn notice(installed: &str, latest: &str, python: &str) -> Option<String> {
+    is_newer(latest, installed).then(|| {
+        format!(
+            "⬆️ **Update available:** serena-agent {} → {}, run `{} -m pip install -U serena-agent`\n\n",
+            installed, latest, python
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_latest_version() {
+        let body = r#"{ "info": { "name": "serena-agent", "version": "0.1.4" }, "releases": {} }"#;
+        assert_eq!(parse_latest_version(body), Some("0.1.4".to_string()));
+        assert_eq!(parse_latest_version("not json"), None);
+    }
+
+    #[test]
+    fn test_is_newer() {
+        assert!(is_newer("0.1.4", "0.1.3"));
+        assert!(is_newer("0.2.0", "0.1.10"));
+        assert!(!is_newer("0.1.3", "0.1.3"));
+        assert!(!is_newer("0.1.3", "0.1.10"));
+        assert!(is_newer("0.1.4", "0.1.4rc1"));
+        assert!(!is_newer("0.1.5.dev1", "0.1.5"));
+    }
+
+    #[test]
+    fn test_latest_version_uses_fresh_cache() {
+        let mut state = ExtensionState {
+            update_check: Some(UpdateCheck {
+                checked_at: 1_000,
+                latest_version: "0.1.4".to_string(),
+            }),
+            ..Default::default()
+        };
+        assert_eq!(latest_version(&mut state, 2_000), Some("0.1.4".to_string()));
+    }
+
+    #[test]
+    fn test_notice() {
+        assert_eq!(notice("0.1.4", "0.1.4", "python3"), None);
+        let text = notice("0.1.3", "0.1.4", "python3").unwrap();
+        assert!(text.contains("0.1.3 → 0.1.4"));
+        assert!(text.contains("python3 -m pip install -U serena-agent"));
+    }
+}
@@ -0,0 +1,317 @@
+//! Validation of settings against the generated JSON schema.
+//!
+//! Serde stops at the first problem it finds; this validator walks the whole
+//! settings value and reports every violation with its JSON pointer, so users
+//! can fix all mistakes in one pass. It understands the subset of JSON Schema
+//! that schemars emits for the settings struct.
+
+use std::fmt;
+use zed_extension_api::serde_json::{Map, Value};
+
+/// A single schema violation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ValidationError {
+    /// JSON pointer to the offending value (empty for the root)
+    pub pointer: String,
+    pub message: String,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let pointer = if self.pointer.is_empty() {
+            "/"
+        } else {
+            &self.pointer
+        };
+        write!(f, "`{}`: {}", pointer, self.message)
+    }
+}
+
+/// Validates `instance` against `schema`, returning every violation found.
+pub(crate) fn validate(schema: &Value, instance: &Value) -> Vec<ValidationError> {
+    let mut errors = Vec::new();
+    Validator { root: schema }.check(schema, instance, "", &mut errors);
+    errors
+}
+
+/// Formats violations as a single multi-line error message.
+pub(crate) fn format_errors(errors: &[ValidationError]) -> String {
+    let mut message = format!("Invalid settings ({} problem(s)):", errors.len());
+    for error in errors {
+        message.push_str(&format!("\n- {}", error));
+    }
+    message
+}
+
+struct Validator<'a> {
+    root: &'a Value,
+}
+
+impl Validator<'_> {
+    fn check(
+        &self,
+        schema: &Value,
+        instance: &Value,
+        pointer: &str,
+        errors: &mut Vec<ValidationError>,
+    ) {
+        let Some(schema) = schema.as_object() else {
+            // `true` accepts everything, `false` rejects everything
+            if schema == &Value::Bool(false) {
+                errors.push(error(pointer, "no value is allowed here"));
+            }
+            return;
+        };
+
+        if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+            match self.resolve(reference) {
+                Some(target) => self.check(target, instance, pointer, errors),
+                None => errors.push(error(
+                    pointer,
+                    &format!("unresolvable schema reference {}", reference),
+                )),
+            }
+        }
+
+        for key in ["anyOf", "oneOf"] {
+            if let Some(options) = schema.get(key).and_then(Value::as_array) {
+                let matched = options.iter().any(|option| {
+                    let mut option_errors = Vec::new();
+                    self.check(option, instance, pointer, &mut option_errors);
+                    option_errors.is_empty()
+                });
+                if !matched {
+                    // `Option<T>` becomes `anyOf: [T, null]`; report T's errors directly
+                    let non_null: Vec<&Value> = options
+                        .iter()
+                        .filter(|option| option.get("type") != Some(&Value::from("null")))
+                        .collect();
+                    match non_null.as_slice() {
+                        [only] => self.check(only, instance, pointer, errors),
+                        _ => errors.push(error(
+                            pointer,
+                            &format!("{} does not match any allowed form", describe(instance)),
+                        )),
+                    }
+                }
+            }
+        }
+
+        for sub_schema in schema
+            .get("allOf")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+        {
+            self.check(sub_schema, instance, pointer, errors);
+        }
+
+        if let Some(types) = schema.get("type") {
+            let allowed: Vec<&str> = match types {
+                Value::String(t) => vec![t.as_str()],
+                Value::Array(ts) => ts.iter().filter_map(Value::as_str).collect(),
+                _ => Vec::new(),
+            };
+            if !allowed.is_empty() && !allowed.iter().any(|t| matches_type(t, instance)) {
+                errors.push(error(
+                    pointer,
+                    &format!(
+                        "expected {}, found {}",
+                        allowed.join(" or "),
+                        describe(instance)
+                    ),
+                ));
+                // Further keywords would only produce noise for a mistyped value
+                return;
+            }
+        }
+
+        if let Some(expected) = schema.get("const") {
+            if instance != expected {
+                errors.push(error(pointer, &format!("expected {}", expected)));
+            }
+        }
+
+        if let Some(options) = schema.get("enum").and_then(Value::as_array) {
+            if !options.contains(instance) {
+                let options: Vec<String> = options.iter().map(Value::to_string).collect();
+                errors.push(error(
+                    pointer,
+                    &format!("{} is not one of {}", instance, options.join(", ")),
+                ));
+            }
+        }
+
+        if let Some(number) = instance.as_f64() {
+            if let Some(minimum) = schema.get("minimum").and_then(Value::as_f64) {
+                if number < minimum {
+                    errors.push(error(
+                        pointer,
+                        &format!("{} is less than the minimum of {}", instance, minimum),
+                    ));
+                }
+            }
+            if let Some(maximum) = schema.get("maximum").and_then(Value::as_f64) {
+                if number > maximum {
+                    errors.push(error(
+                        pointer,
+                        &format!("{} is greater than the maximum of {}", instance, maximum),
+                    ));
+                }
+            }
+        }
+
+        if let Some(object) = instance.as_object() {
+            self.check_object(schema, object, pointer, errors);
+        }
+
+        if let (Some(items), Some(array)) = (schema.get("items"), instance.as_array()) {
+            for (index, item) in array.iter().enumerate() {
+                self.check(items, item, &format!("{}/{}", pointer, index), errors);
+            }
+        }
+    }
+
+    fn check_object(
+        &self,
+        schema: &Map<String, Value>,
+        object: &Map<String, Value>,
+        pointer: &str,
+        errors: &mut Vec<ValidationError>,
+    ) {
+        let properties = schema.get("properties").and_then(Value::as_object);
+
+        for required in schema
+            .get("required")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+        {
+            if let Some(name) = required.as_str() {
+                if !object.contains_key(name) {
+                    errors.push(error(
+                        pointer,
+                        &format!("missing required property `{}`", name),
+                    ));
+                }
+            }
+        }
+
+        for (key, value) in object {
+            let child = format!("{}/{}", pointer, escape_pointer(key));
+            match properties.and_then(|p| p.get(key)) {
+                Some(property) => self.check(property, value, &child, errors),
+                None => match schema.get("additionalProperties") {
+                    Some(Value::Bool(false)) => {
+                        errors.push(error(&child, "unknown setting"));
+                    }
+                    Some(additional) => self.check(additional, value, &child, errors),
+                    None => {}
+                },
+            }
+        }
+    }
+
+    fn resolve(&self, reference: &str) -> Option<&Value> {
+        self.root.pointer(reference.strip_prefix('#')?)
+    }
+}
+
+fn error(pointer: &str, message: &str) -> ValidationError {
+    ValidationError {
+        pointer: pointer.to_string(),
+        message: message.to_string(),
+    }
+}
+
+fn matches_type(expected: &str, instance: &Value) -> bool {
+    match expected {
+        "null" => instance.is_null(),
+        "boolean" => instance.is_boolean(),
+        "string" => instance.is_string(),
+        "array" => instance.is_array(),
+        "object" => instance.is_object(),
+        "number" => instance.is_number(),
+        "integer" => {
+            instance.is_i64()
+                || instance.is_u64()
+                || instance.as_f64().is_some_and(|n| n.fract() == 0.0)
+        }
+        _ => true,
+    }
+}
+
+fn describe(instance: &Value) -> String {
+    match instance {
+        Value::Null => "null".to_string(),
+        Value::Bool(_) => "boolean".to_string(),
+        Value::Number(_) => "number".to_string(),
+        Value::String(_) => "string".to_string(),
+        Value::Array(_) => "array".to_string(),
+        Value::Object(_) => "object".to_string(),
+    }
+}
+
+/// Escapes a key for use in a JSON pointer (RFC 6901).
+fn escape_pointer(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zed_extension_api::serde_json::json;
+
+    fn schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "python_executable": { "type": ["string", "null"] },
+                "port": { "type": "integer", "minimum": 0, "maximum": 65535 },
+                "modes": { "type": ["array", "null"], "items": { "type": "string" } },
+                "transport": { "anyOf": [{ "$ref": "#/$defs/Transport" }, { "type": "null" }] }
+            },
+            "$defs": {
+                "Transport": { "type": "string", "enum": ["stdio", "sse"] }
+            }
+        })
+    }
+
+    #[test]
+    fn test_valid_settings() {
+        let settings = json!({
+            "python_executable": null,
+            "port": 8000,
+            "modes": ["planning"],
+            "transport": "sse",
+            "unknown": true
+        });
+        assert!(validate(&schema(), &settings).is_empty());
+    }
+
+    #[test]
+    fn test_reports_all_errors() {
+        let settings = json!({
+            "python_executable": 3,
+            "port": 70000,
+            "modes": ["planning", 1],
+            "transport": "http"
+        });
+        let errors = validate(&schema(), &settings);
+        let pointers: Vec<&str> = errors.iter().map(|e| e.pointer.as_str()).collect();
+        assert_eq!(
+            pointers,
+            vec!["/modes/1", "/port", "/python_executable", "/transport"]
+        );
+        assert_eq!(errors[3].message, r#""http" is not one of "stdio", "sse""#);
+    }
+
+    #[test]
+    fn test_format_errors() {
+        let errors = validate(&schema(), &json!({ "port": -1 }));
+        assert_eq!(
+            format_errors(&errors),
+            "Invalid settings (1 problem(s)):\n- `/port`: -1 is less than the minimum of 0"
+        );
+    }
+}
@@ -0,0 +1,389 @@
+"""Launch wrapper for the Serena context server, generated by the Zed extension.
+
+Usage: python -c <this source> <options-json> <command> [args...]
+
+Supported options:
+  niceness              Unix only: scheduling priority adjustment for the server
+  memory_limit_mb       Unix only: address-space ceiling (RLIMIT_AS) for the server
+  idle_timeout_seconds  stop the server after this long without MCP traffic
+  pre_launch_command    argv run before the server starts; the server is not
+                        started if it fails or times out
+  post_exit_command     argv run after the server exits
+  hook_timeout_seconds  how long either hook may run (default 30)
+  working_directory     directory the hooks and the server run in
+  kill_process_tree     stop the language servers Serena started together with
+                        it: a Job Object on Windows, a new session on Unix
+  shutdown_grace_seconds
+                        Unix only: how long the server may take to exit after
+                        SIGTERM before it is killed (default 10)
+  index_command         argv that indexes the project after pre_launch_command;
+                        the server starts anyway if it fails or times out
+  index_timeout_seconds how long indexing may run (default 45)
+  language_server_cache {"link": ..., "target": ...}: make Serena's language
+                        server directory `link` a link to `target`
+  language_server_bundle
+                        {"directory": ..., "bundle": ...}: link what `bundle`
+                        holds and `directory` lacks into `directory`
+
+Unix-only options are ignored on Windows. Hooks run without a shell, with
+stdin closed and stdout sent to stderr so they cannot corrupt the MCP stream.
+"""
+
+import json
+import os
+import signal
+import shutil
+import subprocess
+import sys
+import threading
+import time
+
+DEFAULT_HOOK_TIMEOUT_SECONDS = 30
+DEFAULT_SHUTDOWN_GRACE_SECONDS = 10
+DEFAULT_INDEX_TIMEOUT_SECONDS = 45
+
+# Waits for the wrapper to exit, however it exits, then stops the server's
+# process group. Reading stdin returns once the wrapper's end of the pipe closes.
+REAPER_SOURCE = """
+import os, signal, sys, time
+sys.stdin.buffer.read()
+group, grace = int(sys.argv[1]), float(sys.argv[2])
+for sig in (signal.SIGTERM, signal.SIGKILL):
+    try:
+        os.killpg(group, sig)
+    except OSError:
+        break
+    time.sleep(grace)
+"""
+
+# Keeps the Job Object open for as long as the wrapper runs
+job = None
+
+
+def log(message):
+    sys.stderr.write("serena: %s\n" % message)
+    sys.stderr.flush()
+
+
+def apply_limits(options):
+    niceness = options.get("niceness")
+    if niceness:
+        os.nice(niceness)
+
+    memory_limit_mb = options.get("memory_limit_mb")
+    if memory_limit_mb:
+        import resource
+
+        size = memory_limit_mb * 1024 * 1024
+        resource.setrlimit(resource.RLIMIT_AS, (size, size))
+
+
+def run_hook(name, command, timeout):
+    if not command:
+        return True
+    try:
+        result = subprocess.run(
+            command, stdin=subprocess.DEVNULL, stdout=sys.stderr, timeout=timeout
+        )
+    except subprocess.TimeoutExpired:
+        log("%s timed out after %ss" % (name, timeout))
+        return False
+    except OSError as error:
+        log("%s could not be run: %s" % (name, error))
+        return False
+    if result.returncode != 0:
+        log("%s exited with status %s" % (name, result.returncode))
+        return False
+    return True
+
+
+def is_link(path):
+    """Whether `path` is a symlink or, on Windows, a junction."""
+    try:
+        os.readlink(path)
+        return True
+    except (OSError, ValueError):
+        return False
+
+
+def link_language_servers(link, target):
+    """Points Serena's language server directory at `target`, a junction on
+    Windows. A directory that already holds downloads is left alone, so
+    nothing is lost; Serena then keeps using it."""
+    try:
+        os.makedirs(target, exist_ok=True)
+        if is_link(link):
+            if os.path.realpath(link) == os.path.realpath(target):
+                return
+            # Junctions are directories to Windows
+            (os.rmdir if os.name == "nt" else os.unlink)(link)
+        elif os.path.isdir(link):
+            if os.listdir(link):
+                log(
+                    "%s already holds language servers; move them to %s and delete it "
+                    "to use language_server_cache" % (link, target)
+                )
+                return
+            os.rmdir(link)
+        os.makedirs(os.path.dirname(link), exist_ok=True)
+        make_link(link, target)
+    except (OSError, subprocess.CalledProcessError) as error:
+        log("cannot link %s to %s: %s" % (link, target, error))
+
+
+def make_link(link, target):
+    """Creates `link` pointing at `target`. Windows gets a junction for a
+    directory, which needs no privileges, and a copy of a file."""
+    if os.name != "nt":
+        os.symlink(target, link, target_is_directory=os.path.isdir(target))
+    elif os.path.isdir(target):
+        subprocess.run(
+            ["cmd", "/c", "mklink", "/J", link, target],
+            stdin=subprocess.DEVNULL,
+            stdout=subprocess.DEVNULL,
+            check=True,
+        )
+    else:
+        shutil.copy2(target, link)
+
+
+def seed_language_servers(directory, bundle):
+    """Links every entry of `bundle` that `directory` lacks into it, merging
+    subdirectories both have, so Serena finds its language servers instead of
+    downloading them. Nothing in `directory` is replaced and the bundle is
+    never written to."""
+    try:
+        os.makedirs(directory, exist_ok=True)
+        names = os.listdir(bundle)
+    except OSError as error:
+        log("cannot use language_server_bundle %s: %s" % (bundle, error))
+        return
+    for name in names:
+        source = os.path.join(bundle, name)
+        entry = os.path.join(directory, name)
+        try:
+            if is_link(entry) and not os.path.exists(entry):
+                # Left behind by a bundle that moved
+                (os.rmdir if os.name == "nt" else os.unlink)(entry)
+            if not os.path.lexists(entry):
+                make_link(entry, source)
+            elif os.path.isdir(source) and os.path.isdir(entry) and not is_link(entry):
+                seed_language_servers(entry, source)
+        except (OSError, subprocess.CalledProcessError) as error:
+            log("cannot link %s from language_server_bundle: %s" % (source, error))
+
+
+def join_job():
+    """Puts the wrapper in a Job Object that kills its members when the last
+    handle closes, i.e. when the wrapper exits or is killed. Processes started
+    afterwards, Serena and its language servers, join it too."""
+    global job
+    import ctypes
+    from ctypes import wintypes
+
+    class BasicLimits(ctypes.Structure):
+        _fields_ = [
+            ("PerProcessUserTimeLimit", ctypes.c_int64),
+            ("PerJobUserTimeLimit", ctypes.c_int64),
+            ("LimitFlags", wintypes.DWORD),
+            ("MinimumWorkingSetSize", ctypes.c_size_t),
+            ("MaximumWorkingSetSize", ctypes.c_size_t),
+            ("ActiveProcessLimit", wintypes.DWORD),
+            ("Affinity", ctypes.c_size_t),
+            ("PriorityClass", wintypes.DWORD),
+            ("SchedulingClass", wintypes.DWORD),
+        ]
+
+    class ExtendedLimits(ctypes.Structure):
+        _fields_ = [
+            ("BasicLimitInformation", BasicLimits),
+            ("IoInfo", ctypes.c_ulonglong * 6),
+            ("ProcessMemoryLimit", ctypes.c_size_t),
+            ("JobMemoryLimit", ctypes.c_size_t),
+            ("PeakProcessMemoryUsed", ctypes.c_size_t),
+            ("PeakJobMemoryUsed", ctypes.c_size_t),
+        ]
+
+    JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE = 0x2000
+    JOB_OBJECT_EXTENDED_LIMIT_INFORMATION = 9
+
+    kernel32 = ctypes.WinDLL("kernel32", use_last_error=True)
+    kernel32.CreateJobObjectW.restype = wintypes.HANDLE
+    kernel32.CreateJobObjectW.argtypes = [ctypes.c_void_p, wintypes.LPCWSTR]
+    kernel32.SetInformationJobObject.argtypes = [
+        wintypes.HANDLE,
+        ctypes.c_int,
+        ctypes.c_void_p,
+        wintypes.DWORD,
+    ]
+    kernel32.GetCurrentProcess.restype = wintypes.HANDLE
+    kernel32.AssignProcessToJobObject.argtypes = [wintypes.HANDLE, wintypes.HANDLE]
+
+    job = kernel32.CreateJobObjectW(None, None)
+    info = ExtendedLimits()
+    info.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE
+    if not (
+        job
+        and kernel32.SetInformationJobObject(
+            job, JOB_OBJECT_EXTENDED_LIMIT_INFORMATION, ctypes.byref(info), ctypes.sizeof(info)
+        )
+        and kernel32.AssignProcessToJobObject(job, kernel32.GetCurrentProcess())
+    ):
+        log("cannot create a job object: error %s" % ctypes.get_last_error())
+
+
+def start_reaper(group, grace):
+    """Starts a process that stops `group` once the wrapper is gone, even if
+    the wrapper itself is killed and can't clean up."""
+    read_end, _write_end = os.pipe()
+    try:
+        subprocess.Popen(
+            [sys.executable, "-c", REAPER_SOURCE, str(group), str(grace)],
+            stdin=read_end,
+            stdout=subprocess.DEVNULL,
+            start_new_session=True,
+        )
+    except OSError as error:
+        log("cannot start the process tree reaper: %s" % error)
+    os.close(read_end)
+    # The write end stays open until the wrapper exits, and isn't inherited
+
+
+def pump(source_fd, target_fd, activity):
+    while True:
+        try:
+            chunk = os.read(source_fd, 65536)
+        except OSError:
+            chunk = b""
+        if not chunk:
+            break
+        activity[0] = time.monotonic()
+        os.write(target_fd, chunk)
+    try:
+        os.close(target_fd)
+    except OSError:
+        pass
+
+
+def signal_server(process, group, sig):
+    """Sends `sig` to the server, or to its whole process group."""
+    try:
+        if group:
+            os.killpg(process.pid, sig)
+        elif sig == signal.SIGTERM:
+            process.terminate()
+        else:
+            process.kill()
+    except OSError:
+        pass
+
+
+def shut_down(process, group, grace):
+    """Asks the server to exit so it can save its state, and kills it if it
+    is still running after `grace` seconds."""
+    signal_server(process, group, signal.SIGTERM)
+    try:
+        process.wait(timeout=grace)
+    except subprocess.TimeoutExpired:
+        log("killing the server, still running %ss after SIGTERM" % grace)
+        signal_server(process, group, getattr(signal, "SIGKILL", signal.SIGTERM))
+
+
+def watch_idle(process, activity, idle_timeout, stop):
+    while process.poll() is None:
+        time.sleep(1)
+        if time.monotonic() - activity[0] > idle_timeout:
+            log("stopping after %ss without activity" % idle_timeout)
+            stop()
+            break
+
+
+def main():
+    options = json.loads(sys.argv[1])
+    command = sys.argv[2:]
+    posix = os.name == "posix"
+    idle_timeout = options.get("idle_timeout_seconds")
+    post_exit = options.get("post_exit_command")
+    hook_timeout = options.get("hook_timeout_seconds") or DEFAULT_HOOK_TIMEOUT_SECONDS
+    kill_tree = options.get("kill_process_tree")
+    grace = options.get("shutdown_grace_seconds")
+    if grace is None:
+        grace = DEFAULT_SHUTDOWN_GRACE_SECONDS
+
+    if kill_tree and not posix:
+        join_job()
+
+    working_directory = options.get("working_directory")
+    if working_directory:
+        try:
+            os.chdir(working_directory)
+        except OSError as error:
+            log("cannot use working_directory %s: %s" % (working_directory, error))
+            sys.exit(1)
+
+    cache = options.get("language_server_cache")
+    if cache:
+        link_language_servers(cache["link"], cache["target"])
+    bundle = options.get("language_server_bundle")
+    if bundle:
+        seed_language_servers(bundle["directory"], bundle["bundle"])
+
+    if not run_hook("pre_launch_command", options.get("pre_launch_command"), hook_timeout):
+        sys.exit(1)
+
+    # Whatever indexing didn't finish, Serena does as symbols are needed
+    index_timeout = options.get("index_timeout_seconds") or DEFAULT_INDEX_TIMEOUT_SECONDS
+    if not run_hook("index_command", options.get("index_command"), index_timeout):
+        log("starting without a complete index")
+
+    # The wrapper has to outlive the server to stop its process group or to
+    # enforce a configured grace period
+    supervise = kill_tree or options.get("shutdown_grace_seconds") is not None
+    if not idle_timeout and not post_exit and not (supervise and posix):
+        if posix:
+            apply_limits(options)
+            os.execvp(command[0], command)
+        sys.exit(subprocess.call(command))
+
+    process = subprocess.Popen(
+        command,
+        stdin=subprocess.PIPE if idle_timeout else None,
+        stdout=subprocess.PIPE if idle_timeout else None,
+        preexec_fn=(lambda: apply_limits(options)) if posix else None,
+        start_new_session=bool(kill_tree and posix),
+        bufsize=0,
+    )
+
+    group = bool(kill_tree and posix)
+    if group:
+        start_reaper(process.pid, grace)
+
+    def stop():
+        shut_down(process, group, grace)
+
+    # Forward termination so the post-exit hook still runs when Zed stops us.
+    # The main thread is waiting for the server, so the grace period runs in
+    # another one.
+    for name in ("SIGTERM", "SIGINT", "SIGHUP"):
+        if hasattr(signal, name):
+            signal.signal(
+                getattr(signal, name),
+                lambda *_: threading.Thread(target=stop, daemon=True).start(),
+            )
+
+    if idle_timeout:
+        activity = [time.monotonic()]
+        for source, target in (
+            (sys.stdin.fileno(), process.stdin.fileno()),
+            (process.stdout.fileno(), sys.stdout.fileno()),
+        ):
+            threading.Thread(target=pump, args=(source, target, activity), daemon=True).start()
+        watch_idle(process, activity, idle_timeout, stop)
+
+    status = process.wait()
+    run_hook("post_exit_command", post_exit, hook_timeout)
+    # Report death by signal the way a shell would
+    sys.exit(128 - status if status < 0 else status)
+
+
+main()
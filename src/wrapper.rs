@@ -0,0 +1,275 @@
+//! Python launch wrapper for process-level controls and hooks.
+//!
+//! Serena always runs on a Python interpreter we have already located, so the
+//! wrapper is written in Python too (see `wrapper.py`) and works the same on
+//! every platform. It is only inserted when a setting actually needs it.
+
+use serde::Serialize;
+use zed_extension_api::serde_json::{self, json};
+
+/// Source of the wrapper, passed to the interpreter with `-c`.
+pub const WRAPPER_SOURCE: &str = include_str!("wrapper.py");
+
+/// Resource controls and hooks applied to the Serena process by the wrapper.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct LaunchOptions {
+    /// Scheduling priority adjustment (Unix only)
+    pub niceness: Option<i32>,
+    /// Address-space ceiling in megabytes (Unix only)
+    pub memory_limit_mb: Option<u64>,
+    /// Stop the server after this many seconds without MCP traffic
+    pub idle_timeout_seconds: Option<u64>,
+    /// Command run before the server starts; the server is not started if it fails
+    pub pre_launch_command: Option<Vec<String>>,
+    /// Command run after the server exits
+    pub post_exit_command: Option<Vec<String>>,
+    /// Seconds either hook may run before it is killed (the wrapper defaults to 30)
+    pub hook_timeout_seconds: Option<u64>,
+    /// Directory the hooks and the server run in
+    pub working_directory: Option<String>,
+    /// Stop the server's child processes with it (a Job Object on Windows, a session on Unix)
+    pub kill_process_tree: bool,
+    /// Seconds the server may take to exit after SIGTERM before it is killed (the wrapper defaults to 10)
+    pub shutdown_grace_seconds: Option<u64>,
+    /// Command that indexes the project before the server starts; the server starts anyway if it fails
+    pub index_command: Option<Vec<String>>,
+    /// Seconds indexing may run before it is stopped (the wrapper defaults to 45)
+    pub index_timeout_seconds: Option<u64>,
+    /// Where Serena's downloaded language servers are kept
+    pub language_server_cache: Option<LanguageServerCache>,
+    /// Pre-downloaded language servers linked into Serena's language server directory
+    pub language_server_bundle: Option<LanguageServerBundle>,
+}
+
+/// A directory Serena's language server directory is linked to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LanguageServerCache {
+    /// Serena's `language_servers` directory, replaced by the link
+    pub link: String,
+    /// Directory the link points to
+    pub target: String,
+}
+
+/// A directory of pre-downloaded language servers Serena should use instead
+/// of downloading its own.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LanguageServerBundle {
+    /// Serena's `language_servers` directory, which entries are linked into
+    pub directory: String,
+    /// The bundle, which is only read
+    pub bundle: String,
+}
+
+impl LaunchOptions {
+    /// Returns true when no control or hook is configured and no wrapper is needed.
+    pub fn is_empty(&self) -> bool {
+        self.niceness.unwrap_or(0) == 0
+            && self.memory_limit_mb.unwrap_or(0) == 0
+            && self.idle_timeout_seconds.unwrap_or(0) == 0
+            && self.pre_launch_command.as_ref().is_none_or(Vec::is_empty)
+            && self.post_exit_command.as_ref().is_none_or(Vec::is_empty)
+            && self.index_command.as_ref().is_none_or(Vec::is_empty)
+            && self.language_server_cache.is_none()
+            && self.language_server_bundle.is_none()
+            && self.working_directory.as_ref().is_none_or(String::is_empty)
+            && !self.kill_process_tree
+            && self.shutdown_grace_seconds.is_none()
+    }
+
+    /// Wraps `command` so it runs under the wrapper with these options.
+    ///
+    /// Returns the command unchanged when nothing is configured.
+    pub fn wrap(&self, python: &str, command: Vec<String>) -> Vec<String> {
+        if self.is_empty() {
+            return command;
+        }
+
+        let options = json!({
+            "niceness": self.niceness,
+            "memory_limit_mb": self.memory_limit_mb,
+            "idle_timeout_seconds": self.idle_timeout_seconds,
+            "pre_launch_command": self.pre_launch_command,
+            "post_exit_command": self.post_exit_command,
+            "hook_timeout_seconds": self.hook_timeout_seconds,
+            "working_directory": self.working_directory,
+            "kill_process_tree": self.kill_process_tree,
+            "shutdown_grace_seconds": self.shutdown_grace_seconds,
+            "index_command": self.index_command,
+            "index_timeout_seconds": self.index_timeout_seconds,
+            "language_server_cache": self.language_server_cache,
+            "language_server_bundle": self.language_server_bundle,
+        });
+        let mut wrapped = vec![
+            python.to_string(),
+            "-c".to_string(),
+            WRAPPER_SOURCE.to_string(),
+            serde_json::to_string(&options).unwrap_or_default(),
+        ];
+        wrapped.extend(command);
+        wrapped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(unix)]
+    use crate::testing::{KillOnDrop, KillPidOnDrop};
+
+    #[test]
+    fn test_no_limits_is_passthrough() {
+        let command = vec!["serena".to_string(), "start-mcp-server".to_string()];
+        let limits = LaunchOptions {
+            niceness: Some(0),
+            pre_launch_command: Some(Vec::new()),
+            ..Default::default()
+        };
+        assert!(limits.is_empty());
+        assert_eq!(limits.wrap("python3.11", command.clone()), command);
+    }
+
+    #[test]
+    fn test_wrap() {
+        let limits = LaunchOptions {
+            niceness: Some(10),
+            memory_limit_mb: Some(4096),
+            ..Default::default()
+        };
+        let wrapped = limits.wrap("python3.11", vec!["serena".to_string()]);
+
+        assert_eq!(wrapped[0], "python3.11");
+        assert_eq!(wrapped[1], "-c");
+        assert_eq!(wrapped[2], WRAPPER_SOURCE);
+        assert_eq!(
+            wrapped[3],
+            r#"{"hook_timeout_seconds":null,"idle_timeout_seconds":null,"index_command":null,"index_timeout_seconds":null,"kill_process_tree":false,"language_server_bundle":null,"language_server_cache":null,"memory_limit_mb":4096,"niceness":10,"post_exit_command":null,"pre_launch_command":null,"shutdown_grace_seconds":null,"working_directory":null}"#
+        );
+        assert_eq!(wrapped[4], "serena");
+    }
+
+    #[test]
+    fn test_hooks_need_wrapper() {
+        let options = LaunchOptions {
+            post_exit_command: Some(vec!["rm".to_string(), "-f".to_string(), "lock".to_string()]),
+            hook_timeout_seconds: Some(5),
+            ..Default::default()
+        };
+        assert!(!options.is_empty());
+
+        let wrapped = options.wrap("python3.12", vec!["serena".to_string()]);
+        assert!(wrapped[3].contains(r#""post_exit_command":["rm","-f","lock"]"#));
+        assert!(wrapped[3].contains(r#""hook_timeout_seconds":5"#));
+    }
+
+    #[test]
+    fn test_index_command_needs_wrapper() {
+        let options = LaunchOptions {
+            index_command: Some(vec![
+                "serena".to_string(),
+                "project".to_string(),
+                "index".to_string(),
+                "/src/app".to_string(),
+            ]),
+            ..Default::default()
+        };
+        assert!(!options.is_empty());
+        let wrapped = options.wrap("python3.12", vec!["serena".to_string()]);
+        assert!(wrapped[3].contains(r#""index_command":["serena","project","index","/src/app"]"#));
+    }
+
+    #[test]
+    fn test_kill_process_tree_needs_wrapper() {
+        let options = LaunchOptions {
+            kill_process_tree: true,
+            ..Default::default()
+        };
+        assert!(!options.is_empty());
+        let wrapped = options.wrap("python3.12", vec!["serena".to_string()]);
+        assert!(wrapped[3].contains(r#""kill_process_tree":true"#));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_kill_process_tree_stops_orphans() {
+        let temp = tempfile::tempdir().unwrap();
+        let pidfile = temp.path().join("orphan.pid");
+        let options = LaunchOptions {
+            kill_process_tree: true,
+            ..Default::default()
+        };
+        // The server leaves a child behind, like a language server
+        let server = vec![
+            "/bin/sh".to_string(),
+            "-c".to_string(),
+            format!("sleep 30 & echo $! > {}", pidfile.display()),
+        ];
+        let argv = options.wrap("python3", server);
+        let Ok(status) = std::process::Command::new(&argv[0])
+            .args(&argv[1..])
+            .status()
+        else {
+            // No interpreter to run the wrapper with
+            return;
+        };
+        assert!(status.success());
+
+        let pid = KillPidOnDrop(std::fs::read_to_string(&pidfile).unwrap());
+        let alive = || {
+            std::process::Command::new("kill")
+                .args(["-0", pid.0.trim()])
+                .stderr(std::process::Stdio::null())
+                .status()
+                .unwrap()
+                .success()
+        };
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+        while alive() && std::time::Instant::now() < deadline {
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+        assert!(!alive());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_shutdown_grace_period() {
+        let temp = tempfile::tempdir().unwrap();
+        let ready = temp.path().join("ready");
+        let options = LaunchOptions {
+            shutdown_grace_seconds: Some(1),
+            ..Default::default()
+        };
+        // A server that ignores SIGTERM has to be killed
+        let server = vec![
+            "python3".to_string(),
+            "-c".to_string(),
+            format!(
+                "import signal, time; signal.signal(signal.SIGTERM, signal.SIG_IGN); \
+                 open({:?}, 'w').close(); time.sleep(30)",
+                ready.display().to_string()
+            ),
+        ];
+        let argv = options.wrap("python3", server);
+        let Ok(wrapper) = std::process::Command::new(&argv[0])
+            .args(&argv[1..])
+            .spawn()
+        else {
+            // No interpreter to run the wrapper with
+            return;
+        };
+        let mut wrapper = KillOnDrop(wrapper);
+        let started = std::time::Instant::now();
+        while !ready.exists() {
+            assert!(started.elapsed() < std::time::Duration::from_secs(10));
+            std::thread::sleep(std::time::Duration::from_millis(50));
+        }
+        std::process::Command::new("kill")
+            .arg(wrapper.0.id().to_string())
+            .status()
+            .unwrap();
+
+        let stopped = std::time::Instant::now();
+        let status = wrapper.0.wait().unwrap();
+        assert_eq!(status.code(), Some(128 + 9));
+        assert!(stopped.elapsed() < std::time::Duration::from_secs(10));
+    }
+}
@@ -0,0 +1,97 @@
+//! The interpreter Zed's Python language servers are configured with.
+//!
+//! Projects often point pyright or basedpyright at a virtual environment in
+//! `.zed/settings.json`. Offering that interpreter to Serena keeps the
+//! assistant and the editor's language tooling on one environment. Context
+//! servers can't query language server settings through the extension API,
+//! so the project's settings file is read directly.
+
+use std::path::{Path, PathBuf};
+
+use python_discovery::Os;
+use zed_extension_api::serde_json::Value;
+
+use crate::jsonc;
+
+/// Zed settings file relative to the project root.
+const SETTINGS_FILE: &str = ".zed/settings.json";
+
+/// Language servers whose `python` settings are consulted, in order.
+const LANGUAGE_SERVERS: [&str; 2] = ["basedpyright", "pyright"];
+
+/// Reads the interpreter the project's Python language servers use, if the
+/// project configures one.
+pub(crate) fn configured_python(root: &Path, os: Os) -> Option<PathBuf> {
+    let contents = std::fs::read_to_string(root.join(SETTINGS_FILE)).ok()?;
+    let settings: Value = jsonc::from_str(&contents).ok()?;
+    python_from_settings(&settings, root, os)
+}
+
+/// Extracts the interpreter from Zed settings.
+///
+/// `python.pythonPath` wins; otherwise `python.venv` names an environment
+/// below `python.venvPath`, which defaults to the project root. Relative
+/// paths resolve against `root`.
+fn python_from_settings(settings: &Value, root: &Path, os: Os) -> Option<PathBuf> {
+    LANGUAGE_SERVERS.iter().find_map(|server| {
+        let python = settings.pointer(&format!("/lsp/{}/settings/python", server))?;
+        let setting = |key| python.get(key).and_then(Value::as_str);
+        if let Some(path) = setting("pythonPath") {
+            return Some(root.join(path));
+        }
+        let venv = setting("venv")?;
+        let prefix = match setting("venvPath") {
+            Some(venv_path) => root.join(venv_path).join(venv),
+            None => root.join(venv),
+        };
+        Some(match os {
+            Os::Windows => prefix.join("Scripts").join("python.exe"),
+            Os::Mac | Os::Linux | Os::OtherUnix => prefix.join("bin").join("python"),
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zed_extension_api::serde_json::json;
+
+    #[test]
+    fn test_python_from_settings() {
+        let root = Path::new("/src/app");
+        let python = |settings| python_from_settings(&settings, root, Os::Linux);
+
+        assert_eq!(python(json!({})), None);
+        assert_eq!(
+            python(json!({
+                "lsp": { "pyright": { "settings": { "python": { "pythonPath": ".venv/bin/python" } } } }
+            })),
+            Some(PathBuf::from("/src/app/.venv/bin/python"))
+        );
+        assert_eq!(
+            python(json!({
+                "lsp": { "basedpyright": { "settings": { "python": { "pythonPath": "/opt/py/bin/python3.12" } } } }
+            })),
+            Some(PathBuf::from("/opt/py/bin/python3.12"))
+        );
+        assert_eq!(
+            python(json!({
+                "lsp": { "pyright": { "settings": { "python": { "venvPath": "/envs", "venv": "app" } } } }
+            })),
+            Some(PathBuf::from("/envs/app/bin/python"))
+        );
+        assert_eq!(
+            python_from_settings(
+                &json!({ "lsp": { "pyright": { "settings": { "python": { "venv": ".venv" } } } } }),
+                Path::new("C:\\src\\app"),
+                Os::Windows
+            ),
+            Some(
+                Path::new("C:\\src\\app")
+                    .join(".venv")
+                    .join("Scripts")
+                    .join("python.exe")
+            )
+        );
+    }
+}
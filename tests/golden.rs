@@ -0,0 +1,406 @@
+//! Golden-file tests for the launch command.
+//!
+//! Each case renders the exact [`Command`] built for a platform, settings and
+//! simulated filesystem and compares it with `tests/golden/<case>.txt`, so a
+//! launcher refactor can't silently change what gets executed. After an
+//! intended change, regenerate the files with
+//! `UPDATE_GOLDEN=1 cargo test --features lib golden` and review the diff.
+
+#![cfg(feature = "lib")]
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use python_discovery::sandbox::Sandbox;
+use zed_extension_api::{serde_json::json, Command, Os};
+use zed_serena_context_server::launch::{build_command, Launch};
+use zed_serena_context_server::{installation, scripts, shell, wrapper};
+
+/// A filesystem holding only the listed files.
+struct VirtualFs(HashSet<PathBuf>);
+
+impl scripts::FileSystem for VirtualFs {
+    fn is_file(&self, path: &Path) -> bool {
+        self.0.contains(path)
+    }
+
+    fn list_dir(&self, path: &Path) -> Vec<String> {
+        scripts::list_children(&self.0, path)
+    }
+}
+
+/// One row of the matrix.
+struct Case {
+    name: &'static str,
+    os: Os,
+    settings: Option<zed_extension_api::serde_json::Value>,
+    python: &'static str,
+    module: &'static str,
+    serena_version: Option<&'static str>,
+    /// `start-mcp-server --help` output of the installed release
+    help: Option<&'static str>,
+    config_overlay: Option<&'static str>,
+    files: &'static [&'static str],
+    /// Folders open in Zed
+    worktrees: usize,
+    env: &'static [(&'static str, &'static str)],
+    sandbox: Option<Sandbox>,
+}
+
+impl Default for Case {
+    fn default() -> Self {
+        Self {
+            name: "",
+            os: Os::Linux,
+            settings: None,
+            python: "/usr/bin/python3.12",
+            module: installation::DEFAULT_MODULE,
+            serena_version: None,
+            help: None,
+            config_overlay: None,
+            files: &[],
+            worktrees: 1,
+            env: &[],
+            sandbox: None,
+        }
+    }
+}
+
+fn cases() -> Vec<Case> {
+    vec![
+        Case {
+            name: "linux_defaults",
+            ..Default::default()
+        },
+        Case {
+            name: "linux_venv_script",
+            python: "/src/app/.venv/bin/python",
+            files: &["/src/app/.venv/bin/serena"],
+            settings: Some(json!({
+                "project_root": "/src/app",
+                "context": "ide-assistant",
+                "modes": ["planning", "editing"],
+            })),
+            ..Default::default()
+        },
+        Case {
+            name: "linux_pipx_script",
+            files: &["/home/dev/.local/pipx/venvs/serena-agent/bin/serena"],
+            ..Default::default()
+        },
+        Case {
+            name: "mac_user_site_script",
+            os: Os::Mac,
+            python: "/opt/homebrew/bin/python3.12",
+            files: &["/home/dev/Library/Python/3.12/bin/serena"],
+            ..Default::default()
+        },
+        Case {
+            name: "windows_scripts_dir",
+            os: Os::Windows,
+            python: "C:/Python312/python.exe",
+            files: &["C:/Python312/Scripts/serena.exe"],
+            ..Default::default()
+        },
+        Case {
+            name: "isolated_ignores_script",
+            files: &["/usr/bin/serena"],
+            settings: Some(json!({ "isolated": true })),
+            ..Default::default()
+        },
+        Case {
+            name: "environment_and_secrets",
+            settings: Some(json!({
+                "project_root": "/src/app",
+                "analytics": true,
+                "environment": { "SERENA_LOG_LEVEL": "debug" },
+                "api_keys": { "anthropic": "env:MY_ANTHROPIC_KEY" },
+                "pythonpath": ["plugins", "~/patches"],
+                "site_packages_extra": ["/opt/extra"],
+            })),
+            env: &[
+                ("HOME", "/home/dev"),
+                ("MY_ANTHROPIC_KEY", "sk-test"),
+                ("PYTHONPATH", "/existing"),
+            ],
+            ..Default::default()
+        },
+        Case {
+            name: "wrapped_with_limits_and_hooks",
+            settings: Some(json!({
+                "project_root": "/src/app",
+                "niceness": 5,
+                "memory_limit_mb": 2048,
+                "idle_timeout_minutes": 30,
+                "pre_launch_command": ["direnv", "allow"],
+                "working_directory": "sub",
+                "shutdown_grace_seconds": 20,
+            })),
+            ..Default::default()
+        },
+        Case {
+            name: "precompute_symbols",
+            settings: Some(json!({
+                "project_root": "/src/app",
+                "precompute_symbols": true,
+                "precompute_timeout_seconds": 90,
+            })),
+            files: &["/usr/bin/serena"],
+            ..Default::default()
+        },
+        Case {
+            name: "restricted_environment",
+            settings: Some(json!({
+                "restricted_environment": true,
+                "analytics": true,
+                "environment": { "NO_PROXY": "localhost,127.0.0.1,::1,.corp.example" },
+            })),
+            ..Default::default()
+        },
+        Case {
+            name: "language_server_cache",
+            settings: Some(json!({
+                "project_root": "/src/app",
+                "isolate_memories": true,
+                "language_server_cache": "~/.cache/serena-language-servers",
+            })),
+            ..Default::default()
+        },
+        Case {
+            name: "language_server_bundle",
+            settings: Some(json!({
+                "project_root": "/src/app",
+                "restricted_environment": true,
+                "language_server_bundle": "/mnt/tools/serena-language-servers",
+            })),
+            ..Default::default()
+        },
+        Case {
+            name: "empty_workspace",
+            worktrees: 0,
+            settings: Some(json!({ "project_root": "/src/app", "share_instance": true })),
+            ..Default::default()
+        },
+        Case {
+            name: "shared_instance",
+            settings: Some(json!({
+                "project_root": "/src/app",
+                "share_instance": true,
+                "port": 9121,
+                "niceness": 5,
+                "idle_timeout_minutes": 30,
+                "reap_stale_instances": true,
+            })),
+            ..Default::default()
+        },
+        Case {
+            name: "flatpak_sandbox",
+            settings: Some(json!({ "environment": { "SERENA_LOG_LEVEL": "debug" } })),
+            sandbox: Some(Sandbox::Flatpak),
+            ..Default::default()
+        },
+        Case {
+            name: "cli_entry_module",
+            module: "serena.cli",
+            ..Default::default()
+        },
+        Case {
+            name: "custom_serena_command",
+            files: &["/usr/bin/serena"],
+            settings: Some(json!({ "serena_command": ["uv", "run", "serena"] })),
+            ..Default::default()
+        },
+        Case {
+            name: "renamed_script",
+            python: "/src/app/.venv/bin/python",
+            files: &[
+                "/src/app/.venv/bin/serena",
+                "/src/app/.venv/bin/serena-corp",
+            ],
+            settings: Some(json!({ "serena_command": ["serena-corp"] })),
+            ..Default::default()
+        },
+        Case {
+            name: "isolated_rejects_serena_command",
+            settings: Some(json!({ "isolated": true, "serena_command": ["serena-corp"] })),
+            ..Default::default()
+        },
+        Case {
+            name: "login_shell",
+            files: &["/home/dev/.pyenv/shims/serena"],
+            python: "/home/dev/.pyenv/shims/python",
+            settings: Some(json!({ "launch_via_shell": true, "niceness": 5 })),
+            env: &[("SHELL", "/usr/bin/zsh")],
+            ..Default::default()
+        },
+        Case {
+            name: "login_shell_windows",
+            os: Os::Windows,
+            python: "C:/Python312/python.exe",
+            settings: Some(json!({ "launch_via_shell": true })),
+            ..Default::default()
+        },
+        Case {
+            name: "windows_kill_process_tree",
+            os: Os::Windows,
+            python: "C:/Python312/python.exe",
+            settings: Some(json!({ "kill_process_tree": true })),
+            ..Default::default()
+        },
+        Case {
+            name: "pre_transport_release",
+            serena_version: Some("0.0.9"),
+            ..Default::default()
+        },
+        Case {
+            name: "options_from_help",
+            settings: Some(json!({
+                "project_root": "/src/app",
+                "context": "ide-assistant",
+                "modes": ["planning"],
+                "web_dashboard": false,
+            })),
+            help: Some(
+                "Options:\n  --project TEXT  Path\n  --mode TEXT  Modes\n  --help  Show this message\n",
+            ),
+            config_overlay: Some("/work/serena-overlay.yml"),
+            ..Default::default()
+        },
+        Case {
+            name: "sse_without_shared_instance",
+            settings: Some(json!({ "transport": "sse", "port": 9121 })),
+            ..Default::default()
+        },
+        Case {
+            name: "sse_without_project_root",
+            settings: Some(json!({ "transport": "sse", "share_instance": true })),
+            ..Default::default()
+        },
+        Case {
+            name: "shared_instance_without_project_root",
+            settings: Some(json!({ "share_instance": true, "reap_stale_instances": true })),
+            ..Default::default()
+        },
+        Case {
+            name: "shared_instance_stdio",
+            settings: Some(json!({
+                "project_root": "/src/app",
+                "share_instance": true,
+                "transport": "stdio",
+            })),
+            ..Default::default()
+        },
+        Case {
+            name: "web_dashboard_off",
+            settings: Some(json!({ "web_dashboard": false })),
+            ..Default::default()
+        },
+        Case {
+            name: "config_overlay",
+            settings: Some(json!({
+                "excluded_tools": ["execute_shell_command"],
+                "serena_config": { "ls_specific_settings": { "python": { "ls_path": "/opt/pyright" } } },
+            })),
+            config_overlay: Some("/work/overlays/0123456789abcdef.yml"),
+            ..Default::default()
+        },
+        Case {
+            name: "isolated_memories",
+            settings: Some(json!({ "project_root": "/src/client-a", "isolate_memories": true })),
+            ..Default::default()
+        },
+        Case {
+            name: "isolated_memories_without_root",
+            settings: Some(json!({ "isolate_memories": true })),
+            ..Default::default()
+        },
+        Case {
+            name: "shared_instance_windows",
+            os: Os::Windows,
+            python: "C:/Python312/python.exe",
+            settings: Some(json!({ "project_root": "C:/src/app", "share_instance": true })),
+            ..Default::default()
+        },
+    ]
+}
+
+/// Renders one argument per line; the wrapper source is elided since
+/// `wrapper.py` has its own tests.
+fn render(result: &Result<Command, String>) -> String {
+    let command = match result {
+        Ok(command) => command,
+        Err(error) => return format!("error: {}\n", error),
+    };
+    let mut out = format!("command: {}\n", command.command);
+    for arg in &command.args {
+        let arg = arg
+            .replace(&shell::quote(wrapper::WRAPPER_SOURCE), "<wrapper.py>")
+            .replace(wrapper::WRAPPER_SOURCE, "<wrapper.py>");
+        for (i, line) in arg.lines().enumerate() {
+            out.push_str(if i == 0 { "arg: " } else { "   | " });
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+    for (key, value) in &command.env {
+        out.push_str(&format!("env: {}={}\n", key, value));
+    }
+    out
+}
+
+fn run(case: &Case) -> Result<Command, String> {
+    let fs = VirtualFs(case.files.iter().map(PathBuf::from).collect());
+    build_command(
+        &Launch {
+            settings: case.settings.clone(),
+            python: Path::new(case.python),
+            module: case.module,
+            serena_version: case.serena_version,
+            help: case.help,
+            config_overlay: case.config_overlay.map(Path::new),
+            os: case.os,
+            home: Some(Path::new("/home/dev")),
+            worktrees: case.worktrees,
+            work_dir: Some(Path::new("/work")),
+            sandbox: case.sandbox,
+        },
+        |name| {
+            case.env
+                .iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| value.to_string())
+        },
+        &fs,
+    )
+}
+
+#[test]
+fn golden_commands() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    let mut mismatches = Vec::new();
+
+    for case in cases() {
+        let actual = render(&run(&case));
+        let path = dir.join(format!("{}.txt", case.name));
+        if update {
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(&path, &actual).unwrap();
+            continue;
+        }
+        match std::fs::read_to_string(&path) {
+            Ok(expected) if expected == actual => {}
+            Ok(expected) => mismatches.push(format!(
+                "{}:\n--- expected\n{}--- actual\n{}",
+                case.name, expected, actual
+            )),
+            Err(_) => mismatches.push(format!("{}: missing {}", case.name, path.display())),
+        }
+    }
+
+    assert!(
+        mismatches.is_empty(),
+        "launch commands changed (rerun with UPDATE_GOLDEN=1 if intended):\n{}",
+        mismatches.join("\n")
+    );
+}
@@ -0,0 +1,33 @@
+//! Drives the launcher surface the way another extension would.
+//!
+//! Run with `cargo test --features lib`.
+
+#![cfg(feature = "lib")]
+
+use zed_extension_api::Os;
+use zed_serena_context_server::pythonpath;
+use zed_serena_context_server::wrapper::LaunchOptions;
+
+#[test]
+fn launcher_wraps_commands_only_when_needed() {
+    let command = vec!["serena".to_string(), "start-mcp-server".to_string()];
+    assert_eq!(
+        LaunchOptions::default().wrap("python3", command.clone()),
+        command
+    );
+
+    let wrapped = LaunchOptions {
+        niceness: Some(5),
+        ..Default::default()
+    }
+    .wrap("python3", command.clone());
+    assert_eq!(&wrapped[..2], ["python3", "-c"]);
+    assert_eq!(&wrapped[4..], command.as_slice());
+}
+
+#[test]
+fn pythonpath_uses_platform_separator() {
+    let entries = ["/a".to_string(), "/b".to_string()];
+    let joined = pythonpath::build(&entries, &[], None, None, Os::Linux, |_| None).unwrap();
+    assert_eq!(joined.as_deref(), Some("/a:/b"));
+}
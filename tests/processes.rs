@@ -0,0 +1,267 @@
+//! Drives probing, installation and the readiness check against a simulated
+//! host, whose programs answer from a script instead of running.
+//!
+//! Run with `cargo test --features lib`.
+
+#![cfg(feature = "lib")]
+
+use std::cell::RefCell;
+use std::path::Path;
+use std::time::Duration;
+
+use python_discovery::detection::{find_python_interpreters, PythonPolicy};
+use python_discovery::discovery::PythonPreference;
+use python_discovery::probe_cache::ProbeCache;
+use python_discovery::process::{Invocation, Output, Runner};
+use python_discovery::sandbox::Sandbox;
+use python_discovery::{Arch, Host, Os};
+use zed_extension_api::serde_json::json;
+use zed_serena_context_server::installation::{self, Channel, InstallOptions};
+use zed_serena_context_server::launch::{self, Launch};
+use zed_serena_context_server::readiness::{LaunchCommand, Readiness};
+use zed_serena_context_server::scripts;
+
+/// Answers runs with `answer`, which sees the command the run starts.
+///
+/// The helpers the extension runs commands through are unwrapped: the
+/// `flatpak-spawn --host` prefix, the deadline supervisor and the readiness
+/// handshake. A handshake is recorded with a leading `handshake` argument.
+/// `answer` returning `None` means the program doesn't exist.
+struct FakeHost<F> {
+    answer: F,
+    runs: RefCell<Vec<Vec<String>>>,
+}
+
+impl<F: Fn(&[String]) -> Option<Output>> FakeHost<F> {
+    fn new(answer: F) -> Self {
+        Self {
+            answer,
+            runs: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn runs(&self) -> Vec<String> {
+        self.runs.borrow().iter().map(|run| run.join(" ")).collect()
+    }
+}
+
+impl<F: Fn(&[String]) -> Option<Output>> Runner for FakeHost<F> {
+    fn run(&self, invocation: &Invocation) -> Result<Output, String> {
+        let mut argv: Vec<String> = [invocation.program.clone()]
+            .into_iter()
+            .chain(invocation.args.iter().cloned())
+            .collect();
+        if argv[0] == "flatpak-spawn" {
+            let options = argv[1..].iter().take_while(|arg| arg.starts_with("--"));
+            argv.drain(..1 + options.count());
+        }
+        if argv.get(1).map(String::as_str) == Some("-c") {
+            if argv[2].contains("serena-deadline") {
+                argv.drain(..4);
+            } else if argv[2].contains("tools/list") {
+                argv.splice(..5, ["handshake".to_string()]);
+            }
+        }
+        self.runs.borrow_mut().push(argv.clone());
+        (self.answer)(&argv).ok_or_else(|| "No such file or directory".to_string())
+    }
+}
+
+fn output(status: i32, stdout: &str, stderr: &str) -> Option<Output> {
+    Some(Output {
+        status: Some(status),
+        stdout: stdout.as_bytes().to_vec(),
+        stderr: stderr.as_bytes().to_vec(),
+    })
+}
+
+fn ok(stdout: &str) -> Option<Output> {
+    output(0, stdout, "")
+}
+
+#[test]
+fn probing_runs_interpreters_on_the_flatpak_host() {
+    let host = FakeHost::new(|argv: &[String]| match argv.join(" ").as_str() {
+        "which python3.12" => ok("/usr/bin/python3.12\n"),
+        "/usr/bin/python3.12 --version" => ok("Python 3.12.4\n"),
+        "which python3.10" => ok("/usr/bin/python3.10\n"),
+        "/usr/bin/python3.10 --version" => ok("Python 3.10.14\n"),
+        _ => None,
+    });
+    let found = find_python_interpreters(
+        None,
+        PythonPolicy::default(),
+        PythonPreference::SystemFirst,
+        &[],
+        None,
+        Host {
+            os: Os::Linux,
+            arch: Arch::X86_64,
+            sandbox: Some(Sandbox::Flatpak),
+        },
+        &host,
+        &mut ProbeCache::default(),
+    )
+    .unwrap();
+    assert_eq!(found[0].path, "/usr/bin/python3.12");
+    assert_eq!(found[0].version, "3.12.4");
+    // Too old for Serena
+    assert!(found.iter().all(|found| found.version != "3.10.14"));
+    assert!(host
+        .runs()
+        .contains(&"/usr/bin/python3.12 --version".to_string()));
+}
+
+#[test]
+fn install_creates_the_managed_env_and_falls_back_to_pip() {
+    let temp = tempfile::tempdir().unwrap();
+    let env_dir = temp.path().join("serena");
+    let env_python = installation::env_python(&env_dir, zed_extension_api::Os::Linux);
+    let env_python = env_python.to_string_lossy().to_string();
+    let options = InstallOptions {
+        extras: Vec::new(),
+        constraints: None,
+        user: false,
+        reinstall: false,
+        timeout: Duration::from_secs(600),
+    };
+
+    // uv isn't installed, so pip installs
+    let host = FakeHost::new(|argv: &[String]| match argv[0].as_str() {
+        "uv" => None,
+        _ => ok(""),
+    });
+    let python = installation::ensure_managed_env(
+        "python3",
+        &env_dir,
+        zed_extension_api::Os::Linux,
+        Channel::Stable,
+        &options,
+        &host,
+        &mut Vec::new(),
+    )
+    .unwrap();
+    assert_eq!(python, env_python);
+    let venv = format!("python3 -m venv {}", env_dir.display());
+    let uv = format!("uv pip install --python {} serena-agent", env_python);
+    let pip = format!("{} -m pip install serena-agent", env_python);
+    assert_eq!(host.runs(), [venv.clone(), uv.clone(), pip.clone()]);
+
+    // A failed install leaves no half-made environment behind
+    let host = FakeHost::new(|argv: &[String]| match argv[0].as_str() {
+        "uv" => output(
+            1,
+            "",
+            "ERROR: No matching distribution found for serena-agent\n",
+        ),
+        _ => ok(""),
+    });
+    let error = installation::ensure_managed_env(
+        "python3",
+        &env_dir,
+        zed_extension_api::Os::Linux,
+        Channel::Stable,
+        &options,
+        &host,
+        &mut Vec::new(),
+    )
+    .unwrap_err();
+    assert!(
+        error.contains("No matching distribution found"),
+        "{}",
+        error
+    );
+    assert!(!env_dir.exists());
+}
+
+#[test]
+fn preflight_checks_report_supervisor_timeouts() {
+    let host = FakeHost::new(|_: &[String]| output(124, "", "serena-deadline: timed out\n"));
+    let timeout = Duration::from_millis(1500);
+    let error = installation::serena_installed(
+        "/opt/venv/bin/python",
+        &mut ProbeCache::default(),
+        timeout,
+        &host,
+    )
+    .unwrap_err();
+    assert!(
+        error.ends_with("timed out after 1.5s; raise preflight_timeout_seconds to wait longer"),
+        "{}",
+        error
+    );
+    assert_eq!(
+        host.runs(),
+        ["/opt/venv/bin/python -c import serena; print('installed')"]
+    );
+}
+
+#[test]
+fn readiness_checks_the_bare_server() {
+    let launch = Launch {
+        settings: Some(json!({
+            "project_root": "/src/app",
+            "pre_launch_command": ["direnv", "allow"],
+            "precompute_symbols": true,
+            "niceness": 5,
+        })),
+        python: Path::new("/usr/bin/python3.12"),
+        module: installation::DEFAULT_MODULE,
+        serena_version: None,
+        help: None,
+        config_overlay: None,
+        os: zed_extension_api::Os::Linux,
+        home: None,
+        worktrees: 1,
+        work_dir: Some(Path::new("/work")),
+        sandbox: None,
+    };
+    let fs = VirtualFsOf(&["/usr/bin/serena"]);
+    let check = launch::check_command(&launch, |_| None, &fs).unwrap();
+    let check = LaunchCommand::from_command(&check);
+
+    let host = FakeHost::new(|argv: &[String]| match argv[0].as_str() {
+        "handshake" => ok("{\"millis\": 840, \"tools\": 24}\n"),
+        _ => None,
+    });
+    let readiness = Readiness::check(
+        7,
+        "/usr/bin/python3.12",
+        &check,
+        None,
+        Duration::from_secs(30),
+        &host,
+    );
+    assert_eq!(readiness.error, None);
+    assert_eq!((readiness.millis, readiness.tools), (Some(840), Some(24)));
+    // Serena alone, without the wrapper running hooks and precomputation
+    assert_eq!(
+        host.runs(),
+        ["handshake /usr/bin/serena start-mcp-server --project /src/app --transport stdio"]
+    );
+
+    let silent = FakeHost::new(|_: &[String]| ok("{\"error\": \"timeout\"}\n"));
+    let readiness = Readiness::check(
+        7,
+        "/usr/bin/python3.12",
+        &check,
+        None,
+        Duration::from_secs(30),
+        &silent,
+    );
+    assert_eq!(readiness.error.as_deref(), Some("no answer within 30s"));
+}
+
+/// A filesystem holding only the listed files.
+struct VirtualFsOf(&'static [&'static str]);
+
+impl scripts::FileSystem for VirtualFsOf {
+    fn is_file(&self, path: &Path) -> bool {
+        self.0.iter().any(|file| Path::new(file) == path)
+    }
+
+    fn list_dir(&self, path: &Path) -> Vec<String> {
+        let files: Vec<_> = self.0.iter().map(std::path::PathBuf::from).collect();
+        scripts::list_children(&files, path)
+    }
+}